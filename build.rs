@@ -0,0 +1,48 @@
+fn main() {
+	#[cfg(feature = "ffi")]
+	generate_c_header();
+}
+
+/// Generates the C header for `src/ffi.rs` into `OUT_DIR`. With `NEO_VM_UPDATE_HEADER` set, the
+/// checked-in `include/neo_vm.h` is refreshed as well. Failures are reported as warnings so that
+/// a header problem never breaks the library build.
+#[cfg(feature = "ffi")]
+fn generate_c_header() {
+	use std::{env, fs, path::PathBuf};
+
+	println!("cargo:rerun-if-changed=src/ffi.rs");
+	println!("cargo:rerun-if-changed=cbindgen.toml");
+	println!("cargo:rerun-if-env-changed=NEO_VM_UPDATE_HEADER");
+
+	let (Ok(crate_dir), Ok(out_dir)) = (env::var("CARGO_MANIFEST_DIR"), env::var("OUT_DIR")) else {
+		println!("cargo:warning=CARGO_MANIFEST_DIR or OUT_DIR is not set, skipping neo_vm.h");
+		return
+	};
+	let bindings = match cbindgen::generate(&crate_dir) {
+		Ok(bindings) => bindings,
+		Err(e) => {
+			println!("cargo:warning=Unable to generate neo_vm.h: {e}");
+			return
+		},
+	};
+
+	let mut header = Vec::new();
+	bindings.write(&mut header);
+
+	let mut targets = vec![PathBuf::from(out_dir).join("neo_vm.h")];
+	if env::var_os("NEO_VM_UPDATE_HEADER").is_some() {
+		targets.push(PathBuf::from(crate_dir).join("include").join("neo_vm.h"));
+	}
+	for target in targets {
+		if fs::read(&target).is_ok_and(|existing| existing == header) {
+			continue
+		}
+		let written = match target.parent() {
+			Some(dir) => fs::create_dir_all(dir).and_then(|_| fs::write(&target, &header)),
+			None => fs::write(&target, &header),
+		};
+		if let Err(e) = written {
+			println!("cargo:warning=Unable to write {}: {e}", target.display());
+		}
+	}
+}
@@ -0,0 +1,126 @@
+/*
+ * C ABI of neo-vm-rs.
+ *
+ * Ownership rules:
+ * - Every *_new function returns a handle owned by the caller, released with the matching
+ *   *_free function. Passing a null handle to a *_free function is a no-op.
+ * - Strings returned by the library are owned by the caller and released with
+ *   neo_string_free.
+ * - Input pointers are borrowed for the duration of the call only. Scripts passed to
+ *   neo_engine_load_script are copied, so the NeoScript may be freed right after.
+ * - Syscall callbacks receive the engine that is executing. That pointer is only valid during
+ *   the callback and must not be freed or retained.
+ * - Handles are not thread-safe. An engine and everything derived from it must stay on the
+ *   thread that created it.
+ */
+
+#ifndef NEO_VM_H
+#define NEO_VM_H
+
+/* Generated by cbindgen from src/ffi.rs. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+typedef struct NeoEngine NeoEngine;
+typedef struct NeoScript NeoScript;
+
+// Mirror of `ExecutionEngineLimits` with a C layout.
+typedef struct NeoLimits {
+  uint32_t max_shift;
+  uint32_t max_stack_size;
+  uint32_t max_item_size;
+  uint32_t max_comparable_size;
+  uint32_t max_invocation_stack_size;
+  uint32_t max_try_nesting_depth;
+  bool catch_engine_exceptions;
+} NeoLimits;
+
+// A syscall implemented by the host. Returns 0 on success; any other value faults the engine.
+typedef int32_t (*NeoSyscallCallback)(NeoEngine *engine, void *user_data);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Returns the default limits.
+struct NeoLimits neo_limits_default(void);
+
+// Creates an engine. `limits` may be null to use the defaults.
+// The returned handle must be released with `neo_engine_free`.
+NeoEngine *neo_engine_new(const struct NeoLimits *limits);
+
+// Releases an engine created by `neo_engine_new`.
+void neo_engine_free(NeoEngine *engine);
+
+// Parses and validates `len` bytes at `data` as a script.
+// Returns null if the script is invalid. The handle must be released with `neo_script_free`.
+NeoScript *neo_script_new(const uint8_t *data, uintptr_t len, bool strict);
+
+// Releases a script created by `neo_script_new`.
+void neo_script_free(NeoScript *script);
+
+// Loads a copy of `script` as a new context. `rvcount` is the number of values the context
+// must return, or -1 for any. Returns 0 on success and -1 on error.
+int32_t neo_engine_load_script(NeoEngine *engine, const NeoScript *script, int32_t rvcount);
+
+// Registers `callback` as the handler of the syscall named `name` and returns its id.
+// `user_data` is passed back to the callback untouched. Returns 0 on error.
+uint32_t neo_engine_register_syscall(NeoEngine *engine,
+                                     const char *name,
+                                     uint64_t price,
+                                     NeoSyscallCallback callback,
+                                     void *user_data);
+
+// Sets the gas the engine may consume before faulting.
+void neo_engine_set_gas_limit(NeoEngine *engine, uint64_t gas_limit);
+
+// Runs the loaded scripts and returns the final `VMState`. A panic inside the engine faults
+// it, with the panic message as the fault message.
+uint8_t neo_engine_execute(NeoEngine *engine);
+
+// Returns the current `VMState`: 0 none, 1 halt, 2 fault, 4 break.
+uint8_t neo_engine_state(const NeoEngine *engine);
+
+// Returns the gas consumed so far.
+uint64_t neo_engine_gas_consumed(const NeoEngine *engine);
+
+// Returns the state, uncaught exception and result stack as JSON, with each item in the
+// `{"type": ..., "value": ...}` form. Returns null on error.
+// The string must be released with `neo_string_free`.
+char *neo_engine_result_json(const NeoEngine *engine);
+
+// Returns the message of the engine error that caused a fault, or null if there is none.
+// The string must be released with `neo_string_free`.
+char *neo_engine_fault_message(const NeoEngine *engine);
+
+// Pops the top item of the current evaluation stack and returns it as JSON in the
+// `{"type": ..., "value": ...}` form. Intended for syscall callbacks. Returns null on error.
+// The string must be released with `neo_string_free`.
+char *neo_engine_pop_json(NeoEngine *engine);
+
+// Pushes an integer onto the current evaluation stack.
+// Returns false if no script is loaded.
+bool neo_engine_push_integer(NeoEngine *engine, int64_t value);
+
+// Pushes a boolean onto the current evaluation stack.
+// Returns false if no script is loaded.
+bool neo_engine_push_bool(NeoEngine *engine, bool value);
+
+// Pushes a copy of `len` bytes at `data` as a `ByteString`.
+// Returns false if no script is loaded.
+bool neo_engine_push_bytes(NeoEngine *engine, const uint8_t *data, uintptr_t len);
+
+// Pushes `null` onto the current evaluation stack.
+// Returns false if no script is loaded.
+bool neo_engine_push_null(NeoEngine *engine);
+
+// Releases a string returned by this library.
+void neo_string_free(char *value);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* NEO_VM_H */
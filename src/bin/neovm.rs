@@ -0,0 +1,220 @@
+//! `neovm`: runs a NeoVM script and prints the final state and result stack as JSON.
+//!
+//! ```sh
+//! neovm 11129e40                    # hex
+//! neovm --base64 ERKeQA==           # base64
+//! neovm --file contract.bin --gas 1000000 --trace
+//! ```
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use clap::Parser;
+use neo_vm_rs::{
+	crypto::ecdsa::Curve, crypto_service::register_crypto_services,
+	execution_engine::ExecutionEngine, execution_engine_limits::ExecutionEngineLimits,
+	execution_result::ExecutionResult, fault_report::FaultReport, script::debug_info::DebugInfo,
+	stack_item_json, std_lib_service::register_std_lib_services, vm::script::Script,
+	vm_state::VMState,
+};
+use std::{fs, path::PathBuf, process::ExitCode, rc::Rc};
+
+#[derive(Parser, Debug)]
+#[command(name = "neovm", about = "Run a NeoVM script")]
+struct Args {
+	/// The script as a hex string, optionally prefixed with `0x`, or as base64 with `--base64`.
+	#[arg(required_unless_present = "file", conflicts_with = "file")]
+	script: Option<String>,
+
+	/// Read the raw script bytes from a file.
+	#[arg(short, long)]
+	file: Option<PathBuf>,
+
+	/// Treat the script argument as base64.
+	#[arg(long)]
+	base64: bool,
+
+	/// Gas budget; the engine faults once it is exceeded.
+	#[arg(long)]
+	gas: Option<u64>,
+
+	/// Print every instruction to stderr before it executes.
+	#[arg(long)]
+	trace: bool,
+
+	/// Debug info JSON emitted by the compiler, used to show source lines in traces and faults.
+	#[arg(long)]
+	debug_info: Option<PathBuf>,
+
+	/// Skip strict validation of the script.
+	#[arg(long)]
+	lenient: bool,
+
+	/// Register the crypto services, verifying signatures over this hex container data.
+	#[arg(long)]
+	sign_data: Option<String>,
+
+	/// The curve `CheckSig` and `CheckMultisig` verify on: secp256r1 or secp256k1.
+	#[arg(long, default_value = "secp256r1", requires = "sign_data")]
+	curve: String,
+
+	/// Register the StdLib methods as `StdLib.<method>` syscalls.
+	#[arg(long)]
+	std_lib: bool,
+
+	#[arg(long)]
+	max_shift: Option<usize>,
+
+	#[arg(long)]
+	max_stack_size: Option<usize>,
+
+	#[arg(long)]
+	max_item_size: Option<usize>,
+
+	#[arg(long)]
+	max_comparable_size: Option<usize>,
+
+	#[arg(long)]
+	max_invocation_stack_size: Option<usize>,
+
+	#[arg(long)]
+	max_try_nesting_depth: Option<usize>,
+}
+
+impl Args {
+	fn limits(&self) -> ExecutionEngineLimits {
+		let defaults = ExecutionEngineLimits::default();
+		ExecutionEngineLimits {
+			max_shift: self.max_shift.unwrap_or(defaults.max_shift),
+			max_stack_size: self.max_stack_size.unwrap_or(defaults.max_stack_size),
+			max_item_size: self.max_item_size.unwrap_or(defaults.max_item_size),
+			max_comparable_size: self.max_comparable_size.unwrap_or(defaults.max_comparable_size),
+			max_invocation_stack_size: self
+				.max_invocation_stack_size
+				.unwrap_or(defaults.max_invocation_stack_size),
+			max_try_nesting_depth: self
+				.max_try_nesting_depth
+				.unwrap_or(defaults.max_try_nesting_depth),
+			catch_engine_exceptions: defaults.catch_engine_exceptions,
+		}
+	}
+
+	fn script_bytes(&self) -> Result<Vec<u8>, String> {
+		if let Some(path) = &self.file {
+			return fs::read(path).map_err(|e| format!("cannot read {}: {e}", path.display()))
+		}
+
+		let text = self.script.as_deref().unwrap_or_default().trim();
+		if self.base64 {
+			return STANDARD.decode(text).map_err(|e| format!("script is not base64: {e}"))
+		}
+		decode_hex(text.strip_prefix("0x").unwrap_or(text))
+			.ok_or_else(|| "script is not hex; pass --base64 for base64 input".to_string())
+	}
+}
+
+fn load_debug_info(path: &PathBuf) -> Result<DebugInfo, String> {
+	let json =
+		fs::read_to_string(path).map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+	DebugInfo::parse(&json).map_err(|e| e.to_string())
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+	if text.len() % 2 != 0 || !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+		return None
+	}
+	(0..text.len())
+		.step_by(2)
+		.map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+		.collect()
+}
+
+fn trace(engine: &ExecutionEngine, debug_info: Option<&DebugInfo>) {
+	let Some(context) = &engine.current_context else { return };
+	let context = context.borrow();
+	let instruction = match context.current_instruction() {
+		Ok(instruction) => instruction,
+		Err(e) => {
+			eprintln!("{:04X} {e}", context.instruction_pointer);
+			return
+		},
+	};
+	let operand: String = instruction.operand.iter().map(|b| format!("{b:02x}")).collect();
+	let location = debug_info
+		.and_then(|info| info.location_at(context.instruction_pointer))
+		.map(|location| format!(" {location}"))
+		.unwrap_or_default();
+	let stack = context.evaluation_stack();
+	let stack = stack.borrow();
+	let top = stack
+		.peek(0)
+		.and_then(|top| stack_item_json::to_json(&*top.borrow()))
+		.map(|top| format!(" top={top}"))
+		.unwrap_or_default();
+	eprintln!(
+		"{:04X} {:<12} {:<16} depth={} gas={}{location}{top}",
+		context.instruction_pointer,
+		format!("{:?}", instruction.opcode),
+		operand,
+		stack.size(),
+		engine.gas_consumed
+	);
+}
+
+fn run(args: &Args) -> Result<ExitCode, String> {
+	let bytes = args.script_bytes()?;
+	let debug_info = args.debug_info.as_ref().map(load_debug_info).transpose()?;
+	let script = Script::new(bytes, !args.lenient).map_err(|e| format!("invalid script: {e}"))?;
+
+	let mut engine = ExecutionEngine::with_options(args.limits());
+	if let Some(gas) = args.gas {
+		engine.gas_limit = gas;
+	}
+	if let Some(sign_data) = &args.sign_data {
+		let sign_data = decode_hex(sign_data.strip_prefix("0x").unwrap_or(sign_data))
+			.ok_or_else(|| "sign data is not hex".to_string())?;
+		let curve = match args.curve.as_str() {
+			"secp256r1" => Curve::Secp256r1,
+			"secp256k1" => Curve::Secp256k1,
+			curve => return Err(format!("unknown curve {curve}")),
+		};
+		register_crypto_services(&mut engine, Rc::new(sign_data), curve);
+	}
+	if args.std_lib {
+		register_std_lib_services(&mut engine);
+	}
+	engine.load_script(script, -1, 0);
+
+	if args.trace {
+		engine.state = VMState::None;
+		while engine.state != VMState::Halt && engine.state != VMState::Fault {
+			trace(&engine, debug_info.as_ref());
+			engine.execute_next();
+		}
+	} else {
+		engine.execute();
+	}
+
+	let result = ExecutionResult::from_engine(&engine).map_err(|e| e.to_string())?;
+	println!("{}", serde_json::to_string_pretty(&result).unwrap());
+
+	match FaultReport::from_engine(&engine) {
+		Some(mut report) => {
+			if let Some(debug_info) = &debug_info {
+				report.annotate(debug_info);
+			}
+			eprint!("{report}");
+			Ok(ExitCode::FAILURE)
+		},
+		None => Ok(ExitCode::SUCCESS),
+	}
+}
+
+fn main() -> ExitCode {
+	let args = Args::parse();
+	match run(&args) {
+		Ok(code) => code,
+		Err(e) => {
+			eprintln!("error: {e}");
+			ExitCode::from(2)
+		},
+	}
+}
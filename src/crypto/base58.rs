@@ -0,0 +1,71 @@
+use std::{
+	error::Error,
+	fmt::{Display, Formatter},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Base58Error {
+	/// A character outside the Base58 alphabet, at the given index.
+	InvalidCharacter(usize),
+	/// The last four bytes are not the start of the double SHA-256 of the payload.
+	InvalidChecksum,
+	/// The input is too short to hold a checksum.
+	TooShort,
+}
+
+impl Display for Base58Error {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Base58Error::InvalidCharacter(index) =>
+				write!(f, "Invalid Base58 character at index {index}"),
+			Base58Error::InvalidChecksum => write!(f, "Invalid Base58Check checksum"),
+			Base58Error::TooShort => write!(f, "Base58Check data is too short"),
+		}
+	}
+}
+
+impl Error for Base58Error {}
+
+pub fn encode(data: &[u8]) -> String {
+	bs58::encode(data).into_string()
+}
+
+pub fn decode(text: &str) -> Result<Vec<u8>, Base58Error> {
+	bs58::decode(text).into_vec().map_err(from_bs58)
+}
+
+/// Encodes `data` followed by the first four bytes of its double SHA-256.
+pub fn check_encode(data: &[u8]) -> String {
+	bs58::encode(data).with_check().into_string()
+}
+
+/// Decodes Base58Check text, verifying and removing the checksum.
+pub fn check_decode(text: &str) -> Result<Vec<u8>, Base58Error> {
+	bs58::decode(text).with_check(None).into_vec().map_err(from_bs58)
+}
+
+fn from_bs58(error: bs58::decode::Error) -> Base58Error {
+	match error {
+		bs58::decode::Error::InvalidCharacter { index, .. }
+		| bs58::decode::Error::NonAsciiCharacter { index } => Base58Error::InvalidCharacter(index),
+		bs58::decode::Error::NoChecksum => Base58Error::TooShort,
+		_ => Base58Error::InvalidChecksum,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn round_trips() {
+		assert_eq!(encode(&[0, 0, 1]), "112");
+		assert_eq!(decode("112").unwrap(), vec![0, 0, 1]);
+		assert_eq!(check_decode(&check_encode(b"neo")).unwrap(), b"neo");
+		assert_eq!(decode("0OIl"), Err(Base58Error::InvalidCharacter(0)));
+
+		let mut text = check_encode(b"neo");
+		text.push('1');
+		assert_eq!(check_decode(&text), Err(Base58Error::InvalidChecksum));
+	}
+}
@@ -0,0 +1,75 @@
+use p256::ecdsa::signature::Verifier;
+
+/// The elliptic curves signatures can be checked on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Curve {
+	/// NIST P-256, the curve of Neo account keys.
+	#[default]
+	Secp256r1,
+
+	/// The curve used by Bitcoin and Ethereum.
+	Secp256k1,
+}
+
+/// Checks a 64-byte `r || s` signature of the SHA-256 hash of `message`.
+///
+/// `public_key` is a SEC1 point, compressed or not. Malformed keys and signatures are reported
+/// as invalid rather than as errors, as Neo does. Signatures with a high `s` are accepted on both
+/// curves.
+pub fn verify(message: &[u8], signature: &[u8], public_key: &[u8], curve: Curve) -> bool {
+	match curve {
+		Curve::Secp256r1 => {
+			use p256::ecdsa::{Signature, VerifyingKey};
+			let (Ok(key), Ok(signature)) =
+				(VerifyingKey::from_sec1_bytes(public_key), Signature::from_slice(signature))
+			else {
+				return false
+			};
+			key.verify(message, &signature).is_ok()
+		},
+		Curve::Secp256k1 => {
+			use k256::ecdsa::{Signature, VerifyingKey};
+			let (Ok(key), Ok(signature)) =
+				(VerifyingKey::from_sec1_bytes(public_key), Signature::from_slice(signature))
+			else {
+				return false
+			};
+			// k256 only verifies low-s signatures.
+			let signature = signature.normalize_s().unwrap_or(signature);
+			key.verify(message, &signature).is_ok()
+		},
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use p256::ecdsa::signature::Signer;
+
+	fn sign_r1(message: &[u8]) -> (Vec<u8>, Vec<u8>) {
+		let key = p256::ecdsa::SigningKey::from_slice(&[7; 32]).unwrap();
+		let signature: p256::ecdsa::Signature = key.sign(message);
+		let public_key = key.verifying_key().to_encoded_point(true);
+		(signature.to_bytes().to_vec(), public_key.as_bytes().to_vec())
+	}
+
+	#[test]
+	fn verifies_secp256r1() {
+		let (signature, public_key) = sign_r1(b"message");
+		assert!(verify(b"message", &signature, &public_key, Curve::Secp256r1));
+		assert!(!verify(b"massage", &signature, &public_key, Curve::Secp256r1));
+		assert!(!verify(b"message", &signature, &public_key, Curve::Secp256k1));
+		assert!(!verify(b"message", &signature[1..], &public_key, Curve::Secp256r1));
+		assert!(!verify(b"message", &signature, &[2; 33], Curve::Secp256r1));
+	}
+
+	#[test]
+	fn verifies_secp256k1() {
+		let key = k256::ecdsa::SigningKey::from_slice(&[7; 32]).unwrap();
+		let signature: k256::ecdsa::Signature = key.sign(b"message");
+		let public_key = key.verifying_key().to_encoded_point(false);
+		let signature = signature.to_bytes();
+		assert!(verify(b"message", &signature, public_key.as_bytes(), Curve::Secp256k1));
+		assert!(!verify(b"message", &signature, public_key.as_bytes(), Curve::Secp256r1));
+	}
+}
@@ -0,0 +1,58 @@
+use murmur3::murmur3_32;
+use ripemd::Ripemd160;
+use sha2::{Digest, Sha256};
+use sha3::Keccak256;
+use std::io::Cursor;
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+	Sha256::digest(data).into()
+}
+
+pub fn ripemd160(data: &[u8]) -> [u8; 20] {
+	Ripemd160::digest(data).into()
+}
+
+/// RIPEMD-160 of SHA-256, the hash that identifies scripts and contracts.
+pub fn hash160(data: &[u8]) -> [u8; 20] {
+	ripemd160(&sha256(data))
+}
+
+/// SHA-256 applied twice, as used by Base58Check.
+pub fn hash256(data: &[u8]) -> [u8; 32] {
+	sha256(&sha256(data))
+}
+
+/// Keccak-256 as used by Ethereum, which differs from SHA3-256 in its padding.
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+	Keccak256::digest(data).into()
+}
+
+/// 32-bit MurmurHash3 with the given seed.
+pub fn murmur32(data: &[u8], seed: u32) -> u32 {
+	murmur3_32(&mut Cursor::new(data), seed).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn hex(bytes: &[u8]) -> String {
+		bytes.iter().map(|b| format!("{b:02x}")).collect()
+	}
+
+	#[test]
+	fn known_digests() {
+		assert_eq!(
+			hex(&sha256(b"")),
+			"e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+		);
+		assert_eq!(hex(&ripemd160(b"")), "9c1185a5c5e9fc54612808977ee8f548b2258d31");
+		assert_eq!(hex(&hash160(b"")), "b472a266d0bd89c13706a4132ccfb16f7c3b9fcb");
+		assert_eq!(
+			hex(&keccak256(b"")),
+			"c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+		);
+		assert_eq!(murmur32(b"", 0), 0);
+		assert_eq!(murmur32(b"hello", 0), 0x248bfa47);
+	}
+}
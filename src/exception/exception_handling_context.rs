@@ -1,6 +1,6 @@
 use crate::exception::exception_handling_state::ExceptionHandlingState;
 
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone)]
 pub struct ExceptionHandlingContext {
 	pub(crate) catch_pointer: i32,
 	pub(crate) finally_pointer: i32,
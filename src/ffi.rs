@@ -0,0 +1,409 @@
+//! C ABI for embedding the engine in non-Rust hosts, built with `--features ffi`.
+//!
+//! The header `include/neo_vm.h` is generated from this module by `cbindgen`. Its preamble,
+//! configured in `cbindgen.toml`, documents who owns each handle and string.
+//!
+//! # Safety
+//!
+//! Every pointer argument must be null or a live value of its type: a handle returned by this
+//! library and not yet freed, or `len` readable bytes for a data pointer.
+//!
+//! A panic never unwinds into the host. Each function catches it and returns its error value:
+//! null, 0, -1, false or `FAULT`, as documented on the function.
+#![allow(clippy::missing_safety_doc)]
+
+use crate::{
+	execution_engine::ExecutionEngine,
+	execution_engine_limits::ExecutionEngineLimits,
+	execution_result::ExecutionResult,
+	null::Null,
+	primitive_types::{boolean::Boolean, byte_string::ByteString, integer::Integer},
+	stack_item_json,
+	vm::script::Script,
+	vm_exception::VMException,
+	vm_state::VMState,
+};
+use std::{
+	cell::RefCell,
+	ffi::{c_char, c_void, CStr, CString},
+	panic::{self, AssertUnwindSafe},
+	ptr,
+	rc::Rc,
+	slice,
+};
+
+/// Opaque handle to an `ExecutionEngine`.
+#[repr(transparent)]
+pub struct NeoEngine(ExecutionEngine);
+
+/// Opaque handle to a validated `Script`.
+#[repr(transparent)]
+pub struct NeoScript(Script);
+
+/// Mirror of `ExecutionEngineLimits` with a C layout.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct NeoLimits {
+	pub max_shift: u32,
+	pub max_stack_size: u32,
+	pub max_item_size: u32,
+	pub max_comparable_size: u32,
+	pub max_invocation_stack_size: u32,
+	pub max_try_nesting_depth: u32,
+	pub catch_engine_exceptions: bool,
+}
+
+impl From<ExecutionEngineLimits> for NeoLimits {
+	fn from(limits: ExecutionEngineLimits) -> Self {
+		Self {
+			max_shift: limits.max_shift as u32,
+			max_stack_size: limits.max_stack_size as u32,
+			max_item_size: limits.max_item_size as u32,
+			max_comparable_size: limits.max_comparable_size as u32,
+			max_invocation_stack_size: limits.max_invocation_stack_size as u32,
+			max_try_nesting_depth: limits.max_try_nesting_depth as u32,
+			catch_engine_exceptions: limits.catch_engine_exceptions,
+		}
+	}
+}
+
+impl From<NeoLimits> for ExecutionEngineLimits {
+	fn from(limits: NeoLimits) -> Self {
+		Self {
+			max_shift: limits.max_shift as usize,
+			max_stack_size: limits.max_stack_size as usize,
+			max_item_size: limits.max_item_size as usize,
+			max_comparable_size: limits.max_comparable_size as usize,
+			max_invocation_stack_size: limits.max_invocation_stack_size as usize,
+			max_try_nesting_depth: limits.max_try_nesting_depth as usize,
+			catch_engine_exceptions: limits.catch_engine_exceptions,
+		}
+	}
+}
+
+/// A syscall implemented by the host. Returns 0 on success; any other value faults the engine.
+pub type NeoSyscallCallback =
+	Option<unsafe extern "C" fn(engine: *mut NeoEngine, user_data: *mut c_void) -> i32>;
+
+/// Runs `f`, returning `on_panic` instead of unwinding across the C boundary.
+fn guard<T>(on_panic: T, f: impl FnOnce() -> T) -> T {
+	panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(on_panic)
+}
+
+fn into_c_string(value: String) -> *mut c_char {
+	CString::new(value).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// Returns the default limits.
+#[no_mangle]
+pub extern "C" fn neo_limits_default() -> NeoLimits {
+	ExecutionEngineLimits::default().into()
+}
+
+/// Creates an engine. `limits` may be null to use the defaults.
+/// The returned handle must be released with `neo_engine_free`.
+#[no_mangle]
+pub unsafe extern "C" fn neo_engine_new(limits: *const NeoLimits) -> *mut NeoEngine {
+	guard(ptr::null_mut(), || {
+		let limits = match limits.as_ref() {
+			Some(limits) => (*limits).into(),
+			None => ExecutionEngineLimits::default(),
+		};
+		Box::into_raw(Box::new(NeoEngine(ExecutionEngine::with_options(limits))))
+	})
+}
+
+/// Releases an engine created by `neo_engine_new`.
+#[no_mangle]
+pub unsafe extern "C" fn neo_engine_free(engine: *mut NeoEngine) {
+	guard((), || {
+		if !engine.is_null() {
+			drop(Box::from_raw(engine));
+		}
+	})
+}
+
+/// Parses and validates `len` bytes at `data` as a script.
+/// Returns null if the script is invalid. The handle must be released with `neo_script_free`.
+#[no_mangle]
+pub unsafe extern "C" fn neo_script_new(
+	data: *const u8,
+	len: usize,
+	strict: bool,
+) -> *mut NeoScript {
+	guard(ptr::null_mut(), || {
+		if data.is_null() && len != 0 {
+			return ptr::null_mut()
+		}
+		let bytes = if len == 0 { Vec::new() } else { slice::from_raw_parts(data, len).to_vec() };
+		match Script::new(bytes, strict) {
+			Ok(script) => Box::into_raw(Box::new(NeoScript(script))),
+			Err(_) => ptr::null_mut(),
+		}
+	})
+}
+
+/// Releases a script created by `neo_script_new`.
+#[no_mangle]
+pub unsafe extern "C" fn neo_script_free(script: *mut NeoScript) {
+	guard((), || {
+		if !script.is_null() {
+			drop(Box::from_raw(script));
+		}
+	})
+}
+
+/// Loads a copy of `script` as a new context. `rvcount` is the number of values the context
+/// must return, or -1 for any. Returns 0 on success and -1 on error.
+#[no_mangle]
+pub unsafe extern "C" fn neo_engine_load_script(
+	engine: *mut NeoEngine,
+	script: *const NeoScript,
+	rvcount: i32,
+) -> i32 {
+	guard(-1, || match (engine.as_mut(), script.as_ref()) {
+		(Some(engine), Some(script)) => {
+			engine.0.load_script(script.0.clone(), rvcount, 0);
+			0
+		},
+		_ => -1,
+	})
+}
+
+/// Registers `callback` as the handler of the syscall named `name` and returns its id.
+/// `user_data` is passed back to the callback untouched. Returns 0 on error.
+#[no_mangle]
+pub unsafe extern "C" fn neo_engine_register_syscall(
+	engine: *mut NeoEngine,
+	name: *const c_char,
+	price: u64,
+	callback: NeoSyscallCallback,
+	user_data: *mut c_void,
+) -> u32 {
+	guard(0, || {
+		let (Some(engine), Some(callback)) = (engine.as_mut(), callback) else { return 0 };
+		if name.is_null() {
+			return 0
+		}
+		let Ok(name) = CStr::from_ptr(name).to_str() else { return 0 };
+
+		let service = name.to_string();
+		engine.0.register_syscall(
+			name,
+			price,
+			Rc::new(move |engine: &mut ExecutionEngine| {
+				let handle = engine as *mut ExecutionEngine as *mut NeoEngine;
+				match callback(handle, user_data) {
+					0 => Ok(()),
+					code => Err(VMException::Custom(format!(
+						"Syscall {service} failed with code {code}"
+					))),
+				}
+			}),
+		)
+	})
+}
+
+/// Sets the gas the engine may consume before faulting.
+#[no_mangle]
+pub unsafe extern "C" fn neo_engine_set_gas_limit(engine: *mut NeoEngine, gas_limit: u64) {
+	guard((), || {
+		if let Some(engine) = engine.as_mut() {
+			engine.0.gas_limit = gas_limit;
+		}
+	})
+}
+
+/// Runs the loaded scripts and returns the final `VMState`. A panic inside the engine faults
+/// it, with the panic message as the fault message.
+#[no_mangle]
+pub unsafe extern "C" fn neo_engine_execute(engine: *mut NeoEngine) -> u8 {
+	let Some(engine) = engine.as_mut() else { return VMState::Fault as u8 };
+	match panic::catch_unwind(AssertUnwindSafe(|| engine.0.execute())) {
+		Ok(state) => state as u8,
+		Err(payload) => {
+			let message = payload
+				.downcast_ref::<&str>()
+				.map(|message| message.to_string())
+				.or_else(|| payload.downcast_ref::<String>().cloned())
+				.unwrap_or_default();
+			engine.0.state = VMState::Fault;
+			engine.0.fault_exception = Some(VMException::Custom(format!("panic: {message}")));
+			VMState::Fault as u8
+		},
+	}
+}
+
+/// Returns the current `VMState`: 0 none, 1 halt, 2 fault, 4 break.
+#[no_mangle]
+pub unsafe extern "C" fn neo_engine_state(engine: *const NeoEngine) -> u8 {
+	guard(VMState::Fault as u8, || {
+		engine.as_ref().map_or(VMState::Fault as u8, |engine| engine.0.state as u8)
+	})
+}
+
+/// Returns the gas consumed so far.
+#[no_mangle]
+pub unsafe extern "C" fn neo_engine_gas_consumed(engine: *const NeoEngine) -> u64 {
+	guard(0, || engine.as_ref().map_or(0, |engine| engine.0.gas_consumed))
+}
+
+/// Returns the state, uncaught exception and result stack as JSON, with each item in the
+/// `{"type": ..., "value": ...}` form. Returns null on error.
+/// The string must be released with `neo_string_free`.
+#[no_mangle]
+pub unsafe extern "C" fn neo_engine_result_json(engine: *const NeoEngine) -> *mut c_char {
+	guard(ptr::null_mut(), || {
+		let Some(engine) = engine.as_ref() else { return ptr::null_mut() };
+		match ExecutionResult::from_engine(&engine.0) {
+			Ok(result) => into_c_string(result.to_json_string()),
+			Err(_) => ptr::null_mut(),
+		}
+	})
+}
+
+/// Returns the message of the engine error that caused a fault, or null if there is none.
+/// The string must be released with `neo_string_free`.
+#[no_mangle]
+pub unsafe extern "C" fn neo_engine_fault_message(engine: *const NeoEngine) -> *mut c_char {
+	guard(ptr::null_mut(), || {
+		match engine.as_ref().and_then(|engine| engine.0.fault_exception.as_ref()) {
+			Some(e) => into_c_string(e.to_string()),
+			None => ptr::null_mut(),
+		}
+	})
+}
+
+/// Pops the top item of the current evaluation stack and returns it as JSON in the
+/// `{"type": ..., "value": ...}` form. Intended for syscall callbacks. Returns null on error.
+/// The string must be released with `neo_string_free`.
+#[no_mangle]
+pub unsafe extern "C" fn neo_engine_pop_json(engine: *mut NeoEngine) -> *mut c_char {
+	guard(ptr::null_mut(), || {
+		let Some(engine) = engine.as_mut() else { return ptr::null_mut() };
+		let Ok(item) = engine.0.pop() else { return ptr::null_mut() };
+		let json = stack_item_json::to_json(&*item.borrow());
+		match json {
+			Ok(json) => into_c_string(json.to_string()),
+			Err(_) => ptr::null_mut(),
+		}
+	})
+}
+
+/// Pushes an integer onto the current evaluation stack.
+/// Returns false if no script is loaded.
+#[no_mangle]
+pub unsafe extern "C" fn neo_engine_push_integer(engine: *mut NeoEngine, value: i64) -> bool {
+	guard(false, || {
+		engine.as_mut().is_some_and(|engine| {
+			engine.0.push(Rc::new(RefCell::new(Integer::from(value)))).is_ok()
+		})
+	})
+}
+
+/// Pushes a boolean onto the current evaluation stack.
+/// Returns false if no script is loaded.
+#[no_mangle]
+pub unsafe extern "C" fn neo_engine_push_bool(engine: *mut NeoEngine, value: bool) -> bool {
+	guard(false, || {
+		engine
+			.as_mut()
+			.is_some_and(|engine| engine.0.push(Rc::new(RefCell::new(Boolean::new(value)))).is_ok())
+	})
+}
+
+/// Pushes a copy of `len` bytes at `data` as a `ByteString`.
+/// Returns false if no script is loaded.
+#[no_mangle]
+pub unsafe extern "C" fn neo_engine_push_bytes(
+	engine: *mut NeoEngine,
+	data: *const u8,
+	len: usize,
+) -> bool {
+	guard(false, || {
+		let Some(engine) = engine.as_mut() else { return false };
+		let bytes = if len == 0 || data.is_null() {
+			Vec::new()
+		} else {
+			slice::from_raw_parts(data, len).to_vec()
+		};
+		engine.0.push(Rc::new(RefCell::new(ByteString::new(bytes)))).is_ok()
+	})
+}
+
+/// Pushes `null` onto the current evaluation stack.
+/// Returns false if no script is loaded.
+#[no_mangle]
+pub unsafe extern "C" fn neo_engine_push_null(engine: *mut NeoEngine) -> bool {
+	guard(false, || {
+		engine
+			.as_mut()
+			.is_some_and(|engine| engine.0.push(Rc::new(RefCell::new(Null))).is_ok())
+	})
+}
+
+/// Releases a string returned by this library.
+#[no_mangle]
+pub unsafe extern "C" fn neo_string_free(value: *mut c_char) {
+	guard((), || {
+		if !value.is_null() {
+			drop(CString::from_raw(value));
+		}
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn runs_a_script_and_reports_the_result_as_json() {
+		unsafe {
+			let engine = neo_engine_new(ptr::null());
+			let bytes = [0x11, 0x12, 0x9e, 0x40]; // PUSH1 PUSH2 ADD RET
+			let script = neo_script_new(bytes.as_ptr(), bytes.len(), true);
+			assert_eq!(neo_engine_load_script(engine, script, -1), 0);
+			neo_script_free(script);
+			assert_eq!(neo_engine_execute(engine), VMState::Halt as u8);
+
+			let result = neo_engine_result_json(engine);
+			let json = CStr::from_ptr(result).to_str().unwrap().to_string();
+			neo_string_free(result);
+			assert!(json.contains(r#""value":"3""#), "{json}");
+			neo_engine_free(engine);
+		}
+	}
+
+	#[test]
+	fn turns_panics_into_faults() {
+		unsafe extern "C" fn succeed(_: *mut NeoEngine, _: *mut c_void) -> i32 {
+			0
+		}
+
+		unsafe {
+			let engine = neo_engine_new(ptr::null());
+			let name = CString::new("System.Runtime.Panic").unwrap();
+			let id = neo_engine_register_syscall(
+				engine,
+				name.as_ptr(),
+				0,
+				Some(succeed),
+				ptr::null_mut(),
+			);
+			// Replace the host callback with one that panics, as a bug in the engine would.
+			(*engine).0.syscalls.get_mut(&id).unwrap().handler = Rc::new(|_| panic!("boom"));
+
+			let mut bytes = vec![0x41];
+			bytes.extend_from_slice(&id.to_le_bytes());
+			let script = neo_script_new(bytes.as_ptr(), bytes.len(), true);
+			neo_engine_load_script(engine, script, -1);
+			neo_script_free(script);
+
+			assert_eq!(neo_engine_execute(engine), VMState::Fault as u8);
+			let message = neo_engine_fault_message(engine);
+			assert!(CStr::from_ptr(message).to_str().unwrap().ends_with("panic: boom"));
+			neo_string_free(message);
+			neo_engine_free(engine);
+		}
+	}
+}
@@ -0,0 +1,98 @@
+//! Harnesses shared by the `cargo fuzz` targets and the offline regression tests.
+//!
+//! Each harness takes arbitrary bytes and panics only when the vm misbehaves: a decoder
+//! panicking on malformed input, or the engine exceeding one of its configured limits.
+
+use crate::{
+	execution_engine::ExecutionEngine, execution_engine_limits::ExecutionEngineLimits,
+	instruction::Instruction, vm::script::Script, vm_state::VMState,
+};
+
+/// The gas a fuzzed script may consume before the run is abandoned. Every instruction that
+/// can loop costs gas, so this bounds the number of steps a script can take.
+pub const FUZZ_GAS_LIMIT: u64 = 10_000;
+
+/// How [`fuzz_execute`] ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FuzzOutcome {
+	/// The input is not a valid strict script.
+	Rejected,
+	/// The script ran to completion.
+	Halted,
+	/// The engine faulted, including on an exceeded limit.
+	Faulted,
+	/// The script was still running when it exhausted [`FUZZ_GAS_LIMIT`].
+	OutOfGas,
+}
+
+/// Tight limits so that fuzzed scripts hit the boundaries quickly.
+pub fn fuzz_limits() -> ExecutionEngineLimits {
+	ExecutionEngineLimits {
+		max_shift: 64,
+		max_stack_size: 128,
+		max_item_size: 4096,
+		max_comparable_size: 1024,
+		max_invocation_stack_size: 16,
+		max_try_nesting_depth: 4,
+		catch_engine_exceptions: true,
+	}
+}
+
+/// Decodes an instruction at every offset of `data`.
+pub fn fuzz_instruction(data: &[u8]) {
+	for ip in 0..=data.len() {
+		if let Ok(instruction) = Instruction::from_script(data, ip) {
+			assert!(
+				ip + instruction.size() <= data.len(),
+				"instruction at {ip} overruns the script"
+			);
+		}
+	}
+}
+
+/// Builds the script in both strict and lenient mode.
+pub fn fuzz_script(data: &[u8]) {
+	let _ = Script::new(data.to_vec(), true);
+	let _ = Script::new(data.to_vec(), false);
+}
+
+/// Executes `data` as a strict script under [`fuzz_limits`] and [`FUZZ_GAS_LIMIT`].
+///
+/// Scripts may loop forever, so running out of gas is reported as [`FuzzOutcome::OutOfGas`]
+/// rather than treated as a failure.
+pub fn fuzz_execute(data: &[u8]) -> FuzzOutcome {
+	let script = match Script::new(data.to_vec(), true) {
+		Ok(script) => script,
+		Err(_) => return FuzzOutcome::Rejected,
+	};
+
+	let limits = fuzz_limits();
+	let mut engine = ExecutionEngine::with_options(limits);
+	engine.gas_limit = FUZZ_GAS_LIMIT;
+	engine.load_script(script, -1, 0);
+
+	match engine.execute() {
+		VMState::Halt => {
+			let references = engine.reference_counter.borrow().count();
+			assert!(
+				references <= limits.max_stack_size,
+				"stack size limit bypassed: {references} > {}",
+				limits.max_stack_size
+			);
+			assert!(engine.invocation_stack.is_empty(), "halted with frames left");
+			FuzzOutcome::Halted
+		},
+		VMState::Fault => {
+			assert!(
+				engine.fault_exception.is_some() || engine.uncaught_exception.is_some(),
+				"faulted without an exception"
+			);
+			if engine.gas_consumed > engine.gas_limit {
+				FuzzOutcome::OutOfGas
+			} else {
+				FuzzOutcome::Faulted
+			}
+		},
+		state => panic!("execution stopped in state {state:?}"),
+	}
+}
@@ -1,15 +1,17 @@
-#![feature(associated_type_defaults)]
-#![feature(linked_list_remove)]
-#![feature(exclusive_range_pattern)]
-
 extern crate core;
 
 pub use num_bigint::BigInt;
+pub mod crypto;
 pub mod exception;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fuzzing;
 pub mod script;
 pub mod types;
 
 pub mod vm;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 pub use exception::*;
 pub use script::*;
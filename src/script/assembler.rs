@@ -0,0 +1,461 @@
+use crate::{
+	interop_service::syscall_hash,
+	op_code::OpCode,
+	script::script_builder::{BranchTarget, ScriptBuilder},
+	stack_item_type::StackItemType,
+};
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use std::{
+	collections::HashMap,
+	error::Error,
+	fmt::{Display, Formatter},
+};
+
+/// An error in assembly source, tagged with the 1-based line it was found on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssemblerError {
+	pub line: usize,
+	pub message: String,
+}
+
+impl AssemblerError {
+	fn new(line: usize, message: impl Into<String>) -> Self {
+		Self { line, message: message.into() }
+	}
+}
+
+impl Display for AssemblerError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		write!(f, "line {}: {}", self.line, self.message)
+	}
+}
+
+impl Error for AssemblerError {}
+
+#[derive(Debug, Clone)]
+enum Target {
+	Label { name: String, line: usize },
+	Offset(i32),
+}
+
+#[derive(Debug, Clone)]
+enum Item {
+	/// An instruction whose operand does not depend on the layout.
+	Fixed { opcode: OpCode, operand: Vec<u8> },
+
+	/// A jump, call, `PushA`, `Try` or `EndTry`, laid out by the builder.
+	Branch { opcode: OpCode, targets: Vec<Target> },
+
+	/// Bytes from a `.bytes` or `.string` directive.
+	Data(Vec<u8>),
+}
+
+/// Assembles the mnemonic syntax produced by the disassembler.
+///
+/// ```text
+/// ; comments run to the end of the line
+/// start:
+///     PUSHDATA1 "hello"
+///     JMPIF start        ; short or long form is chosen automatically
+///     JMP_L start        ; an explicit `_L` mnemonic always uses the long form
+/// 0010: SYSCALL System.Runtime.Log
+///     .bytes 0x0102
+/// ```
+///
+/// A leading `OFFSET:` column, as written by the disassembler, is ignored. Labels must be on
+/// their own line. Targets are either label names or signed relative offsets such as `+5`.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssemblerError> {
+	let mut items = Vec::new();
+	let mut labels = HashMap::new();
+
+	for (index, raw_line) in source.lines().enumerate() {
+		let line = index + 1;
+		let text = strip_comment(raw_line).trim();
+		if text.is_empty() {
+			continue
+		}
+
+		let (head, rest) = split_word(text);
+		let text = match head.strip_suffix(':') {
+			Some(name) if rest.is_empty() => {
+				if !is_identifier(name) {
+					return Err(AssemblerError::new(line, format!("invalid label name `{name}`")))
+				}
+				if labels.insert(name.to_string(), items.len()).is_some() {
+					return Err(AssemblerError::new(line, format!("duplicate label `{name}`")))
+				}
+				continue
+			},
+			Some(column) if column.bytes().all(|b| b.is_ascii_hexdigit()) => rest,
+			Some(_) => return Err(AssemblerError::new(line, "labels must be on their own line")),
+			None => text,
+		};
+
+		items.push(parse_line(line, text)?);
+	}
+
+	let mut builder = ScriptBuilder::new();
+	let mut markers = vec![Vec::new(); items.len() + 1];
+	let mut handles = HashMap::new();
+	for (name, &index) in &labels {
+		let label = builder.new_label();
+		markers[index].push(label);
+		handles.insert(name.as_str(), label);
+	}
+
+	for (index, item) in items.iter().enumerate() {
+		for &label in &markers[index] {
+			builder.mark_label(label).expect("every label is marked once");
+		}
+		match item {
+			Item::Fixed { opcode, operand } => builder.emit(*opcode, operand),
+			Item::Data(bytes) => builder.emit_raw(bytes),
+			Item::Branch { opcode, targets } => {
+				let targets = targets
+					.iter()
+					.map(|target| match target {
+						Target::Offset(offset) => Ok(BranchTarget::Offset(*offset)),
+						Target::Label { name, line } => handles
+							.get(name.as_str())
+							.map(|&label| BranchTarget::Label(label))
+							.ok_or_else(|| {
+								AssemblerError::new(*line, format!("undefined label `{name}`"))
+							}),
+					})
+					.collect::<Result<Vec<_>, _>>()?;
+				builder.branch(*opcode, &targets).expect("labels come from this builder");
+			},
+		}
+	}
+	for &label in &markers[items.len()] {
+		builder.mark_label(label).expect("every label is marked once");
+	}
+
+	Ok(builder.to_bytes().expect("every label is marked"))
+}
+
+fn parse_line(line: usize, text: &str) -> Result<Item, AssemblerError> {
+	let (mnemonic, rest) = split_word(text);
+	let args = split_operands(rest);
+
+	match mnemonic {
+		".bytes" => {
+			let [arg] = args.as_slice() else {
+				return Err(AssemblerError::new(line, ".bytes expects one hex operand"))
+			};
+			return parse_hex(arg)
+				.map(Item::Data)
+				.ok_or_else(|| AssemblerError::new(line, format!("invalid hex data `{arg}`")))
+		},
+		".string" => {
+			let [arg] = args.as_slice() else {
+				return Err(AssemblerError::new(line, ".string expects one string operand"))
+			};
+			return parse_string(arg)
+				.map(Item::Data)
+				.ok_or_else(|| AssemblerError::new(line, format!("invalid string `{arg}`")))
+		},
+		_ => {},
+	}
+
+	let opcode = OpCode::from_mnemonic(mnemonic)
+		.ok_or_else(|| AssemblerError::new(line, format!("unknown mnemonic `{mnemonic}`")))?;
+
+	if opcode == OpCode::PushA || opcode.long_form().is_some() || opcode.short_form().is_some() {
+		let expected = if matches!(opcode, OpCode::Try | OpCode::TryL) { 2 } else { 1 };
+		expect_operands(line, opcode, &args, expected)?;
+		let targets = args.iter().map(|arg| parse_target(line, arg)).collect::<Result<_, _>>()?;
+		return Ok(Item::Branch { opcode, targets })
+	}
+
+	let operand = match opcode {
+		OpCode::PushInt8
+		| OpCode::PushInt16
+		| OpCode::PushInt32
+		| OpCode::PushInt64
+		| OpCode::PushInt128
+		| OpCode::PushInt256 => {
+			expect_operands(line, opcode, &args, 1)?;
+			let width = opcode.operand_size().unwrap_or(0) as usize;
+			let value = parse_int(args[0]).ok_or_else(|| {
+				AssemblerError::new(line, format!("invalid integer `{}`", args[0]))
+			})?;
+			encode_int(&value, width).ok_or_else(|| {
+				AssemblerError::new(line, format!("{value} does not fit in {}", opcode.mnemonic()))
+			})?
+		},
+		OpCode::PushData1 | OpCode::PushData2 | OpCode::PushData4 => {
+			expect_operands(line, opcode, &args, 1)?;
+			let data = parse_data(args[0])
+				.ok_or_else(|| AssemblerError::new(line, format!("invalid data `{}`", args[0])))?;
+			let mut operand = match opcode {
+				OpCode::PushData1 => u8::try_from(data.len()).map(|len| vec![len]).ok(),
+				OpCode::PushData2 =>
+					u16::try_from(data.len()).map(|len| len.to_le_bytes().to_vec()).ok(),
+				_ => i32::try_from(data.len()).map(|len| len.to_le_bytes().to_vec()).ok(),
+			}
+			.ok_or_else(|| {
+				AssemblerError::new(line, format!("data is too long for {}", opcode.mnemonic()))
+			})?;
+			operand.extend_from_slice(&data);
+			operand
+		},
+		OpCode::Syscall => {
+			expect_operands(line, opcode, &args, 1)?;
+			let hash = match parse_int(args[0]) {
+				Some(value) => value.to_u32().ok_or_else(|| {
+					AssemblerError::new(line, format!("invalid syscall id `{}`", args[0]))
+				})?,
+				None => syscall_hash(args[0]),
+			};
+			hash.to_le_bytes().to_vec()
+		},
+		OpCode::CallT => {
+			expect_operands(line, opcode, &args, 1)?;
+			parse_unsigned::<u16>(line, args[0])?.to_le_bytes().to_vec()
+		},
+		OpCode::NewArrayT | OpCode::IsType | OpCode::Convert => {
+			expect_operands(line, opcode, &args, 1)?;
+			vec![parse_type(line, args[0])?]
+		},
+		_ => {
+			let size = opcode.operand_size().unwrap_or(0) as usize;
+			expect_operands(line, opcode, &args, size)?;
+			args.iter()
+				.map(|arg| parse_unsigned::<u8>(line, arg))
+				.collect::<Result<_, _>>()?
+		},
+	};
+
+	Ok(Item::Fixed { opcode, operand })
+}
+
+fn expect_operands(
+	line: usize,
+	opcode: OpCode,
+	args: &[&str],
+	expected: usize,
+) -> Result<(), AssemblerError> {
+	if args.len() != expected {
+		return Err(AssemblerError::new(
+			line,
+			format!("{} expects {expected} operand(s), found {}", opcode.mnemonic(), args.len()),
+		))
+	}
+	Ok(())
+}
+
+fn parse_target(line: usize, arg: &str) -> Result<Target, AssemblerError> {
+	if is_identifier(arg) {
+		return Ok(Target::Label { name: arg.to_string(), line })
+	}
+	parse_int(arg)
+		.and_then(|value| value.to_i32())
+		.map(Target::Offset)
+		.ok_or_else(|| AssemblerError::new(line, format!("invalid target `{arg}`")))
+}
+
+fn parse_unsigned<T: TryFrom<BigInt>>(line: usize, arg: &str) -> Result<T, AssemblerError> {
+	parse_int(arg)
+		.and_then(|value| T::try_from(value).ok())
+		.ok_or_else(|| AssemblerError::new(line, format!("operand `{arg}` is out of range")))
+}
+
+fn parse_type(line: usize, arg: &str) -> Result<u8, AssemblerError> {
+	if let Some(value) = parse_int(arg) {
+		return value
+			.to_u8()
+			.ok_or_else(|| AssemblerError::new(line, format!("invalid type code `{arg}`")))
+	}
+	(0..=u8::MAX)
+		.filter_map(StackItemType::from_u8)
+		.find(|ty| format!("{ty:?}").eq_ignore_ascii_case(arg))
+		.map(|ty| ty as u8)
+		.ok_or_else(|| AssemblerError::new(line, format!("unknown stack item type `{arg}`")))
+}
+
+/// Parses a decimal or `0x` hexadecimal integer with an optional sign.
+fn parse_int(text: &str) -> Option<BigInt> {
+	let (negative, digits) = match text.as_bytes().first()? {
+		b'-' => (true, &text[1..]),
+		b'+' => (false, &text[1..]),
+		_ => (false, text),
+	};
+	let value = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+		Some(hex) => BigInt::parse_bytes(hex.as_bytes(), 16)?,
+		None if digits.bytes().all(|b| b.is_ascii_digit()) =>
+			BigInt::parse_bytes(digits.as_bytes(), 10)?,
+		None => return None,
+	};
+	Some(if negative { -value } else { value })
+}
+
+/// Encodes `value` as `width` bytes of little-endian two's complement.
+fn encode_int(value: &BigInt, width: usize) -> Option<Vec<u8>> {
+	let mut bytes = value.to_signed_bytes_le();
+	if bytes.len() > width {
+		return None
+	}
+	let fill = if value.sign() == num_bigint::Sign::Minus { 0xff } else { 0x00 };
+	bytes.resize(width, fill);
+	Some(bytes)
+}
+
+fn parse_data(arg: &str) -> Option<Vec<u8>> {
+	if arg.starts_with('"') {
+		parse_string(arg)
+	} else {
+		parse_hex(arg)
+	}
+}
+
+fn parse_hex(arg: &str) -> Option<Vec<u8>> {
+	let hex = arg.strip_prefix("0x").or_else(|| arg.strip_prefix("0X"))?;
+	if hex.len() % 2 != 0 {
+		return None
+	}
+	(0..hex.len())
+		.step_by(2)
+		.map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+		.collect()
+}
+
+/// Parses a double-quoted string with the escapes Rust's `{:?}` produces.
+fn parse_string(arg: &str) -> Option<Vec<u8>> {
+	let inner = arg.strip_prefix('"')?.strip_suffix('"')?;
+	let mut text = String::with_capacity(inner.len());
+	let mut chars = inner.chars();
+	while let Some(c) = chars.next() {
+		if c != '\\' {
+			text.push(c);
+			continue
+		}
+		match chars.next()? {
+			'n' => text.push('\n'),
+			'r' => text.push('\r'),
+			't' => text.push('\t'),
+			'0' => text.push('\0'),
+			'\\' => text.push('\\'),
+			'"' => text.push('"'),
+			'\'' => text.push('\''),
+			'x' => {
+				let hex: String = chars.by_ref().take(2).collect();
+				text.push(char::from(u8::from_str_radix(&hex, 16).ok()?));
+			},
+			'u' => {
+				if chars.next()? != '{' {
+					return None
+				}
+				let hex: String = chars.by_ref().take_while(|&c| c != '}').collect();
+				text.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+			},
+			_ => return None,
+		}
+	}
+	Some(text.into_bytes())
+}
+
+fn is_identifier(text: &str) -> bool {
+	let mut chars = text.chars();
+	matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+		&& chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/// Splits off the first whitespace-delimited word.
+fn split_word(text: &str) -> (&str, &str) {
+	match text.find(char::is_whitespace) {
+		Some(end) => (&text[..end], text[end..].trim_start()),
+		None => (text, ""),
+	}
+}
+
+/// Splits operands on commas that are not inside a string literal.
+fn split_operands(text: &str) -> Vec<&str> {
+	if text.is_empty() {
+		return Vec::new()
+	}
+	let mut operands = Vec::new();
+	let mut start = 0;
+	let mut in_string = false;
+	let mut escaped = false;
+	for (i, c) in text.char_indices() {
+		match c {
+			_ if escaped => escaped = false,
+			'\\' if in_string => escaped = true,
+			'"' => in_string = !in_string,
+			',' if !in_string => {
+				operands.push(text[start..i].trim());
+				start = i + 1;
+			},
+			_ => {},
+		}
+	}
+	operands.push(text[start..].trim());
+	operands
+}
+
+/// Removes a `;` comment, ignoring semicolons inside string literals.
+fn strip_comment(line: &str) -> &str {
+	let mut in_string = false;
+	let mut escaped = false;
+	for (i, c) in line.char_indices() {
+		match c {
+			_ if escaped => escaped = false,
+			'\\' if in_string => escaped = true,
+			'"' => in_string = !in_string,
+			';' if !in_string => return &line[..i],
+			_ => {},
+		}
+	}
+	line
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::script::disassembler::disassemble;
+
+	#[test]
+	fn disassembled_script_assembles_to_identical_bytes() {
+		let mut script = vec![
+			0x11, // PUSH1
+			0x24, 0x07, // JMPIF +7
+			0x23, 0x05, 0x00, 0x00, 0x00, // JMP_L +5, long although it would fit
+			0x3b, 0x05, 0x00, // TRY catch +5, no finally
+			0x3d, 0x09, // ENDTRY +9
+			0x0c, 0x05, b'h', b'e', b'l', b'l', b'o', // PUSHDATA1 "hello"
+			0x41, // SYSCALL
+		];
+		script.extend_from_slice(&syscall_hash("System.Runtime.Log").to_le_bytes());
+		script.extend_from_slice(&[
+			0x40, // RET
+			0x01, 0xd4, 0xfe, // PUSHINT16 -300
+			0x40, // RET
+			0xff, // undecodable
+		]);
+
+		let listing = disassemble(&script);
+		assert_eq!(assemble(&listing).unwrap(), script, "{listing}");
+	}
+
+	#[test]
+	fn widens_jumps_that_do_not_fit() {
+		let source = format!("JMP end\n.bytes 0x{}\nend:\nRET", "00".repeat(200));
+		let bytes = assemble(&source).unwrap();
+		assert_eq!(bytes[0], OpCode::JmpL as u8);
+		assert_eq!(i32::from_le_bytes(bytes[1..5].try_into().unwrap()), 205);
+
+		let bytes = assemble("start:\nNOP\nJMP start").unwrap();
+		assert_eq!(bytes, vec![OpCode::Nop as u8, OpCode::Jmp as u8, 0xff]);
+	}
+
+	#[test]
+	fn errors_carry_line_numbers() {
+		let error = assemble("PUSH1\n\nFOO 1\n").unwrap_err();
+		assert_eq!(error.line, 3);
+
+		let error = assemble("PUSH1\nJMP nowhere\n").unwrap_err();
+		assert_eq!(error.line, 2);
+	}
+}
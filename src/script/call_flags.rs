@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+use std::{
+	fmt::{Display, Formatter},
+	ops::{BitAnd, BitOr},
+};
+
+/// The permissions a contract call grants to the callee.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CallFlags(pub u8);
+
+impl CallFlags {
+	/// No flag is set.
+	pub const NONE: Self = Self(0);
+
+	/// The callee may read contract states.
+	pub const READ_STATES: Self = Self(0b0000_0001);
+
+	/// The callee may modify contract states.
+	pub const WRITE_STATES: Self = Self(0b0000_0010);
+
+	/// The callee may call other contracts.
+	pub const ALLOW_CALL: Self = Self(0b0000_0100);
+
+	/// The callee may send notifications.
+	pub const ALLOW_NOTIFY: Self = Self(0b0000_1000);
+
+	/// `READ_STATES | WRITE_STATES`.
+	pub const STATES: Self = Self(0b0000_0011);
+
+	/// `READ_STATES | ALLOW_CALL`.
+	pub const READ_ONLY: Self = Self(0b0000_0101);
+
+	/// Every flag.
+	pub const ALL: Self = Self(0b0000_1111);
+
+	pub fn contains(&self, other: Self) -> bool {
+		self.0 & other.0 == other.0
+	}
+}
+
+impl Display for CallFlags {
+	/// Writes the name of a named combination, or the set flags joined by `, ` as Neo does.
+	/// Undefined bits are written in hex.
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		let named = [
+			(Self::NONE, "None"),
+			(Self::STATES, "States"),
+			(Self::READ_ONLY, "ReadOnly"),
+			(Self::ALL, "All"),
+		];
+		if let Some((_, name)) = named.iter().find(|(flags, _)| flags == self) {
+			return write!(f, "{name}")
+		}
+
+		let flags = [
+			(Self::READ_STATES, "ReadStates"),
+			(Self::WRITE_STATES, "WriteStates"),
+			(Self::ALLOW_CALL, "AllowCall"),
+			(Self::ALLOW_NOTIFY, "AllowNotify"),
+		];
+		let mut names: Vec<String> = flags
+			.iter()
+			.filter(|(flag, _)| self.contains(*flag))
+			.map(|(_, name)| name.to_string())
+			.collect();
+		let undefined = self.0 & !Self::ALL.0;
+		if undefined != 0 {
+			names.push(format!("{undefined:#04x}"));
+		}
+		write!(f, "{}", names.join(", "))
+	}
+}
+
+impl BitOr for CallFlags {
+	type Output = Self;
+
+	fn bitor(self, rhs: Self) -> Self {
+		Self(self.0 | rhs.0)
+	}
+}
+
+impl BitAnd for CallFlags {
+	type Output = Self;
+
+	fn bitand(self, rhs: Self) -> Self {
+		Self(self.0 & rhs.0)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn combines_flags() {
+		assert_eq!(CallFlags::READ_STATES | CallFlags::WRITE_STATES, CallFlags::STATES);
+		assert_eq!(CallFlags::READ_STATES | CallFlags::ALLOW_CALL, CallFlags::READ_ONLY);
+		assert_eq!(
+			CallFlags::STATES | CallFlags::READ_ONLY | CallFlags::ALLOW_NOTIFY,
+			CallFlags::ALL
+		);
+		assert_eq!(CallFlags::STATES & CallFlags::READ_ONLY, CallFlags::READ_STATES);
+		assert!(CallFlags::ALL.contains(CallFlags::READ_ONLY));
+		assert!(CallFlags::READ_ONLY.contains(CallFlags::NONE));
+		assert!(!CallFlags::READ_ONLY.contains(CallFlags::STATES));
+		assert_eq!(CallFlags::default(), CallFlags::NONE);
+	}
+
+	#[test]
+	fn names_flags() {
+		assert_eq!(CallFlags::NONE.to_string(), "None");
+		assert_eq!(CallFlags::ALLOW_CALL.to_string(), "AllowCall");
+		assert_eq!(CallFlags::STATES.to_string(), "States");
+		assert_eq!(CallFlags::READ_ONLY.to_string(), "ReadOnly");
+		assert_eq!(CallFlags::ALL.to_string(), "All");
+		assert_eq!(
+			(CallFlags::WRITE_STATES | CallFlags::ALLOW_NOTIFY).to_string(),
+			"WriteStates, AllowNotify"
+		);
+		assert_eq!(CallFlags(0x11).to_string(), "ReadStates, 0x10");
+	}
+}
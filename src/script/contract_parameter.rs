@@ -0,0 +1,72 @@
+use crate::uint::UInt160;
+use num_bigint::BigInt;
+use serde::{Deserialize, Serialize};
+
+/// A typed argument for a contract call, or any nested value to rebuild on the stack, pushed by
+/// [`ScriptBuilder::push_parameter`].
+///
+/// [`ScriptBuilder::push_parameter`]: crate::script::script_builder::ScriptBuilder::push_parameter
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ContractParameter {
+	Any,
+	Boolean(bool),
+	Integer(BigInt),
+	ByteArray(Vec<u8>),
+	String(String),
+	/// A script hash, pushed in the little-endian order it is stored in.
+	Hash160(UInt160),
+	Array(Vec<ContractParameter>),
+	/// A struct, rebuilt with `PackStruct`. Contract ABIs have no struct type, but scripts and
+	/// tests often need one on the stack.
+	Struct(Vec<ContractParameter>),
+	/// Entries in insertion order.
+	Map(Vec<(ContractParameter, ContractParameter)>),
+}
+
+impl From<bool> for ContractParameter {
+	fn from(value: bool) -> Self {
+		Self::Boolean(value)
+	}
+}
+
+impl From<i64> for ContractParameter {
+	fn from(value: i64) -> Self {
+		Self::Integer(BigInt::from(value))
+	}
+}
+
+impl From<BigInt> for ContractParameter {
+	fn from(value: BigInt) -> Self {
+		Self::Integer(value)
+	}
+}
+
+impl From<Vec<u8>> for ContractParameter {
+	fn from(value: Vec<u8>) -> Self {
+		Self::ByteArray(value)
+	}
+}
+
+impl From<&str> for ContractParameter {
+	fn from(value: &str) -> Self {
+		Self::String(value.to_string())
+	}
+}
+
+impl From<String> for ContractParameter {
+	fn from(value: String) -> Self {
+		Self::String(value)
+	}
+}
+
+impl From<UInt160> for ContractParameter {
+	fn from(value: UInt160) -> Self {
+		Self::Hash160(value)
+	}
+}
+
+impl From<Vec<ContractParameter>> for ContractParameter {
+	fn from(value: Vec<ContractParameter>) -> Self {
+		Self::Array(value)
+	}
+}
@@ -0,0 +1,611 @@
+use crate::{
+	op_code::OpCode,
+	script::disassembler::{DisassembledInstruction, Disassembler},
+	vm::script::{Script, ScriptError},
+};
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	fmt::Write,
+	ops::Range,
+};
+
+/// How control gets from one block to the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EdgeKind {
+	/// Execution continues with the next instruction.
+	Fallthrough,
+	/// A taken jump, or an `EndTry` without a finally block.
+	Jump,
+	/// A `Call` to the entry of a function. Execution resumes at the fallthrough edge.
+	Call,
+	/// An exception thrown in a try or catch block, going to the catch or finally block.
+	Exception,
+	/// An `EndTry` entering the finally block.
+	Finally,
+	/// Leaving a finally block for the target of the `EndTry` that entered it.
+	EndFinally,
+}
+
+impl EdgeKind {
+	/// Whether the edge is only taken when an exception is thrown.
+	pub fn is_exceptional(&self) -> bool {
+		matches!(self, EdgeKind::Exception)
+	}
+}
+
+/// An edge between two blocks, given by their index in [`ControlFlowGraph::blocks`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Edge {
+	pub from: usize,
+	pub to: usize,
+	pub kind: EdgeKind,
+}
+
+/// A run of instructions that is only entered at the top and only left at the bottom.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+	/// The offset of the first instruction.
+	pub start: usize,
+	/// The offset just past the last instruction.
+	pub end: usize,
+	pub instructions: Vec<DisassembledInstruction>,
+}
+
+impl BasicBlock {
+	/// The last instruction, which decides where control goes next.
+	pub fn terminator(&self) -> &DisassembledInstruction {
+		self.instructions.last().unwrap()
+	}
+}
+
+/// The blocks reachable from a script entry point, a `Call` target or a `PushA` target without
+/// following calls.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+	pub entry: usize,
+	/// Indexes into [`ControlFlowGraph::blocks`], in script order.
+	pub blocks: Vec<usize>,
+}
+
+/// The basic blocks of a script and the edges between them.
+///
+/// Blocks end at jumps, calls, `Try`, `EndTry`, `EndFinally`, `Ret`, `Throw` and `Abort`.
+/// Every block inside a try block has an exceptional edge to its catch block, or to its finally
+/// block if there is no catch, and every block of a catch block has one to the finally block.
+/// Blocks of a catch block without a finally block, and of a finally block, have one to the
+/// handler of the enclosing try instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlFlowGraph {
+	pub blocks: Vec<BasicBlock>,
+	pub edges: Vec<Edge>,
+	pub functions: Vec<Function>,
+	script: Vec<u8>,
+}
+
+/// The parts of a script guarded by one `Try` instruction.
+struct TryRegion {
+	body: Range<usize>,
+	catch: Option<Range<usize>>,
+	finally: Option<Range<usize>>,
+}
+
+impl TryRegion {
+	fn contains(&self, offset: usize) -> bool {
+		self.body.contains(&offset) || self.catch.as_ref().is_some_and(|c| c.contains(&offset))
+	}
+
+	/// Where an exception thrown at `offset` goes.
+	fn handler(&self, offset: usize) -> Option<usize> {
+		match &self.catch {
+			Some(catch) if self.body.contains(&offset) => Some(catch.start),
+			_ => self.finally_start(),
+		}
+	}
+
+	fn finally_start(&self) -> Option<usize> {
+		self.finally.as_ref().map(|finally| finally.start)
+	}
+
+	/// Everything from the start of the try body to the end of the last handler.
+	fn extent(&self) -> Range<usize> {
+		let ends = [Some(&self.body), self.catch.as_ref(), self.finally.as_ref()];
+		self.body.start..ends.into_iter().flatten().map(|range| range.end).max().unwrap()
+	}
+}
+
+impl ControlFlowGraph {
+	/// Builds the graph of `script`, which must pass strict validation.
+	pub fn new(script: &Script) -> Result<Self, ScriptError> {
+		let mut script = script.clone();
+		script.validate()?;
+
+		let mut instructions = Vec::new();
+		let mut ip = 0;
+		while ip < script.len() {
+			let instruction = script.get_instruction(ip)?;
+			let size = instruction.size();
+			instructions.push(DisassembledInstruction { offset: ip, instruction });
+			ip += size;
+		}
+
+		let regions = try_regions(&instructions);
+		let blocks = split_blocks(instructions, &regions);
+		let index = blocks.iter().enumerate().map(|(i, b)| (b.start, i)).collect();
+
+		let mut graph = Self {
+			blocks,
+			edges: Vec::new(),
+			functions: Vec::new(),
+			script: script.as_bytes().to_vec(),
+		};
+		graph.connect(&regions, &index);
+		graph.group_functions(&index);
+		Ok(graph)
+	}
+
+	/// The index of the block containing `offset`.
+	pub fn block_at(&self, offset: usize) -> Option<usize> {
+		let index = self.blocks.partition_point(|block| block.start <= offset).checked_sub(1)?;
+		(offset < self.blocks[index].end).then_some(index)
+	}
+
+	/// The edges leaving `block`.
+	pub fn successors(&self, block: usize) -> impl Iterator<Item = &Edge> {
+		self.edges.iter().filter(move |edge| edge.from == block)
+	}
+
+	/// The edges entering `block`.
+	pub fn predecessors(&self, block: usize) -> impl Iterator<Item = &Edge> {
+		self.edges.iter().filter(move |edge| edge.to == block)
+	}
+
+	fn connect(&mut self, regions: &[TryRegion], index: &BTreeMap<usize, usize>) {
+		let mut edges = BTreeSet::new();
+		for (from, block) in self.blocks.iter().enumerate() {
+			let last = block.terminator();
+			let next = block.end;
+			let targets = last.instruction.target_offsets();
+			let target = |i: usize| (last.offset as i64 + targets[i] as i64) as usize;
+			let mut add = |offset: usize, kind| {
+				if let Some(&to) = index.get(&offset) {
+					edges.insert(Edge { from, to, kind });
+				}
+			};
+
+			match last.instruction.opcode {
+				OpCode::Jmp | OpCode::JmpL => add(target(0), EdgeKind::Jump),
+				OpCode::Ret | OpCode::Throw | OpCode::Abort | OpCode::AbortMsg => {},
+				OpCode::Call | OpCode::CallL => {
+					add(target(0), EdgeKind::Call);
+					add(next, EdgeKind::Fallthrough);
+				},
+				OpCode::EndTry | OpCode::EndTryL =>
+					match innermost(regions, last.offset).and_then(TryRegion::finally_start) {
+						Some(finally) => add(finally, EdgeKind::Finally),
+						None => add(target(0), EdgeKind::Jump),
+					},
+				OpCode::EndFinally => {
+					for end in end_finally_targets(&self.blocks, regions, last.offset) {
+						add(end, EdgeKind::EndFinally);
+					}
+				},
+				opcode if is_conditional_jump(opcode) => {
+					add(target(0), EdgeKind::Jump);
+					add(next, EdgeKind::Fallthrough);
+				},
+				_ => add(next, EdgeKind::Fallthrough),
+			}
+
+			if let Some(handler) = exception_handler(regions, block.start) {
+				add(handler, EdgeKind::Exception);
+			}
+		}
+		self.edges = edges.into_iter().collect();
+	}
+
+	fn group_functions(&mut self, index: &BTreeMap<usize, usize>) {
+		let mut entries = BTreeSet::new();
+		if !self.blocks.is_empty() {
+			entries.insert(0);
+		}
+		for block in &self.blocks {
+			for decoded in &block.instructions {
+				if let OpCode::Call | OpCode::CallL | OpCode::PushA = decoded.instruction.opcode {
+					let offset = decoded.instruction.target_offsets()[0];
+					entries.insert((decoded.offset as i64 + offset as i64) as usize);
+				}
+			}
+		}
+
+		for entry in entries {
+			let Some(&first) = index.get(&entry) else { continue };
+			let mut seen = BTreeSet::from([first]);
+			let mut pending = vec![first];
+			while let Some(block) = pending.pop() {
+				for edge in self.successors(block) {
+					if edge.kind != EdgeKind::Call && seen.insert(edge.to) {
+						pending.push(edge.to);
+					}
+				}
+			}
+			self.functions.push(Function { entry, blocks: seen.into_iter().collect() });
+		}
+	}
+
+	/// Renders the graph in Graphviz DOT, with one cluster per function.
+	pub fn to_dot(&self) -> String {
+		let disassembler = Disassembler::new(&self.script);
+		let mut dot = String::from("digraph cfg {\n\tnode [shape=box, fontname=monospace];\n");
+		let mut placed = BTreeSet::new();
+		for function in &self.functions {
+			writeln!(dot, "\tsubgraph cluster_{:04X} {{", function.entry).unwrap();
+			writeln!(dot, "\t\tlabel=\"fn_{:04X}\";", function.entry).unwrap();
+			for &block in &function.blocks {
+				if !placed.insert(block) {
+					continue
+				}
+				let mut label = String::new();
+				for decoded in &self.blocks[block].instructions {
+					label.push_str(&escape(&disassembler.format_line(decoded)));
+					label.push_str("\\l");
+				}
+				writeln!(dot, "\t\tb{block} [label=\"{label}\"];").unwrap();
+			}
+			dot.push_str("\t}\n");
+		}
+		for edge in &self.edges {
+			let style = match edge.kind {
+				EdgeKind::Fallthrough | EdgeKind::Jump => "",
+				EdgeKind::Call => ", style=dotted",
+				_ => ", style=dashed",
+			};
+			let kind = format!("{:?}", edge.kind).to_lowercase();
+			writeln!(dot, "\tb{} -> b{} [label=\"{kind}\"{style}];", edge.from, edge.to).unwrap();
+		}
+		dot.push_str("}\n");
+		dot
+	}
+}
+
+pub(crate) fn is_conditional_jump(opcode: OpCode) -> bool {
+	matches!(
+		opcode,
+		OpCode::JmpIf
+			| OpCode::JmpIfL
+			| OpCode::JmpIfNot
+			| OpCode::JmpIfNotL
+			| OpCode::JmpEq
+			| OpCode::JmpEqL
+			| OpCode::JmpNe
+			| OpCode::JmpNeL
+			| OpCode::JmpGt
+			| OpCode::JmpGtL
+			| OpCode::JmpGe
+			| OpCode::JmpGeL
+			| OpCode::JmpLt
+			| OpCode::JmpLtL
+			| OpCode::JmpLe
+			| OpCode::JmpLeL
+	)
+}
+
+/// Whether a block has to end after `opcode`.
+fn ends_block(opcode: OpCode) -> bool {
+	is_conditional_jump(opcode)
+		|| matches!(
+			opcode,
+			OpCode::Jmp
+				| OpCode::JmpL
+				| OpCode::Call
+				| OpCode::CallL
+				| OpCode::CallA
+				| OpCode::Try
+				| OpCode::TryL
+				| OpCode::EndTry
+				| OpCode::EndTryL
+				| OpCode::EndFinally
+				| OpCode::Ret
+				| OpCode::Throw
+				| OpCode::Abort
+				| OpCode::AbortMsg
+		)
+}
+
+/// Finds the body, catch and finally blocks of every `Try`.
+///
+/// A try body runs up to its catch or finally block, and a catch block up to the finally block.
+/// Without a finally block, the catch block ends at its first `EndTry` that is not inside a
+/// nested try body. A finally block ends at its first `EndFinally` that is not inside a try
+/// nested in it.
+fn try_regions(instructions: &[DisassembledInstruction]) -> Vec<TryRegion> {
+	let mut regions = Vec::new();
+	for decoded in instructions {
+		let instruction = &decoded.instruction;
+		if !matches!(instruction.opcode, OpCode::Try | OpCode::TryL) {
+			continue
+		}
+		let handler =
+			|offset: i32| (offset != 0).then(|| (decoded.offset as i64 + offset as i64) as usize);
+		let targets = instruction.target_offsets();
+		let (catch, finally) = (handler(targets[0]), handler(targets[1]));
+		let body_start = decoded.offset + instruction.size();
+		let body_end = catch.into_iter().chain(finally).min().unwrap_or(body_start);
+		regions.push(TryRegion {
+			body: body_start..body_end,
+			catch: catch.map(|c| c..c),
+			finally: finally.map(|f| f..f),
+		});
+	}
+
+	for i in 0..regions.len() {
+		let Some(catch) = regions[i].catch.clone() else { continue };
+		let end = match regions[i].finally_start() {
+			Some(finally) => finally,
+			None => instructions
+				.iter()
+				.filter(|d| d.offset >= catch.start)
+				.find(|d| {
+					matches!(d.instruction.opcode, OpCode::EndTry | OpCode::EndTryL)
+						&& !regions.iter().any(|r| r.body.contains(&d.offset))
+				})
+				.map_or(usize::MAX, |d| d.offset + d.instruction.size()),
+		};
+		regions[i].catch = Some(catch.start..end);
+	}
+
+	// Tries nested in a finally block start after it, so resolving the latest finally blocks
+	// first gives every nested try its extent before the enclosing block is scanned.
+	let mut order: Vec<_> = (0..regions.len()).filter(|&i| regions[i].finally.is_some()).collect();
+	order.sort_by_key(|&i| std::cmp::Reverse(regions[i].finally_start()));
+	for i in order {
+		let start = regions[i].finally_start().unwrap();
+		let end = instructions
+			.iter()
+			.filter(|d| d.offset >= start)
+			.find(|d| {
+				d.instruction.opcode == OpCode::EndFinally
+					&& !regions
+						.iter()
+						.any(|r| r.body.start > start && r.extent().contains(&d.offset))
+			})
+			.map_or(usize::MAX, |d| d.offset + d.instruction.size());
+		regions[i].finally = Some(start..end);
+	}
+	regions
+}
+
+/// The innermost try region containing `offset`: the one with the latest start.
+fn innermost(regions: &[TryRegion], offset: usize) -> Option<&TryRegion> {
+	regions
+		.iter()
+		.filter(|region| region.contains(offset))
+		.max_by_key(|region| region.body.start)
+}
+
+/// Where an exception thrown at `offset` goes: the handler of the innermost try region that has
+/// one. A catch block without a finally block has none, so its exceptions propagate outward as
+/// they do at run time.
+fn exception_handler(regions: &[TryRegion], offset: usize) -> Option<usize> {
+	let mut enclosing: Vec<_> = regions.iter().filter(|region| region.contains(offset)).collect();
+	enclosing.sort_by_key(|region| std::cmp::Reverse(region.body.start));
+	enclosing.into_iter().find_map(|region| region.handler(offset))
+}
+
+/// Where the finally block holding the `EndFinally` at `offset` can continue: the targets of
+/// the `EndTry` instructions that enter it. Nested finally blocks start later, so the innermost
+/// block containing `offset` is the one with the latest start.
+fn end_finally_targets(blocks: &[BasicBlock], regions: &[TryRegion], offset: usize) -> Vec<usize> {
+	let Some(finally) = regions
+		.iter()
+		.filter_map(|r| r.finally.as_ref())
+		.filter(|finally| finally.contains(&offset))
+		.map(|finally| finally.start)
+		.max()
+	else {
+		return Vec::new()
+	};
+	blocks
+		.iter()
+		.map(BasicBlock::terminator)
+		.filter(|last| matches!(last.instruction.opcode, OpCode::EndTry | OpCode::EndTryL))
+		.filter(|last| {
+			innermost(regions, last.offset).and_then(TryRegion::finally_start) == Some(finally)
+		})
+		.map(|last| (last.offset as i64 + last.instruction.target_offsets()[0] as i64) as usize)
+		.collect()
+}
+
+fn split_blocks(
+	instructions: Vec<DisassembledInstruction>,
+	regions: &[TryRegion],
+) -> Vec<BasicBlock> {
+	let mut leaders = BTreeSet::new();
+	leaders.insert(0);
+	for decoded in &instructions {
+		let instruction = &decoded.instruction;
+		if ends_block(instruction.opcode) {
+			leaders.insert(decoded.offset + instruction.size());
+		}
+		for offset in instruction.target_offsets() {
+			leaders.insert((decoded.offset as i64 + offset as i64) as usize);
+		}
+	}
+	for region in regions {
+		leaders.insert(region.body.start);
+		leaders.extend(region.catch.as_ref().map(|catch| catch.start));
+		leaders.extend(region.finally_start());
+	}
+
+	let mut blocks: Vec<BasicBlock> = Vec::new();
+	for decoded in instructions {
+		let end = decoded.offset + decoded.instruction.size();
+		match blocks.last_mut() {
+			Some(block) if !leaders.contains(&decoded.offset) => {
+				block.end = end;
+				block.instructions.push(decoded);
+			},
+			_ =>
+				blocks.push(BasicBlock { start: decoded.offset, end, instructions: vec![decoded] }),
+		}
+	}
+	blocks
+}
+
+fn escape(text: &str) -> String {
+	text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::script::assembler::assemble;
+
+	fn graph(source: &str) -> ControlFlowGraph {
+		ControlFlowGraph::new(&Script::new(assemble(source).unwrap(), true).unwrap()).unwrap()
+	}
+
+	fn edges(graph: &ControlFlowGraph) -> Vec<(usize, usize, EdgeKind)> {
+		graph
+			.edges
+			.iter()
+			.map(|edge| (graph.blocks[edge.from].start, graph.blocks[edge.to].start, edge.kind))
+			.collect()
+	}
+
+	#[test]
+	fn splits_at_branches() {
+		let graph = graph(
+			"PUSH1
+			JMPIF L_else
+			PUSH2
+			JMP L_end
+			L_else:
+			PUSH3
+			L_end:
+			RET",
+		);
+		let starts = graph.blocks.iter().map(|block| block.start).collect::<Vec<_>>();
+		assert_eq!(starts, vec![0, 3, 6, 7]);
+		assert_eq!(
+			edges(&graph),
+			vec![
+				(0, 3, EdgeKind::Fallthrough),
+				(0, 6, EdgeKind::Jump),
+				(3, 7, EdgeKind::Jump),
+				(6, 7, EdgeKind::Fallthrough),
+			]
+		);
+		assert_eq!(graph.block_at(4), Some(1));
+		assert_eq!(graph.block_at(8), None);
+	}
+
+	#[test]
+	fn groups_call_targets_into_functions() {
+		let graph = graph(
+			"CALL L_f
+			RET
+			L_f:
+			PUSH1
+			RET",
+		);
+		assert_eq!(graph.functions.len(), 2);
+		assert_eq!(graph.functions[0].blocks, vec![0, 1]);
+		assert_eq!(graph.functions[1].entry, 3);
+		assert_eq!(graph.functions[1].blocks, vec![2]);
+		assert!(edges(&graph).contains(&(0, 3, EdgeKind::Call)));
+
+		let dot = graph.to_dot();
+		assert!(dot.starts_with("digraph cfg {"));
+		assert!(dot.contains("subgraph cluster_0003"));
+		assert!(dot.contains("b0 -> b2 [label=\"call\", style=dotted];"));
+	}
+
+	#[test]
+	fn models_try_catch_finally() {
+		let graph = graph(
+			"TRY L_catch, L_finally
+			PUSH1
+			THROW
+			L_catch:
+			DROP
+			ENDTRY L_end
+			L_finally:
+			ENDFINALLY
+			L_end:
+			RET",
+		);
+		let edges = edges(&graph);
+		assert!(edges.contains(&(0, 3, EdgeKind::Fallthrough)));
+		assert!(edges.contains(&(3, 5, EdgeKind::Exception)));
+		assert!(edges.contains(&(5, 8, EdgeKind::Finally)));
+		assert!(edges.contains(&(8, 9, EdgeKind::EndFinally)));
+		assert!(!edges.iter().any(|&(from, to, _)| from == 5 && to == 9));
+	}
+
+	#[test]
+	fn propagates_exceptions_to_enclosing_handlers() {
+		let from_catch = graph(
+			"TRY L_outer, 0
+			TRY L_inner, 0
+			PUSH1
+			THROW
+			L_inner:
+			THROW
+			ENDTRY L_after
+			L_after:
+			ENDTRY L_end
+			L_outer:
+			DROP
+			ENDTRY L_end
+			L_end:
+			RET",
+		);
+		let from_catch = edges(&from_catch);
+		assert!(from_catch.contains(&(6, 8, EdgeKind::Exception)));
+		assert!(from_catch.contains(&(8, 13, EdgeKind::Exception)));
+		assert!(from_catch.contains(&(11, 13, EdgeKind::Exception)));
+
+		let from_finally = graph(
+			"TRY L_outer, 0
+			TRY 0, L_finally
+			ENDTRY L_after
+			L_finally:
+			PUSH1
+			THROW
+			ENDFINALLY
+			L_after:
+			ENDTRY L_end
+			L_outer:
+			DROP
+			ENDTRY L_end
+			L_end:
+			RET",
+		);
+		let from_finally = edges(&from_finally);
+		assert!(from_finally.contains(&(6, 8, EdgeKind::Finally)));
+		assert!(from_finally.contains(&(8, 13, EdgeKind::Exception)));
+	}
+
+	#[test]
+	fn leaves_nested_finally_blocks_for_their_own_targets() {
+		let graph = graph(
+			"TRY 0, L_outer
+			ENDTRY L_after_outer
+			L_outer:
+			TRY 0, L_inner
+			ENDTRY L_after_inner
+			L_inner:
+			ENDFINALLY
+			L_after_inner:
+			ENDFINALLY
+			L_after_outer:
+			RET",
+		);
+		let edges = edges(&graph);
+		assert!(edges.contains(&(3, 5, EdgeKind::Finally)));
+		assert!(edges.contains(&(8, 10, EdgeKind::Finally)));
+		assert!(edges.contains(&(10, 11, EdgeKind::EndFinally)));
+		assert!(edges.contains(&(11, 12, EdgeKind::EndFinally)));
+		assert!(!edges.iter().any(|&(from, to, _)| from == 11 && to == 11));
+	}
+}
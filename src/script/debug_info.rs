@@ -0,0 +1,337 @@
+use crate::{
+	execution_context::ExecutionContext, instruction::SlotKind, slot::Slot, stack_item_json,
+	vm_exception::VMException,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+	error::Error,
+	fmt::{Display, Formatter},
+};
+
+/// A position in a source document.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SourceLocation {
+	pub document: String,
+	pub line: u32,
+	pub column: u32,
+}
+
+impl Display for SourceLocation {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}:{}:{}", self.document, self.line, self.column)
+	}
+}
+
+/// Maps the instruction at `address` to a span of a source document.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SequencePoint {
+	pub address: usize,
+	pub document: usize,
+	pub start: (u32, u32),
+	pub end: (u32, u32),
+}
+
+/// A parameter, local or static variable.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DebugVariable {
+	pub name: String,
+	pub kind: String,
+	/// The slot index the variable lives in.
+	pub index: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DebugMethod {
+	pub id: String,
+	/// The name as `Namespace,Method`.
+	pub name: String,
+	/// The first and last instruction offsets of the method, inclusive.
+	pub range: (usize, usize),
+	pub parameters: Vec<DebugVariable>,
+	pub return_type: String,
+	pub variables: Vec<DebugVariable>,
+	pub sequence_points: Vec<SequencePoint>,
+}
+
+impl DebugMethod {
+	/// The name as `Namespace.Method`.
+	pub fn display_name(&self) -> String {
+		self.name.replacen(',', ".", 1)
+	}
+
+	pub fn contains(&self, ip: usize) -> bool {
+		self.range.0 <= ip && ip <= self.range.1
+	}
+}
+
+/// The debug information a Neo compiler emits next to a NEF.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DebugInfo {
+	/// The script hash of the contract, as written by the compiler.
+	pub hash: String,
+	pub documents: Vec<String>,
+	pub methods: Vec<DebugMethod>,
+	pub static_variables: Vec<DebugVariable>,
+}
+
+/// A slot entry of a context, named from debug info where possible.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedSlot {
+	pub kind: SlotKind,
+	pub index: usize,
+	pub name: Option<String>,
+	/// The value in the typed JSON form of `stack_item_json`.
+	pub value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DebugInfoError {
+	InvalidJson(String),
+	InvalidFormat(String),
+}
+
+impl Display for DebugInfoError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			DebugInfoError::InvalidJson(message) => write!(f, "Invalid debug info JSON: {message}"),
+			DebugInfoError::InvalidFormat(message) => write!(f, "Invalid debug info: {message}"),
+		}
+	}
+}
+
+impl Error for DebugInfoError {}
+
+#[derive(Deserialize)]
+struct RawDebugInfo {
+	hash: String,
+	documents: Vec<String>,
+	#[serde(rename = "document-root", default)]
+	document_root: Option<String>,
+	methods: Vec<RawMethod>,
+	#[serde(rename = "static-variables", default)]
+	static_variables: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct RawMethod {
+	id: String,
+	name: String,
+	range: String,
+	#[serde(default)]
+	params: Vec<String>,
+	#[serde(rename = "return", default)]
+	return_type: String,
+	#[serde(default)]
+	variables: Vec<String>,
+	#[serde(rename = "sequence-points", default)]
+	sequence_points: Vec<String>,
+}
+
+impl DebugInfo {
+	/// Parses the JSON debug info format, resolving documents against `document-root`.
+	pub fn parse(json: &str) -> Result<Self, DebugInfoError> {
+		let raw: RawDebugInfo =
+			serde_json::from_str(json).map_err(|e| DebugInfoError::InvalidJson(e.to_string()))?;
+
+		let documents = match &raw.document_root {
+			Some(root) if !root.is_empty() => raw
+				.documents
+				.iter()
+				.map(|document| format!("{}/{}", root.trim_end_matches('/'), document))
+				.collect(),
+			_ => raw.documents,
+		};
+
+		let mut methods = Vec::with_capacity(raw.methods.len());
+		for method in raw.methods {
+			let range = method
+				.range
+				.split_once('-')
+				.and_then(|(start, end)| Some((start.parse().ok()?, end.parse().ok()?)))
+				.ok_or_else(|| invalid(format!("method range `{}`", method.range)))?;
+			let sequence_points = method
+				.sequence_points
+				.iter()
+				.map(|point| parse_sequence_point(point, documents.len()))
+				.collect::<Result<_, _>>()?;
+			methods.push(DebugMethod {
+				id: method.id,
+				name: method.name,
+				range,
+				parameters: parse_variables(&method.params)?,
+				return_type: method.return_type,
+				variables: parse_variables(&method.variables)?,
+				sequence_points,
+			});
+		}
+
+		Ok(Self {
+			hash: raw.hash,
+			documents,
+			methods,
+			static_variables: parse_variables(&raw.static_variables)?,
+		})
+	}
+
+	/// The method whose range contains `ip`.
+	pub fn method_at(&self, ip: usize) -> Option<&DebugMethod> {
+		self.methods.iter().find(|method| method.contains(ip))
+	}
+
+	/// The start of the last sequence point at or before `ip` in the method containing it.
+	pub fn location_at(&self, ip: usize) -> Option<SourceLocation> {
+		let point = self
+			.method_at(ip)?
+			.sequence_points
+			.iter()
+			.filter(|point| point.address <= ip)
+			.max_by_key(|point| point.address)?;
+		Some(SourceLocation {
+			document: self.documents[point.document].clone(),
+			line: point.start.0,
+			column: point.start.1,
+		})
+	}
+
+	/// The sequence point placed exactly at `ip`, if any.
+	pub fn sequence_point(&self, ip: usize) -> Option<&SequencePoint> {
+		self.method_at(ip)?.sequence_points.iter().find(|point| point.address == ip)
+	}
+
+	/// The name of the variable in slot `index` of `kind` while executing `ip`.
+	pub fn variable_name(&self, ip: usize, kind: SlotKind, index: usize) -> Option<&str> {
+		let variables = match kind {
+			SlotKind::Static => &self.static_variables,
+			SlotKind::Local => &self.method_at(ip)?.variables,
+			SlotKind::Argument => &self.method_at(ip)?.parameters,
+		};
+		variables
+			.iter()
+			.find(|variable| variable.index == index)
+			.map(|v| v.name.as_str())
+	}
+
+	/// Lists the static, local and argument slots of `context` with their variable names.
+	pub fn describe_slots(
+		&self,
+		context: &ExecutionContext,
+	) -> Result<Vec<NamedSlot>, VMException> {
+		let ip = context.instruction_pointer;
+		let fields = context.fields();
+		let slots = [
+			(SlotKind::Static, fields.as_ref()),
+			(SlotKind::Local, context.local_variables.as_ref()),
+			(SlotKind::Argument, context.arguments.as_ref()),
+		];
+		let mut named = Vec::new();
+		for (kind, slot) in slots {
+			let Some(slot) = slot else { continue };
+			named.extend(self.name_slot(ip, kind, slot)?);
+		}
+		Ok(named)
+	}
+
+	fn name_slot(
+		&self,
+		ip: usize,
+		kind: SlotKind,
+		slot: &Slot,
+	) -> Result<Vec<NamedSlot>, VMException> {
+		(0..slot.len())
+			.map(|index| {
+				Ok(NamedSlot {
+					kind,
+					index,
+					name: self.variable_name(ip, kind, index).map(str::to_string),
+					value: stack_item_json::to_json(&*slot.get(index)?.borrow())?,
+				})
+			})
+			.collect()
+	}
+}
+
+fn invalid(message: String) -> DebugInfoError {
+	DebugInfoError::InvalidFormat(message)
+}
+
+/// Parses `name,type` or `name,type,index` entries; a missing index is the entry's position.
+fn parse_variables(entries: &[String]) -> Result<Vec<DebugVariable>, DebugInfoError> {
+	entries
+		.iter()
+		.enumerate()
+		.map(|(position, entry)| {
+			let parts: Vec<&str> = entry.split(',').collect();
+			let index = match parts.as_slice() {
+				[_, _] => position,
+				[_, _, index] =>
+					index.parse().map_err(|_| invalid(format!("variable `{entry}`")))?,
+				_ => return Err(invalid(format!("variable `{entry}`"))),
+			};
+			Ok(DebugVariable { name: parts[0].to_string(), kind: parts[1].to_string(), index })
+		})
+		.collect()
+}
+
+/// Parses `address[document]startLine:startColumn-endLine:endColumn`.
+fn parse_sequence_point(text: &str, documents: usize) -> Result<SequencePoint, DebugInfoError> {
+	let parse = || -> Option<SequencePoint> {
+		let (address, rest) = text.split_once('[')?;
+		let (document, span) = rest.split_once(']')?;
+		let (start, end) = span.split_once('-')?;
+		let position = |text: &str| -> Option<(u32, u32)> {
+			let (line, column) = text.split_once(':')?;
+			Some((line.parse().ok()?, column.parse().ok()?))
+		};
+		Some(SequencePoint {
+			address: address.parse().ok()?,
+			document: document.parse().ok()?,
+			start: position(start)?,
+			end: position(end)?,
+		})
+	};
+	match parse() {
+		Some(point) if point.document < documents => Ok(point),
+		_ => Err(invalid(format!("sequence point `{text}`"))),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const DEBUG_INFO: &str = r#"{
+		"hash": "0x0102030405060708090a0b0c0d0e0f1011121314",
+		"documents": ["Token.cs"],
+		"methods": [
+			{
+				"id": "Token.Transfer",
+				"name": "Token,Transfer",
+				"range": "10-30",
+				"params": ["from,Hash160", "amount,Integer"],
+				"return": "Boolean",
+				"variables": ["balance,Integer,0"],
+				"sequence-points": ["10[0]12:9-12:40", "18[0]13:13-13:30"]
+			}
+		],
+		"static-variables": ["owner,Hash160,0"]
+	}"#;
+
+	#[test]
+	fn resolves_locations_and_names() {
+		let info = DebugInfo::parse(DEBUG_INFO).unwrap();
+		assert_eq!(info.method_at(20).unwrap().display_name(), "Token.Transfer");
+		assert!(info.method_at(31).is_none());
+		assert_eq!(info.location_at(17).unwrap().to_string(), "Token.cs:12:9");
+		assert_eq!(info.location_at(25).unwrap().to_string(), "Token.cs:13:13");
+		assert_eq!(info.variable_name(12, SlotKind::Argument, 1), Some("amount"));
+		assert_eq!(info.variable_name(12, SlotKind::Local, 0), Some("balance"));
+		assert_eq!(info.variable_name(0, SlotKind::Static, 0), Some("owner"));
+	}
+
+	#[test]
+	fn rejects_sequence_points_outside_documents() {
+		let json = DEBUG_INFO.replace("18[0]", "18[1]");
+		assert!(matches!(DebugInfo::parse(&json), Err(DebugInfoError::InvalidFormat(_))));
+	}
+}
@@ -0,0 +1,302 @@
+use crate::{
+	instruction::{Instruction, SlotKind},
+	interop_service::syscall_name,
+	op_code::OpCode,
+	script::debug_info::DebugInfo,
+	stack_item_type::StackItemType,
+};
+use std::{collections::BTreeSet, fmt::Write};
+
+/// An instruction decoded at a known offset of a script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisassembledInstruction {
+	pub offset: usize,
+	pub instruction: Instruction,
+}
+
+/// Turns script bytes back into a labelled listing.
+///
+/// Each instruction is written as `OFFSET: MNEMONIC OPERAND`. Jump, call, `PushA` and try
+/// targets become labels named `L_<offset>`, and bytes that cannot be decoded are kept as a
+/// `.bytes` directive so that the listing always assembles back to the same script.
+pub struct Disassembler<'a> {
+	script: &'a [u8],
+	instructions: Vec<DisassembledInstruction>,
+	trailing: Option<usize>,
+	debug_info: Option<&'a DebugInfo>,
+}
+
+impl<'a> Disassembler<'a> {
+	pub fn new(script: &'a [u8]) -> Self {
+		let mut instructions = Vec::new();
+		let mut trailing = None;
+		let mut ip = 0;
+		while ip < script.len() {
+			match Instruction::from_script(script, ip) {
+				Ok(instruction) => {
+					let size = instruction.size();
+					instructions.push(DisassembledInstruction { offset: ip, instruction });
+					ip += size;
+				},
+				Err(_) => {
+					trailing = Some(ip);
+					break
+				},
+			}
+		}
+
+		Self { script, instructions, trailing, debug_info: None }
+	}
+
+	/// Annotates the listing with method names, source lines and variable names.
+	pub fn with_debug_info(mut self, debug_info: &'a DebugInfo) -> Self {
+		self.debug_info = Some(debug_info);
+		self
+	}
+
+	/// The decoded instructions, in script order.
+	pub fn instructions(&self) -> &[DisassembledInstruction] {
+		&self.instructions
+	}
+
+	/// The offset of the first byte that could not be decoded, if any.
+	pub fn trailing_offset(&self) -> Option<usize> {
+		self.trailing
+	}
+
+	/// Returns the absolute offset `offset` refers to from the instruction at `ip`, provided it
+	/// lands on an instruction boundary.
+	fn resolve(&self, ip: usize, offset: i32) -> Option<usize> {
+		let target = ip as i64 + offset as i64;
+		if target < 0 {
+			return None
+		}
+		let target = target as usize;
+		self.instructions
+			.binary_search_by_key(&target, |decoded| decoded.offset)
+			.ok()
+			.map(|_| target)
+	}
+
+	/// The offsets that are referenced by a jump, call, `PushA` or try instruction.
+	pub fn labels(&self) -> BTreeSet<usize> {
+		let mut labels = BTreeSet::new();
+		for decoded in &self.instructions {
+			for offset in decoded.instruction.target_offsets() {
+				if is_absent_try_target(decoded.instruction.opcode, offset) {
+					continue
+				}
+				if let Some(target) = self.resolve(decoded.offset, offset) {
+					labels.insert(target);
+				}
+			}
+		}
+		labels
+	}
+
+	/// Renders the operand of `decoded`, using labels for resolvable targets.
+	pub fn format_operand(&self, decoded: &DisassembledInstruction) -> String {
+		let instruction = &decoded.instruction;
+		let operand = &instruction.operand;
+		match instruction.opcode {
+			OpCode::PushInt8
+			| OpCode::PushInt16
+			| OpCode::PushInt32
+			| OpCode::PushInt64
+			| OpCode::PushInt128
+			| OpCode::PushInt256 => instruction.token_bigint().to_string(),
+			OpCode::PushData1 | OpCode::PushData2 | OpCode::PushData4 => format_data(operand),
+			OpCode::Syscall => {
+				let hash = instruction.token_u32();
+				match syscall_name(hash) {
+					Some(name) => name.to_string(),
+					None => format!("0x{hash:08x}"),
+				}
+			},
+			OpCode::CallT => instruction.token_u16().to_string(),
+			OpCode::InitSlot => format!("{}, {}", instruction.token_u8(), instruction.token_u8_1()),
+			OpCode::NewArrayT | OpCode::IsType | OpCode::Convert => {
+				let code = instruction.token_u8();
+				match StackItemType::from_u8(code) {
+					Some(ty) => format!("{ty:?}"),
+					None => format!("0x{code:02x}"),
+				}
+			},
+			opcode if !instruction.target_offsets().is_empty() => instruction
+				.target_offsets()
+				.into_iter()
+				.map(|offset| {
+					if is_absent_try_target(opcode, offset) {
+						return "0".to_string()
+					}
+					match self.resolve(decoded.offset, offset) {
+						Some(target) => label_name(target),
+						None => format!("{offset:+}"),
+					}
+				})
+				.collect::<Vec<_>>()
+				.join(", "),
+			_ if operand.is_empty() => String::new(),
+			_ => operand.iter().map(|b| b.to_string()).collect::<Vec<_>>().join(", "),
+		}
+	}
+
+	/// Produces the full listing.
+	pub fn disassemble(&self) -> String {
+		let labels = self.labels();
+		let mut listing = String::new();
+		for decoded in &self.instructions {
+			if let Some(debug_info) = self.debug_info {
+				let offset = decoded.offset;
+				if let Some(method) = debug_info.methods.iter().find(|m| m.range.0 == offset) {
+					writeln!(listing, "; {}", method.display_name()).unwrap();
+				}
+				if debug_info.sequence_point(offset).is_some() {
+					let location = debug_info.location_at(offset).unwrap();
+					writeln!(listing, "; {location}").unwrap();
+				}
+			}
+			if labels.contains(&decoded.offset) {
+				writeln!(listing, "{}:", label_name(decoded.offset)).unwrap();
+			}
+			writeln!(listing, "{}", self.format_line(decoded)).unwrap();
+		}
+		if let Some(offset) = self.trailing {
+			writeln!(listing, "{offset:04X}: .bytes {}", hex(&self.script[offset..])).unwrap();
+		}
+		listing
+	}
+
+	/// Formats a single instruction line, including a trailing comment where useful.
+	pub fn format_line(&self, decoded: &DisassembledInstruction) -> String {
+		let mnemonic = decoded.instruction.opcode.mnemonic();
+		let operand = self.format_operand(decoded);
+		let mut line = if operand.is_empty() {
+			format!("{:04X}: {mnemonic}", decoded.offset)
+		} else {
+			format!("{:04X}: {mnemonic} {operand}", decoded.offset)
+		};
+		if let Some(comment) = printable(&decoded.instruction).or_else(|| self.variable(decoded)) {
+			write!(line, " ; {comment}").unwrap();
+		}
+		line
+	}
+
+	/// Names the variable a slot instruction accesses, from the debug info.
+	fn variable(&self, decoded: &DisassembledInstruction) -> Option<String> {
+		let (kind, _, index) = decoded.instruction.slot_access()?;
+		let name = self.debug_info?.variable_name(decoded.offset, kind, index)?;
+		let kind = match kind {
+			SlotKind::Static => "static",
+			SlotKind::Local => "local",
+			SlotKind::Argument => "arg",
+		};
+		Some(format!("{kind} {name}"))
+	}
+}
+
+/// Disassembles `script` into a labelled listing.
+pub fn disassemble(script: &[u8]) -> String {
+	Disassembler::new(script).disassemble()
+}
+
+/// The name given to the label at `offset`.
+pub fn label_name(offset: usize) -> String {
+	format!("L_{offset:04X}")
+}
+
+/// A zero catch or finally offset in `Try` marks the block as absent rather than a target.
+fn is_absent_try_target(opcode: OpCode, offset: i32) -> bool {
+	offset == 0 && (opcode == OpCode::Try || opcode == OpCode::TryL)
+}
+
+fn hex(bytes: &[u8]) -> String {
+	let mut text = String::with_capacity(2 + bytes.len() * 2);
+	text.push_str("0x");
+	for byte in bytes {
+		write!(text, "{byte:02x}").unwrap();
+	}
+	text
+}
+
+fn format_data(data: &[u8]) -> String {
+	if data.is_empty() {
+		return "\"\"".to_string()
+	}
+	hex(data)
+}
+
+/// Shows `PushData` operands as a quoted string when every byte is printable ASCII.
+fn printable(instruction: &Instruction) -> Option<String> {
+	match instruction.opcode {
+		OpCode::PushData1 | OpCode::PushData2 | OpCode::PushData4 => {},
+		_ => return None,
+	}
+	let data = &instruction.operand;
+	if data.is_empty() || !data.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+		return None
+	}
+	Some(format!("{:?}", String::from_utf8_lossy(data)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::interop_service::syscall_hash;
+
+	#[test]
+	fn comments_printable_push_data() {
+		// PUSHDATA1 "hi"; PUSHDATA1 00 ff; PUSHDATA1 ""; RET
+		let script = [0x0C, 0x02, b'h', b'i', 0x0C, 0x02, 0x00, 0xFF, 0x0C, 0x00, 0x40];
+		assert_eq!(
+			disassemble(&script),
+			"0000: PUSHDATA1 0x6869 ; \"hi\"\n\
+			 0004: PUSHDATA1 0x00ff\n\
+			 0008: PUSHDATA1 \"\"\n\
+			 000A: RET\n"
+		);
+	}
+
+	#[test]
+	fn names_known_syscalls() {
+		let mut script = vec![0x41];
+		script.extend_from_slice(&syscall_hash("System.Contract.Call").to_le_bytes());
+		script.extend_from_slice(&[0x41, 0x01, 0x02, 0x03, 0x04]);
+		assert_eq!(
+			disassemble(&script),
+			"0000: SYSCALL System.Contract.Call\n0005: SYSCALL 0x04030201\n"
+		);
+	}
+
+	#[test]
+	fn names_stack_item_types() {
+		// CONVERT ByteString; ISTYPE Integer; NEWARRAY_T Map
+		let script = [0xDB, 0x28, 0xD9, 0x21, 0xC4, 0x48];
+		assert_eq!(
+			disassemble(&script),
+			"0000: CONVERT ByteString\n0002: ISTYPE Integer\n0004: NEWARRAY_T Map\n"
+		);
+	}
+
+	#[test]
+	fn labels_jump_targets() {
+		// JMP +4; JMP +1 (into its own operand); PUSH1; RET
+		let script = [0x22, 0x04, 0x22, 0x01, 0x11, 0x40];
+		let disassembler = Disassembler::new(&script);
+		assert_eq!(disassembler.labels(), BTreeSet::from([4]));
+		assert_eq!(
+			disassembler.disassemble(),
+			"0000: JMP L_0004\n0002: JMP +1\nL_0004:\n0004: PUSH1\n0005: RET\n"
+		);
+	}
+
+	#[test]
+	fn keeps_trailing_bytes() {
+		// PUSH1; PUSHDATA1 with a length past the end of the script
+		let script = [0x11, 0x0C, 0x05, 0x01];
+		let disassembler = Disassembler::new(&script);
+		assert_eq!(disassembler.instructions().len(), 1);
+		assert_eq!(disassembler.trailing_offset(), Some(1));
+		assert_eq!(disassembler.disassemble(), "0000: PUSH1\n0001: .bytes 0x0c0501\n");
+	}
+}
@@ -0,0 +1,335 @@
+use crate::{
+	execution_engine_limits::ExecutionEngineLimits,
+	instruction::SlotKind,
+	interop_service::syscall_name,
+	op_code::OpCode,
+	script::{
+		control_flow::is_conditional_jump,
+		disassembler::{DisassembledInstruction, Disassembler},
+	},
+};
+use std::{
+	collections::{BTreeMap, BTreeSet, HashSet},
+	fmt::{Display, Formatter},
+};
+
+/// A problem found in a script, at the offset of the instruction that has it.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Lint {
+	pub offset: usize,
+	pub kind: LintKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LintKind {
+	/// No path from an entry point reaches the instructions up to `end`.
+	Unreachable { end: usize },
+	/// A jump, call, `PushA` or try target that lands inside the data of the `PushData` at
+	/// `data_offset`.
+	JumpIntoPushData { target: i64, data_offset: usize },
+	/// A target that is outside the script or inside the operand of another instruction.
+	InvalidJumpTarget { target: i64 },
+	/// A local or argument is accessed on a path without `InitSlot`.
+	MissingInitSlot { kind: SlotKind },
+	/// A local or argument index that is beyond the count declared by `InitSlot`.
+	SlotIndexOutOfRange { kind: SlotKind, index: usize, count: usize },
+	/// An `EndTry` that is reachable outside of any try or catch block.
+	EndTryOutsideTry,
+	/// A `Syscall` whose id is not in the allowlist.
+	DisallowedSyscall { hash: u32 },
+	/// Bytes that do not decode as an instruction.
+	UndecodableBytes,
+}
+
+impl Display for Lint {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{:04X}: ", self.offset)?;
+		match &self.kind {
+			LintKind::Unreachable { end } => write!(f, "unreachable code up to {end:04X}"),
+			LintKind::JumpIntoPushData { target, data_offset } =>
+				write!(f, "target {target:04X} is inside the PushData at {data_offset:04X}"),
+			LintKind::InvalidJumpTarget { target } =>
+				write!(f, "target {target} is not an instruction boundary"),
+			LintKind::MissingInitSlot { kind } =>
+				write!(f, "{kind:?} slot accessed without INITSLOT"),
+			LintKind::SlotIndexOutOfRange { kind, index, count } =>
+				write!(f, "{kind:?} index {index} but INITSLOT declares {count}"),
+			LintKind::EndTryOutsideTry => write!(f, "ENDTRY outside of a try block"),
+			LintKind::DisallowedSyscall { hash } => match syscall_name(*hash) {
+				Some(name) => write!(f, "syscall {name} is not allowed"),
+				None => write!(f, "syscall 0x{hash:08x} is not allowed"),
+			},
+			LintKind::UndecodableBytes => write!(f, "bytes do not decode as an instruction"),
+		}
+	}
+}
+
+/// Checks a script for code that is unreachable, malformed or outside what a host allows.
+///
+/// Every instruction reachable from offset 0 or from a `Call` or `PushA` target is followed
+/// with the slot counts declared by `InitSlot` and the current try nesting depth, so slot and
+/// `EndTry` lints are only reported for paths that can actually run.
+#[derive(Debug, Clone, Default)]
+pub struct Linter {
+	syscall_allowlist: Option<BTreeSet<u32>>,
+}
+
+/// What is known on entry to an instruction along one path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct PathState {
+	/// The local and argument counts from `InitSlot`.
+	slots: Option<(usize, usize)>,
+	try_depth: usize,
+}
+
+impl Linter {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Reports every `Syscall` whose id is not in `hashes`.
+	pub fn allow_syscalls(mut self, hashes: impl IntoIterator<Item = u32>) -> Self {
+		self.syscall_allowlist.get_or_insert_with(BTreeSet::new).extend(hashes);
+		self
+	}
+
+	/// Lints `script`, returning the findings ordered by offset.
+	pub fn lint(&self, script: &[u8]) -> Vec<Lint> {
+		let disassembler = Disassembler::new(script);
+		let instructions = disassembler.instructions();
+		let index = instructions
+			.iter()
+			.enumerate()
+			.map(|(i, decoded)| (decoded.offset, i))
+			.collect::<BTreeMap<_, _>>();
+
+		let mut lints = BTreeSet::new();
+		let mut add = |offset, kind| {
+			lints.insert(Lint { offset, kind });
+		};
+
+		for decoded in instructions {
+			for target in targets(decoded) {
+				if target >= 0 && index.contains_key(&(target as usize)) {
+					continue
+				}
+				let data = instructions.iter().find(|d| {
+					is_push_data(d.instruction.opcode)
+						&& (d.offset as i64) < target
+						&& target < (d.offset + d.instruction.size()) as i64
+				});
+				match data {
+					Some(data) => add(
+						decoded.offset,
+						LintKind::JumpIntoPushData { target, data_offset: data.offset },
+					),
+					None => add(decoded.offset, LintKind::InvalidJumpTarget { target }),
+				}
+			}
+
+			if let (OpCode::Syscall, Some(allowlist)) =
+				(decoded.instruction.opcode, &self.syscall_allowlist)
+			{
+				let hash = decoded.instruction.token_u32();
+				if !allowlist.contains(&hash) {
+					add(decoded.offset, LintKind::DisallowedSyscall { hash });
+				}
+			}
+		}
+		if let Some(offset) = disassembler.trailing_offset() {
+			add(offset, LintKind::UndecodableBytes);
+		}
+
+		let reachable = self.walk(instructions, &index, &mut add);
+
+		let mut start = None;
+		for (i, decoded) in instructions.iter().enumerate() {
+			match (reachable.contains(&i), start) {
+				(false, None) => start = Some(decoded.offset),
+				(true, Some(offset)) => {
+					add(offset, LintKind::Unreachable { end: decoded.offset });
+					start = None;
+				},
+				_ => {},
+			}
+		}
+		if let Some(offset) = start {
+			let end = disassembler.trailing_offset().unwrap_or(script.len());
+			add(offset, LintKind::Unreachable { end });
+		}
+
+		lints.into_iter().collect()
+	}
+
+	/// Follows every path from the entry points, returning the indexes of the instructions
+	/// reached.
+	fn walk(
+		&self,
+		instructions: &[DisassembledInstruction],
+		index: &BTreeMap<usize, usize>,
+		add: &mut impl FnMut(usize, LintKind),
+	) -> HashSet<usize> {
+		let max_try_depth = ExecutionEngineLimits::default().max_try_nesting_depth;
+		let entry = PathState { slots: None, try_depth: 0 };
+		let mut pending = vec![(0, entry)];
+		for decoded in instructions {
+			if let OpCode::Call | OpCode::CallL | OpCode::PushA = decoded.instruction.opcode {
+				pending.extend(targets(decoded).map(|target| (target, entry)));
+			}
+		}
+
+		let mut seen = HashSet::new();
+		let mut reachable = HashSet::new();
+		while let Some((offset, state)) = pending.pop() {
+			let Some(&i) = usize::try_from(offset).ok().and_then(|offset| index.get(&offset))
+			else {
+				continue
+			};
+			if !seen.insert((i, state)) {
+				continue
+			}
+			reachable.insert(i);
+
+			let decoded = &instructions[i];
+			let instruction = &decoded.instruction;
+			let next = (decoded.offset + instruction.size()) as i64;
+			let mut state = state;
+
+			if let Some((kind, _, slot_index)) = instruction.slot_access() {
+				let count = match (kind, state.slots) {
+					(SlotKind::Static, _) => None,
+					(_, None) => {
+						add(decoded.offset, LintKind::MissingInitSlot { kind });
+						None
+					},
+					(SlotKind::Local, Some((locals, _))) => Some(locals),
+					(_, Some((_, arguments))) => Some(arguments),
+				};
+				if let Some(count) = count.filter(|&count| slot_index >= count) {
+					let kind = LintKind::SlotIndexOutOfRange { kind, index: slot_index, count };
+					add(decoded.offset, kind);
+				}
+			}
+
+			match instruction.opcode {
+				OpCode::InitSlot => {
+					let slots =
+						(instruction.token_u8() as usize, instruction.token_u8_1() as usize);
+					state.slots = Some(slots);
+					pending.push((next, state));
+				},
+				OpCode::Jmp | OpCode::JmpL => pending.extend(targets(decoded).map(|t| (t, state))),
+				OpCode::Ret
+				| OpCode::Throw
+				| OpCode::Abort
+				| OpCode::AbortMsg
+				| OpCode::EndFinally => {},
+				OpCode::Try | OpCode::TryL => {
+					state.try_depth = (state.try_depth + 1).min(max_try_depth);
+					pending.push((next, state));
+					pending.extend(targets(decoded).map(|t| (t, state)));
+				},
+				OpCode::EndTry | OpCode::EndTryL => {
+					if state.try_depth == 0 {
+						add(decoded.offset, LintKind::EndTryOutsideTry);
+					}
+					state.try_depth = state.try_depth.saturating_sub(1);
+					pending.extend(targets(decoded).map(|t| (t, state)));
+				},
+				OpCode::Call | OpCode::CallL => pending.push((next, state)),
+				opcode if is_conditional_jump(opcode) => {
+					pending.push((next, state));
+					pending.extend(targets(decoded).map(|t| (t, state)));
+				},
+				_ => pending.push((next, state)),
+			}
+		}
+		reachable
+	}
+}
+
+/// Lints `script` without a syscall allowlist.
+pub fn lint(script: &[u8]) -> Vec<Lint> {
+	Linter::new().lint(script)
+}
+
+/// The absolute targets of a jump, call, `PushA`, `Try` or `EndTry`, skipping absent catch
+/// and finally blocks.
+fn targets(decoded: &DisassembledInstruction) -> impl Iterator<Item = i64> + '_ {
+	let is_try = matches!(decoded.instruction.opcode, OpCode::Try | OpCode::TryL);
+	decoded
+		.instruction
+		.target_offsets()
+		.into_iter()
+		.filter(move |&offset| !(is_try && offset == 0))
+		.map(move |offset| decoded.offset as i64 + offset as i64)
+}
+
+fn is_push_data(opcode: OpCode) -> bool {
+	matches!(opcode, OpCode::PushData1 | OpCode::PushData2 | OpCode::PushData4)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{interop_service::syscall_hash, script::assembler::assemble};
+
+	fn kinds(source: &str) -> Vec<LintKind> {
+		lint(&assemble(source).unwrap()).into_iter().map(|lint| lint.kind).collect()
+	}
+
+	#[test]
+	fn clean_script_has_no_lints() {
+		let source = "INITSLOT 1, 1
+			LDARG0
+			STLOC0
+			TRY L_catch, 0
+			ENDTRY L_end
+			L_catch:
+			ENDTRY L_end
+			L_end:
+			LDLOC0
+			RET";
+		assert_eq!(kinds(source), vec![]);
+	}
+
+	#[test]
+	fn reports_unreachable_code() {
+		let lints = lint(&assemble("RET\nPUSH1\nPUSH2\nRET").unwrap());
+		assert_eq!(lints, vec![Lint { offset: 1, kind: LintKind::Unreachable { end: 4 } }]);
+		assert_eq!(lints[0].to_string(), "0001: unreachable code up to 0004");
+	}
+
+	#[test]
+	fn reports_jumps_into_push_data() {
+		// JMP +3 lands in the data of PUSHDATA1 "ab"
+		let script = [0x22, 0x03, 0x0c, 0x02, 0x61, 0x62, 0x40];
+		assert!(lint(&script).contains(&Lint {
+			offset: 0,
+			kind: LintKind::JumpIntoPushData { target: 3, data_offset: 2 },
+		}));
+	}
+
+	#[test]
+	fn reports_slot_problems() {
+		assert_eq!(kinds("LDLOC0\nRET"), vec![LintKind::MissingInitSlot { kind: SlotKind::Local }]);
+		assert_eq!(
+			kinds("INITSLOT 1, 0\nSTLOC 2\nLDARG0\nRET"),
+			vec![
+				LintKind::SlotIndexOutOfRange { kind: SlotKind::Local, index: 2, count: 1 },
+				LintKind::SlotIndexOutOfRange { kind: SlotKind::Argument, index: 0, count: 0 },
+			]
+		);
+	}
+
+	#[test]
+	fn reports_end_try_outside_try_and_disallowed_syscalls() {
+		assert_eq!(kinds("ENDTRY L_end\nL_end:\nRET"), vec![LintKind::EndTryOutsideTry]);
+
+		let allowed = syscall_hash("System.Runtime.Platform");
+		let script = assemble("SYSCALL System.Runtime.Platform\nSYSCALL System.Runtime.Log\nRET");
+		let lints = Linter::new().allow_syscalls([allowed]).lint(&script.unwrap());
+		let hash = syscall_hash("System.Runtime.Log");
+		assert_eq!(lints, vec![Lint { offset: 5, kind: LintKind::DisallowedSyscall { hash } }]);
+	}
+}
@@ -0,0 +1,432 @@
+use crate::{
+	execution_context::ExecutionContext, execution_engine::ExecutionEngine, stack_item::StackItem,
+	stack_item_type::StackItemType, vm::script::Script, vm_state::VMState,
+};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::{
+	cell::RefCell,
+	collections::HashSet,
+	error::Error,
+	fmt::{Display, Formatter},
+	rc::Rc,
+};
+
+/// The type of a parameter or return value declared in a contract ABI.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ContractParameterType {
+	Any,
+	Boolean,
+	Integer,
+	ByteArray,
+	String,
+	Hash160,
+	Hash256,
+	PublicKey,
+	Signature,
+	Array,
+	Map,
+	InteropInterface,
+	Void,
+}
+
+impl ContractParameterType {
+	/// Whether `item` is a valid value of this type.
+	pub fn matches(&self, item: &dyn StackItem) -> bool {
+		let ty = item.get_type();
+		let is_bytes = matches!(ty, StackItemType::ByteString | StackItemType::Buffer);
+		let bytes = if is_bytes { item.get_slice().ok() } else { None };
+		match self {
+			ContractParameterType::Any => true,
+			ContractParameterType::Void => false,
+			ContractParameterType::Boolean => ty == StackItemType::Boolean,
+			ContractParameterType::Integer => ty == StackItemType::Integer,
+			ContractParameterType::ByteArray => is_bytes,
+			ContractParameterType::String =>
+				bytes.is_some_and(|bytes| std::str::from_utf8(bytes).is_ok()),
+			ContractParameterType::Hash160 => bytes.is_some_and(|bytes| bytes.len() == 20),
+			ContractParameterType::Hash256 => bytes.is_some_and(|bytes| bytes.len() == 32),
+			ContractParameterType::PublicKey => bytes.is_some_and(|bytes| bytes.len() == 33),
+			ContractParameterType::Signature => bytes.is_some_and(|bytes| bytes.len() == 64),
+			ContractParameterType::Array =>
+				matches!(ty, StackItemType::Array | StackItemType::Struct),
+			ContractParameterType::Map => ty == StackItemType::Map,
+			ContractParameterType::InteropInterface => ty == StackItemType::InteropInterface,
+		}
+	}
+}
+
+/// Either `"*"` or an explicit list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WildcardContainer<T> {
+	Wildcard,
+	List(Vec<T>),
+}
+
+impl<T: Serialize> Serialize for WildcardContainer<T> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		match self {
+			WildcardContainer::Wildcard => serializer.serialize_str("*"),
+			WildcardContainer::List(items) => items.serialize(serializer),
+		}
+	}
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for WildcardContainer<T> {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		#[derive(Deserialize)]
+		#[serde(untagged)]
+		enum Repr<T> {
+			Wildcard(String),
+			List(Vec<T>),
+		}
+		match Repr::deserialize(deserializer)? {
+			Repr::Wildcard(text) if text == "*" => Ok(WildcardContainer::Wildcard),
+			Repr::Wildcard(text) =>
+				Err(D::Error::custom(format!("expected \"*\", found {text:?}"))),
+			Repr::List(items) => Ok(WildcardContainer::List(items)),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContractParameterDefinition {
+	pub name: String,
+	#[serde(rename = "type")]
+	pub kind: ContractParameterType,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContractMethod {
+	pub name: String,
+	pub parameters: Vec<ContractParameterDefinition>,
+	#[serde(rename = "returntype")]
+	pub return_type: ContractParameterType,
+	/// The entry point of the method in the contract script.
+	pub offset: usize,
+	/// Whether the method only reads state.
+	pub safe: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContractEvent {
+	pub name: String,
+	pub parameters: Vec<ContractParameterDefinition>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContractAbi {
+	pub methods: Vec<ContractMethod>,
+	pub events: Vec<ContractEvent>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContractGroup {
+	/// The hex-encoded compressed public key of the group.
+	#[serde(rename = "pubkey")]
+	pub public_key: String,
+	/// The base64-encoded signature of the contract hash.
+	pub signature: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContractPermission {
+	/// `"*"`, a `0x`-prefixed contract hash or a group public key.
+	pub contract: String,
+	pub methods: WildcardContainer<String>,
+}
+
+/// The manifest published alongside a contract's NEF.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContractManifest {
+	pub name: String,
+	pub groups: Vec<ContractGroup>,
+	#[serde(default)]
+	pub features: Value,
+	#[serde(rename = "supportedstandards")]
+	pub supported_standards: Vec<String>,
+	pub abi: ContractAbi,
+	pub permissions: Vec<ContractPermission>,
+	pub trusts: WildcardContainer<String>,
+	#[serde(default)]
+	pub extra: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifestError {
+	InvalidJson(String),
+	InvalidManifest(String),
+	InvalidOffset { method: String, offset: usize },
+	ArgumentCount { expected: usize, actual: usize },
+	ArgumentType { index: usize, expected: ContractParameterType, actual: StackItemType },
+	ReturnType { expected: ContractParameterType, actual: Option<StackItemType> },
+}
+
+impl Display for ManifestError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ManifestError::InvalidJson(message) => write!(f, "Invalid manifest JSON: {message}"),
+			ManifestError::InvalidManifest(message) => write!(f, "Invalid manifest: {message}"),
+			ManifestError::InvalidOffset { method, offset } =>
+				write!(f, "Method {method} starts at {offset}, which is not an instruction"),
+			ManifestError::ArgumentCount { expected, actual } =>
+				write!(f, "Expected {expected} arguments, found {actual}"),
+			ManifestError::ArgumentType { index, expected, actual } =>
+				write!(f, "Argument {index} should be {expected:?}, found {actual:?}"),
+			ManifestError::ReturnType { expected, actual: Some(actual) } =>
+				write!(f, "Return value should be {expected:?}, found {actual:?}"),
+			ManifestError::ReturnType { expected, actual: None } =>
+				write!(f, "Return value should be {expected:?}, found nothing"),
+		}
+	}
+}
+
+impl Error for ManifestError {}
+
+impl ContractManifest {
+	/// Parses and validates a manifest.
+	pub fn parse(json: &str) -> Result<Self, ManifestError> {
+		let manifest: Self =
+			serde_json::from_str(json).map_err(|e| ManifestError::InvalidJson(e.to_string()))?;
+		manifest.validate()?;
+		Ok(manifest)
+	}
+
+	pub fn to_json_string(&self) -> String {
+		serde_json::to_string(self).unwrap()
+	}
+
+	/// Checks the rules the JSON schema cannot express.
+	pub fn validate(&self) -> Result<(), ManifestError> {
+		if self.name.is_empty() {
+			return Err(ManifestError::InvalidManifest("name is empty".into()))
+		}
+		let mut methods = HashSet::new();
+		for method in &self.abi.methods {
+			if method.name.is_empty() {
+				return Err(ManifestError::InvalidManifest("method name is empty".into()))
+			}
+			if !methods.insert((method.name.as_str(), method.parameters.len())) {
+				return Err(ManifestError::InvalidManifest(format!(
+					"method {} with {} parameters is declared twice",
+					method.name,
+					method.parameters.len()
+				)))
+			}
+			if method.parameters.iter().any(|p| p.kind == ContractParameterType::Void) {
+				return Err(ManifestError::InvalidManifest(format!(
+					"method {} has a Void parameter",
+					method.name
+				)))
+			}
+		}
+		if !matches!(&self.features, Value::Null)
+			&& self.features.as_object().is_none_or(|features| !features.is_empty())
+		{
+			return Err(ManifestError::InvalidManifest("features must be empty".into()))
+		}
+		Ok(())
+	}
+
+	/// Finds the ABI method `name` taking `parameter_count` arguments.
+	pub fn method(&self, name: &str, parameter_count: usize) -> Option<&ContractMethod> {
+		self.abi
+			.methods
+			.iter()
+			.find(|method| method.name == name && method.parameters.len() == parameter_count)
+	}
+}
+
+impl ContractMethod {
+	/// Type-checks `args` and loads `script` at the method's offset with them on the stack,
+	/// the first argument on top. The offset must start an instruction; a strict-mode script
+	/// also rejects offsets its validation did not decode.
+	pub fn load(
+		&self,
+		engine: &mut ExecutionEngine,
+		script: Script,
+		args: Vec<Rc<RefCell<dyn StackItem>>>,
+	) -> Result<Rc<RefCell<ExecutionContext>>, ManifestError> {
+		if args.len() != self.parameters.len() {
+			return Err(ManifestError::ArgumentCount {
+				expected: self.parameters.len(),
+				actual: args.len(),
+			})
+		}
+		for (index, (arg, parameter)) in args.iter().zip(&self.parameters).enumerate() {
+			let arg = arg.borrow();
+			if !parameter.kind.matches(&*arg) {
+				return Err(ManifestError::ArgumentType {
+					index,
+					expected: parameter.kind,
+					actual: arg.get_type(),
+				})
+			}
+		}
+		if self.offset >= script.len() || script.get_instruction(self.offset).is_err() {
+			return Err(ManifestError::InvalidOffset {
+				method: self.name.clone(),
+				offset: self.offset,
+			})
+		}
+
+		let rvcount = if self.return_type == ContractParameterType::Void { 0 } else { 1 };
+		let context = engine.load_script(script, rvcount, self.offset);
+		let stack = context.borrow().evaluation_stack();
+		for arg in args.into_iter().rev() {
+			stack.borrow_mut().push(arg);
+		}
+		Ok(context)
+	}
+
+	/// Checks the value a halted engine returned against the declared return type.
+	pub fn check_return(&self, engine: &ExecutionEngine) -> Result<(), ManifestError> {
+		if self.return_type == ContractParameterType::Void || engine.state != VMState::Halt {
+			return Ok(())
+		}
+		let result_stack = engine.result_stack.borrow();
+		let actual = result_stack.peek(0).ok();
+		match actual {
+			Some(item) if self.return_type.matches(&*item.borrow()) => Ok(()),
+			actual => Err(ManifestError::ReturnType {
+				expected: self.return_type,
+				actual: actual.map(|item| item.borrow().get_type()),
+			}),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::primitive_types::{byte_string::ByteString, integer::Integer};
+	use num_bigint::BigInt;
+
+	const MANIFEST: &str = r#"{
+		"name": "Token",
+		"groups": [],
+		"features": {},
+		"supportedstandards": ["NEP-17"],
+		"abi": {
+			"methods": [
+				{
+					"name": "balanceOf",
+					"parameters": [{ "name": "account", "type": "Hash160" }],
+					"returntype": "Integer",
+					"offset": 12,
+					"safe": true
+				}
+			],
+			"events": [
+				{
+					"name": "Transfer",
+					"parameters": [
+						{ "name": "from", "type": "Hash160" },
+						{ "name": "to", "type": "Hash160" },
+						{ "name": "amount", "type": "Integer" }
+					]
+				}
+			]
+		},
+		"permissions": [{ "contract": "*", "methods": ["onNEP17Payment"] }],
+		"trusts": "*",
+		"extra": null
+	}"#;
+
+	#[test]
+	fn parses_and_round_trips() {
+		let manifest = ContractManifest::parse(MANIFEST).unwrap();
+		let method = manifest.method("balanceOf", 1).unwrap();
+		assert_eq!(method.offset, 12);
+		assert_eq!(method.return_type, ContractParameterType::Integer);
+		assert_eq!(manifest.trusts, WildcardContainer::Wildcard);
+		assert_eq!(
+			manifest.permissions[0].methods,
+			WildcardContainer::List(vec!["onNEP17Payment".to_string()])
+		);
+		assert_eq!(ContractManifest::parse(&manifest.to_json_string()).unwrap(), manifest);
+	}
+
+	#[test]
+	fn rejects_duplicate_methods() {
+		let mut manifest = ContractManifest::parse(MANIFEST).unwrap();
+		manifest.abi.methods.push(manifest.abi.methods[0].clone());
+		assert!(matches!(manifest.validate(), Err(ManifestError::InvalidManifest(_))));
+	}
+
+	fn add_method(offset: usize) -> ContractMethod {
+		let parameter = |name: &str| ContractParameterDefinition {
+			name: name.into(),
+			kind: ContractParameterType::Integer,
+		};
+		ContractMethod {
+			name: "add".into(),
+			parameters: vec![parameter("a"), parameter("b")],
+			return_type: ContractParameterType::Integer,
+			offset,
+			safe: true,
+		}
+	}
+
+	fn integer(value: i32) -> Rc<RefCell<dyn StackItem>> {
+		Rc::new(RefCell::new(Integer::new(&BigInt::from(value))))
+	}
+
+	#[test]
+	fn loads_methods_at_their_offset_with_arguments() {
+		// RET; ADD; RET
+		let script = Script::new(vec![0x40, 0x9E, 0x40], true).unwrap();
+		let mut engine = ExecutionEngine::new();
+		let method = add_method(1);
+		method.load(&mut engine, script, vec![integer(2), integer(3)]).unwrap();
+		assert_eq!(engine.execute(), VMState::Halt);
+		method.check_return(&engine).unwrap();
+		let result = engine.result_stack.borrow().peek(0).unwrap();
+		assert_eq!(result.borrow().get_integer().unwrap(), BigInt::from(5));
+	}
+
+	#[test]
+	fn rejects_wrong_argument_counts_and_offsets() {
+		// PUSHDATA1 "@"; RET: offset 2 is inside the push and 4 is past the end.
+		let script = || Script::new(vec![0x0C, 0x01, 0x40, 0x40], true).unwrap();
+		let mut engine = ExecutionEngine::new();
+		assert_eq!(
+			add_method(0).load(&mut engine, script(), vec![integer(1)]).unwrap_err(),
+			ManifestError::ArgumentCount { expected: 2, actual: 1 }
+		);
+		for offset in [2, 4] {
+			assert!(matches!(
+				add_method(offset).load(&mut engine, script(), vec![integer(1), integer(2)]),
+				Err(ManifestError::InvalidOffset { .. })
+			));
+		}
+	}
+
+	#[test]
+	fn rejects_mismatched_argument_and_return_types() {
+		// PUSHDATA1 "@"; RET
+		let script = Script::new(vec![0x0C, 0x01, 0x40, 0x40], true).unwrap();
+		let mut engine = ExecutionEngine::new();
+		let method = ContractMethod { parameters: vec![], ..add_method(0) };
+		method.load(&mut engine, script, vec![]).unwrap();
+		assert_eq!(engine.execute(), VMState::Halt);
+		assert_eq!(
+			method.check_return(&engine),
+			Err(ManifestError::ReturnType {
+				expected: ContractParameterType::Integer,
+				actual: Some(StackItemType::ByteString),
+			})
+		);
+
+		let bytes: Rc<RefCell<dyn StackItem>> = Rc::new(RefCell::new(ByteString::new(vec![1])));
+		let method = add_method(0);
+		assert!(matches!(
+			method.load(
+				&mut engine,
+				Script::new(vec![0x40], true).unwrap(),
+				vec![bytes, integer(1)]
+			),
+			Err(ManifestError::ArgumentType { index: 0, .. })
+		));
+	}
+}
@@ -1,4 +1,15 @@
-mod script_builder;
+pub mod assembler;
+pub mod call_flags;
+pub mod contract_parameter;
+pub mod control_flow;
+pub mod debug_info;
+pub mod disassembler;
+pub mod linter;
+pub mod manifest;
+pub mod nef;
+pub mod optimizer;
+pub mod script_builder;
+pub mod type_analysis;
 
 pub fn add(left: usize, right: usize) -> usize {
 	left + right
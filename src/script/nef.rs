@@ -0,0 +1,445 @@
+use crate::{
+	execution_engine_limits::ExecutionEngineLimits,
+	script::call_flags::CallFlags,
+	uint::UInt160,
+	vm::script::{Script, ScriptError},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+	error::Error,
+	fmt::{Display, Formatter},
+};
+
+/// `NEF3` read as a little-endian integer.
+pub const NEF_MAGIC: u32 = 0x3346_454E;
+
+/// The size of the zero-padded compiler field.
+pub const COMPILER_SIZE: usize = 64;
+
+const MAX_SOURCE_SIZE: usize = 256;
+const MAX_TOKENS: usize = 128;
+const MAX_METHOD_NAME_SIZE: usize = 32;
+
+/// A contract method called through `OpCode::CallT`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MethodToken {
+	/// The script hash of the called contract.
+	pub hash: UInt160,
+
+	/// The name of the called method.
+	pub method: String,
+
+	/// The number of arguments taken from the evaluation stack.
+	pub parameters_count: u16,
+
+	/// Whether the method pushes a return value.
+	pub has_return_value: bool,
+
+	/// The flags the call is made with.
+	pub call_flags: CallFlags,
+}
+
+/// A compiled contract in the NEF container format.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct NefFile {
+	/// The compiler name and version, at most 64 bytes of UTF-8.
+	pub compiler: String,
+
+	/// The URL of the contract source, at most 256 bytes.
+	pub source: String,
+
+	/// The methods `OpCode::CallT` indexes into.
+	pub tokens: Vec<MethodToken>,
+
+	/// The contract script.
+	pub script: Vec<u8>,
+
+	/// The first four bytes of the double SHA-256 of everything before it.
+	pub checksum: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NefError {
+	UnexpectedEnd,
+	InvalidMagic(u32),
+	InvalidFormat(String),
+	InvalidChecksum { expected: u32, actual: u32 },
+	TrailingBytes(usize),
+}
+
+impl Display for NefError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			NefError::UnexpectedEnd => write!(f, "Unexpected end of NEF data"),
+			NefError::InvalidMagic(magic) => write!(f, "Invalid NEF magic: {magic:#010x}"),
+			NefError::InvalidFormat(message) => write!(f, "Invalid NEF: {message}"),
+			NefError::InvalidChecksum { expected, actual } =>
+				write!(f, "NEF checksum mismatch: expected {expected:#010x}, found {actual:#010x}"),
+			NefError::TrailingBytes(count) => write!(f, "{count} bytes after the NEF checksum"),
+		}
+	}
+}
+
+impl Error for NefError {}
+
+impl NefFile {
+	/// Builds a NEF around `script` and computes its checksum.
+	///
+	/// Fails if a field exceeds the size the format allows.
+	pub fn new(
+		compiler: &str,
+		source: &str,
+		tokens: Vec<MethodToken>,
+		script: Vec<u8>,
+	) -> Result<Self, NefError> {
+		let mut nef = Self {
+			compiler: compiler.to_string(),
+			source: source.to_string(),
+			tokens,
+			script,
+			checksum: 0,
+		};
+		nef.validate()?;
+		nef.checksum = nef.compute_checksum();
+		Ok(nef)
+	}
+
+	/// Checks that every field fits the limits `parse` enforces.
+	pub fn validate(&self) -> Result<(), NefError> {
+		if self.compiler.len() > COMPILER_SIZE {
+			return Err(NefError::InvalidFormat(format!(
+				"compiler is {} bytes, at most {COMPILER_SIZE} allowed",
+				self.compiler.len()
+			)))
+		}
+		if self.compiler.contains('\0') {
+			return Err(NefError::InvalidFormat("compiler contains a NUL byte".into()))
+		}
+		if self.source.len() > MAX_SOURCE_SIZE {
+			return Err(NefError::InvalidFormat(format!(
+				"source is {} bytes, at most {MAX_SOURCE_SIZE} allowed",
+				self.source.len()
+			)))
+		}
+		if self.tokens.len() > MAX_TOKENS {
+			return Err(NefError::InvalidFormat(format!(
+				"{} method tokens, at most {MAX_TOKENS} allowed",
+				self.tokens.len()
+			)))
+		}
+		if let Some(token) = self.tokens.iter().find(|t| t.method.len() > MAX_METHOD_NAME_SIZE) {
+			return Err(NefError::InvalidFormat(format!(
+				"method token `{}` is longer than {MAX_METHOD_NAME_SIZE} bytes",
+				token.method
+			)))
+		}
+		if let Some(token) = self.tokens.iter().find(|t| t.method.starts_with('_')) {
+			return Err(NefError::InvalidFormat(format!(
+				"method token `{}` is private",
+				token.method
+			)))
+		}
+		if let Some(token) = self.tokens.iter().find(|t| !CallFlags::ALL.contains(t.call_flags)) {
+			return Err(NefError::InvalidFormat(format!(
+				"invalid call flags {:#04x}",
+				token.call_flags.0
+			)))
+		}
+		if self.script.is_empty() {
+			return Err(NefError::InvalidFormat("script is empty".into()))
+		}
+		Ok(())
+	}
+
+	/// Parses and validates NEF bytes, including the checksum.
+	pub fn parse(bytes: &[u8]) -> Result<Self, NefError> {
+		let mut reader = Reader { bytes, position: 0 };
+
+		let magic = u32::from_le_bytes(reader.read_array()?);
+		if magic != NEF_MAGIC {
+			return Err(NefError::InvalidMagic(magic))
+		}
+
+		let compiler = reader.read_bytes(COMPILER_SIZE)?;
+		let compiler = compiler.split(|&b| b == 0).next().unwrap_or_default();
+		let compiler = String::from_utf8(compiler.to_vec())
+			.map_err(|_| NefError::InvalidFormat("compiler is not UTF-8".into()))?;
+		let source = reader.read_var_string(MAX_SOURCE_SIZE)?;
+		if reader.read_array::<1>()? != [0] {
+			return Err(NefError::InvalidFormat("reserved byte is not zero".into()))
+		}
+
+		let count = reader.read_var_int(MAX_TOKENS as u64)? as usize;
+		let mut tokens = Vec::with_capacity(count);
+		for _ in 0..count {
+			tokens.push(reader.read_method_token()?);
+		}
+		if reader.read_array::<2>()? != [0, 0] {
+			return Err(NefError::InvalidFormat("reserved bytes are not zero".into()))
+		}
+
+		let length = reader.read_var_int(ExecutionEngineLimits::default().max_item_size as u64)?;
+		let script = reader.read_bytes(length as usize)?.to_vec();
+		if script.is_empty() {
+			return Err(NefError::InvalidFormat("script is empty".into()))
+		}
+
+		let checksum = u32::from_le_bytes(reader.read_array()?);
+		if reader.position != bytes.len() {
+			return Err(NefError::TrailingBytes(bytes.len() - reader.position))
+		}
+
+		let nef = Self { compiler, source, tokens, script, checksum };
+		let expected = nef.compute_checksum();
+		if expected != checksum {
+			return Err(NefError::InvalidChecksum { expected, actual: checksum })
+		}
+		Ok(nef)
+	}
+
+	/// Serializes the NEF, writing the stored checksum.
+	///
+	/// Fails instead of truncating if a field exceeds its limit.
+	pub fn to_bytes(&self) -> Result<Vec<u8>, NefError> {
+		self.validate()?;
+		let mut bytes = self.header_bytes();
+		bytes.extend_from_slice(&self.checksum.to_le_bytes());
+		Ok(bytes)
+	}
+
+	/// Computes the checksum of the NEF's current contents.
+	pub fn compute_checksum(&self) -> u32 {
+		let hash = Sha256::digest(Sha256::digest(self.header_bytes()));
+		u32::from_le_bytes([hash[0], hash[1], hash[2], hash[3]])
+	}
+
+	/// Wraps the contract script in a `Script`.
+	pub fn to_script(&self, strict_mode: bool) -> Result<Script, ScriptError> {
+		Script::new(self.script.clone(), strict_mode)
+	}
+
+	/// Everything but the checksum.
+	fn header_bytes(&self) -> Vec<u8> {
+		let mut bytes = Vec::with_capacity(COMPILER_SIZE + self.script.len() + 64);
+		bytes.extend_from_slice(&NEF_MAGIC.to_le_bytes());
+		bytes.extend_from_slice(self.compiler.as_bytes());
+		bytes.resize(bytes.len() + COMPILER_SIZE.saturating_sub(self.compiler.len()), 0);
+		write_var_bytes(&mut bytes, self.source.as_bytes());
+		bytes.push(0);
+		write_var_int(&mut bytes, self.tokens.len() as u64);
+		for token in &self.tokens {
+			bytes.extend_from_slice(token.hash.as_bytes());
+			write_var_bytes(&mut bytes, token.method.as_bytes());
+			bytes.extend_from_slice(&token.parameters_count.to_le_bytes());
+			bytes.push(token.has_return_value as u8);
+			bytes.push(token.call_flags.0);
+		}
+		bytes.extend_from_slice(&[0, 0]);
+		write_var_bytes(&mut bytes, &self.script);
+		bytes
+	}
+}
+
+fn write_var_int(bytes: &mut Vec<u8>, value: u64) {
+	match value {
+		0..=0xFC => bytes.push(value as u8),
+		0xFD..=0xFFFF => {
+			bytes.push(0xFD);
+			bytes.extend_from_slice(&(value as u16).to_le_bytes());
+		},
+		0x1_0000..=0xFFFF_FFFF => {
+			bytes.push(0xFE);
+			bytes.extend_from_slice(&(value as u32).to_le_bytes());
+		},
+		_ => {
+			bytes.push(0xFF);
+			bytes.extend_from_slice(&value.to_le_bytes());
+		},
+	}
+}
+
+fn write_var_bytes(bytes: &mut Vec<u8>, data: &[u8]) {
+	write_var_int(bytes, data.len() as u64);
+	bytes.extend_from_slice(data);
+}
+
+struct Reader<'a> {
+	bytes: &'a [u8],
+	position: usize,
+}
+
+impl<'a> Reader<'a> {
+	fn read_bytes(&mut self, count: usize) -> Result<&'a [u8], NefError> {
+		let end = self.position.checked_add(count).ok_or(NefError::UnexpectedEnd)?;
+		let bytes = self.bytes.get(self.position..end).ok_or(NefError::UnexpectedEnd)?;
+		self.position = end;
+		Ok(bytes)
+	}
+
+	fn read_array<const N: usize>(&mut self) -> Result<[u8; N], NefError> {
+		Ok(self.read_bytes(N)?.try_into().unwrap())
+	}
+
+	fn read_var_int(&mut self, max: u64) -> Result<u64, NefError> {
+		let value = match self.read_array::<1>()?[0] {
+			0xFD => u16::from_le_bytes(self.read_array()?) as u64,
+			0xFE => u32::from_le_bytes(self.read_array()?) as u64,
+			0xFF => u64::from_le_bytes(self.read_array()?),
+			value => value as u64,
+		};
+		if value > max {
+			return Err(NefError::InvalidFormat(format!("length {value} exceeds {max}")))
+		}
+		Ok(value)
+	}
+
+	fn read_var_string(&mut self, max: usize) -> Result<String, NefError> {
+		let length = self.read_var_int(max as u64)? as usize;
+		String::from_utf8(self.read_bytes(length)?.to_vec())
+			.map_err(|_| NefError::InvalidFormat("string is not UTF-8".into()))
+	}
+
+	fn read_method_token(&mut self) -> Result<MethodToken, NefError> {
+		let hash = UInt160::new(self.read_array()?);
+		let method = self.read_var_string(MAX_METHOD_NAME_SIZE)?;
+		if method.starts_with('_') {
+			return Err(NefError::InvalidFormat(format!("method token `{method}` is private")))
+		}
+		let parameters_count = u16::from_le_bytes(self.read_array()?);
+		let has_return_value = match self.read_array::<1>()?[0] {
+			0 => false,
+			1 => true,
+			value => return Err(NefError::InvalidFormat(format!("invalid boolean {value}"))),
+		};
+		let call_flags = CallFlags(self.read_array::<1>()?[0]);
+		if !CallFlags::ALL.contains(call_flags) {
+			return Err(NefError::InvalidFormat(format!("invalid call flags {:#04x}", call_flags.0)))
+		}
+		Ok(MethodToken { hash, method, parameters_count, has_return_value, call_flags })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn sample() -> NefFile {
+		let token = MethodToken {
+			hash: UInt160::new([0x11; 20]),
+			method: "balanceOf".into(),
+			parameters_count: 1,
+			has_return_value: true,
+			call_flags: CallFlags::READ_ONLY,
+		};
+		NefFile::new("neo-rs-test 1.0", "https://example.com/src", vec![token], vec![0x11, 0x40])
+			.unwrap()
+	}
+
+	#[test]
+	fn round_trips_through_bytes() {
+		let nef = sample();
+		let bytes = nef.to_bytes().unwrap();
+		assert_eq!(&bytes[..4], b"NEF3");
+		assert_eq!(NefFile::parse(&bytes).unwrap(), nef);
+	}
+
+	#[test]
+	fn rejects_corrupted_files() {
+		let mut bytes = sample().to_bytes().unwrap();
+		let last = bytes.len() - 5;
+		bytes[last] ^= 0xFF;
+		assert!(matches!(NefFile::parse(&bytes), Err(NefError::InvalidChecksum { .. })));
+
+		let bytes = sample().to_bytes().unwrap();
+		assert_eq!(NefFile::parse(&bytes[..bytes.len() - 1]), Err(NefError::UnexpectedEnd));
+		assert!(matches!(NefFile::parse(&[0; 8]), Err(NefError::InvalidMagic(0))));
+	}
+
+	#[test]
+	fn rejects_oversized_compiler() {
+		let compiler = "c".repeat(COMPILER_SIZE + 1);
+		assert!(matches!(
+			NefFile::new(&compiler, "", vec![], vec![0x40]),
+			Err(NefError::InvalidFormat(_))
+		));
+		assert!(NefFile::new(&compiler[1..], "", vec![], vec![0x40]).is_ok());
+
+		let mut nef = sample();
+		nef.compiler = compiler;
+		assert!(matches!(nef.to_bytes(), Err(NefError::InvalidFormat(_))));
+	}
+
+	#[test]
+	fn rejects_oversized_source() {
+		let source = "s".repeat(MAX_SOURCE_SIZE + 1);
+		assert!(matches!(
+			NefFile::new("", &source, vec![], vec![0x40]),
+			Err(NefError::InvalidFormat(_))
+		));
+
+		let mut nef = sample();
+		nef.source = source;
+		assert!(matches!(nef.to_bytes(), Err(NefError::InvalidFormat(_))));
+	}
+
+	#[test]
+	fn rejects_too_many_tokens() {
+		let token = sample().tokens[0].clone();
+		let tokens = vec![token; MAX_TOKENS + 1];
+		assert!(matches!(
+			NefFile::new("", "", tokens.clone(), vec![0x40]),
+			Err(NefError::InvalidFormat(_))
+		));
+		assert!(NefFile::new("", "", tokens[1..].to_vec(), vec![0x40]).is_ok());
+
+		let mut nef = sample();
+		nef.tokens = tokens;
+		assert!(matches!(nef.to_bytes(), Err(NefError::InvalidFormat(_))));
+	}
+
+	/// Asserts that `new` and `to_bytes` reject what `parse` would reject, and that the NEF
+	/// round-trips once `fix` undoes the change.
+	fn assert_rejected_like_parse(break_nef: impl Fn(&mut NefFile), fix: impl Fn(&mut NefFile)) {
+		let mut nef = sample();
+		break_nef(&mut nef);
+		assert!(matches!(
+			NefFile::new(&nef.compiler, &nef.source, nef.tokens.clone(), nef.script.clone()),
+			Err(NefError::InvalidFormat(_))
+		));
+		assert!(matches!(nef.to_bytes(), Err(NefError::InvalidFormat(_))));
+
+		fix(&mut nef);
+		let nef = NefFile::new(&nef.compiler, &nef.source, nef.tokens, nef.script).unwrap();
+		assert_eq!(NefFile::parse(&nef.to_bytes().unwrap()).unwrap(), nef);
+	}
+
+	#[test]
+	fn rejects_empty_script() {
+		assert_rejected_like_parse(|nef| nef.script.clear(), |nef| nef.script.push(0x40));
+	}
+
+	#[test]
+	fn rejects_private_method_tokens() {
+		assert_rejected_like_parse(
+			|nef| nef.tokens[0].method = "_deploy".into(),
+			|nef| nef.tokens[0].method = "deploy_".into(),
+		);
+	}
+
+	#[test]
+	fn rejects_undefined_call_flags() {
+		assert_rejected_like_parse(
+			|nef| nef.tokens[0].call_flags = CallFlags(0x10),
+			|nef| nef.tokens[0].call_flags = CallFlags::ALL,
+		);
+	}
+
+	#[test]
+	fn rejects_nul_in_compiler() {
+		assert_rejected_like_parse(
+			|nef| nef.compiler = "neo-rs\0test".into(),
+			|nef| nef.compiler = nef.compiler.replace('\0', " "),
+		);
+	}
+}
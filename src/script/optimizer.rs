@@ -0,0 +1,331 @@
+use crate::{
+	instruction::Instruction,
+	op_code::OpCode,
+	primitive_types::integer::Integer,
+	script::{
+		disassembler::Disassembler,
+		script_builder::{BranchTarget, Label, ScriptBuilder},
+	},
+	vm::script::{Script, ScriptError},
+};
+use num_bigint::BigInt;
+use num_traits::{ToPrimitive, Zero};
+use std::collections::{BTreeSet, HashMap};
+
+/// Rewrites `script` into a shorter one that computes the same results.
+///
+/// The optimizer drops `Nop`, removes jumps to the next instruction and `Dup Drop` pairs,
+/// folds `Push1 Add`/`Push1 Sub` into `Inc`/`Dec` and folds arithmetic on constants. Every jump,
+/// call, try and `PushA` target is relocated, and long jumps become short ones where the new
+/// offset fits. Instructions that are jump targets are never merged into the instruction
+/// before them.
+///
+/// Removing `Dup Drop` assumes the script does not rely on `Dup` faulting on an empty stack.
+/// Gas use and offsets change, so debug info for the original script no longer applies.
+pub fn optimize(script: &Script) -> Result<Script, ScriptError> {
+	let mut validated = script.clone();
+	validated.validate()?;
+
+	let mut nodes = decode(script.as_bytes());
+	while peephole(&mut nodes) {}
+	Script::new(encode(&nodes), true)
+}
+
+/// An instruction of the script being rewritten.
+struct Node {
+	/// Original offsets of jump targets that now land on this instruction.
+	labels: Vec<usize>,
+	instruction: Instruction,
+	/// The encoded instruction, used as is unless it is a branch.
+	bytes: Vec<u8>,
+	/// The original offsets a branch goes to. `None` is an absent catch or finally block.
+	targets: Vec<Option<usize>>,
+}
+
+impl Node {
+	/// A new instruction built with `emit`.
+	fn build(emit: impl FnOnce(&mut ScriptBuilder)) -> Self {
+		let mut builder = ScriptBuilder::new();
+		emit(&mut builder);
+		let bytes = builder.to_bytes().expect("no labels are used");
+		let instruction = Instruction::from_script(&bytes, 0).unwrap();
+		Self { labels: Vec::new(), instruction, bytes, targets: Vec::new() }
+	}
+
+	fn opcode(&self) -> OpCode {
+		self.instruction.opcode
+	}
+
+	/// The integer this instruction pushes, if it is an integer push.
+	fn constant(&self) -> Option<BigInt> {
+		let code = self.opcode() as u8;
+		match self.opcode() {
+			OpCode::PushInt8
+			| OpCode::PushInt16
+			| OpCode::PushInt32
+			| OpCode::PushInt64
+			| OpCode::PushInt128
+			| OpCode::PushInt256 => Some(self.instruction.token_bigint()),
+			_ if (OpCode::PushM1 as u8..=OpCode::Push16 as u8).contains(&code) =>
+				Some(BigInt::from(code as i16 - OpCode::Push0 as i16)),
+			_ => None,
+		}
+	}
+}
+
+fn decode(script: &[u8]) -> Vec<Node> {
+	let disassembler = Disassembler::new(script);
+	let mut targeted = BTreeSet::<usize>::new();
+	let mut nodes = Vec::new();
+	for decoded in disassembler.instructions() {
+		let instruction = &decoded.instruction;
+		let is_try = matches!(instruction.opcode, OpCode::Try | OpCode::TryL);
+		let targets = instruction
+			.target_offsets()
+			.into_iter()
+			.map(|offset| {
+				(!is_try || offset != 0).then(|| (decoded.offset as i64 + offset as i64) as usize)
+			})
+			.collect::<Vec<_>>();
+		targeted.extend(targets.iter().flatten());
+
+		let end = decoded.offset + instruction.size();
+		nodes.push(Node {
+			labels: vec![decoded.offset],
+			instruction: instruction.clone(),
+			bytes: script[decoded.offset..end].to_vec(),
+			targets,
+		});
+	}
+	for node in &mut nodes {
+		node.labels.retain(|offset| targeted.contains(offset));
+	}
+	nodes
+}
+
+/// Removes the node at `index`, moving its labels to the next node. Returns `false` when the
+/// labels have nowhere to go.
+fn remove(nodes: &mut Vec<Node>, index: usize) -> bool {
+	if index + 1 == nodes.len() && !nodes[index].labels.is_empty() {
+		return false
+	}
+	let node = nodes.remove(index);
+	if let Some(next) = nodes.get_mut(index) {
+		next.labels.splice(0..0, node.labels);
+	}
+	true
+}
+
+/// Applies the first rewrite that matches anywhere in `nodes`.
+fn peephole(nodes: &mut Vec<Node>) -> bool {
+	for i in 0..nodes.len() {
+		if let (Some(a), Some(b), Some(op)) =
+			(nodes[i].constant(), plain(nodes, i + 1), plain(nodes, i + 2))
+		{
+			if let Some(value) = b.constant().and_then(|b| fold_binary(op.opcode(), &a, &b)) {
+				nodes.drain(i + 1..i + 3);
+				replace_with_push(&mut nodes[i], &value);
+				return true
+			}
+		}
+
+		if let (Some(a), Some(op)) = (nodes[i].constant(), plain(nodes, i + 1)) {
+			if let Some(value) = fold_unary(op.opcode(), &a) {
+				nodes.remove(i + 1);
+				replace_with_push(&mut nodes[i], &value);
+				return true
+			}
+		}
+
+		match (nodes[i].opcode(), plain(nodes, i + 1).map(Node::opcode)) {
+			(OpCode::Push1, Some(op @ (OpCode::Add | OpCode::Sub))) => {
+				let opcode = if op == OpCode::Add { OpCode::Inc } else { OpCode::Dec };
+				let labels = std::mem::take(&mut nodes[i].labels);
+				nodes[i] = Node::build(|builder| builder.emit(opcode, &[]));
+				nodes[i].labels = labels;
+				nodes.remove(i + 1);
+				return true
+			},
+			(OpCode::Dup, Some(OpCode::Drop)) if i + 2 < nodes.len() => {
+				nodes.remove(i + 1);
+				remove(nodes, i);
+				return true
+			},
+			(OpCode::Nop, _) =>
+				if remove(nodes, i) {
+					return true
+				},
+			(OpCode::Jmp | OpCode::JmpL, _) => {
+				let target = nodes[i].targets[0];
+				let next = nodes.get(i + 1).map_or(&[][..], |node| &node.labels[..]);
+				if target.is_some_and(|target| next.contains(&target)) && remove(nodes, i) {
+					return true
+				}
+			},
+			_ => {},
+		}
+	}
+	false
+}
+
+/// The node at `index`, unless it is a jump target. Only the first instruction of a pattern may
+/// be one.
+fn plain(nodes: &[Node], index: usize) -> Option<&Node> {
+	nodes.get(index).filter(|node| node.labels.is_empty())
+}
+
+fn replace_with_push(node: &mut Node, value: &Integer) {
+	let labels = std::mem::take(&mut node.labels);
+	*node = Node::build(|builder| builder.push_bigint(value.value()));
+	node.labels = labels;
+}
+
+/// `value` if NeoVM can hold it. Folding never creates a bigger integer, so overflow still
+/// faults at run time.
+fn fits(value: BigInt) -> Option<Integer> {
+	Integer::try_from(value).ok()
+}
+
+fn fold_binary(opcode: OpCode, a: &BigInt, b: &BigInt) -> Option<Integer> {
+	match opcode {
+		OpCode::Add => fits(a + b),
+		OpCode::Sub => fits(a - b),
+		OpCode::Mul => fits(a * b),
+		OpCode::Div if !b.is_zero() => fits(a / b),
+		OpCode::Mod if !b.is_zero() => fits(a % b),
+		OpCode::Shl if b.to_u32().is_some_and(|shift| shift <= 256) =>
+			fits(a << b.to_usize().unwrap()),
+		_ => None,
+	}
+}
+
+fn fold_unary(opcode: OpCode, a: &BigInt) -> Option<Integer> {
+	match opcode {
+		OpCode::Inc => fits(a + 1),
+		OpCode::Dec => fits(a - 1),
+		OpCode::Negate => fits(-a),
+		_ => None,
+	}
+}
+
+fn encode(nodes: &[Node]) -> Vec<u8> {
+	let mut builder = ScriptBuilder::new();
+	let mut labels: HashMap<usize, Label> = HashMap::new();
+	for node in nodes {
+		for offset in node.labels.iter().chain(node.targets.iter().flatten()) {
+			labels.entry(*offset).or_insert_with(|| builder.new_label());
+		}
+	}
+
+	for node in nodes {
+		for offset in &node.labels {
+			builder.mark_label(labels[offset]).expect("every label is marked once");
+		}
+		if node.targets.is_empty() {
+			builder.emit_raw(&node.bytes);
+			continue
+		}
+		let targets = node
+			.targets
+			.iter()
+			.map(|target| match target {
+				Some(offset) => BranchTarget::Label(labels[offset]),
+				None => BranchTarget::Offset(0),
+			})
+			.collect::<Vec<_>>();
+		builder
+			.branch(node.opcode().short_form().unwrap_or(node.opcode()), &targets)
+			.expect("labels come from this builder");
+	}
+	builder.to_bytes().expect("every target is marked")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{execution_result::run_script, script::assembler::assemble};
+
+	/// Optimizes `source`, checks that it still behaves the same and returns the new bytes.
+	fn check(source: &str) -> Vec<u8> {
+		let original = Script::new(assemble(source).unwrap(), true).unwrap();
+		let optimized = optimize(&original).unwrap();
+		assert!(optimized.len() <= original.len());
+
+		let (expected, actual) =
+			(run_script(original.as_bytes(), |_| {}), run_script(optimized.as_bytes(), |_| {}));
+		assert_eq!(actual.state, expected.state);
+		assert_eq!(actual.exception, expected.exception);
+		assert_eq!(actual.stack, expected.stack);
+		optimized.as_bytes().to_vec()
+	}
+
+	#[test]
+	fn folds_constants() {
+		// PUSH6, RET
+		assert_eq!(check("NOP\nPUSH2\nPUSH3\nADD\nPUSH1\nADD\nRET"), vec![0x16, 0x40]);
+		// PUSHINT16 1000, RET
+		assert_eq!(check("PUSHINT8 100\nPUSH10\nMUL\nRET"), vec![0x01, 0xe8, 0x03, 0x40]);
+		// Division by zero is left to fault at run time.
+		assert_eq!(check("PUSH1\nPUSH0\nDIV\nRET"), vec![0x11, 0x10, 0xa1, 0x40]);
+	}
+
+	#[test]
+	fn uses_inc_and_drops_dead_instructions() {
+		// INITSLOT 1 0, PUSH0, STLOC0, LDLOC0, INC, RET
+		assert_eq!(
+			check("INITSLOT 1, 0\nPUSH0\nSTLOC0\nLDLOC0\nPUSH1\nADD\nDUP\nDROP\nRET"),
+			vec![0x57, 0x01, 0x00, 0x10, 0x70, 0x68, 0x9c, 0x40]
+		);
+		assert_eq!(check("PUSH1\nJMP_L L_next\nL_next:\nRET"), vec![0x11, 0x40]);
+	}
+
+	#[test]
+	fn relocates_targets() {
+		let source = "INITSLOT 1, 0
+			PUSH3
+			STLOC0
+			L_loop:
+			NOP
+			LDLOC0
+			PUSH1
+			SUB
+			DUP
+			STLOC0
+			JMPIF_L L_loop
+			TRY L_catch, 0
+			PUSHA L_f
+			CALLA
+			THROW
+			L_catch:
+			ENDTRY_L L_end
+			L_end:
+			LDLOC0
+			RET
+			L_f:
+			NOP
+			PUSH5
+			PUSH1
+			ADD
+			RET";
+		let optimized = check(source);
+		let listing = Disassembler::new(&optimized).disassemble();
+		assert!(listing.contains("JMPIF L_"));
+		assert!(!listing.contains("NOP"));
+		assert!(!listing.contains("_L "));
+	}
+
+	#[test]
+	fn keeps_jump_targets_intact() {
+		// The jump lands on ADD, so PUSH1 ADD must not become INC.
+		check(
+			"PUSH2
+			PUSH1
+			PUSH0
+			JMPIFNOT L_add
+			PUSH1
+			L_add:
+			ADD
+			RET",
+		);
+	}
+}
@@ -1,50 +1,512 @@
-use crate::op_code::OpCode;
-use num_bigint::BigInt;
+use crate::{
+	execution_engine_limits::ExecutionEngineLimits,
+	interop_service::syscall_hash,
+	op_code::OpCode,
+	script::{call_flags::CallFlags, contract_parameter::ContractParameter},
+	uint::UInt160,
+};
+use num_bigint::{BigInt, Sign};
+use num_traits::ToPrimitive;
 use serde::{Deserialize, Serialize};
+use std::{
+	cell::RefCell,
+	error::Error,
+	fmt::{Display, Formatter},
+	hash::{Hash, Hasher},
+	rc::Rc,
+	sync::atomic::{AtomicUsize, Ordering},
+};
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash,Serialize,Deserialize)]
+/// Gives every builder a distinct id, so labels can be traced back to the builder that made them.
+static NEXT_BUILDER_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A position in the script that branches can target before it is marked. Labels only belong
+/// to the builder that created them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Label {
+	builder: usize,
+	index: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptBuilderError {
+	/// The label was created by another builder.
+	ForeignLabel(Label),
+	/// The label was already marked.
+	LabelMarkedTwice(Label),
+	/// A branch targets a label that was never marked.
+	UnmarkedLabel(Label),
+	/// The opcode is not a jump, call, `PushA`, `Try` or `EndTry`, or takes a different number
+	/// of targets.
+	InvalidBranch(OpCode),
+}
+
+impl Display for ScriptBuilderError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ScriptBuilderError::ForeignLabel(label) =>
+				write!(f, "Label {} belongs to another builder", label.index),
+			ScriptBuilderError::LabelMarkedTwice(label) =>
+				write!(f, "Label {} is marked twice", label.index),
+			ScriptBuilderError::UnmarkedLabel(label) =>
+				write!(f, "Label {} is never marked", label.index),
+			ScriptBuilderError::InvalidBranch(opcode) =>
+				write!(f, "{opcode:?} is not a branch with these targets"),
+		}
+	}
+}
+
+impl Error for ScriptBuilderError {}
+
+/// Where a branch emitted with [`ScriptBuilder::branch`] points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BranchTarget {
+	Label(Label),
+	/// A raw offset relative to the branch instruction; `Offset(0)` marks an absent `Try` target.
+	Offset(i32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+enum Segment {
+	Code(Vec<u8>),
+	/// A branch whose offsets are resolved in `to_bytes`. Short opcodes are widened to their
+	/// long form when a target does not fit in one byte; long opcodes stay long.
+	Branch {
+		opcode: OpCode,
+		targets: Vec<BranchTarget>,
+	},
+}
+
+/// Which branches use their long form, and the offset of every segment plus the end offset.
+type Layout = Rc<(Vec<bool>, Vec<usize>)>;
+
+/// The layout of the current segments, kept until the next segment or label mark. It is
+/// derived state, so builders compare and hash as if it were absent.
+#[derive(Debug, Clone, Default)]
+struct LayoutCache(RefCell<Option<Layout>>);
+
+impl PartialEq for LayoutCache {
+	fn eq(&self, _: &Self) -> bool {
+		true
+	}
+}
+
+impl Eq for LayoutCache {}
+
+impl Hash for LayoutCache {
+	fn hash<H: Hasher>(&self, _: &mut H) {}
+}
+
+/// Identifies the builder that labels belong to. Clones and deserialized builders get a fresh
+/// id, so they never accept each other's labels. Like the layout cache, it is ignored when
+/// builders are compared or hashed.
+#[derive(Debug)]
+struct BuilderId(usize);
+
+impl Default for BuilderId {
+	fn default() -> Self {
+		Self(NEXT_BUILDER_ID.fetch_add(1, Ordering::Relaxed))
+	}
+}
+
+impl Clone for BuilderId {
+	fn clone(&self) -> Self {
+		Self::default()
+	}
+}
+
+impl PartialEq for BuilderId {
+	fn eq(&self, _: &Self) -> bool {
+		true
+	}
+}
+
+impl Eq for BuilderId {}
+
+impl Hash for BuilderId {
+	fn hash<H: Hasher>(&self, _: &mut H) {}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ScriptBuilder {
+	#[serde(skip)]
+	id: BuilderId,
 	output: Vec<u8>,
+	segments: Vec<Segment>,
+	/// The segment each label was marked before.
+	labels: Vec<Option<usize>>,
+	/// How many `try_*` blocks enclose the code being emitted.
+	try_depth: usize,
+	#[serde(skip)]
+	layout: LayoutCache,
+}
+
+impl Default for ScriptBuilder {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 impl ScriptBuilder {
 	pub fn new() -> Self {
-		Self { output: Vec::new() }
+		Self {
+			id: BuilderId::default(),
+			output: Vec::new(),
+			segments: Vec::new(),
+			labels: Vec::new(),
+			try_depth: 0,
+			layout: LayoutCache::default(),
+		}
 	}
 
+	/// The length of the script emitted so far. Branches to labels that are not marked yet are
+	/// counted at their short size.
 	pub fn len(&self) -> usize {
-		self.output.len()
+		let layout = self.layout();
+		layout.1.last().copied().unwrap_or(0) + self.output.len()
 	}
 
-	pub fn push_int(&mut self, value: i64) {
-		if value >= 0 && value <= 16 {
-			let opcode = OpCode::Push0 + (value as u8);
-			self.emit(opcode, Vec::new());
-			return
+	pub fn is_empty(&self) -> bool {
+		self.segments.is_empty() && self.output.is_empty()
+	}
+
+	/// Creates a label to be placed later with [`mark_label`](Self::mark_label).
+	pub fn new_label(&mut self) -> Label {
+		self.labels.push(None);
+		Label { builder: self.id.0, index: self.labels.len() - 1 }
+	}
+
+	/// Places `label` at the current end of the script.
+	pub fn mark_label(&mut self, label: Label) -> Result<(), ScriptBuilderError> {
+		self.check_label(label)?;
+		if self.labels[label.index].is_some() {
+			return Err(ScriptBuilderError::LabelMarkedTwice(label))
 		}
+		self.place(label);
+		Ok(())
+	}
+
+	/// Emits a conditional or unconditional jump to `label`.
+	pub fn jump_to(&mut self, opcode: OpCode, label: Label) -> Result<(), ScriptBuilderError> {
+		let short = opcode.short_form().unwrap_or(opcode);
+		if !(OpCode::Jmp as u8..=OpCode::JmpLe as u8).contains(&(short as u8)) {
+			return Err(ScriptBuilderError::InvalidBranch(opcode))
+		}
+		self.branch(opcode, &[BranchTarget::Label(label)])
+	}
+
+	/// Emits a `Call` to `label`.
+	pub fn call_to(&mut self, label: Label) -> Result<(), ScriptBuilderError> {
+		self.branch(OpCode::Call, &[BranchTarget::Label(label)])
+	}
+
+	/// Emits a `PushA` pushing a pointer to `label`.
+	pub fn push_address(&mut self, label: Label) -> Result<(), ScriptBuilderError> {
+		self.branch(OpCode::PushA, &[BranchTarget::Label(label)])
+	}
+
+	/// Emits a `Try` with optional catch and finally blocks.
+	pub fn try_to(
+		&mut self,
+		catch: Option<Label>,
+		finally: Option<Label>,
+	) -> Result<(), ScriptBuilderError> {
+		let target =
+			|label: Option<Label>| label.map_or(BranchTarget::Offset(0), BranchTarget::Label);
+		self.branch(OpCode::Try, &[target(catch), target(finally)])
+	}
+
+	/// Emits an `EndTry` continuing at `label`.
+	pub fn end_try_to(&mut self, label: Label) -> Result<(), ScriptBuilderError> {
+		self.branch(OpCode::EndTry, &[BranchTarget::Label(label)])
+	}
+
+	fn check_label(&self, label: Label) -> Result<(), ScriptBuilderError> {
+		if label.builder != self.id.0 || label.index >= self.labels.len() {
+			return Err(ScriptBuilderError::ForeignLabel(label))
+		}
+		Ok(())
+	}
+
+	/// Emits `try_body` guarded by a `Try`, followed by the `catch` and `finally` blocks. The
+	/// exception is on the stack when `catch` starts. All offsets are resolved on
+	/// [`to_bytes`](Self::to_bytes), using the short forms where they fit.
+	///
+	/// # Panics
+	///
+	/// Panics if the blocks nest deeper than the default `max_try_nesting_depth`.
+	pub fn try_catch_finally(
+		&mut self,
+		try_body: impl FnOnce(&mut Self),
+		catch: impl FnOnce(&mut Self),
+		finally: impl FnOnce(&mut Self),
+	) {
+		self.emit_try(try_body, Some(catch), Some(finally));
+	}
+
+	/// Like [`try_catch_finally`](Self::try_catch_finally) without a finally block.
+	pub fn try_catch(&mut self, try_body: impl FnOnce(&mut Self), catch: impl FnOnce(&mut Self)) {
+		self.emit_try(try_body, Some(catch), None::<fn(&mut Self)>);
+	}
+
+	/// Like [`try_catch_finally`](Self::try_catch_finally) without a catch block.
+	pub fn try_finally(
+		&mut self,
+		try_body: impl FnOnce(&mut Self),
+		finally: impl FnOnce(&mut Self),
+	) {
+		self.emit_try(try_body, None::<fn(&mut Self)>, Some(finally));
+	}
+
+	fn emit_try<T, C, F>(&mut self, try_body: T, catch: Option<C>, finally: Option<F>)
+	where
+		T: FnOnce(&mut Self),
+		C: FnOnce(&mut Self),
+		F: FnOnce(&mut Self),
+	{
+		let max_depth = ExecutionEngineLimits::default().max_try_nesting_depth;
+		assert!(self.try_depth < max_depth, "try blocks nest deeper than {max_depth}");
+
+		let catch_label = catch.as_ref().map(|_| self.new_label());
+		let finally_label = finally.as_ref().map(|_| self.new_label());
+		let end = self.new_label();
 
-		let is_negative = value < 0;
-		let mut abs_value = if is_negative { -value } else { value };
+		// The labels are this builder's own and each is marked once, so nothing here can fail.
+		let target =
+			|label: Option<Label>| label.map_or(BranchTarget::Offset(0), BranchTarget::Label);
+		let end_try = [BranchTarget::Label(end)];
 
-		// let mut buffer = [0u8; 32];
-		let bytes_written = abs_value.to_le_bytes();
-		// buffer[..bytes_written.len()].copy_from_slice(&bytes_written);
+		// The try context stays on the try stack while its catch and finally blocks run.
+		self.try_depth += 1;
+		self.push_branch(OpCode::Try, &[target(catch_label), target(finally_label)]);
+		try_body(self);
+		self.push_branch(OpCode::EndTry, &end_try);
+		if let (Some(label), Some(catch)) = (catch_label, catch) {
+			self.place(label);
+			catch(self);
+			self.push_branch(OpCode::EndTry, &end_try);
+		}
+		if let (Some(label), Some(finally)) = (finally_label, finally) {
+			self.place(label);
+			finally(self);
+			self.raw(OpCode::EndFinally, Vec::new());
+		}
+		self.try_depth -= 1;
+		self.place(end);
+	}
+
+	/// Emits a branch instruction whose targets are resolved in [`to_bytes`](Self::to_bytes).
+	///
+	/// Short opcodes get the shortest encoding that fits; long opcodes always use four-byte
+	/// offsets. `Try` and `TryL` take a catch and a finally target, the others one target.
+	pub fn branch(
+		&mut self,
+		opcode: OpCode,
+		targets: &[BranchTarget],
+	) -> Result<(), ScriptBuilderError> {
+		let is_branch = has_long_offsets(opcode) || opcode.long_form().is_some();
+		let expected = if matches!(opcode, OpCode::Try | OpCode::TryL) { 2 } else { 1 };
+		if !is_branch || targets.len() != expected {
+			return Err(ScriptBuilderError::InvalidBranch(opcode))
+		}
+		for target in targets {
+			if let BranchTarget::Label(label) = target {
+				self.check_label(*label)?;
+			}
+		}
+		self.push_branch(opcode, targets);
+		Ok(())
+	}
+
+	fn push_branch(&mut self, opcode: OpCode, targets: &[BranchTarget]) {
+		self.flush();
+		self.segments.push(Segment::Branch { opcode, targets: targets.to_vec() });
+		self.invalidate_layout();
+	}
+
+	fn place(&mut self, label: Label) {
+		self.flush();
+		self.labels[label.index] = Some(self.segments.len());
+		self.invalidate_layout();
+	}
+
+	fn flush(&mut self) {
+		if !self.output.is_empty() {
+			self.segments.push(Segment::Code(std::mem::take(&mut self.output)));
+			self.invalidate_layout();
+		}
+	}
+
+	fn invalidate_layout(&mut self) {
+		*self.layout.0.get_mut() = None;
+	}
+
+	/// The layout of the current segments, computed on first use after a change.
+	fn layout(&self) -> Layout {
+		if let Some(layout) = &*self.layout.0.borrow() {
+			return layout.clone()
+		}
+		let layout = Rc::new(self.compute_layout());
+		*self.layout.0.borrow_mut() = Some(layout.clone());
+		layout
+	}
+
+	/// Chooses which branches need their long form and returns it with the offset of every
+	/// segment, plus the end offset.
+	fn compute_layout(&self) -> (Vec<bool>, Vec<usize>) {
+		let mut long: Vec<bool> = self
+			.segments
+			.iter()
+			.map(|segment| match segment {
+				Segment::Branch { opcode, .. } => has_long_offsets(*opcode),
+				Segment::Code(_) => false,
+			})
+			.collect();
+		loop {
+			let offsets = self.offsets(&long);
+			let mut changed = false;
+			for (index, segment) in self.segments.iter().enumerate() {
+				let Segment::Branch { targets, .. } = segment else { continue };
+				if long[index] {
+					continue
+				}
+				let fits = targets.iter().all(|target| {
+					self.resolve(target, offsets[index], &offsets)
+						.map_or(true, |offset| i8::try_from(offset).is_ok())
+				});
+				if !fits {
+					long[index] = true;
+					changed = true;
+				}
+			}
+			if !changed {
+				return (long, offsets)
+			}
+		}
+	}
 
-		let written_len = bytes_written.len();
-		let (opcode, pad_len) = match bytes_written.len() {
+	fn offsets(&self, long: &[bool]) -> Vec<usize> {
+		let mut offsets = Vec::with_capacity(self.segments.len() + 1);
+		let mut offset = 0;
+		for (index, segment) in self.segments.iter().enumerate() {
+			offsets.push(offset);
+			offset += match segment {
+				Segment::Code(bytes) => bytes.len(),
+				Segment::Branch { targets, .. } =>
+					1 + targets.len() * if long[index] { 4 } else { 1 },
+			};
+		}
+		offsets.push(offset);
+		offsets
+	}
+
+	/// The offset of `target` relative to `from`, or the label if it is not marked.
+	fn resolve(&self, target: &BranchTarget, from: usize, offsets: &[usize]) -> Result<i32, Label> {
+		match target {
+			BranchTarget::Offset(offset) => Ok(*offset),
+			BranchTarget::Label(label) => self.labels[label.index]
+				.map(|segment| (offsets[segment] as i64 - from as i64) as i32)
+				.ok_or(*label),
+		}
+	}
+
+	pub fn push_int(&mut self, value: i64) {
+		self.push_bigint(&BigInt::from(value));
+	}
+
+	/// Pushes `value` with `PushM1`/`Push0`..`Push16` or the smallest `PushInt*` that holds it.
+	///
+	/// # Panics
+	///
+	/// Panics if `value` does not fit in 256 bits.
+	pub fn push_bigint(&mut self, value: &BigInt) {
+		if let Some(small) = value.to_i8().filter(|small| (-1..=16).contains(small)) {
+			self.output.push((OpCode::Push0 as i8 + small) as u8);
+			return
+		}
+
+		let mut bytes = value.to_signed_bytes_le();
+		let (opcode, width) = match bytes.len() {
 			1 => (OpCode::PushInt8, 1),
 			2 => (OpCode::PushInt16, 2),
-			bytes if bytes <= 4 => (OpCode::PushInt32, 4),
-			bytes if bytes <= 8 => (OpCode::PushInt64, 8),
-			bytes if bytes <= 16 => (OpCode::PushInt128, 16),
-			_ => (OpCode::PushInt256, 32),
+			len if len <= 4 => (OpCode::PushInt32, 4),
+			len if len <= 8 => (OpCode::PushInt64, 8),
+			len if len <= 16 => (OpCode::PushInt128, 16),
+			len if len <= 32 => (OpCode::PushInt256, 32),
+			_ => panic!("Integer does not fit in 256 bits"),
 		};
 
-		let sign_byte = if is_negative { 0xFF } else { 0x00 };
-		let mut padded = vec![sign_byte; pad_len];
-		padded[pad_len - written_len..].copy_from_slice(&bytes_written);
+		let sign_byte = if value.sign() == Sign::Minus { 0xFF } else { 0x00 };
+		bytes.resize(width, sign_byte);
+		self.raw(opcode, bytes);
+	}
 
-		self.raw(opcode, Vec::from(padded));
+	/// Pushes `parameter`, packing arrays with `Pack`, structs with `PackStruct` and maps with
+	/// `PackMap`. Empty composites use `NewArray0`, `NewStruct0` and `NewMap`.
+	pub fn push_parameter(&mut self, parameter: &ContractParameter) {
+		match parameter {
+			ContractParameter::Any => self.push_null(),
+			ContractParameter::Boolean(value) => self.push_bool(*value),
+			ContractParameter::Integer(value) => self.push_bigint(value),
+			ContractParameter::ByteArray(value) => self.push_bytes(value.clone()),
+			ContractParameter::String(value) => self.push_string(value),
+			ContractParameter::Hash160(value) => self.push_bytes(value.as_bytes().to_vec()),
+			ContractParameter::Array(items) => self.push_array(items),
+			ContractParameter::Struct(fields) => self.push_struct(fields),
+			ContractParameter::Map(entries) => {
+				if entries.is_empty() {
+					self.raw(OpCode::NewMap, Vec::new());
+					return
+				}
+				for (key, value) in entries.iter().rev() {
+					self.push_parameter(value);
+					self.push_parameter(key);
+				}
+				self.push_int(entries.len() as i64);
+				self.raw(OpCode::PackMap, Vec::new());
+			},
+		}
+	}
+
+	/// Pushes `items` as an array, emitting the last item first so `Pack` restores their order.
+	pub fn push_array(&mut self, items: &[ContractParameter]) {
+		if items.is_empty() {
+			self.raw(OpCode::NewArray0, Vec::new());
+			return
+		}
+		for item in items.iter().rev() {
+			self.push_parameter(item);
+		}
+		self.push_int(items.len() as i64);
+		self.raw(OpCode::Pack, Vec::new());
+	}
+
+	/// Pushes `fields` as a struct, in the same order as [`push_array`](Self::push_array).
+	pub fn push_struct(&mut self, fields: &[ContractParameter]) {
+		if fields.is_empty() {
+			self.raw(OpCode::NewStruct0, Vec::new());
+			return
+		}
+		for field in fields.iter().rev() {
+			self.push_parameter(field);
+		}
+		self.push_int(fields.len() as i64);
+		self.raw(OpCode::PackStruct, Vec::new());
+	}
+
+	/// Emits a `System.Contract.Call` of `method` on the contract with script hash `hash`.
+	pub fn emit_dynamic_call(
+		&mut self,
+		hash: &UInt160,
+		method: &str,
+		flags: CallFlags,
+		args: &[ContractParameter],
+	) {
+		self.push_array(args);
+		self.push_int(flags.0 as i64);
+		self.push_string(method);
+		self.push_bytes(hash.as_bytes().to_vec());
+		self.push_syscall(syscall_hash("System.Contract.Call"));
 	}
 
 	pub fn push_bool(&mut self, value: bool) {
@@ -115,6 +577,17 @@ impl ScriptBuilder {
 		self.output.extend_from_slice(&operand);
 	}
 
+	/// Emits `opcode` followed by its already-encoded operand.
+	pub fn emit(&mut self, opcode: OpCode, operand: &[u8]) {
+		self.output.push(opcode as u8);
+		self.output.extend_from_slice(operand);
+	}
+
+	/// Appends bytes verbatim.
+	pub fn emit_raw(&mut self, bytes: &[u8]) {
+		self.output.extend_from_slice(bytes);
+	}
+
 	pub fn push_null(&mut self) {
 		let opcode = OpCode::PushNull;
 		self.raw(opcode, Vec::new());
@@ -130,7 +603,7 @@ impl ScriptBuilder {
 	}
 
 	pub fn push_data_byte(&mut self, byte: u8) {
-		let mut bytes = Vec::from([byte]);
+		let bytes = Vec::from([byte]);
 		self.push_bytes(bytes);
 	}
 
@@ -153,34 +626,385 @@ impl ScriptBuilder {
 	//     }
 	// }
 
+	/// Pushes `data` with the smallest `PushData` prefix that fits it.
 	pub fn push_bytes_var(&mut self, data: Vec<u8>) {
-		match data.len() {
-			0...75 => self.push_bytes75(data),
-			76...0xff => {
-				let opcode = OpCode::PushData1;
-				let len = data.len() as u8;
-				let mut operand = vec![len];
-				operand.extend_from_slice(&data);
-				self.raw(opcode, operand);
-			},
-			0x100...0xffff => {
-				let opcode = OpCode::PushData2;
-				let len = data.len() as u16;
-				let mut operand = len.to_le_bytes().to_vec();
-				operand.extend_from_slice(&data);
-				self.raw(opcode, operand);
-			},
-			_ => {
-				let opcode = OpCode::PushData4;
-				let len = data.len() as u32;
-				let mut operand = len.to_le_bytes().to_vec();
-				operand.extend_from_slice(&data);
-				self.raw(opcode, operand);
-			},
+		self.push_bytes(data);
+	}
+
+	/// Resolves all labels and returns the script, or an error if a branch targets a label that
+	/// was never marked.
+	pub fn to_bytes(mut self) -> Result<Vec<u8>, ScriptBuilderError> {
+		self.flush();
+		let layout = self.layout();
+		let (long, offsets) = &*layout;
+		let mut script = Vec::with_capacity(offsets.last().copied().unwrap_or(0));
+		for (index, segment) in self.segments.iter().enumerate() {
+			match segment {
+				Segment::Code(bytes) => script.extend_from_slice(bytes),
+				Segment::Branch { opcode, targets } => {
+					let opcode =
+						if long[index] { opcode.long_form().unwrap_or(*opcode) } else { *opcode };
+					script.push(opcode as u8);
+					for target in targets {
+						let offset = self
+							.resolve(target, offsets[index], offsets)
+							.map_err(ScriptBuilderError::UnmarkedLabel)?;
+						if long[index] {
+							script.extend_from_slice(&offset.to_le_bytes());
+						} else {
+							script.push(offset as i8 as u8);
+						}
+					}
+				},
+			}
 		}
+		Ok(script)
 	}
+}
+
+fn has_long_offsets(opcode: OpCode) -> bool {
+	opcode == OpCode::PushA || opcode.short_form().is_some()
+}
 
-	pub fn to_bytes(self) -> Vec<u8> {
-		self.output
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::instruction::Instruction;
+
+	fn round_trip(value: &BigInt) -> (OpCode, BigInt) {
+		let mut builder = ScriptBuilder::new();
+		builder.push_bigint(value);
+		let script = builder.to_bytes().unwrap();
+		let instruction = Instruction::from_script(&script, 0).unwrap();
+		assert_eq!(instruction.size(), script.len());
+		let decoded = match instruction.opcode {
+			OpCode::PushM1 => BigInt::from(-1),
+			opcode if (OpCode::Push0 as u8..=OpCode::Push16 as u8).contains(&(opcode as u8)) =>
+				BigInt::from(opcode as u8 - OpCode::Push0 as u8),
+			_ => instruction.token_bigint(),
+		};
+		(instruction.opcode, decoded)
+	}
+
+	#[test]
+	fn push_bigint_uses_minimal_encoding_at_every_boundary() {
+		let pow = |bits: u32| BigInt::from(1) << bits;
+		let cases = [
+			(BigInt::from(-1), OpCode::PushM1),
+			(BigInt::from(0), OpCode::Push0),
+			(BigInt::from(16), OpCode::Push16),
+			(BigInt::from(17), OpCode::PushInt8),
+			(BigInt::from(-2), OpCode::PushInt8),
+			(BigInt::from(i8::MAX), OpCode::PushInt8),
+			(BigInt::from(i8::MIN), OpCode::PushInt8),
+			(BigInt::from(i8::MAX) + 1, OpCode::PushInt16),
+			(BigInt::from(i8::MIN) - 1, OpCode::PushInt16),
+			(BigInt::from(i16::MAX), OpCode::PushInt16),
+			(BigInt::from(i16::MIN), OpCode::PushInt16),
+			(BigInt::from(i16::MAX) + 1, OpCode::PushInt32),
+			(BigInt::from(i16::MIN) - 1, OpCode::PushInt32),
+			(BigInt::from(i32::MAX), OpCode::PushInt32),
+			(BigInt::from(i32::MIN), OpCode::PushInt32),
+			(BigInt::from(i32::MAX) + 1, OpCode::PushInt64),
+			(BigInt::from(i32::MIN) - 1, OpCode::PushInt64),
+			(BigInt::from(i64::MAX), OpCode::PushInt64),
+			(BigInt::from(i64::MIN), OpCode::PushInt64),
+			(BigInt::from(i64::MAX) + 1, OpCode::PushInt128),
+			(BigInt::from(i64::MIN) - 1, OpCode::PushInt128),
+			(BigInt::from(i128::MAX), OpCode::PushInt128),
+			(BigInt::from(i128::MIN), OpCode::PushInt128),
+			(BigInt::from(i128::MAX) + 1, OpCode::PushInt256),
+			(BigInt::from(i128::MIN) - 1, OpCode::PushInt256),
+			(pow(255) - 1, OpCode::PushInt256),
+			(-pow(255), OpCode::PushInt256),
+		];
+		for (value, opcode) in cases {
+			assert_eq!(round_trip(&value), (opcode, value.clone()), "{value}");
+		}
+	}
+
+	#[test]
+	fn emit_dynamic_call_packs_arguments_in_order() {
+		let args = [
+			ContractParameter::from(1i64),
+			ContractParameter::Array(vec![]),
+			ContractParameter::Map(vec![("k".into(), true.into())]),
+		];
+		let mut builder = ScriptBuilder::new();
+		builder.emit_dynamic_call(&UInt160::new([0xab; 20]), "transfer", CallFlags::ALL, &args);
+
+		let mut expected = vec![
+			OpCode::PushTrue as u8, // map value, then key
+			OpCode::PushData1 as u8,
+			1,
+			b'k',
+			OpCode::Push1 as u8,
+			OpCode::PackMap as u8,
+			OpCode::NewArray0 as u8,
+			OpCode::Push1 as u8,
+			OpCode::Push3 as u8,
+			OpCode::Pack as u8,
+			OpCode::Push15 as u8,
+			OpCode::PushData1 as u8,
+			8,
+		];
+		expected.extend_from_slice(b"transfer");
+		expected.extend_from_slice(&[OpCode::PushData1 as u8, 20]);
+		expected.extend_from_slice(&[0xab; 20]);
+		expected.push(OpCode::Syscall as u8);
+		expected.extend_from_slice(&syscall_hash("System.Contract.Call").to_le_bytes());
+		assert_eq!(builder.to_bytes().unwrap(), expected);
+	}
+
+	#[test]
+	fn push_parameter_rebuilds_nested_composites() {
+		let value = ContractParameter::Array(vec![
+			ContractParameter::Struct(vec![ContractParameter::Any, false.into()]),
+			ContractParameter::Map(vec![]),
+			ContractParameter::Struct(vec![]),
+			ContractParameter::from(vec![0x01u8, 0x02]),
+		]);
+		let mut builder = ScriptBuilder::new();
+		builder.push_parameter(&value);
+		assert_eq!(
+			builder.to_bytes().unwrap(),
+			vec![
+				OpCode::PushData1 as u8,
+				2,
+				0x01,
+				0x02,
+				OpCode::NewStruct0 as u8,
+				OpCode::NewMap as u8,
+				OpCode::PushFalse as u8,
+				OpCode::PushNull as u8,
+				OpCode::Push2 as u8,
+				OpCode::PackStruct as u8,
+				OpCode::Push4 as u8,
+				OpCode::Pack as u8,
+			]
+		);
+	}
+
+	#[test]
+	fn try_catch_finally_resolves_all_offsets() {
+		let mut builder = ScriptBuilder::new();
+		builder.try_catch_finally(
+			|b| b.push_int(1),
+			|b| b.emit(OpCode::Drop, &[]),
+			|b| b.push_int(2),
+		);
+		assert_eq!(
+			builder.to_bytes().unwrap(),
+			vec![
+				OpCode::Try as u8,
+				6,
+				9,
+				OpCode::Push1 as u8,
+				OpCode::EndTry as u8,
+				7,
+				OpCode::Drop as u8,
+				OpCode::EndTry as u8,
+				4,
+				OpCode::Push2 as u8,
+				OpCode::EndFinally as u8,
+			]
+		);
+	}
+
+	fn nest(builder: &mut ScriptBuilder, depth: usize) {
+		if depth == 0 {
+			builder.emit_raw(&[OpCode::Nop as u8; 100]);
+			return
+		}
+		builder.try_catch(|b| nest(b, depth - 1), |b| b.emit(OpCode::Drop, &[]));
+	}
+
+	#[test]
+	fn nested_try_blocks_widen_to_long_form() {
+		let depth = ExecutionEngineLimits::default().max_try_nesting_depth;
+		let mut builder = ScriptBuilder::new();
+		nest(&mut builder, depth);
+		let script = builder.to_bytes().unwrap();
+		assert_eq!(script[0], OpCode::TryL as u8);
+		assert_eq!(script[script.len() - 3], OpCode::Drop as u8);
+	}
+
+	#[test]
+	#[should_panic]
+	fn try_blocks_beyond_nesting_limit_panic() {
+		let depth = ExecutionEngineLimits::default().max_try_nesting_depth;
+		nest(&mut ScriptBuilder::new(), depth + 1);
+	}
+
+	#[test]
+	#[should_panic]
+	fn push_bigint_rejects_values_beyond_256_bits() {
+		ScriptBuilder::new().push_bigint(&(BigInt::from(1) << 255));
+	}
+
+	#[test]
+	fn labels_resolve_to_shortest_encoding() {
+		let mut builder = ScriptBuilder::new();
+		let end = builder.new_label();
+		let start = builder.new_label();
+		builder.mark_label(start).unwrap();
+		builder.jump_to(OpCode::JmpIf, end).unwrap();
+		builder.try_to(Some(end), None).unwrap();
+		builder.push_address(start).unwrap();
+		builder.mark_label(end).unwrap();
+		builder.push_null();
+		assert_eq!(
+			builder.to_bytes().unwrap(),
+			vec![
+				OpCode::JmpIf as u8,
+				10,
+				OpCode::Try as u8,
+				8,
+				0,
+				OpCode::PushA as u8,
+				0xfb,
+				0xff,
+				0xff,
+				0xff,
+				OpCode::PushNull as u8,
+			]
+		);
+	}
+
+	#[test]
+	fn branches_widen_when_target_is_far() {
+		let mut builder = ScriptBuilder::new();
+		let end = builder.new_label();
+		builder.call_to(end).unwrap();
+		builder.emit_raw(&[0; 200]);
+		builder.mark_label(end).unwrap();
+		builder.emit(OpCode::Ret, &[]);
+		let script = builder.to_bytes().unwrap();
+		assert_eq!(script[0], OpCode::CallL as u8);
+		assert_eq!(i32::from_le_bytes(script[1..5].try_into().unwrap()), 205);
+	}
+
+	#[test]
+	fn rejects_labels_of_other_builders() {
+		let mut other = ScriptBuilder::new();
+		let foreign = other.new_label();
+		let mut builder = ScriptBuilder::new();
+		let label = builder.new_label();
+
+		assert_eq!(builder.mark_label(foreign), Err(ScriptBuilderError::ForeignLabel(foreign)));
+		assert_eq!(builder.call_to(foreign), Err(ScriptBuilderError::ForeignLabel(foreign)));
+		assert_eq!(
+			builder.try_to(Some(label), Some(foreign)),
+			Err(ScriptBuilderError::ForeignLabel(foreign))
+		);
+		assert!(builder.is_empty());
+
+		builder.mark_label(label).unwrap();
+		assert_eq!(builder.mark_label(label), Err(ScriptBuilderError::LabelMarkedTwice(label)));
+	}
+
+	#[test]
+	fn copies_do_not_accept_labels_of_the_original() {
+		let mut builder = ScriptBuilder::new();
+		let label = builder.new_label();
+		builder.jump_to(OpCode::Jmp, label).unwrap();
+
+		let mut clone = builder.clone();
+		assert_eq!(clone, builder);
+		assert_eq!(clone.mark_label(label), Err(ScriptBuilderError::ForeignLabel(label)));
+
+		let json = serde_json::to_string(&builder).unwrap();
+		let mut copy: ScriptBuilder = serde_json::from_str(&json).unwrap();
+		assert_eq!(copy, builder);
+		assert_eq!(copy.mark_label(label), Err(ScriptBuilderError::ForeignLabel(label)));
+	}
+
+	#[test]
+	fn to_bytes_rejects_unmarked_labels() {
+		let mut builder = ScriptBuilder::new();
+		let label = builder.new_label();
+		builder.jump_to(OpCode::JmpIf, label).unwrap();
+		assert_eq!(builder.to_bytes(), Err(ScriptBuilderError::UnmarkedLabel(label)));
+	}
+
+	#[test]
+	fn rejects_invalid_branches() {
+		let mut builder = ScriptBuilder::new();
+		let label = builder.new_label();
+		let target = [BranchTarget::Label(label)];
+		assert_eq!(
+			builder.branch(OpCode::Add, &target),
+			Err(ScriptBuilderError::InvalidBranch(OpCode::Add))
+		);
+		assert_eq!(
+			builder.branch(OpCode::Try, &target),
+			Err(ScriptBuilderError::InvalidBranch(OpCode::Try))
+		);
+		assert_eq!(
+			builder.jump_to(OpCode::Call, label),
+			Err(ScriptBuilderError::InvalidBranch(OpCode::Call))
+		);
+		assert!(builder.is_empty());
+
+		for opcode in [OpCode::JmpEqL, OpCode::CallL, OpCode::PushA, OpCode::EndTry] {
+			builder.branch(opcode, &target).unwrap();
+		}
+		builder
+			.branch(OpCode::TryL, &[BranchTarget::Label(label), BranchTarget::Offset(0)])
+			.unwrap();
+		builder.mark_label(label).unwrap();
+		assert!(builder.to_bytes().is_ok());
+	}
+
+	#[test]
+	fn len_tracks_widened_branches() {
+		let mut builder = ScriptBuilder::new();
+		let end = builder.new_label();
+		builder.jump_to(OpCode::Jmp, end).unwrap();
+		assert_eq!(builder.len(), 2);
+		builder.emit_raw(&[0; 200]);
+		assert_eq!(builder.len(), 202);
+		builder.mark_label(end).unwrap();
+		assert_eq!(builder.len(), 205);
+		builder.emit(OpCode::Ret, &[]);
+		assert_eq!(builder.len(), 206);
+		assert_eq!(builder.to_bytes().unwrap().len(), 206);
+	}
+
+	#[test]
+	fn push_parameter_emits_every_variant() {
+		use ContractParameter as P;
+		use OpCode::*;
+
+		let hash = UInt160::new(std::array::from_fn(|i| i as u8));
+		let mut hash_bytes = vec![PushData1 as u8, 20];
+		hash_bytes.extend(0..20);
+		let cases = [
+			(P::Any, vec![PushNull as u8]),
+			(P::from(true), vec![PushTrue as u8]),
+			(P::from(false), vec![PushFalse as u8]),
+			(P::from(-1i64), vec![PushM1 as u8]),
+			(P::from(1000i64), vec![PushInt16 as u8, 0xE8, 0x03]),
+			(P::from(vec![1u8, 2]), vec![PushData1 as u8, 2, 1, 2]),
+			(P::from("hi"), vec![PushData1 as u8, 2, b'h', b'i']),
+			(P::from(hash), hash_bytes),
+			(P::Array(vec![]), vec![NewArray0 as u8]),
+			(
+				P::Array(vec![1i64.into(), true.into()]),
+				vec![PushTrue as u8, Push1 as u8, Push2 as u8, Pack as u8],
+			),
+			(P::Struct(vec![]), vec![NewStruct0 as u8]),
+			(P::Struct(vec![P::Any]), vec![PushNull as u8, Push1 as u8, PackStruct as u8]),
+			(P::Map(vec![]), vec![NewMap as u8]),
+			(
+				P::Map(vec![(1i64.into(), "a".into())]),
+				vec![PushData1 as u8, 1, b'a', Push1 as u8, Push1 as u8, PackMap as u8],
+			),
+		];
+		for (parameter, expected) in cases {
+			let mut builder = ScriptBuilder::new();
+			builder.push_parameter(&parameter);
+			assert_eq!(builder.to_bytes().unwrap(), expected, "{parameter:?}");
+		}
 	}
 }
@@ -0,0 +1,756 @@
+use crate::{
+	instruction::{Instruction, SlotAccess, SlotKind},
+	op_code::OpCode,
+	script::control_flow::{ControlFlowGraph, EdgeKind},
+	stack_item_type::StackItemType,
+	vm::script::{Script, ScriptError},
+};
+use std::{
+	collections::BTreeMap,
+	fmt::{Debug, Display, Formatter},
+	ops::{BitAnd, BitOr},
+};
+
+const TYPES: [StackItemType; 10] = [
+	StackItemType::Any,
+	StackItemType::Pointer,
+	StackItemType::Boolean,
+	StackItemType::Integer,
+	StackItemType::ByteString,
+	StackItemType::Buffer,
+	StackItemType::Array,
+	StackItemType::Struct,
+	StackItemType::Map,
+	StackItemType::InteropInterface,
+];
+
+/// A set of stack item types. `Any` stands for `Null`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct TypeSet(u16);
+
+impl TypeSet {
+	pub const EMPTY: Self = Self(0);
+	pub const ALL: Self = Self((1 << TYPES.len()) - 1);
+
+	/// Types that convert to an integer: `Boolean`, `Integer`, `ByteString` and `Buffer`.
+	pub const INTEGER: Self = Self(0b111100);
+	/// The primitive types, the only ones usable as keys: `Boolean`, `Integer` and `ByteString`.
+	pub const PRIMITIVE: Self = Self(0b11100);
+	/// Types with a byte representation: the primitive types and `Buffer`.
+	pub const BYTES: Self = Self(0b111100);
+	/// `Array` and `Struct`.
+	pub const ARRAY: Self = Self(0b11000000);
+	/// `Array`, `Struct` and `Map`.
+	pub const COMPOUND: Self = Self(0b111000000);
+
+	pub fn of(ty: StackItemType) -> Self {
+		Self(1 << TYPES.iter().position(|&t| t == ty).unwrap())
+	}
+
+	pub fn contains(&self, ty: StackItemType) -> bool {
+		!(*self & Self::of(ty)).is_empty()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.0 == 0
+	}
+
+	pub fn is_subset(&self, other: Self) -> bool {
+		*self & other == *self
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = StackItemType> + '_ {
+		TYPES.into_iter().filter(|&ty| self.contains(ty))
+	}
+}
+
+impl BitOr for TypeSet {
+	type Output = Self;
+
+	fn bitor(self, other: Self) -> Self {
+		Self(self.0 | other.0)
+	}
+}
+
+impl BitAnd for TypeSet {
+	type Output = Self;
+
+	fn bitand(self, other: Self) -> Self {
+		Self(self.0 & other.0)
+	}
+}
+
+impl Display for TypeSet {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		if *self == Self::ALL {
+			return write!(f, "*")
+		}
+		let names = self.iter().map(|ty| format!("{ty:?}")).collect::<Vec<_>>();
+		write!(f, "{}", names.join("|"))
+	}
+}
+
+impl Debug for TypeSet {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{{{self}}}")
+	}
+}
+
+/// The possible types of everything an instruction can read, on entry to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AbstractState {
+	/// The known top of the evaluation stack, bottom first. Items below it can be anything.
+	pub stack: Vec<TypeSet>,
+	/// `None` until `InitSlot` declares the slots, or when paths disagree on their counts.
+	pub locals: Option<Vec<TypeSet>>,
+	pub arguments: Option<Vec<TypeSet>>,
+	pub statics: Option<Vec<TypeSet>>,
+}
+
+impl AbstractState {
+	/// The state on entry to a function: nothing is known.
+	fn entry() -> Self {
+		Self { stack: Vec::new(), locals: None, arguments: None, statics: None }
+	}
+
+	/// The least state that covers both `self` and `other`.
+	fn join(&self, other: &Self) -> Self {
+		let depth = self.stack.len().min(other.stack.len());
+		let stack = self.stack[self.stack.len() - depth..]
+			.iter()
+			.zip(&other.stack[other.stack.len() - depth..])
+			.map(|(a, b)| *a | *b)
+			.collect();
+		Self {
+			stack,
+			locals: join_slots(&self.locals, &other.locals),
+			arguments: join_slots(&self.arguments, &other.arguments),
+			statics: join_slots(&self.statics, &other.statics),
+		}
+	}
+
+	fn slot(&mut self, kind: SlotKind) -> &mut Option<Vec<TypeSet>> {
+		match kind {
+			SlotKind::Static => &mut self.statics,
+			SlotKind::Local => &mut self.locals,
+			SlotKind::Argument => &mut self.arguments,
+		}
+	}
+}
+
+fn join_slots(a: &Option<Vec<TypeSet>>, b: &Option<Vec<TypeSet>>) -> Option<Vec<TypeSet>> {
+	match (a, b) {
+		(Some(a), Some(b)) if a.len() == b.len() =>
+			Some(a.iter().zip(b).map(|(a, b)| *a | *b).collect()),
+		_ => None,
+	}
+}
+
+/// A fault the analysis predicts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeIssue {
+	pub offset: usize,
+	pub opcode: OpCode,
+	pub kind: TypeIssueKind,
+	/// Whether every possible type faults, rather than only some of them.
+	pub certain: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeIssueKind {
+	/// Operand `operand`, counted from the top of the stack, can have a type the instruction
+	/// rejects with `InvalidType`.
+	InvalidType { operand: usize, found: TypeSet, expected: TypeSet },
+	/// `Convert` can get an item of a type in `from` that does not convert to `to`.
+	UnsupportedConvert { from: TypeSet, to: StackItemType },
+}
+
+impl Display for TypeIssue {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		let certainty = if self.certain { "faults" } else { "may fault" };
+		write!(f, "{:04X}: {} {certainty}: ", self.offset, self.opcode.mnemonic())?;
+		match &self.kind {
+			TypeIssueKind::InvalidType { operand, found, expected } =>
+				write!(f, "operand {operand} is {found}, expected {expected}"),
+			TypeIssueKind::UnsupportedConvert { from, to } =>
+				write!(f, "{from} does not convert to {to:?}"),
+		}
+	}
+}
+
+/// Tracks the possible types of every stack item, local, argument and static field over the
+/// control-flow graph of a script, and reports instructions that can fault with `InvalidType`.
+///
+/// Every function is analyzed from its entry without knowing its arguments or the stack below
+/// it. A call, `CallA`, `CallT` or `Syscall` forgets the stack, and the effect of instructions
+/// with a dynamic item count such as `Pack` or `Roll` is not tracked either. Unknown items are
+/// assumed to be of any type and never produce an issue.
+#[derive(Debug, Clone)]
+pub struct TypeAnalysis {
+	states: BTreeMap<usize, AbstractState>,
+	pub issues: Vec<TypeIssue>,
+}
+
+impl TypeAnalysis {
+	pub fn new(script: &Script) -> Result<Self, ScriptError> {
+		Ok(Self::from_graph(&ControlFlowGraph::new(script)?))
+	}
+
+	pub fn from_graph(graph: &ControlFlowGraph) -> Self {
+		let mut inputs: Vec<Option<AbstractState>> = vec![None; graph.blocks.len()];
+		let mut pending = Vec::new();
+		for function in &graph.functions {
+			if let Some(block) = graph.block_at(function.entry) {
+				inputs[block] = Some(AbstractState::entry());
+				pending.push(block);
+			}
+		}
+
+		while let Some(block) = pending.pop() {
+			let input = inputs[block].clone().unwrap();
+			let mut state = input.clone();
+			for decoded in &graph.blocks[block].instructions {
+				Step::new(&mut state, decoded.offset, None).run(&decoded.instruction);
+			}
+
+			for edge in graph.successors(block) {
+				let incoming = match edge.kind {
+					EdgeKind::Call => continue,
+					EdgeKind::Exception => {
+						let handler = input.join(&state);
+						AbstractState { stack: Vec::new(), ..handler }
+					},
+					_ => state.clone(),
+				};
+				let joined = match &inputs[edge.to] {
+					Some(old) => old.join(&incoming),
+					None => incoming,
+				};
+				if inputs[edge.to].as_ref() != Some(&joined) {
+					inputs[edge.to] = Some(joined);
+					pending.push(edge.to);
+				}
+			}
+		}
+
+		let mut states = BTreeMap::new();
+		let mut issues = Vec::new();
+		for (block, input) in graph.blocks.iter().zip(inputs) {
+			let Some(mut state) = input else { continue };
+			for decoded in &block.instructions {
+				states.insert(decoded.offset, state.clone());
+				Step::new(&mut state, decoded.offset, Some(&mut issues)).run(&decoded.instruction);
+			}
+		}
+		Self { states, issues }
+	}
+
+	/// The state on entry to the instruction at `offset`, or `None` if it is unreachable.
+	pub fn state_before(&self, offset: usize) -> Option<&AbstractState> {
+		self.states.get(&offset)
+	}
+}
+
+/// Whether `Convert` turns an item of type `from` into one of type `to`.
+fn converts(from: StackItemType, to: StackItemType) -> bool {
+	use StackItemType::*;
+	match (from, to) {
+		_ if from == to => true,
+		(_, Boolean) | (Any, _) => true,
+		(Integer | ByteString | Boolean, Integer | ByteString | Buffer) => true,
+		(Buffer, Integer | ByteString) => true,
+		(Array, Struct) | (Struct, Array) => true,
+		_ => false,
+	}
+}
+
+/// Applies one instruction to a state.
+struct Step<'a> {
+	state: &'a mut AbstractState,
+	offset: usize,
+	opcode: OpCode,
+	issues: Option<&'a mut Vec<TypeIssue>>,
+	/// How many operands have been popped, to number them in issues.
+	popped: usize,
+}
+
+impl<'a> Step<'a> {
+	fn new(
+		state: &'a mut AbstractState,
+		offset: usize,
+		issues: Option<&'a mut Vec<TypeIssue>>,
+	) -> Self {
+		Self { state, offset, opcode: OpCode::Nop, issues, popped: 0 }
+	}
+
+	fn report(&mut self, kind: TypeIssueKind, certain: bool) {
+		if let Some(issues) = self.issues.as_deref_mut() {
+			issues.push(TypeIssue { offset: self.offset, opcode: self.opcode, kind, certain });
+		}
+	}
+
+	fn pop(&mut self) -> TypeSet {
+		self.popped += 1;
+		self.state.stack.pop().unwrap_or(TypeSet::ALL)
+	}
+
+	/// Pops an operand that must be one of `expected`, returning the types it can still have
+	/// if execution continues.
+	fn pop_expect(&mut self, expected: TypeSet) -> TypeSet {
+		let operand = self.popped;
+		let found = self.pop();
+		if found == TypeSet::ALL {
+			return expected
+		}
+		if found.is_subset(expected) {
+			return found
+		}
+		let certain = (found & expected).is_empty();
+		self.report(TypeIssueKind::InvalidType { operand, found, expected }, certain);
+		if certain {
+			found
+		} else {
+			found & expected
+		}
+	}
+
+	fn push(&mut self, types: TypeSet) {
+		self.state.stack.push(types);
+	}
+
+	fn push_type(&mut self, ty: StackItemType) {
+		self.push(TypeSet::of(ty));
+	}
+
+	/// Forgets the whole stack.
+	fn forget(&mut self) {
+		self.state.stack.clear();
+	}
+
+	fn unary(&mut self, operand: TypeSet, result: StackItemType) {
+		self.pop_expect(operand);
+		self.push_type(result);
+	}
+
+	fn binary(&mut self, operands: TypeSet, result: StackItemType) {
+		self.pop_expect(operands);
+		self.pop_expect(operands);
+		self.push_type(result);
+	}
+
+	fn run(mut self, instruction: &Instruction) {
+		use StackItemType as T;
+		use TypeSet as S;
+
+		self.opcode = instruction.opcode;
+		if let Some((kind, access, index)) = instruction.slot_access() {
+			match access {
+				SlotAccess::Load => {
+					let slot = self.state.slot(kind).as_ref();
+					let types = slot.and_then(|slot| slot.get(index)).copied();
+					self.push(types.unwrap_or(S::ALL));
+				},
+				SlotAccess::Store => {
+					let types = self.pop();
+					if let Some(slot) =
+						self.state.slot(kind).as_mut().and_then(|s| s.get_mut(index))
+					{
+						*slot = types;
+					}
+				},
+			}
+			return
+		}
+
+		let code = instruction.opcode as u8;
+		match instruction.opcode {
+			OpCode::PushInt8
+			| OpCode::PushInt16
+			| OpCode::PushInt32
+			| OpCode::PushInt64
+			| OpCode::PushInt128
+			| OpCode::PushInt256 => self.push_type(T::Integer),
+			_ if (OpCode::PushM1 as u8..=OpCode::Push16 as u8).contains(&code) =>
+				self.push_type(T::Integer),
+			OpCode::PushTrue | OpCode::PushFalse => self.push_type(T::Boolean),
+			OpCode::PushA => self.push_type(T::Pointer),
+			OpCode::PushNull => self.push_type(T::Any),
+			OpCode::PushData1 | OpCode::PushData2 | OpCode::PushData4 =>
+				self.push_type(T::ByteString),
+
+			OpCode::Nop
+			| OpCode::Jmp
+			| OpCode::JmpL
+			| OpCode::Try
+			| OpCode::TryL
+			| OpCode::EndTry
+			| OpCode::EndTryL
+			| OpCode::EndFinally
+			| OpCode::Ret
+			| OpCode::Abort => {},
+			OpCode::JmpIf
+			| OpCode::JmpIfL
+			| OpCode::JmpIfNot
+			| OpCode::JmpIfNotL
+			| OpCode::Assert
+			| OpCode::Throw
+			| OpCode::AbortMsg
+			| OpCode::Drop => {
+				self.pop();
+			},
+			OpCode::AssertMsg => {
+				self.pop();
+				self.pop();
+			},
+			OpCode::JmpEq
+			| OpCode::JmpEqL
+			| OpCode::JmpNe
+			| OpCode::JmpNeL
+			| OpCode::JmpGt
+			| OpCode::JmpGtL
+			| OpCode::JmpGe
+			| OpCode::JmpGeL
+			| OpCode::JmpLt
+			| OpCode::JmpLtL
+			| OpCode::JmpLe
+			| OpCode::JmpLeL => {
+				self.pop_expect(S::INTEGER);
+				self.pop_expect(S::INTEGER);
+			},
+			OpCode::Call | OpCode::CallL => {
+				self.forget();
+				self.state.statics = None;
+			},
+			OpCode::CallA => {
+				self.pop_expect(S::of(T::Pointer));
+				self.forget();
+				self.state.statics = None;
+			},
+			OpCode::CallT | OpCode::Syscall => self.forget(),
+
+			OpCode::Depth => self.push_type(T::Integer),
+			OpCode::Nip => {
+				let top = self.pop();
+				self.pop();
+				self.push(top);
+			},
+			OpCode::Dup => {
+				let top = self.pop();
+				self.push(top);
+				self.push(top);
+			},
+			OpCode::Over => {
+				let (b, a) = (self.pop(), self.pop());
+				self.push(a);
+				self.push(b);
+				self.push(a);
+			},
+			OpCode::Pick => {
+				self.pop_expect(S::INTEGER);
+				self.push(S::ALL);
+			},
+			OpCode::Tuck => {
+				let (b, a) = (self.pop(), self.pop());
+				self.push(b);
+				self.push(a);
+				self.push(b);
+			},
+			OpCode::Swap => {
+				let (b, a) = (self.pop(), self.pop());
+				self.push(b);
+				self.push(a);
+			},
+			OpCode::Rot => {
+				let (c, b, a) = (self.pop(), self.pop(), self.pop());
+				self.push(b);
+				self.push(c);
+				self.push(a);
+			},
+			OpCode::Reverse3 | OpCode::Reverse4 => {
+				let count = if instruction.opcode == OpCode::Reverse3 { 3 } else { 4 };
+				let items = (0..count).map(|_| self.pop()).collect::<Vec<_>>();
+				for types in items {
+					self.push(types);
+				}
+			},
+			OpCode::Xdrop | OpCode::Roll | OpCode::ReverseN => {
+				self.pop_expect(S::INTEGER);
+				self.forget();
+			},
+			OpCode::InitSSLot => {
+				self.state.statics = Some(vec![S::of(T::Any); instruction.token_u8() as usize]);
+			},
+			OpCode::InitSlot => {
+				let arguments = (0..instruction.token_u8_1()).map(|_| self.pop()).collect();
+				self.state.locals = Some(vec![S::of(T::Any); instruction.token_u8() as usize]);
+				self.state.arguments = Some(arguments);
+			},
+
+			OpCode::NewBuffer => self.unary(S::INTEGER, T::Buffer),
+			OpCode::MemCpy => {
+				self.pop_expect(S::INTEGER);
+				self.pop_expect(S::INTEGER);
+				self.pop_expect(S::BYTES);
+				self.pop_expect(S::INTEGER);
+				self.pop_expect(S::of(T::Buffer));
+			},
+			OpCode::Cat => self.binary(S::BYTES, T::Buffer),
+			OpCode::Substr => {
+				self.pop_expect(S::INTEGER);
+				self.pop_expect(S::INTEGER);
+				self.unary(S::BYTES, T::Buffer);
+			},
+			OpCode::Left | OpCode::Right => {
+				self.pop_expect(S::INTEGER);
+				self.unary(S::BYTES, T::Buffer);
+			},
+
+			OpCode::Invert
+			| OpCode::Sign
+			| OpCode::Abs
+			| OpCode::Negate
+			| OpCode::Inc
+			| OpCode::Dec
+			| OpCode::Sqrt => self.unary(S::INTEGER, T::Integer),
+			OpCode::Nz => self.unary(S::INTEGER, T::Boolean),
+			OpCode::And
+			| OpCode::Or
+			| OpCode::Xor
+			| OpCode::Add
+			| OpCode::Sub
+			| OpCode::Mul
+			| OpCode::Div
+			| OpCode::Mod
+			| OpCode::Pow
+			| OpCode::Shl
+			| OpCode::Shr
+			| OpCode::Min
+			| OpCode::Max => self.binary(S::INTEGER, T::Integer),
+			OpCode::ModMul | OpCode::ModPow => {
+				self.pop_expect(S::INTEGER);
+				self.binary(S::INTEGER, T::Integer);
+			},
+			OpCode::Within => {
+				self.pop_expect(S::INTEGER);
+				self.binary(S::INTEGER, T::Boolean);
+			},
+			OpCode::NumEqual | OpCode::NumNotEqual => self.binary(S::INTEGER, T::Boolean),
+			// Comparing with null is false rather than a fault.
+			OpCode::Lt | OpCode::Le | OpCode::Gt | OpCode::Ge =>
+				self.binary(S::INTEGER | S::of(T::Any), T::Boolean),
+			OpCode::Equal | OpCode::NotEqual | OpCode::BoolAnd | OpCode::BoolOr =>
+				self.binary(S::ALL, T::Boolean),
+			OpCode::Not | OpCode::IsNull | OpCode::IsType => self.unary(S::ALL, T::Boolean),
+
+			OpCode::Pack | OpCode::PackStruct | OpCode::PackMap => {
+				self.pop_expect(S::INTEGER);
+				self.forget();
+				self.push_type(match instruction.opcode {
+					OpCode::Pack => T::Array,
+					OpCode::PackStruct => T::Struct,
+					_ => T::Map,
+				});
+			},
+			OpCode::Unpack => {
+				self.pop_expect(S::COMPOUND);
+				self.forget();
+			},
+			OpCode::NewArray0 => self.push_type(T::Array),
+			OpCode::NewArray | OpCode::NewArrayT => self.unary(S::INTEGER, T::Array),
+			OpCode::NewStruct0 => self.push_type(T::Struct),
+			OpCode::NewStruct => self.unary(S::INTEGER, T::Struct),
+			OpCode::NewMap => self.push_type(T::Map),
+			OpCode::Size => self.unary(S::COMPOUND | S::BYTES, T::Integer),
+			OpCode::HasKey => {
+				self.pop_expect(S::PRIMITIVE);
+				self.unary(S::COMPOUND | S::of(T::Buffer) | S::of(T::ByteString), T::Boolean);
+			},
+			OpCode::Keys => self.unary(S::of(T::Map), T::Array),
+			OpCode::Values => self.unary(S::COMPOUND, T::Array),
+			OpCode::PickItem => {
+				self.pop_expect(S::PRIMITIVE);
+				let container = self.pop_expect(S::COMPOUND | S::BYTES);
+				let item = if container.is_subset(S::BYTES) { S::of(T::Integer) } else { S::ALL };
+				self.push(item);
+			},
+			OpCode::Append => {
+				self.pop();
+				self.pop_expect(S::ARRAY);
+			},
+			OpCode::SetItem => {
+				self.pop();
+				self.pop_expect(S::PRIMITIVE);
+				self.pop_expect(S::COMPOUND | S::of(T::Buffer));
+			},
+			OpCode::ReverseItems => {
+				self.pop_expect(S::ARRAY | S::of(T::Buffer));
+			},
+			OpCode::Remove => {
+				self.pop();
+				self.pop_expect(S::COMPOUND);
+			},
+			OpCode::ClearItems => {
+				self.pop_expect(S::COMPOUND);
+			},
+			OpCode::PopItem => {
+				self.pop_expect(S::ARRAY);
+				self.push(S::ALL);
+			},
+			OpCode::Convert => {
+				let to = StackItemType::from_u8(instruction.token_u8()).unwrap_or(T::Any);
+				let from = self.pop();
+				let unsupported = from
+					.iter()
+					.filter(|&ty| !converts(ty, to))
+					.fold(S::EMPTY, |set, ty| set | S::of(ty));
+				if !unsupported.is_empty() {
+					let certain = unsupported == from;
+					self.report(
+						TypeIssueKind::UnsupportedConvert { from: unsupported, to },
+						certain,
+					);
+				}
+				self.push(S::of(to) | (from & S::of(T::Any)));
+			},
+			_ => self.forget(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::script::assembler::assemble;
+
+	fn analyze(source: &str) -> TypeAnalysis {
+		TypeAnalysis::new(&Script::new(assemble(source).unwrap(), true).unwrap()).unwrap()
+	}
+
+	#[test]
+	fn type_sets() {
+		let set = TypeSet::of(StackItemType::Integer) | TypeSet::of(StackItemType::Map);
+		assert!(set.contains(StackItemType::Map));
+		assert!(!set.is_subset(TypeSet::INTEGER));
+		assert_eq!(set.to_string(), "Integer|Map");
+		assert_eq!(TypeSet::INTEGER.to_string(), "Boolean|Integer|ByteString|Buffer");
+		assert_eq!(TypeSet::PRIMITIVE.to_string(), "Boolean|Integer|ByteString");
+		assert_eq!(TypeSet::COMPOUND.to_string(), "Array|Struct|Map");
+		assert_eq!(TypeSet::ALL.to_string(), "*");
+	}
+
+	#[test]
+	fn reports_certain_faults() {
+		let analysis = analyze("NEWMAP\nPUSH1\nADD\nRET");
+		assert_eq!(
+			analysis.issues,
+			vec![TypeIssue {
+				offset: 2,
+				opcode: OpCode::Add,
+				kind: TypeIssueKind::InvalidType {
+					operand: 1,
+					found: TypeSet::of(StackItemType::Map),
+					expected: TypeSet::INTEGER,
+				},
+				certain: true,
+			}]
+		);
+		assert_eq!(
+			analysis.issues[0].to_string(),
+			"0002: ADD faults: operand 1 is Map, expected Boolean|Integer|ByteString|Buffer"
+		);
+	}
+
+	#[test]
+	fn accepts_buffers_as_integers() {
+		assert!(analyze("PUSH1\nNEWBUFFER\nPUSH1\nADD\nRET").issues.is_empty());
+	}
+
+	#[test]
+	fn joins_branches() {
+		let analysis = analyze(
+			"INITSLOT 1, 1
+			LDARG0
+			JMPIF L_map
+			PUSH1
+			STLOC0
+			JMP L_end
+			L_map:
+			NEWMAP
+			STLOC0
+			L_end:
+			LDLOC0
+			PUSH2
+			MUL
+			RET",
+		);
+		let found = TypeSet::of(StackItemType::Integer) | TypeSet::of(StackItemType::Map);
+		let [issue] = &analysis.issues[..] else { panic!("{:?}", analysis.issues) };
+		assert!(!issue.certain);
+		assert_eq!(issue.opcode, OpCode::Mul);
+		let state = analysis.state_before(issue.offset).unwrap();
+		assert_eq!(state.stack, vec![found, TypeSet::of(StackItemType::Integer)]);
+		assert_eq!(state.locals, Some(vec![found]));
+	}
+
+	#[test]
+	fn checks_convert_targets() {
+		let [issue] = &analyze("NEWMAP\nCONVERT Integer\nRET").issues[..] else { panic!() };
+		assert_eq!(
+			issue.kind,
+			TypeIssueKind::UnsupportedConvert {
+				from: TypeSet::of(StackItemType::Map),
+				to: StackItemType::Integer,
+			}
+		);
+		assert!(analyze("PUSH1\nCONVERT ByteString\nCONVERT Buffer\nRET").issues.is_empty());
+	}
+
+	#[test]
+	fn analyzes_functions_separately() {
+		let analysis = analyze(
+			"CALL L_f
+			PUSH1
+			ADD
+			RET
+			L_f:
+			NEWARRAY0
+			INC
+			RET",
+		);
+		let offsets = analysis.issues.iter().map(|issue| issue.offset).collect::<Vec<_>>();
+		assert_eq!(offsets, vec![6]);
+	}
+
+	#[test]
+	fn checks_keys_and_has_key_containers() {
+		let [issue] = &analyze("PUSH1\nPUSH0\nHASKEY\nRET").issues[..] else { panic!() };
+		assert!(issue.certain);
+		assert_eq!(
+			issue.kind,
+			TypeIssueKind::InvalidType {
+				operand: 1,
+				found: TypeSet::of(StackItemType::Integer),
+				expected: TypeSet::COMPOUND
+					| TypeSet::of(StackItemType::Buffer)
+					| TypeSet::of(StackItemType::ByteString),
+			}
+		);
+		assert!(analyze("PUSHDATA1 0x0102\nPUSH0\nHASKEY\nRET").issues.is_empty());
+
+		for source in [
+			"NEWMAP\nNEWMAP\nHASKEY\nRET",
+			"NEWMAP\nNEWARRAY0\nPICKITEM\nRET",
+			"NEWMAP\nPUSHNULL\nPUSH1\nSETITEM\nRET",
+		] {
+			let [issue] = &analyze(source).issues[..] else { panic!("{source}") };
+			assert!(issue.certain, "{source}");
+			assert!(
+				matches!(
+					issue.kind,
+					TypeIssueKind::InvalidType { expected: TypeSet::PRIMITIVE, .. }
+				),
+				"{source}"
+			);
+		}
+	}
+}
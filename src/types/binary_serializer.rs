@@ -0,0 +1,359 @@
+use crate::{
+	buffer::Buffer,
+	compound_types::{array::Array, map::Map, Struct::Struct},
+	execution_engine_limits::ExecutionEngineLimits,
+	null::Null,
+	primitive_types::{
+		boolean::Boolean, byte_string::ByteString, integer::Integer, primitive_type::PrimitiveType,
+	},
+	reference_counter::ReferenceCounter,
+	stack_item::StackItem,
+	stack_item_type::StackItemType,
+	vm_exception::VMException,
+};
+use num_bigint::BigInt;
+use num_traits::Zero;
+use std::{cell::RefCell, collections::HashSet, rc::Rc};
+
+/// Serializes `item` in the Neo binary format: a type byte followed by the item's payload,
+/// with var-int lengths and compound items written depth first.
+///
+/// Fails on `Pointer` and `InteropInterface`, on a compound item that is reached twice, and
+/// when the output exceeds `max_item_size` or holds more than `max_stack_size` items.
+pub fn serialize(
+	item: &dyn StackItem,
+	limits: &ExecutionEngineLimits,
+) -> Result<Vec<u8>, VMException> {
+	let mut writer = Writer {
+		output: Vec::new(),
+		serialized: HashSet::new(),
+		remaining_items: limits.max_stack_size,
+		max_size: limits.max_item_size,
+	};
+	writer.write_item(item)?;
+	Ok(writer.output)
+}
+
+/// Rebuilds an item from the Neo binary format.
+pub fn deserialize(
+	data: &[u8],
+	limits: &ExecutionEngineLimits,
+	reference_counter: Option<Rc<RefCell<ReferenceCounter>>>,
+) -> Result<Rc<RefCell<dyn StackItem>>, VMException> {
+	let mut reader = Reader { data, position: 0, items: 0, limits };
+	let node = reader.read_node()?;
+	build(node, &reference_counter)
+}
+
+struct Writer {
+	output: Vec<u8>,
+	serialized: HashSet<*const ()>,
+	remaining_items: usize,
+	max_size: usize,
+}
+
+impl Writer {
+	fn write_item(&mut self, item: &dyn StackItem) -> Result<(), VMException> {
+		self.remaining_items = self
+			.remaining_items
+			.checked_sub(1)
+			.ok_or_else(|| VMException::StackOverflow("Too many items to serialize".to_string()))?;
+
+		let ty = item.get_type();
+		self.output.push(ty as u8);
+		match ty {
+			StackItemType::Any => {},
+			StackItemType::Boolean => self.output.push(item.get_boolean()? as u8),
+			StackItemType::Integer => {
+				let value = item.get_integer()?;
+				let bytes = if value.is_zero() { Vec::new() } else { value.to_signed_bytes_le() };
+				self.write_var_bytes(&bytes);
+			},
+			StackItemType::ByteString | StackItemType::Buffer =>
+				self.write_var_bytes(item.get_slice()?),
+			StackItemType::Array | StackItemType::Struct | StackItemType::Map =>
+				self.write_compound(item)?,
+			StackItemType::Pointer | StackItemType::InteropInterface =>
+				return Err(VMException::InvalidType(format!("{ty:?} cannot be serialized"))),
+		}
+
+		if self.output.len() > self.max_size {
+			return Err(VMException::ItemTooLarge(format!(
+				"Serialized item exceeds {} bytes",
+				self.max_size
+			)))
+		}
+		Ok(())
+	}
+
+	fn write_compound(&mut self, item: &dyn StackItem) -> Result<(), VMException> {
+		if !self.serialized.insert(item as *const dyn StackItem as *const ()) {
+			return Err(VMException::InvalidParameter(
+				"Circular or shared reference cannot be serialized".to_string(),
+			))
+		}
+
+		let any = item.as_any();
+		if let Some(map) = any.downcast_ref::<Map>() {
+			self.write_var_int(map.len() as u64);
+			for (key, value) in map.iter() {
+				self.write_item(&*key.borrow())?;
+				self.write_item(&*value.borrow())?;
+			}
+			return Ok(())
+		}
+
+		let items: Vec<&Rc<RefCell<dyn StackItem>>> = match any.downcast_ref::<Struct>() {
+			Some(st) => st.iter().collect(),
+			None => any.downcast_ref::<Array>().unwrap().iter().collect(),
+		};
+		self.write_var_int(items.len() as u64);
+		for sub_item in items {
+			self.write_item(&*sub_item.borrow())?;
+		}
+		Ok(())
+	}
+
+	fn write_var_int(&mut self, value: u64) {
+		match value {
+			0..=0xFC => self.output.push(value as u8),
+			0xFD..=0xFFFF => {
+				self.output.push(0xFD);
+				self.output.extend_from_slice(&(value as u16).to_le_bytes());
+			},
+			0x1_0000..=0xFFFF_FFFF => {
+				self.output.push(0xFE);
+				self.output.extend_from_slice(&(value as u32).to_le_bytes());
+			},
+			_ => {
+				self.output.push(0xFF);
+				self.output.extend_from_slice(&value.to_le_bytes());
+			},
+		}
+	}
+
+	fn write_var_bytes(&mut self, bytes: &[u8]) {
+		self.write_var_int(bytes.len() as u64);
+		self.output.extend_from_slice(bytes);
+	}
+}
+
+/// A decoded item whose compound children are not yet linked.
+enum Node {
+	Null,
+	Boolean(bool),
+	Integer(BigInt),
+	ByteString(Vec<u8>),
+	Buffer(Vec<u8>),
+	Array(Vec<Node>),
+	Struct(Vec<Node>),
+	Map(Vec<(Node, Node)>),
+}
+
+struct Reader<'a> {
+	data: &'a [u8],
+	position: usize,
+	items: usize,
+	limits: &'a ExecutionEngineLimits,
+}
+
+impl<'a> Reader<'a> {
+	fn read_node(&mut self) -> Result<Node, VMException> {
+		self.items += 1;
+		if self.items > self.limits.max_stack_size {
+			return Err(VMException::StackOverflow("Too many items to deserialize".to_string()))
+		}
+
+		let ty = self.read_bytes(1)?[0];
+		let node = match StackItemType::from_u8(ty) {
+			Some(StackItemType::Any) => Node::Null,
+			Some(StackItemType::Boolean) => match self.read_bytes(1)?[0] {
+				0 => Node::Boolean(false),
+				1 => Node::Boolean(true),
+				value => return Err(format_error(format!("Invalid boolean {value}"))),
+			},
+			Some(StackItemType::Integer) => Node::Integer(BigInt::from_signed_bytes_le(
+				self.read_var_bytes(Integer::MAX_SIZE as u64)?,
+			)),
+			Some(StackItemType::ByteString) =>
+				Node::ByteString(self.read_var_bytes(self.limits.max_item_size as u64)?.to_vec()),
+			Some(StackItemType::Buffer) =>
+				Node::Buffer(self.read_var_bytes(self.limits.max_item_size as u64)?.to_vec()),
+			Some(ty @ (StackItemType::Array | StackItemType::Struct)) => {
+				let count = self.read_var_int(self.limits.max_stack_size as u64)?;
+				let items = (0..count).map(|_| self.read_node()).collect::<Result<_, _>>()?;
+				if ty == StackItemType::Array {
+					Node::Array(items)
+				} else {
+					Node::Struct(items)
+				}
+			},
+			Some(StackItemType::Map) => {
+				let count = self.read_var_int(self.limits.max_stack_size as u64)?;
+				let entries = (0..count)
+					.map(|_| Ok((self.read_node()?, self.read_node()?)))
+					.collect::<Result<_, VMException>>()?;
+				Node::Map(entries)
+			},
+			_ => return Err(format_error(format!("Invalid stack item type {ty:#04x}"))),
+		};
+		Ok(node)
+	}
+
+	fn read_bytes(&mut self, count: usize) -> Result<&'a [u8], VMException> {
+		let end = self.position.saturating_add(count);
+		let bytes = self
+			.data
+			.get(self.position..end)
+			.ok_or_else(|| format_error("Unexpected end of data".to_string()))?;
+		self.position = end;
+		Ok(bytes)
+	}
+
+	fn read_var_int(&mut self, max: u64) -> Result<u64, VMException> {
+		let value = match self.read_bytes(1)?[0] {
+			0xFD => u16::from_le_bytes(self.read_bytes(2)?.try_into().unwrap()) as u64,
+			0xFE => u32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()) as u64,
+			0xFF => u64::from_le_bytes(self.read_bytes(8)?.try_into().unwrap()),
+			value => value as u64,
+		};
+		if value > max {
+			return Err(format_error(format!("Length {value} exceeds {max}")))
+		}
+		Ok(value)
+	}
+
+	fn read_var_bytes(&mut self, max: u64) -> Result<&'a [u8], VMException> {
+		let length = self.read_var_int(max)?;
+		self.read_bytes(length as usize)
+	}
+}
+
+fn format_error(message: String) -> VMException {
+	VMException::InvalidParameter(message)
+}
+
+fn build(
+	node: Node,
+	reference_counter: &Option<Rc<RefCell<ReferenceCounter>>>,
+) -> Result<Rc<RefCell<dyn StackItem>>, VMException> {
+	let item: Rc<RefCell<dyn StackItem>> = match node {
+		Node::Null => Rc::new(RefCell::new(Null)),
+		Node::Buffer(bytes) => Rc::new(RefCell::new(Buffer::from(bytes))),
+		Node::Boolean(_) | Node::Integer(_) | Node::ByteString(_) => build_key(node)?,
+		Node::Array(items) => {
+			let items = items
+				.into_iter()
+				.map(|item| build(item, reference_counter))
+				.collect::<Result<_, _>>()?;
+			Rc::new(RefCell::new(Array::new(Some(items), reference_counter.clone())))
+		},
+		Node::Struct(fields) => {
+			let fields = fields
+				.into_iter()
+				.map(|field| build(field, reference_counter))
+				.collect::<Result<_, _>>()?;
+			Rc::new(RefCell::new(Struct::new(Some(fields), reference_counter.clone())))
+		},
+		Node::Map(entries) => {
+			let mut map = Map::new(reference_counter.clone());
+			for (key, value) in entries {
+				map.insert(build_key(key)?, build(value, reference_counter)?)?;
+			}
+			Rc::new(RefCell::new(map))
+		},
+	};
+	Ok(item)
+}
+
+fn build_key(node: Node) -> Result<Rc<RefCell<dyn PrimitiveType>>, VMException> {
+	match node {
+		Node::Boolean(value) => Ok(Rc::new(RefCell::new(Boolean::new(value)))),
+		Node::Integer(value) => Ok(Rc::new(RefCell::new(Integer::try_from(value)?))),
+		Node::ByteString(bytes) if bytes.len() <= Map::MAX_KEY_SIZE =>
+			Ok(Rc::new(RefCell::new(ByteString::new(bytes)))),
+		Node::ByteString(bytes) =>
+			Err(format_error(format!("Map key of {} bytes is too large", bytes.len()))),
+		_ => Err(VMException::InvalidType("Map keys must be primitive items".to_string())),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn round_trip(data: &[u8]) -> Vec<u8> {
+		let limits = ExecutionEngineLimits::default();
+		let item = deserialize(data, &limits, None).unwrap();
+		let data = serialize(&*item.borrow(), &limits).unwrap();
+		data
+	}
+
+	#[test]
+	fn round_trips_every_serializable_type() {
+		let data = [
+			0x40, 0x07, // Array of 7
+			0x00, // Null
+			0x20, 0x01, // true
+			0x21, 0x02, 0x80, 0x00, // Integer 128
+			0x21, 0x00, // Integer 0
+			0x28, 0x02, b'h', b'i', // ByteString "hi"
+			0x30, 0x01, 0xff, // Buffer
+			0x41, 0x01, 0x48, 0x01, 0x21, 0x01, 0x05, 0x20,
+			0x00, // Struct { Map { 5: false } }
+		];
+		assert_eq!(round_trip(&data), data);
+	}
+
+	#[test]
+	fn serializes_map_entries_in_insertion_order() {
+		let mut map = Map::new(None);
+		map.insert(
+			Rc::new(RefCell::new(ByteString::from("b"))),
+			Rc::new(RefCell::new(Integer::from(1))),
+		)
+		.unwrap();
+		map.insert(
+			Rc::new(RefCell::new(Integer::from(7))),
+			Rc::new(RefCell::new(Boolean::new(true))),
+		)
+		.unwrap();
+		map.insert(Rc::new(RefCell::new(ByteString::from("a"))), Rc::new(RefCell::new(Null)))
+			.unwrap();
+		let data = [
+			0x48, 0x03, // Map of 3
+			0x28, 0x01, b'b', 0x21, 0x01, 0x01, // "b": 1
+			0x21, 0x01, 0x07, 0x20, 0x01, // 7: true
+			0x28, 0x01, b'a', 0x00, // "a": null
+		];
+		assert_eq!(serialize(&map, &ExecutionEngineLimits::default()).unwrap(), data);
+		assert_eq!(round_trip(&data), data);
+	}
+
+	#[test]
+	fn rejects_malformed_input() {
+		let limits = ExecutionEngineLimits::default();
+		for data in [
+			&[0x40, 0x02, 0x00][..],         // truncated array
+			&[0x20, 0x02],                   // boolean that is neither 0 nor 1
+			&[0x10, 0x00],                   // Pointer
+			&[0x48, 0x01, 0x40, 0x00, 0x00], // compound map key
+		] {
+			assert!(deserialize(data, &limits, None).is_err(), "{data:?}");
+		}
+
+		let mut too_many = vec![0x40, 0xFD];
+		too_many.extend_from_slice(&(limits.max_stack_size as u16).to_le_bytes());
+		too_many.resize(too_many.len() + limits.max_stack_size, 0x00);
+		assert!(deserialize(&too_many, &limits, None).is_err());
+	}
+
+	#[test]
+	fn rejects_cycles() {
+		let array = Rc::new(RefCell::new(Array::new(None, None)));
+		let item: Rc<RefCell<dyn StackItem>> = array.clone();
+		array.borrow_mut().add(item);
+		let result = serialize(&*array.borrow(), &ExecutionEngineLimits::default());
+		assert!(matches!(result, Err(VMException::InvalidParameter(_))));
+	}
+}
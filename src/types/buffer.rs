@@ -1,231 +1,79 @@
 use crate::{
-    stack_item::{ObjectReferenceEntry, StackItem::VMByteString, StackItem},
-    stack_item_type::StackItemType,
-    types::compound_types::compound_type::CompoundType,
+	primitive_types::integer::Integer,
+	stack_item::{invalid_cast, StackItem},
+	stack_item_type::StackItemType,
+	vm_exception::VMException,
 };
-use num_bigint::{BigInt, Sign};
-use std::{borrow::Cow, cell::RefCell, collections::HashMap, os::unix::raw::ino_t, vec::Vec};
-use crate::execution_engine_limits::ExecutionEngineLimits;
-use crate::primitive_types::boolean::Boolean;
-use crate::primitive_types::byte_string::ByteString;
-use crate::primitive_types::primitive_type::PrimitiveType;
+use num_bigint::BigInt;
+use std::any::Any;
 
-#[derive(Clone, PartialEq, Eq, Hash, Debug, PartialOrd, Ord)]
+/// Represents a mutable memory block in the vm.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
 pub struct Buffer {
-	stack_references: u32,
-	object_references: RefCell<Option<HashMap<dyn CompoundType, ObjectReferenceEntry>>>,
-	dfn: isize,
-	low_link: usize,
-	on_stack: bool,
-	bytes: Cow<'static, [u8]>,
+	data: Vec<u8>,
 }
 
 impl Buffer {
+	/// A zero-filled buffer of `size` bytes.
 	pub fn new(size: usize) -> Self {
-		Self {
-			stack_references: 0,
-			object_references: RefCell::new(None),
-			dfn: 0,
-			low_link: 0,
-			on_stack: false,
-			bytes: Cow::Owned(Vec::with_capacity(size)),
-		}
+		Self { data: vec![0; size] }
 	}
 
-	// pub fn new_with_init(size:usize, zero_initialize:bool/* = true*/) -> Self
-	// {
-	// let _buffer = ArrayPool<byte>.Shared.Rent(size);
-	// let InnerBuffer = new Memory<byte>(_buffer, 0, size);
-	// if (zero_initialize)
-	// {
-	// 	InnerBuffer.Span.Clear();
-	// }
-	// }
-
 	pub fn from_slice(data: &[u8]) -> Self {
-		Self {
-			stack_references: 0,
-			object_references: RefCell::new(None),
-			dfn: 0,
-			low_link: 0,
-			on_stack: false,
-			bytes: Cow::Borrowed(data),
-		}
-	}
-
-	fn to_vec(&self) -> Vec<u8> {
-		self.bytes.to_vec()
-	}
-
-	fn as_slice(&self) -> &[u8] {
-		self.bytes.as_ref()
-	}
-}
-
-impl Drop for Buffer {
-	fn drop(&mut self) {
-		// Return buffer to pool if not static
-	}
-}
-
-impl StackItem for Buffer {
-	const TRUE: Self = ();
-
-	const FALSE: Self = ();
-
-	const NULL: Self = ();
-
-	fn dfn(&self) -> isize {
-		self.dfn
-	}
-
-	fn set_dfn(&mut self, dfn: isize) {
-		self.dfn = dfn;
-	}
-
-	fn low_link(&self) -> usize {
-		self.low_link
-	}
-
-	fn set_low_link(&mut self, link: usize) {
-		self.low_link = link;
-	}
-
-	fn on_stack(&self) -> bool {
-		self.on_stack
-	}
-
-	fn set_on_stack(&mut self, on_stack: bool) {
-		self.on_stack = on_stack;
+		Self { data: data.to_vec() }
 	}
 
-	fn set_object_references(&mut self, refs: Self::ObjectReferences) {
-		self.object_references = refs;
+	pub fn data(&self) -> &[u8] {
+		&self.data
 	}
 
-	fn object_references(&self) -> &Self::ObjectReferences {
-		&self.object_references
+	pub fn data_mut(&mut self) -> &mut [u8] {
+		&mut self.data
 	}
 
-	fn set_stack_references(&mut self, count: usize) {
-		self.stack_references = count as u32;
+	pub fn len(&self) -> usize {
+		self.data.len()
 	}
 
-	fn stack_references(&self) -> usize {
-		self.stack_references as usize
+	pub fn is_empty(&self) -> bool {
+		self.data.is_empty()
 	}
+}
 
-	fn cleanup(&mut self) {
-		todo!()
+impl From<Vec<u8>> for Buffer {
+	fn from(data: Vec<u8>) -> Self {
+		Self { data }
 	}
+}
 
-	fn get_slice(&self) -> &[u8] {
-		self.as_slice()
+impl From<&[u8]> for Buffer {
+	fn from(data: &[u8]) -> Self {
+		Self::from_slice(data)
 	}
+}
 
+impl StackItem for Buffer {
 	fn get_type(&self) -> StackItemType {
 		StackItemType::Buffer
 	}
 
-	fn get_boolean(&self) -> bool {
-		true
-	}
-	fn deep_copy(
-		&self,
-		_ref_map: &HashMap<&dyn StackItem, Box<dyn StackItem>>,
-		as_immutable: bool,
-	) -> Box<dyn StackItem> {
-		if as_immutable {
-			ByteString::from(self.to_vec()).into()
-		} else {
-			Buffer::from_slice(self.as_slice()).into()
-		}
-	}
-	fn deep_copy_with_ref_map(&self, ref_map: &HashMap<&dyn StackItem, &dyn StackItem>, asImmutable: bool) -> Box<dyn StackItem> {
-		todo!()
-	}
-
-	fn equals(&self, other: &Option<dyn StackItem>) -> bool {
-		todo!()
-	}
-
-	fn equals_with_limits(&self, other: &dyn StackItem, limits: &ExecutionEngineLimits) -> bool {
-		todo!()
+	fn as_any(&self) -> &dyn Any {
+		self
 	}
 
-	fn get_integer(&self) -> BigInt {
-		todo!()
+	fn as_any_mut(&mut self) -> &mut dyn Any {
+		self
 	}
 
-	fn get_bytes(&self) -> &[u8] {
-		todo!()
-	}
-}
-
-impl PrimitiveType for Buffer {
-	fn memory(&self) -> &[u8] {
-		self.as_slice()
-	}
-
-	fn convert_to(&self, ty: StackItemType) -> Box<dyn StackItem> {
-		match ty {
-			StackItemType::Integer => {
-				if self.bytes.len() > i32::MAX as usize {
-					panic!("Invalid cast");
-				}
-				BigInt::from_bytes_le(Sign::NoSign, self.as_slice()).into()
-			},
-			StackItemType::ByteString => self.to_vec().into(),
-			StackItemType::Buffer => Buffer::from(self.memory()).into(),
-			StackItemType::Boolean => Boolean::from(self.get_boolean()).into(),
-			_ => panic!("Invalid cast"),
+	/// Only used by `Convert`, which reads a buffer the way it reads a byte string.
+	fn get_integer(&self) -> Result<BigInt, VMException> {
+		if self.data.len() > Integer::MAX_SIZE {
+			return Err(invalid_cast(StackItemType::Buffer, StackItemType::Integer))
 		}
+		Ok(BigInt::from_signed_bytes_le(&self.data))
 	}
-}
 
-impl From<Vec<u8>> for Buffer {
-	fn from(bytes: Vec<u8>) -> Self {
-		Self {
-			stack_references: 0,
-			object_references: RefCell::new(None),
-			dfn: 0,
-			low_link: 0,
-			on_stack: false,
-			bytes: Cow::Owned(bytes),
-		}
-	}
-}
-
-impl From<&[u8]> for Buffer {
-	fn from(bytes: &[u8]) -> Self {
-		Self {
-			stack_references: 0,
-			object_references: RefCell::new(None),
-			dfn: 0,
-			low_link: 0,
-			on_stack: false,
-			bytes: Cow::Borrowed(bytes),
-		}
-	}
-}
-
-impl Into<dyn StackItem> for Buffer {
-	fn into(self) -> Box<dyn StackItem> {
-		StackItem::VMBuffer(self)
-	}
-}
-
-impl Into<dyn PrimitiveType> for Buffer {
-	fn into(self) -> Box<dyn PrimitiveType> {
-		PrimitiveType::VMBuffer(self)
-	}
-}
-
-impl From<dyn PrimitiveType> for Buffer {
-	fn from(ty: &dyn PrimitiveType) -> Self {
-		match ty {
-			PrimitiveType::VMBuffer(b) => b,
-			_ => panic!("Invalid cast"),
-		}
+	fn get_slice(&self) -> Result<&[u8], VMException> {
+		Ok(&self.data)
 	}
 }
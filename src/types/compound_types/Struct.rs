@@ -1,33 +1,22 @@
 use crate::{
+	compound_types::{array::Array, compound_type::CompoundType},
 	execution_engine_limits::ExecutionEngineLimits,
 	reference_counter::ReferenceCounter,
-	stack_item::{ObjectReferenceEntry, StackItem},
+	stack_item::StackItem,
 	stack_item_type::StackItemType,
-	types::compound_types::{
-		array::Array,
-		compound_type::{CompoundType},
-	},
+	vm_exception::VMException,
 };
 use std::{
-	cell::{Ref, RefCell},
-	collections::{HashMap, VecDeque},
-	fmt::Debug,
-	hash::Hash,
+	any::Any,
+	cell::RefCell,
+	ops::{Deref, DerefMut},
 	rc::Rc,
 };
-use num_bigint::BigInt;
-use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+/// Represents a structure in the vm. Unlike arrays, structs are compared and copied by value.
+#[derive(Debug, Default)]
 pub struct Struct {
-	reference_counter: Option<Rc<RefCell<ReferenceCounter>>>,
-	stack_references: u32,
-	object_references: RefCell<Option<HashMap<dyn CompoundType, ObjectReferenceEntry>>>,
-	dfn: isize,
-	low_link: usize,
-	on_stack: bool,
-	array: Vec<Rc<RefCell<dyn StackItem>>>,
-	read_only: bool,
+	fields: Array,
 }
 
 impl Struct {
@@ -36,315 +25,97 @@ impl Struct {
 		fields: Option<Vec<Rc<RefCell<dyn StackItem>>>>,
 		reference_counter: Option<Rc<RefCell<ReferenceCounter>>>,
 	) -> Self {
-		Self {
-			reference_counter,
-			stack_references: 0,
-			object_references: RefCell::new(None),
-			dfn: 0,
-			low_link: 0,
-			on_stack: false,
-			array: fields.unwrap_or_default(),
-			read_only: false,
-		}
+		Self { fields: Array::new(fields, reference_counter) }
 	}
 
 	/// Create a new structure with the same content as this structure.
 	/// All nested structures will be copied by value.
-	pub fn clone(&self, limits: &ExecutionEngineLimits) -> Self {
-		let mut result = Struct::new(None, self.reference_counter.clone());
-		let mut queue = VecDeque::new();
-		queue.push_back(&mut result);
-		queue.push_back(&mut self.clone(limits));
-
-		let mut count = limits.max_stack_size - 1;
-		while !queue.is_empty() {
-			let mut a = queue.pop_front().unwrap();
-			let b = queue.pop_front().unwrap();
-			for item in &b.array {
-				count -= 1;
-
-				if count == 0 {
-					panic!("Beyond clone limits!");
-				}
-				match item.borrow().get_type() {
-					StackItemType::Struct => {
-						let mut sa = Struct::new(None, None);
-						a.array.push(Rc::new(RefCell::new(sa)));
-						queue.push_back(&mut sa);
-						queue.push_back(&mut item.borrow());
-					},
-					_ => {
-						a.array.push(item.clone());
-					},
-				}
+	///
+	/// Fails once more than `max_stack_size - 1` fields would be copied.
+	pub fn clone_with_limits(&self, limits: &ExecutionEngineLimits) -> Result<Self, VMException> {
+		let mut count = limits.max_stack_size.saturating_sub(1);
+		self.copy(&mut count)
+	}
+
+	fn copy(&self, count: &mut usize) -> Result<Self, VMException> {
+		let mut result = Struct::new(None, self.reference_counter().cloned());
+		for item in self.iter() {
+			*count = count
+				.checked_sub(1)
+				.ok_or_else(|| VMException::InvalidParameter("Beyond clone limits!".to_string()))?;
+			let copy = match item.borrow().as_any().downcast_ref::<Struct>() {
+				Some(inner) => Some(inner.copy(count)?),
+				None => None,
+			};
+			match copy {
+				Some(copy) => result.add(Rc::new(RefCell::new(copy))),
+				None => result.add(item.clone()),
 			}
 		}
-
-		result
-	}
-
-	/// Convert this struct to an array
-	pub fn to_array(&self) -> Array {
-		Array {
-			stack_references: self.stack_references,
-			reference_counter: self.reference_counter.clone(),
-			object_references: self.object_references.clone(),
-			dfn: self.dfn,
-			low_link: self.low_link,
-			on_stack: self.on_stack,
-			array: self.array.clone(),
-			read_only: self.read_only,
-		}
-	}
-
-	/// Compare this struct to another for equality
-	pub fn equals(&self, other: &Struct, limits: &ExecutionEngineLimits) -> bool {
-		let mut stack1 = VecDeque::new();
-		let mut stack2 = VecDeque::new();
-
-		stack1.push_back(self);
-		stack2.push_back(other);
-
-		let mut count = limits.max_stack_size;
-		let mut maxComparableSize = limits.max_comparable_size;
-
-		while !stack1.is_empty() {
-			if count == 0 {
-				panic!("Too many struct items to compare");
-			}
-			count -= 1;
-
-			let a = stack1.pop_front().unwrap();
-			let b = stack2.pop_front().unwrap();
-
-			match (a, b) {
-				(StackItem::VMByteString(a), StackItem::VMByteString(b)) =>
-					if a != b {
-						return false
-					},
-				(StackItem::VMStruct(sa), StackItem::VMStruct(sb)) => {
-					if Rc::ptr_eq(&sa, &sb) {
-						continue
-					}
-
-					if sa.fields.len() != sb.fields.len() {
-						return false
-					}
-
-					for item in &sa.fields {
-						stack1.push_back(item.clone());
-					}
-
-					for item in &sb.fields {
-						stack2.push_back(item.clone());
-					}
-				},
-				_ =>
-					if a != b {
-						return false
-					},
-			}
-
-			if maxComparableSize == 0 {
-				panic!("The operand exceeds the maximum comparable size");
-			}
-			maxComparableSize -= 1;
-		}
-
-		true
-	}
-}
-
-impl Clone for Struct {
-	fn clone(&self) -> Self {
-		todo!()
+		Ok(result)
 	}
 }
 
-impl PartialEq<Self> for Struct {
-	fn eq(&self, other: &Self) -> bool {
+impl Deref for Struct {
+	type Target = Array;
 
+	fn deref(&self) -> &Array {
+		&self.fields
 	}
 }
 
-impl Serialize for Struct {
-	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
-		serializer.serialize_bytes(self.array.as_slice())
-	}
-}
-
-impl Deserialize for Struct {
-	fn deserialize<'de, D>(, deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
-		let bytes = Vec::<dyn StackItem>::deserialize(deserializer)?;
-		Ok(Struct::new(Some(Rc::new(RefCell::new(bytes))), None);
+impl DerefMut for Struct {
+	fn deref_mut(&mut self) -> &mut Array {
+		&mut self.fields
 	}
 }
 
 impl StackItem for Struct {
-	const TRUE: Self = Default::default();
-
-	const FALSE: Self = Default::default();
-
-	const NULL: Self = Default::default();
-
-	fn dfn(&self) -> isize {
-		self.dfn
-	}
-
-	fn set_dfn(&mut self, dfn: isize) {
-		self.dfn = dfn;
-	}
-
-	fn low_link(&self) -> usize {
-		self.low_link
-	}
-
-	fn set_low_link(&mut self, link: usize) {
-		self.low_link = link;
-	}
-
-	fn on_stack(&self) -> bool {
-		self.on_stack
-	}
-
-	fn set_on_stack(&mut self, on_stack: bool) {
-		self.on_stack = on_stack;
-	}
-
-	fn set_object_references(&mut self, refs: Self::ObjectReferences) {
-		self.object_references = refs;
-	}
-
-	fn object_references(&self) -> &Self::ObjectReferences {
-		&self.object_references
-	}
-
-	fn set_stack_references(&mut self, count: usize) {
-		self.stack_references = count as u32;
-	}
-
-	fn stack_references(&self) -> usize {
-		self.stack_references as usize
-	}
-
-	fn cleanup(&mut self) {
-		todo!()
-	}
-
-	fn convert_to(&self, ty: StackItemType) -> Box<dyn StackItem> {
-		todo!()
-	}
-
-	fn get_slice(&self) -> &[u8] {
-		todo!()
-	}
-
 	fn get_type(&self) -> StackItemType {
 		StackItemType::Struct
 	}
-	fn get_boolean(&self) -> bool {
-		true
-	}
-	fn deep_copy(&self, asImmutable: bool) -> Box<dyn StackItem> {
-		todo!()
-	}
 
-	fn deep_copy_with_ref_map(&self, ref_map: &HashMap<&dyn StackItem, &dyn StackItem>, asImmutable: bool) -> Box<dyn StackItem> {
-		todo!()
+	fn as_any(&self) -> &dyn Any {
+		self
 	}
 
-	fn equals(&self, other: &Option<dyn StackItem>) -> bool {
-		todo!()
-	}
-
-	fn equals_with_limits(&self, other: &dyn StackItem, limits: &ExecutionEngineLimits) -> bool {
-		todo!()
-	}
-
-	fn get_integer(&self) -> BigInt {
-		todo!()
-	}
-
-	fn get_bytes(&self) -> &[u8] {
-		todo!()
+	fn as_any_mut(&mut self) -> &mut dyn Any {
+		self
 	}
 }
 
 impl CompoundType for Struct {
 	fn count(&self) -> usize {
-		self.array.len()
-	}
-
-	fn sub_items(&self) -> Vec<Ref<RefCell<dyn StackItem>>> {
-		self.array.iter().collect()
+		self.len()
 	}
 
-	fn sub_items_count(&self) -> usize {
-		self.count()
-	}
-
-	fn read_only(&mut self) {
-		self.read_only = true
-	}
-
-	fn is_read_only(&self) -> bool {
-		self.read_only
+	fn sub_items(&self) -> Vec<Rc<RefCell<dyn StackItem>>> {
+		self.fields.sub_items()
 	}
 
 	fn clear(&mut self) {
-		if self.read_only {
-			panic!("Cannot clear read-only struct")
-		}
-		if self.reference_counter.is_some() {
-			for item in self.array.iter() {
-				self.reference_counter
-					.unwrap()
-					.borrow_mut()
-					.remove_stack_reference(item.clone());
-			}
-		}
-		self.array.clear();
+		self.fields.clear()
 	}
 }
 
-impl From<Array> for Struct {
-	fn from(array: Array) -> Self {
-		Self {
-			reference_counter: array.reference_counter,
-			stack_references: array.stack_references,
-			object_references: array.object_references,
-			dfn: array.dfn,
-			low_link: array.low_link,
-			on_stack: array.on_stack,
-			array: array.array,
-			read_only: array.read_only,
-		}
-	}
-}
-
-impl From<&Array> for Struct {
-	fn from(array: &Array) -> Self {
-		Self {
-			reference_counter: array.reference_counter.clone(),
-			stack_references: array.stack_references,
-			object_references: array.object_references.clone(),
-			dfn: array.dfn,
-			low_link: array.low_link,
-			on_stack: array.on_stack,
-			array: array.array.clone(),
-			read_only: array.read_only,
-		}
-	}
-}
-
-impl Clone for Struct {
-	fn clone(&self) -> Self {
-		let mut result = Self::new(None, self.reference_counter.clone());
-
-		for item in &self.array {
-			result.array.push(item.clone());
-		}
-
-		result
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::primitive_types::integer::Integer;
+
+	#[test]
+	fn clone_copies_nested_structs() {
+		let inner: Rc<RefCell<dyn StackItem>> = Rc::new(RefCell::new(Struct::new(
+			Some(vec![Rc::new(RefCell::new(Integer::from(1)))]),
+			None,
+		)));
+		let outer = Struct::new(Some(vec![inner.clone()]), None);
+		let limits = ExecutionEngineLimits::default();
+		let copy = outer.clone_with_limits(&limits).unwrap();
+		assert!(!Rc::ptr_eq(&copy[0], &inner));
+		assert_eq!(copy[0].borrow().as_any().downcast_ref::<Struct>().unwrap().len(), 1);
+
+		let limits = ExecutionEngineLimits { max_stack_size: 2, ..limits };
+		assert!(outer.clone_with_limits(&limits).is_err());
 	}
 }
@@ -1,44 +1,16 @@
 use crate::{
-	reference_counter::ReferenceCounter,
-	stack_item::{ObjectReferenceEntry, StackItem},
-	stack_item_type::StackItemType,
-	types::compound_types::{
-		compound_type::{CompoundType},
-		Struct::Struct,
-	},
+	compound_types::compound_type::CompoundType, reference_counter::ReferenceCounter,
+	stack_item::StackItem, stack_item_type::StackItemType, vm_exception::VMException,
 };
-use std::{
-	cell::{Ref, RefCell},
-	collections::HashMap,
-	fmt::Debug,
-	hash::Hash,
-	ops::Index,
-	rc::Rc,
-};
-use std::any::Any;
-use num_bigint::BigInt;
-use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use serde::ser::SerializeSeq;
-use crate::execution_engine_limits::ExecutionEngineLimits;
+use std::{any::Any, cell::RefCell, ops::Index, rc::Rc};
 
-#[derive(Clone, PartialEq, Eq, Hash, Debug, Default, PartialOrd, Ord)]
+/// Represents an array or a complex object in the vm.
+///
+/// Every element counts as a reference on the reference counter, if the array has one.
+#[derive(Debug, Default)]
 pub struct Array {
-	pub stack_references: u32,
-	pub reference_counter: Option<Rc<RefCell<ReferenceCounter>>>,
-	pub object_references: RefCell<Option<HashMap<dyn CompoundType, ObjectReferenceEntry>>>,
-	pub dfn: isize,
-	pub low_link: usize,
-	pub on_stack: bool,
-	pub array: Vec<Rc<RefCell<dyn StackItem>>>,
-	pub read_only: bool,
-}
-
-impl Index<usize> for Array {
-	type Output = Rc<RefCell<dyn StackItem>>;
-
-	fn index(&self, index: usize) -> &Self::Output {
-		&self.array[index]
-	}
+	reference_counter: Option<Rc<RefCell<ReferenceCounter>>>,
+	items: Vec<Rc<RefCell<dyn StackItem>>>,
 }
 
 impl Array {
@@ -47,301 +19,148 @@ impl Array {
 		reference_counter: Option<Rc<RefCell<ReferenceCounter>>>,
 	) -> Self {
 		let items = items.unwrap_or_default();
-		Self {
-			stack_references: 0,
-			reference_counter,
-			object_references: RefCell::new(None),
-			dfn: 0,
-			low_link: 0,
-			on_stack: false,
-			array: items,
-			read_only: false,
-		}
-	}
-
-	pub fn add(&mut self, item: Rc<RefCell<dyn StackItem>>) {
-		self.array.push(item);
-	}
-
-	pub fn clear(&mut self) {
-		self.array.clear();
-	}
-
-	pub fn convert_to(&self, ty: StackItemType) -> Box<dyn StackItem> {
-		match ty {
-			StackItemType::Array => self.clone().into(),
-			StackItemType::Struct => Struct::from(self).into(),
-			_ => self.clone().into(),
-		}
-	}
-
-	pub fn deep_copy(&self, map: &mut HashMap<&dyn StackItem, dyn StackItem>) -> Box<dyn StackItem> {
-		if let Some(item) = map.get(self.into()) {
-			return item.clone()
-		}
-
-		let mut result = Array::new(None, None);
-		map.insert(self.into(), result.clone().into());
-
-		for item in &self.array {
-			result.add(item.deep_copy(map));
+		if let Some(counter) = &reference_counter {
+			counter.borrow().add_references(items.len());
 		}
-
-		result.into()
-	}
-
-	pub fn iter(&self) -> std::slice::Iter<Rc<RefCell<dyn StackItem>>> {
-		self.array.iter()
+		Self { reference_counter, items }
 	}
 
-	pub fn remove_at(&mut self, index: usize) {
-		self.array.remove(index);
+	pub fn reference_counter(&self) -> Option<&Rc<RefCell<ReferenceCounter>>> {
+		self.reference_counter.as_ref()
 	}
 
-	pub fn reverse(&mut self) {
-		self.array.reverse();
+	pub fn add(&mut self, item: Rc<RefCell<dyn StackItem>>) {
+		self.add_references(1);
+		self.items.push(item);
 	}
-}
 
-impl Clone for Array {
-	fn clone(&self) -> Self {
-		todo!()
+	pub fn get(&self, index: usize) -> Option<&Rc<RefCell<dyn StackItem>>> {
+		self.items.get(index)
 	}
-}
 
-impl PartialEq<Self> for Array {
-	fn eq(&self, other: &Self) -> bool {
-		todo!()
+	/// Replaces the element at `index`.
+	pub fn set(
+		&mut self,
+		index: usize,
+		item: Rc<RefCell<dyn StackItem>>,
+	) -> Result<(), VMException> {
+		let len = self.items.len();
+		let slot = self.items.get_mut(index).ok_or_else(|| {
+			VMException::InvalidParameter(format!("Index {index} out of range for {len} items"))
+		})?;
+		*slot = item;
+		Ok(())
 	}
-}
 
-impl Serialize for Array {
-	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
-		let mut seq = serializer.serialize_seq(Some(self.array.len()))?;
-		for item in self.array.iter() {
-			seq.serialize_element(item)?;
+	pub fn remove_at(&mut self, index: usize) -> Result<Rc<RefCell<dyn StackItem>>, VMException> {
+		if index >= self.items.len() {
+			return Err(VMException::InvalidParameter(format!(
+				"Index {index} out of range for {} items",
+				self.items.len()
+			)))
 		}
-		seq.end()
-	}
-}
-
-impl Deserialize for Array {
-	fn deserialize<'de, D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
-		let items = Vec::<Rc<RefCell<dyn StackItem>>>::deserialize(deserializer)?;
-		Ok(Array::new(Some(items), None))
-	}
-}
-
-impl StackItem for Array {
-	const TRUE: Self = Array::new(None, None);
-
-	const FALSE: Self = Array::new(None, None);
-
-	const NULL: Self = Default::default();
-
-	fn dfn(&self) -> isize {
-		self.dfn
-	}
-
-	fn set_dfn(&mut self, dfn: isize) {
-		self.dfn = dfn;
-	}
-
-	fn low_link(&self) -> usize {
-		self.low_link
-	}
-
-	fn set_low_link(&mut self, link: usize) {
-		self.low_link = link;
-	}
-
-	fn on_stack(&self) -> bool {
-		self.on_stack
+		self.remove_references(1);
+		Ok(self.items.remove(index))
 	}
 
-	fn set_on_stack(&mut self, on_stack: bool) {
-		self.on_stack = on_stack;
+	pub fn pop(&mut self) -> Option<Rc<RefCell<dyn StackItem>>> {
+		let item = self.items.pop()?;
+		self.remove_references(1);
+		Some(item)
 	}
 
-	fn set_object_references(&mut self, refs: Self::ObjectReferences) {
-		self.object_references = refs;
+	pub fn clear(&mut self) {
+		self.remove_references(self.items.len());
+		self.items.clear();
 	}
 
-	fn object_references(&self) -> &Self::ObjectReferences {
-		&self.object_references
+	pub fn reverse(&mut self) {
+		self.items.reverse();
 	}
 
-	fn set_stack_references(&mut self, count: usize) {
-		self.stack_references = count as u32;
+	pub fn len(&self) -> usize {
+		self.items.len()
 	}
 
-	fn stack_references(&self) -> usize {
-		self.stack_references as usize
+	pub fn is_empty(&self) -> bool {
+		self.items.is_empty()
 	}
 
-	fn cleanup(&mut self) {
-		todo!()
+	pub fn iter(&self) -> std::slice::Iter<'_, Rc<RefCell<dyn StackItem>>> {
+		self.items.iter()
 	}
 
-	fn convert_to(&self, ty: StackItemType) -> Box<dyn StackItem> {
-		if self.get_type() == StackItemType::Array && ty == StackItemType::Struct {
-			return StackItem::from(Struct::new(
-				Some(self.array.clone()),
-				self.reference_counter.clone(),
-			))
+	fn add_references(&self, count: usize) {
+		if let Some(counter) = &self.reference_counter {
+			counter.borrow().add_references(count);
 		}
-
-		self.convert_to(ty)?
 	}
 
-	fn get_slice(&self) -> &[u8] {
-		panic!("Cannot get slice of array")
-	}
-
-	fn get_type(&self) -> StackItemType {
-		StackItemType::Array
-	}
-	fn get_boolean(&self) -> bool {
-		true
-	}
-	fn deep_copy(&self, asImmutable: bool) -> Box<dyn StackItem> {
-		let result = if let StackItem::VMStruct(_) = self {
-			StackItem::VMStruct(Struct::new(None, self.reference_counter.clone()))
-		} else {
-			StackItem::VMArray(Array::new(None, self.reference_counter.clone()))
-		};
-
-		for item in self.array.iter() {
-			result.as_array_mut().push(item.deep_copy(asImmutable));
-		}
-
-		if asImmutable {
-			result.make_read_only();
+	fn remove_references(&self, count: usize) {
+		if let Some(counter) = &self.reference_counter {
+			counter.borrow().remove_references(count);
 		}
-
-		result
 	}
+}
 
-	fn deep_copy_with_ref_map(
-		&self,
-		ref_map: &HashMap<&dyn StackItem, &dyn StackItem>,
-		asImmutable: bool,
-	) -> Box<dyn StackItem> {
-		let result = if let StackItemType::Struct = self.get_type() {
-			StackItem::VMStruct(Struct::new(None, self.reference_counter.clone()))
-		} else {
-			StackItem::VMArray(Array::new(None, self.reference_counter.clone()))
-		};
-
-		for item in self.array.iter() {
-			result.as_array_mut().push(item.deep_copy_with_reference_map(ref_map, asImmutable));
-		}
-
-		if asImmutable {
-			result.make_read_only();
-		}
+impl Index<usize> for Array {
+	type Output = Rc<RefCell<dyn StackItem>>;
 
-		result
+	fn index(&self, index: usize) -> &Self::Output {
+		&self.items[index]
 	}
+}
 
-	fn equals(&self, other: &Option<dyn StackItem>) -> bool {
-		if let Some(other) = other {
-			if self.array.len() != other.as_array().len() {
-				return false;
-			}
-			for i in 0..self.array.len() {
-				if !self.array[i].equals(&other.as_array()[i]) {
-					return false;
-				}
-			}
-			true
-		} else {
-			false
-		}
+impl Drop for Array {
+	fn drop(&mut self) {
+		self.remove_references(self.items.len());
 	}
+}
 
-	fn equals_with_limits(&self, other: &dyn StackItem, limits: &ExecutionEngineLimits) -> bool {
-		if self.array.len() > limits.max_comparable_size || other.as_array().len() > limits.max_comparable_size {
-			panic!("Max comparable size exceeded")
-		} else {
-			self.equals(other)
-		}
+impl StackItem for Array {
+	fn get_type(&self) -> StackItemType {
+		StackItemType::Array
 	}
 
-	fn get_integer(&self) -> BigInt {
-		panic!("Cannot get integer from array");
+	fn as_any(&self) -> &dyn Any {
+		self
 	}
 
-	fn get_bytes(&self) -> &[u8] {
-		panic!("Cannot get bytes from array");
+	fn as_any_mut(&mut self) -> &mut dyn Any {
+		self
 	}
 }
 
 impl CompoundType for Array {
 	fn count(&self) -> usize {
-		self.array.len()
-	}
-
-	fn sub_items(&self) -> Vec<Ref<RefCell<dyn StackItem>>> {
-		self.array.iter().collect()
-	}
-
-	fn sub_items_count(&self) -> usize {
-		self.array.len()
+		self.len()
 	}
 
-	fn read_only(&mut self) {
-		self.read_only = true
-	}
-
-	fn is_read_only(&self) -> bool {
-		self.read_only
+	fn sub_items(&self) -> Vec<Rc<RefCell<dyn StackItem>>> {
+		self.items.clone()
 	}
 
 	fn clear(&mut self) {
-		if self.read_only {
-			panic!("Cannot clear read-only array")
-		}
-		if self.reference_counter.is_some() {
-			for item in self.array.iter() {
-				self.reference_counter
-					.unwrap()
-					.borrow_mut()
-					.remove_stack_reference(item.clone());
-			}
-		}
-		self.array.clear();
+		Array::clear(self)
 	}
 }
 
-
-impl Clone for Array {
-	fn clone(&mut self) -> Self {
-		let result = if let StackItem::VMStruct(_) = self {
-			StackItem::VMStruct(Struct::new(None, self.reference_counter.clone()))
-		} else {
-			StackItem::VMArray(Array::new(None, self.reference_counter.clone()))
-		};
-
-		self.array.append( result.clone());
-
-		for item in self.array.iter() {
-			result.as_array_mut().push(item.clone());
-		}
-
-		// if as_immutable {
-		// 	result.make_read_only();
-		// }
-
-		Self {
-			stack_references: self.stack_references,
-			reference_counter: self.reference_counter.clone(),
-			object_references: self.object_references.clone(),
-			dfn: self.dfn,
-			low_link: self.low_link,
-			on_stack: self.on_stack,
-			array: self.array.clone(),
-			read_only: false,
-		}
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::primitive_types::integer::Integer;
+
+	#[test]
+	fn counts_elements_as_references() {
+		let counter = Rc::new(RefCell::new(ReferenceCounter::new()));
+		let item = || Rc::new(RefCell::new(Integer::from(1))) as Rc<RefCell<dyn StackItem>>;
+		let mut array = Array::new(Some(vec![item(), item()]), Some(counter.clone()));
+		assert_eq!(counter.borrow().count(), 2);
+		array.add(item());
+		array.remove_at(0).unwrap();
+		assert!(array.remove_at(2).is_err());
+		assert_eq!(counter.borrow().count(), 2);
+		drop(array);
+		assert_eq!(counter.borrow().count(), 0);
 	}
 }
@@ -1,25 +1,45 @@
-use crate::{
-	stack_item::{StackItem},
-};
-use std::{
-	cell::{Ref, RefCell},
-	hash::Hash,
-};
+use crate::stack_item::StackItem;
+use std::{cell::RefCell, rc::Rc};
 
+/// The items that hold other items: `Array`, `Struct` and `Map`.
 pub trait CompoundType: StackItem {
+	/// The number of elements, or of entries for a map.
 	fn count(&self) -> usize;
-	fn sub_items(&self) -> Vec<Ref<RefCell<dyn StackItem>>>;
-	fn sub_items_count(&self) -> usize{
-		self.sub_items().len()
-	}
-	fn read_only(&self);
-	fn is_read_only(&self) -> bool {
-		false
-	}
 
+	/// The items held, with the keys of a map before its values.
+	fn sub_items(&self) -> Vec<Rc<RefCell<dyn StackItem>>>;
+
+	/// Removes every element and hands back its references.
 	fn clear(&mut self);
+}
+
+/// The item as a compound type, or `None` for any other item.
+pub fn as_compound(item: &dyn StackItem) -> Option<&dyn CompoundType> {
+	use crate::{
+		compound_types::{array::Array, map::Map, Struct::Struct},
+		stack_item_type::StackItemType,
+	};
+	let any = item.as_any();
+	match item.get_type() {
+		StackItemType::Array => any.downcast_ref::<Array>().map(|x| x as &dyn CompoundType),
+		StackItemType::Struct => any.downcast_ref::<Struct>().map(|x| x as &dyn CompoundType),
+		StackItemType::Map => any.downcast_ref::<Map>().map(|x| x as &dyn CompoundType),
+		_ => None,
+	}
+}
 
-	fn as_bool(&self) -> bool {
-		true
+/// The item as a mutable compound type, or `None` for any other item.
+pub fn as_compound_mut(item: &mut dyn StackItem) -> Option<&mut dyn CompoundType> {
+	use crate::{
+		compound_types::{array::Array, map::Map, Struct::Struct},
+		stack_item_type::StackItemType,
+	};
+	let ty = item.get_type();
+	let any = item.as_any_mut();
+	match ty {
+		StackItemType::Array => any.downcast_mut::<Array>().map(|x| x as &mut dyn CompoundType),
+		StackItemType::Struct => any.downcast_mut::<Struct>().map(|x| x as &mut dyn CompoundType),
+		StackItemType::Map => any.downcast_mut::<Map>().map(|x| x as &mut dyn CompoundType),
+		_ => None,
 	}
-}
\ No newline at end of file
+}
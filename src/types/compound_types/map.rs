@@ -1,87 +1,67 @@
 use crate::{
-	primitive_types::primitive_type::PrimitiveType,
-	reference_counter::ReferenceCounter,
-	stack_item::{ObjectReferenceEntry, StackItem,},
-	stack_item_type::StackItemType,
-	types::compound_types::compound_type::{CompoundType},
+	compound_types::compound_type::CompoundType, primitive_types::primitive_type::PrimitiveType,
+	reference_counter::ReferenceCounter, stack_item::StackItem, stack_item_type::StackItemType,
+	vm_exception::VMException,
 };
-use std::{
-	cell::RefCell,
-	collections::{
-		hash_map::{Entry, Iter, IterMut},
-		HashMap,
-	},
-	fmt::Debug,
-	hash::Hash,
-	rc::Rc,
-};
-use std::any::Any;
-use num_bigint::BigInt;
-use crate::execution_engine_limits::ExecutionEngineLimits;
-
-#[derive(Clone, PartialEq, Eq, Hash, Debug, Default, PartialOrd, Ord)]
+use indexmap::IndexMap;
+use std::{any::Any, cell::RefCell, rc::Rc};
+
+type Key = Rc<RefCell<dyn PrimitiveType>>;
+type Value = Rc<RefCell<dyn StackItem>>;
+
+/// Represents an ordered collection of key-value pairs in the vm.
+///
+/// Entries keep their insertion order, which is the order `Keys`, `Values` and the serializers
+/// see. Keys are compared by type and bytes. Every key and every value counts as a reference on
+/// the reference counter, if the map has one.
+#[derive(Debug, Default)]
 pub struct Map {
-	stack_references: u32,
 	reference_counter: Option<Rc<RefCell<ReferenceCounter>>>,
-	object_references: RefCell<Option<HashMap<dyn CompoundType, ObjectReferenceEntry>>>,
-	dfn: isize,
-	low_link: usize,
-	on_stack: bool,
-	dictionary: HashMap<Rc<RefCell<dyn PrimitiveType>>, Rc<RefCell<dyn StackItem>>>,
-	read_only: bool,
+	dictionary: IndexMap<(StackItemType, Vec<u8>), (Key, Value)>,
 }
 
 impl Map {
+	/// The largest size of a key in bytes.
 	pub const MAX_KEY_SIZE: usize = 64;
 
 	pub fn new(reference_counter: Option<Rc<RefCell<ReferenceCounter>>>) -> Self {
-		Self {
-			stack_references: 0,
-			reference_counter,
-			object_references: RefCell::new(None),
-			dfn: 0,
-			low_link: 0,
-			on_stack: false,
-			dictionary: HashMap::new(),
-			read_only: false,
+		Self { reference_counter, dictionary: IndexMap::new() }
+	}
+
+	/// Sets the value of `key`. A replaced value keeps the position of the entry.
+	pub fn insert(&mut self, key: Key, value: Value) -> Result<(), VMException> {
+		let id = Self::id(&*key.borrow())?;
+		match self.dictionary.get_mut(&id) {
+			Some(entry) => entry.1 = value,
+			None => {
+				if let Some(counter) = &self.reference_counter {
+					counter.borrow().add_references(2);
+				}
+				self.dictionary.insert(id, (key, value));
+			},
 		}
+		Ok(())
 	}
 
-	pub fn insert(&mut self, key: Rc<RefCell<dyn PrimitiveType>>, value: Rc<RefCell<dyn StackItem>>) {
-		if key.size() > Self::MAX_KEY_SIZE {
-			panic!("Max key size exceeded: {}", key.size());
-		}
-
-		self.dictionary.insert(key.clone(), value);
+	pub fn get(&self, key: &dyn PrimitiveType) -> Result<Option<Value>, VMException> {
+		Ok(self.dictionary.get(&Self::id(key)?).map(|(_, value)| value.clone()))
 	}
 
-	pub fn get(&self, key: Rc<RefCell<dyn PrimitiveType>>) -> Option<Rc<RefCell<dyn StackItem>>> {
-		if key.size() > Self::MAX_KEY_SIZE {
-			panic!("Max key size exceeded: {}", key.size());
-		}
-		match self.dictionary.get(&key) {
-			Some(value) => Some(value.clone()),
-			None => None,
-		}
+	pub fn contains_key(&self, key: &dyn PrimitiveType) -> Result<bool, VMException> {
+		Ok(self.dictionary.contains_key(&Self::id(key)?))
 	}
 
-	pub fn contains_key(&self, key: Rc<RefCell<dyn PrimitiveType>>) -> bool {
-		if key.size() > Self::MAX_KEY_SIZE {
-			panic!("Max key size exceeded: {}", key.size());
-		}
-
-		self.dictionary.contains_key(&key)
-	}
-
-	pub fn remove(&mut self, key: Rc<RefCell<dyn PrimitiveType>>) -> Option<Rc<RefCell<dyn StackItem>>> {
-		if key.size() > Self::MAX_KEY_SIZE {
-			panic!("Max key size exceeded: {}", key.size());
+	/// Removes `key`, keeping the order of the other entries.
+	pub fn remove(&mut self, key: &dyn PrimitiveType) -> Result<Option<Value>, VMException> {
+		let removed = self.dictionary.shift_remove(&Self::id(key)?);
+		if removed.is_some() {
+			if let Some(counter) = &self.reference_counter {
+				counter.borrow().remove_references(2);
+			}
 		}
-
-		self.dictionary.remove(&key)
+		Ok(removed.map(|(_, value)| value))
 	}
 
-	// Other map methods...
 	pub fn len(&self) -> usize {
 		self.dictionary.len()
 	}
@@ -91,178 +71,120 @@ impl Map {
 	}
 
 	pub fn clear(&mut self) {
+		if let Some(counter) = &self.reference_counter {
+			counter.borrow().remove_references(2 * self.dictionary.len());
+		}
 		self.dictionary.clear();
 	}
 
-	pub fn keys(&self) -> Vec<Rc<RefCell<dyn StackItem>>> {
-		self.dictionary.into_keys().collect()
+	pub fn keys(&self) -> impl Iterator<Item = &Key> + '_ {
+		self.dictionary.values().map(|(key, _)| key)
 	}
 
-	pub fn values(&self) -> Vec<Rc<RefCell<dyn StackItem>>> {
-		self.dictionary.into_values().collect()
+	pub fn values(&self) -> impl Iterator<Item = &Value> + '_ {
+		self.dictionary.values().map(|(_, value)| value)
 	}
 
-	pub fn iter(&self) -> Iter<'_, Rc<RefCell<dyn PrimitiveType>>, Rc<RefCell<dyn StackItem>>> {
-		self.dictionary.iter()
+	/// The entries in insertion order.
+	pub fn iter(&self) -> impl Iterator<Item = (&Key, &Value)> + '_ {
+		self.dictionary.values().map(|(key, value)| (key, value))
 	}
 
-	pub fn iter_mut(&mut self) -> IterMut<'_, Rc<RefCell<dyn PrimitiveType>>, Rc<RefCell<dyn StackItem>>> {
-		self.dictionary.iter_mut()
+	fn id(key: &dyn PrimitiveType) -> Result<(StackItemType, Vec<u8>), VMException> {
+		if key.size() > Self::MAX_KEY_SIZE {
+			return Err(VMException::InvalidParameter(format!(
+				"Max key size exceeded: {}",
+				key.size()
+			)))
+		}
+		Ok((key.get_type(), key.memory().to_vec()))
 	}
+}
 
-	pub fn entry(
-		&mut self,
-		key: Rc<RefCell<dyn PrimitiveType>>,
-	) -> Entry<'_, Rc<RefCell<dyn PrimitiveType>>, Rc<RefCell<dyn StackItem>>> {
-		self.dictionary.entry(key)
+impl Drop for Map {
+	fn drop(&mut self) {
+		if let Some(counter) = &self.reference_counter {
+			counter.borrow().remove_references(2 * self.dictionary.len());
+		}
 	}
 }
 
 impl StackItem for Map {
-	const TRUE: Self =  Default::default();
-
-	const FALSE: Self =  Default::default();
-
-	const NULL: Self = Default::default();
-
-	fn dfn(&self) -> isize {
-		self.dfn
-	}
-
-	fn set_dfn(&mut self, dfn: isize) {
-		self.dfn = dfn;
-	}
-
-	fn low_link(&self) -> usize {
-		self.low_link
-	}
-
-	fn set_low_link(&mut self, link: usize) {
-		self.low_link = link;
-	}
-
-	fn on_stack(&self) -> bool {
-		self.on_stack
-	}
-
-	fn set_on_stack(&mut self, on_stack: bool) {
-		self.on_stack = on_stack;
-	}
-
-	fn set_object_references(&mut self, refs: Self::ObjectReferences) {
-		self.object_references = refs;
-	}
-
-	fn object_references(&self) -> &Self::ObjectReferences {
-		&self.object_references
-	}
-
-	fn set_stack_references(&mut self, count: usize) {
-		self.stack_references = count as u32;
-	}
-
-	fn stack_references(&self) -> usize {
-		self.stack_references as usize
-	}
-
-	fn cleanup(&mut self) {
-		todo!()
-	}
-
-	fn convert_to(&self, ty: StackItemType) -> Box<dyn StackItem> {
-		todo!()
-	}
-
-	fn get_slice(&self) -> &[u8] {
-		panic!("Cannot get slice of map")
-	}
-
 	fn get_type(&self) -> StackItemType {
 		StackItemType::Map
 	}
-	fn get_boolean(&self) -> bool {
-		true
-	}
-	fn deep_copy(&self, asImmutable: bool) -> Box<dyn StackItem> {
-		todo!()
-	}
-
-	fn deep_copy_with_ref_map(&self, ref_map: &HashMap<&dyn StackItem, &dyn StackItem>, asImmutable: bool) -> Box<dyn StackItem> {
-		todo!()
-	}
-
-	fn equals(&self, other: &Option<dyn StackItem>) -> bool {
-		todo!()
-	}
-
-	fn equals_with_limits(&self, other: &dyn StackItem, limits: &ExecutionEngineLimits) -> bool {
-		todo!()
-	}
-
-	fn get_integer(&self) -> BigInt {
-		todo!()
-	}
 
-	fn get_interface<T: Any>(&self) -> Option<&T> {
-		todo!()
+	fn as_any(&self) -> &dyn Any {
+		self
 	}
 
-	fn get_bytes(&self) -> &[u8] {
-		todo!()
+	fn as_any_mut(&mut self) -> &mut dyn Any {
+		self
 	}
 }
 
 impl CompoundType for Map {
 	fn count(&self) -> usize {
-		self.dictionary.len()
+		self.len()
 	}
 
 	fn sub_items(&self) -> Vec<Rc<RefCell<dyn StackItem>>> {
-		self.dictionary.keys().chain(self.dictionary.values()).cloned().collect()
+		let keys = self.keys().map(|key| key.clone() as Value);
+		keys.chain(self.values().cloned()).collect()
 	}
 
-	fn sub_items_count(&self) -> usize {
-		self.count() * 2
+	fn clear(&mut self) {
+		Map::clear(self)
 	}
+}
 
-	fn read_only(&mut self) {
-		self.read_only = true;
-	}
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::primitive_types::{boolean::Boolean, byte_string::ByteString, integer::Integer};
 
-	fn is_read_only(&self) -> bool {
-		self.read_only
+	fn key(item: impl PrimitiveType + 'static) -> Key {
+		Rc::new(RefCell::new(item))
 	}
 
-	fn clear(&mut self) {
-		if self.read_only {
-			panic!("Cannot clear read-only map")
-		}
-		if self.reference_counter.is_some() {
-			for (key, value) in self.dictionary.iter() {
-				self.reference_counter
-					.unwrap()
-					.get_mut()
-					.remove_stack_reference(key.clone().into());
-				self.reference_counter.unwrap().get_mut().remove_stack_reference(value.clone());
-			}
-		}
-		self.dictionary.clear();
+	fn value(n: i32) -> Value {
+		Rc::new(RefCell::new(Integer::from(n)))
 	}
-}
 
-impl PartialEq for Map {
-	fn eq(&self, other: &Self) -> bool {
-		self.dictionary == other.dictionary
-	}
-}
-impl Clone for Map {
-	fn clone(&self) -> Self {
-		let mut result = Self::new(self.reference_counter.clone());
-		// ref_map.insert(self, result.clone());
-		for (key, value) in self.dictionary.iter() {
-			result.dictionary.insert(key.clone(), value.clone());
+	#[test]
+	fn keeps_insertion_order() {
+		let counter = Rc::new(RefCell::new(ReferenceCounter::new()));
+		let mut map = Map::new(Some(counter.clone()));
+		for (i, name) in ["c", "a", "b"].into_iter().enumerate() {
+			map.insert(key(ByteString::from(name)), value(i as i32)).unwrap();
 		}
-
-		result
+		map.insert(key(ByteString::from("a")), value(9)).unwrap();
+		let keys = map.keys().map(|key| key.borrow().memory().to_vec()).collect::<Vec<_>>();
+		assert_eq!(keys, [b"c", b"a", b"b"]);
+		assert_eq!(
+			map.get(&ByteString::from("a"))
+				.unwrap()
+				.unwrap()
+				.borrow()
+				.get_integer()
+				.unwrap(),
+			9.into()
+		);
+		assert_eq!(counter.borrow().count(), 6);
+
+		map.remove(&ByteString::from("c")).unwrap();
+		let keys = map.keys().map(|key| key.borrow().memory().to_vec()).collect::<Vec<_>>();
+		assert_eq!(keys, [b"a", b"b"]);
+		drop(map);
+		assert_eq!(counter.borrow().count(), 0);
+	}
+
+	#[test]
+	fn keys_compare_by_type_and_bytes() {
+		let mut map = Map::new(None);
+		map.insert(key(Integer::from(1)), value(1)).unwrap();
+		assert!(!map.contains_key(&Boolean::new(true)).unwrap());
+		assert!(map.contains_key(&Integer::from(1)).unwrap());
+		assert!(map.insert(key(ByteString::new(vec![0; 65])), value(0)).is_err());
 	}
 }
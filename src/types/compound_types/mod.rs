@@ -1,3 +1,4 @@
+#[allow(non_snake_case)]
 pub mod Struct;
 pub mod array;
 pub mod compound_type;
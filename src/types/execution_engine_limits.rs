@@ -1,7 +1,9 @@
-use std::num::NonZeroU32;
+use crate::vm_exception::VMException;
+use serde::{Deserialize, Serialize};
 
 /// Represents the restrictions on the vm.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
 pub struct ExecutionEngineLimits {
 	/// The maximum number of bits that `OpCode::SHL` and `OpCode::SHR` can shift.
 	pub max_shift: usize,
@@ -42,17 +44,19 @@ impl Default for ExecutionEngineLimits {
 impl ExecutionEngineLimits {
 	/// Assert that the size of the item meets the limit.
 	#[inline]
-	pub fn assert_max_item_size(&self, size: u32) {
-		if size == 0 || size > self.max_item_size as u32 {
-			panic!("MaxItemSize exceeded: {size}");
+	pub fn assert_max_item_size(&self, size: usize) -> Result<(), VMException> {
+		if size > self.max_item_size {
+			return Err(VMException::ItemTooLarge(format!("MaxItemSize exceeded: {size}")))
 		}
+		Ok(())
 	}
 
 	/// Assert that the number of bits shifted meets the limit.
 	#[inline]
-	pub fn assert_shift(&self, shift: i32) {
-		if shift > self.max_shift as i32 || shift < 0 {
-			panic!("Invalid shift value: {shift}");
+	pub fn assert_shift(&self, shift: i64) -> Result<(), VMException> {
+		if shift < 0 || shift > self.max_shift as i64 {
+			return Err(VMException::InvalidParameter(format!("Invalid shift value: {shift}")))
 		}
+		Ok(())
 	}
 }
@@ -0,0 +1,372 @@
+use crate::{
+	compound_types::{array::Array, map::Map, Struct::Struct},
+	execution_engine_limits::ExecutionEngineLimits,
+	null::Null,
+	primitive_types::{boolean::Boolean, byte_string::ByteString, integer::Integer},
+	reference_counter::ReferenceCounter,
+	stack_item::StackItem,
+	stack_item_type::StackItemType,
+	vm_exception::VMException,
+};
+use num_bigint::BigInt;
+use num_traits::FromPrimitive;
+use serde::{
+	de::{Error as _, MapAccess, SeqAccess, Visitor},
+	Deserialize, Deserializer,
+};
+use std::{
+	cell::RefCell,
+	collections::HashSet,
+	fmt::{Formatter, Write},
+	rc::Rc,
+};
+
+/// The largest integer JSON numbers can hold without losing precision, `2^53 - 1`.
+pub const MAX_SAFE_INTEGER: i64 = 9_007_199_254_740_991;
+
+/// The smallest integer JSON numbers can hold without losing precision.
+pub const MIN_SAFE_INTEGER: i64 = -MAX_SAFE_INTEGER;
+
+/// The deepest nesting `deserialize` accepts, as in `StdLib.jsonDeserialize`.
+pub const MAX_JSON_DEPTH: usize = 10;
+
+/// Serializes `item` like `StdLib.jsonSerialize`.
+///
+/// Arrays and structs become arrays, maps with `ByteString` keys become objects, byte strings
+/// and buffers become strings (they must be valid UTF-8), integers become numbers within the
+/// safe range and `Null` becomes `null`. Strings are escaped like the default encoder of .NET's
+/// `Utf8JsonWriter`, so the output matches the chain byte for byte. Fails once the output
+/// exceeds `max_size` bytes.
+pub fn serialize(item: &dyn StackItem, max_size: usize) -> Result<Vec<u8>, VMException> {
+	let mut writer = Writer { output: String::new(), max_size, path: Vec::new() };
+	writer.write_item(item)?;
+	Ok(writer.output.into_bytes())
+}
+
+/// Deserializes JSON like `StdLib.jsonDeserialize`.
+///
+/// Numbers must be integers, objects become maps keyed by `ByteString`, and nesting is limited
+/// to [`MAX_JSON_DEPTH`]. At most `max_stack_size` items, map keys included, are created.
+pub fn deserialize(
+	json: &[u8],
+	limits: &ExecutionEngineLimits,
+	reference_counter: Option<Rc<RefCell<ReferenceCounter>>>,
+) -> Result<Rc<RefCell<dyn StackItem>>, VMException> {
+	let token: JsonToken =
+		serde_json::from_slice(json).map_err(|e| VMException::InvalidParameter(e.to_string()))?;
+	if token.depth() > MAX_JSON_DEPTH {
+		return Err(VMException::InvalidParameter(format!(
+			"JSON is nested deeper than {MAX_JSON_DEPTH}"
+		)))
+	}
+	let mut remaining = limits.max_stack_size;
+	build(token, &mut remaining, &reference_counter)
+}
+
+struct Writer {
+	output: String,
+	max_size: usize,
+	path: Vec<*const ()>,
+}
+
+impl Writer {
+	fn write_item(&mut self, item: &dyn StackItem) -> Result<(), VMException> {
+		match item.get_type() {
+			StackItemType::Any => self.output.push_str("null"),
+			StackItemType::Boolean =>
+				self.output.push_str(if item.get_boolean()? { "true" } else { "false" }),
+			StackItemType::Integer => {
+				let value = item.get_integer()?;
+				if value > BigInt::from(MAX_SAFE_INTEGER) || value < BigInt::from(MIN_SAFE_INTEGER)
+				{
+					return Err(VMException::InvalidParameter(format!(
+						"{value} is outside the JSON safe integer range"
+					)))
+				}
+				self.output.push_str(&value.to_string());
+			},
+			StackItemType::ByteString | StackItemType::Buffer =>
+				self.write_string(item.get_slice()?)?,
+			StackItemType::Array | StackItemType::Struct | StackItemType::Map => {
+				let id = item as *const dyn StackItem as *const ();
+				if self.path.contains(&id) {
+					return Err(VMException::InvalidParameter("Circular reference.".to_string()))
+				}
+				self.path.push(id);
+				self.write_compound(item)?;
+				self.path.pop();
+			},
+			ty => return Err(VMException::InvalidType(format!("{ty:?} cannot be serialized"))),
+		}
+		self.check_size()
+	}
+
+	fn write_compound(&mut self, item: &dyn StackItem) -> Result<(), VMException> {
+		let any = item.as_any();
+		if let Some(map) = any.downcast_ref::<Map>() {
+			self.output.push('{');
+			for (index, (key, value)) in map.iter().enumerate() {
+				if index > 0 {
+					self.output.push(',');
+				}
+				let key = key.borrow();
+				if key.get_type() != StackItemType::ByteString {
+					return Err(VMException::InvalidType(
+						"Map keys must be byte strings to serialize as JSON".to_string(),
+					))
+				}
+				self.write_string(key.get_slice()?)?;
+				self.output.push(':');
+				self.write_item(&*value.borrow())?;
+			}
+			self.output.push('}');
+			return Ok(())
+		}
+
+		let items: Vec<&Rc<RefCell<dyn StackItem>>> = match any.downcast_ref::<Struct>() {
+			Some(st) => st.iter().collect(),
+			None => any.downcast_ref::<Array>().unwrap().iter().collect(),
+		};
+		self.output.push('[');
+		for (index, sub_item) in items.into_iter().enumerate() {
+			if index > 0 {
+				self.output.push(',');
+			}
+			self.write_item(&*sub_item.borrow())?;
+		}
+		self.output.push(']');
+		Ok(())
+	}
+
+	fn write_string(&mut self, bytes: &[u8]) -> Result<(), VMException> {
+		let text = std::str::from_utf8(bytes)
+			.map_err(|_| VMException::InvalidParameter("String is not valid UTF-8".to_string()))?;
+		self.output.push('"');
+		for c in text.chars() {
+			match c {
+				'\n' => self.output.push_str("\\n"),
+				'\r' => self.output.push_str("\\r"),
+				'\t' => self.output.push_str("\\t"),
+				'\\' => self.output.push_str("\\\\"),
+				'\u{8}' => self.output.push_str("\\b"),
+				'\u{c}' => self.output.push_str("\\f"),
+				' '..='~' if !"\"&'+<>`".contains(c) => self.output.push(c),
+				_ =>
+					for unit in c.encode_utf16(&mut [0; 2]) {
+						write!(self.output, "\\u{unit:04X}").unwrap();
+					},
+			}
+		}
+		self.output.push('"');
+		self.check_size()
+	}
+
+	fn check_size(&self) -> Result<(), VMException> {
+		if self.output.len() > self.max_size {
+			return Err(VMException::ItemTooLarge(format!("JSON exceeds {} bytes", self.max_size)))
+		}
+		Ok(())
+	}
+}
+
+/// A parsed JSON value that keeps object properties in order and rejects duplicate keys.
+enum JsonToken {
+	Null,
+	Boolean(bool),
+	Integer(BigInt),
+	Float(f64),
+	String(String),
+	Array(Vec<JsonToken>),
+	Object(Vec<(String, JsonToken)>),
+}
+
+impl JsonToken {
+	fn depth(&self) -> usize {
+		match self {
+			JsonToken::Array(items) => 1 + items.iter().map(JsonToken::depth).max().unwrap_or(0),
+			JsonToken::Object(properties) =>
+				1 + properties.iter().map(|(_, value)| value.depth()).max().unwrap_or(0),
+			_ => 0,
+		}
+	}
+}
+
+impl<'de> Deserialize<'de> for JsonToken {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		deserializer.deserialize_any(JsonTokenVisitor)
+	}
+}
+
+struct JsonTokenVisitor;
+
+impl<'de> Visitor<'de> for JsonTokenVisitor {
+	type Value = JsonToken;
+
+	fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+		f.write_str("a JSON value")
+	}
+
+	fn visit_unit<E>(self) -> Result<JsonToken, E> {
+		Ok(JsonToken::Null)
+	}
+
+	fn visit_bool<E>(self, value: bool) -> Result<JsonToken, E> {
+		Ok(JsonToken::Boolean(value))
+	}
+
+	fn visit_i64<E>(self, value: i64) -> Result<JsonToken, E> {
+		Ok(JsonToken::Integer(BigInt::from(value)))
+	}
+
+	fn visit_u64<E>(self, value: u64) -> Result<JsonToken, E> {
+		Ok(JsonToken::Integer(BigInt::from(value)))
+	}
+
+	fn visit_f64<E>(self, value: f64) -> Result<JsonToken, E> {
+		Ok(JsonToken::Float(value))
+	}
+
+	fn visit_str<E>(self, value: &str) -> Result<JsonToken, E> {
+		Ok(JsonToken::String(value.to_string()))
+	}
+
+	fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<JsonToken, A::Error> {
+		let mut items = Vec::new();
+		while let Some(item) = seq.next_element()? {
+			items.push(item);
+		}
+		Ok(JsonToken::Array(items))
+	}
+
+	fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<JsonToken, A::Error> {
+		let mut keys = HashSet::new();
+		let mut properties = Vec::new();
+		while let Some((key, value)) = map.next_entry::<String, JsonToken>()? {
+			if !keys.insert(key.clone()) {
+				return Err(A::Error::custom(format!("duplicate property `{key}`")))
+			}
+			properties.push((key, value));
+		}
+		Ok(JsonToken::Object(properties))
+	}
+}
+
+fn build(
+	token: JsonToken,
+	remaining: &mut usize,
+	reference_counter: &Option<Rc<RefCell<ReferenceCounter>>>,
+) -> Result<Rc<RefCell<dyn StackItem>>, VMException> {
+	take_item(remaining)?;
+	let item: Rc<RefCell<dyn StackItem>> = match token {
+		JsonToken::Null => Rc::new(RefCell::new(Null)),
+		JsonToken::Boolean(value) => Rc::new(RefCell::new(Boolean::new(value))),
+		JsonToken::Integer(value) => Rc::new(RefCell::new(Integer::try_from(value)?)),
+		JsonToken::Float(value) => {
+			if value.fract() != 0.0 {
+				return Err(VMException::InvalidParameter(
+					"Decimal value is not allowed".to_string(),
+				))
+			}
+			let value = BigInt::from_f64(value).ok_or_else(|| {
+				VMException::InvalidParameter(format!("{value} is not a finite number"))
+			})?;
+			Rc::new(RefCell::new(Integer::try_from(value)?))
+		},
+		JsonToken::String(value) => Rc::new(RefCell::new(ByteString::new(value.into_bytes()))),
+		JsonToken::Array(items) => {
+			let items = items
+				.into_iter()
+				.map(|item| build(item, remaining, reference_counter))
+				.collect::<Result<_, _>>()?;
+			Rc::new(RefCell::new(Array::new(Some(items), reference_counter.clone())))
+		},
+		JsonToken::Object(properties) => {
+			let mut map = Map::new(reference_counter.clone());
+			for (key, value) in properties {
+				take_item(remaining)?;
+				if key.len() > Map::MAX_KEY_SIZE {
+					return Err(VMException::InvalidParameter(format!(
+						"Map key of {} bytes is too large",
+						key.len()
+					)))
+				}
+				let value = build(value, remaining, reference_counter)?;
+				map.insert(Rc::new(RefCell::new(ByteString::new(key.into_bytes()))), value)?;
+			}
+			Rc::new(RefCell::new(map))
+		},
+	};
+	Ok(item)
+}
+
+fn take_item(remaining: &mut usize) -> Result<(), VMException> {
+	*remaining = remaining
+		.checked_sub(1)
+		.ok_or_else(|| VMException::StackOverflow("Too many items in JSON".to_string()))?;
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn round_trip(json: &str) -> Result<String, VMException> {
+		let limits = ExecutionEngineLimits::default();
+		let item = deserialize(json.as_bytes(), &limits, None)?;
+		let bytes = serialize(&*item.borrow(), limits.max_item_size)?;
+		Ok(String::from_utf8(bytes).unwrap())
+	}
+
+	#[test]
+	fn round_trips_stdlib_values() {
+		let json = r#"[1,-2,true,null,"hé\"",{"a":{"b":[]}},9007199254740991,1e3]"#;
+		assert_eq!(
+			round_trip(json).unwrap(),
+			r#"[1,-2,true,null,"h\u00E9\u0022",{"a":{"b":[]}},9007199254740991,1000]"#
+		);
+	}
+
+	#[test]
+	fn escapes_strings_like_utf8_json_writer() {
+		let json = r#"{"<k>":"a+b&c'd`e\\f\n\t\b\f\r\u0001\u007f 😀 ~"}"#;
+		assert_eq!(
+			round_trip(json).unwrap(),
+			r#"{"\u003Ck\u003E":"a\u002Bb\u0026c\u0027d\u0060e\\f\n\t\b\f\r\u0001\u007F \uD83D\uDE00 ~"}"#
+		);
+	}
+
+	#[test]
+	fn rejects_integers_beyond_32_bytes() {
+		let limits = ExecutionEngineLimits::default();
+		for json in ["1e300", "-1e300", &"9".repeat(100)] {
+			assert!(
+				matches!(
+					deserialize(json.as_bytes(), &limits, None),
+					Err(VMException::InvalidType(_))
+				),
+				"{json}"
+			);
+		}
+		assert!(deserialize(b"1e76", &limits, None).is_ok());
+	}
+
+	#[test]
+	fn keeps_object_keys_in_document_order() {
+		let json = r#"{"zeta":1,"alpha":{"y":true,"x":null},"mid":"m"}"#;
+		assert_eq!(round_trip(json).unwrap(), json);
+	}
+
+	#[test]
+	fn rejects_what_stdlib_rejects() {
+		let limits = ExecutionEngineLimits::default();
+		for json in ["1.5", r#"{"a":1,"a":2}"#, "[[[[[[[[[[[1]]]]]]]]]]]", "[1,", "NaN"] {
+			assert!(deserialize(json.as_bytes(), &limits, None).is_err(), "{json}");
+		}
+		assert!(deserialize(b"[[[[[[[[[[1]]]]]]]]]]", &limits, None).is_ok());
+
+		let unsafe_integer = Integer::new(&(BigInt::from(MAX_SAFE_INTEGER) + 1));
+		assert!(serialize(&unsafe_integer, limits.max_item_size).is_err());
+		assert!(serialize(&ByteString::new(vec![0xff]), limits.max_item_size).is_err());
+		assert!(serialize(&ByteString::new(b"abc".to_vec()), 4).is_err());
+	}
+}
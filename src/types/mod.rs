@@ -1,9 +1,12 @@
+pub mod binary_serializer;
 pub mod execution_engine_limits;
 pub mod interop_interface;
+pub mod json_serializer;
 pub mod reference_counter;
 pub mod stack_item;
+pub mod stack_item_json;
 pub mod stack_item_type;
-pub mod tarjan;
+pub mod uint;
 
 pub mod buffer;
 
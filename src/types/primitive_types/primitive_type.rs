@@ -1,38 +1,30 @@
-use std::cell::RefCell;
-use std::collections::HashMap;
-use serde::__private::de::Content::String;
 use crate::{
-	stack_item::{StackItem},
+	primitive_types::{boolean::Boolean, byte_string::ByteString, integer::Integer},
+	stack_item::{invalid_cast, StackItem},
+	stack_item_type::StackItemType,
+	vm_exception::VMException,
 };
-use crate::buffer::Buffer;
-use crate::primitive_types::boolean::Boolean;
-use crate::primitive_types::byte_string::ByteString;
-use crate::primitive_types::integer::Integer;
-use crate::stack_item_type::StackItemType;
+use std::{cell::RefCell, rc::Rc};
 
-pub trait PrimitiveType: StackItem + Clone {
+/// The immutable items that can be map keys: `Boolean`, `Integer` and `ByteString`.
+pub trait PrimitiveType: StackItem {
+	/// The bytes of the item. Integers are little-endian two's complement, zero is empty.
 	fn memory(&self) -> &[u8];
 
 	/// The size of the vm object in bytes.
 	fn size(&self) -> usize {
 		self.memory().len()
 	}
+}
 
-	fn convert_to(&self, type_: StackItemType) -> Result<Self, Err>  {
-		match type_ {
-			StackItemType::Integer => Ok(Integer::from(self.get_integer())),
-			StackItemType::ByteString =>  Ok(ByteString::from( String::from_utf8(self.memory())?)),
-			StackItemType::Buffer =>  Ok(Buffer::from(self.get_slice()).into()),
-			StackItemType::Boolean =>  Ok(Boolean::from(self.get_boolean().into()).into()),
-			_ => panic!(), //self.base_convert_to(ty),
-		}
-	}
-
-	fn deep_copy_with_ref_map(&self, ref_map: &HashMap<&dyn StackItem, &dyn StackItem>) -> Box<dyn StackItem> {
-		self.clone()
-	}
-
-	fn get_slice(&self) -> &[u8]{
-		self.memory()
-	}
+/// Copies a primitive item, e.g. to use it as a map key. Fails for every other type.
+pub fn to_primitive(item: &dyn StackItem) -> Result<Rc<RefCell<dyn PrimitiveType>>, VMException> {
+	let primitive: Rc<RefCell<dyn PrimitiveType>> = match item.get_type() {
+		StackItemType::Boolean => Rc::new(RefCell::new(Boolean::new(item.get_boolean()?))),
+		StackItemType::Integer => Rc::new(RefCell::new(Integer::try_from(item.get_integer()?)?)),
+		StackItemType::ByteString =>
+			Rc::new(RefCell::new(ByteString::new(item.get_slice()?.to_vec()))),
+		ty => return Err(invalid_cast(ty, StackItemType::ByteString)),
+	};
+	Ok(primitive)
 }
@@ -1,223 +1,108 @@
-#![feature(linked_list_remove)]
-
 use crate::{
-	stack_item::{StackItem},
+	compound_types::compound_type::{as_compound, as_compound_mut},
+	stack_item::StackItem,
 };
 use std::{
-	cell::RefCell,
-	cmp::Eq,
-	collections::{HashMap, HashSet, LinkedList},
-	hash::{Hash, Hasher},
-	marker::PhantomData,
-	rc::Rc,
+	cell::{Cell, RefCell},
+	collections::HashMap,
+	rc::{Rc, Weak},
 };
-use crate::compound_types::compound_type::CompoundType;
-
-#[derive(Debug)]
-pub struct ReferenceEntry<T>
-where
-	T: CompoundType,
-{
-	item: T,
-	references: usize,
-}
 
-#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+/// Counts the references that `ExecutionEngineLimits::max_stack_size` applies to: one for every
+/// item on an evaluation stack or in a slot, and one for every element of a live array or struct
+/// and for every key and value of a live map.
+///
+/// Stacks, slots and compound items hand their references back when they are dropped. Compound
+/// items that only a reference cycle keeps alive are never dropped, so
+/// [`check_zero_referred`](Self::check_zero_referred) finds and clears them.
+#[derive(Debug, Default)]
 pub struct ReferenceCounter {
-	tracked_items: HashSet<Rc<RefCell<dyn StackItem>>>,
-	zero_referred: HashSet<Rc<RefCell<dyn StackItem>>>,
-	cached_components: Option<LinkedList<HashSet<Rc<RefCell<dyn StackItem>>>>>,
-	references_count: usize,
-	phantom: PhantomData<Rc<RefCell<dyn StackItem>>>,
+	references: Cell<usize>,
+
+	/// The compound items that have been pushed onto a stack, keyed by address. Only these can
+	/// end up in a cycle that nothing else reaches.
+	tracked: RefCell<HashMap<*const (), Weak<RefCell<dyn StackItem>>>>,
 }
 
 impl ReferenceCounter {
 	pub fn new() -> Self {
-		Self {
-			tracked_items: HashSet::new(),
-			zero_referred: HashSet::new(),
-			cached_components: None,
-			references_count: 0,
-			phantom: PhantomData,
-		}
+		Self::default()
 	}
 
-	fn need_track(&self, item: Rc<RefCell<dyn StackItem>>) -> bool {
-		// Track compound types and buffers
-		if let StackItem::CompoundType(_) | StackItem::Buffer(_) = item {
-			true
-		} else {
-			false
-		}
+	pub fn add_references(&self, count: usize) {
+		self.references.set(self.references.get() + count);
 	}
 
-	fn add_reference(
-		&mut self,
-		item: Rc<RefCell<dyn StackItem>>,
-		parent: &dyn CompoundType,
-	) {
-		self.references_count += 1;
-		if !self.need_track(item) {
-			return
-		}
-
-		self.cached_components = None;
-
-		self.tracked_items.insert(item.clone());
-
-		if let Some(refs) = &mut item.borrow().get_stack_item().object_references {
-			if let Some(entry) = refs.get_mut(parent) {
-				entry.references += 1;
-			} else {
-				refs.insert(parent.clone(), ReferenceEntry { item: parent.clone(), references: 1 });
-			}
-		}
+	pub fn remove_references(&self, count: usize) {
+		self.references.set(self.references.get().saturating_sub(count));
 	}
 
-	pub(crate) fn add_stack_reference(
-		&mut self,
-		mut item: Rc<RefCell<dyn StackItem>>,
-		count: usize, /* = 1*/
-	) {
-		self.references_count += count;
-
-		if !self.need_track(item.clone()) {
-			return
+	/// Adds the reference of an item pushed onto a stack and tracks it if it is a compound item.
+	pub fn add_stack_reference(&self, item: &Rc<RefCell<dyn StackItem>>) {
+		self.add_references(1);
+		if item.try_borrow().map_or(true, |item| as_compound(&*item).is_some()) {
+			self.tracked
+				.borrow_mut()
+				.insert(Rc::as_ptr(item) as *const (), Rc::downgrade(item));
 		}
-
-		if self.tracked_items.insert(item.clone()) {
-			self.cached_components
-				.as_mut()
-				.map(|components| components.push_back(HashSet::from([item.clone()])));
-		}
-
-		item.get_mut().set_stack_references(item.borrow().stack_references() + count);
-		self.zero_referred.remove(&item);
 	}
 
-	fn add_zero_referred(&mut self, item: Rc<RefCell<dyn StackItem>>) {
-		self.zero_referred.insert(item.clone());
-
-		if !self.need_track(item) {
-			return
-		}
-
-		self.cached_components
-			.as_mut()
-			.map(|components| components.push_back(HashSet::from([item.clone()])));
-
-		self.tracked_items.insert(item.clone());
+	/// The number of references currently held.
+	pub fn count(&self) -> usize {
+		self.references.get()
 	}
 
-
-	fn check_zero_referred(&mut self) -> usize {
-		if !self.zero_referred.is_empty() {
-			self.zero_referred.clear();
-
-			let mut components = self.cached_components.get_or_insert_with(|| LinkedList::new());
-
-			for item in &self.tracked_items {
-				item.reset();
-			}
-
-			let mut node = components.front_mut();
-			while let Some(component) = node {
-				let mut on_stack = false;
-
-				for item in &component {
-					if item.stack_references > 0
-						|| item
-							.object_references
-							.as_ref()
-							.map(|refs| {
-								refs.values()
-									.any(|entry| entry.references > 0 && entry.item.on_stack)
-							})
-							.unwrap_or(false)
-					{
-						on_stack = true;
-						break
-					}
-				}
-
-				if on_stack {
-					for item in &component {
-						item.on_stack = true;
-					}
-					node = node.next_mut();
-				} else {
-					for item in &component {
-						self.tracked_items.remove(item);
-
-						if let StackItem::CompoundType(compound) = item {
-							self.references_count -= compound.sub_items.len();
-
-							for subitem in &compound.sub_items {
-								if component.contains(subitem) {
-									continue
-								}
-
-								if self.need_track(subitem) {
-									subitem.object_references.as_mut().map(|refs| {
-										refs.remove(&compound);
-									});
-								}
-							}
-						}
-
-						item.cleanup();
-					}
-
-					let node_to_remove = node.take().unwrap();
-					let pos = components.iter().position(|&x| &x == node_to_remove).unwrap();
-					// #![feature(linked_list_remove)]
-					components.remove(pos);
-				}
+	/// Clears the tracked compound items that are only reachable from each other and returns the
+	/// number of references still held.
+	///
+	/// An item is a root if anything other than a tracked item holds it, such as a stack or a
+	/// slot. Must not be called while a tracked item is mutably borrowed.
+	pub fn check_zero_referred(&self) -> usize {
+		let items: Vec<Rc<RefCell<dyn StackItem>>> = {
+			let mut tracked = self.tracked.borrow_mut();
+			tracked.retain(|_, item| item.strong_count() > 0);
+			tracked.values().filter_map(Weak::upgrade).collect()
+		};
+		// Every count includes the handle in `items`.
+		let owners: Vec<usize> = items.iter().map(|item| Rc::strong_count(item) - 1).collect();
+		let index: HashMap<*const (), usize> = items
+			.iter()
+			.enumerate()
+			.map(|(i, item)| (Rc::as_ptr(item) as *const (), i))
+			.collect();
+		let tracked_sub_items = |item: &Rc<RefCell<dyn StackItem>>| -> Vec<usize> {
+			as_compound(&*item.borrow())
+				.map(|compound| compound.sub_items())
+				.unwrap_or_default()
+				.iter()
+				.filter_map(|sub_item| index.get(&(Rc::as_ptr(sub_item) as *const ())).copied())
+				.collect()
+		};
+
+		let mut internal = vec![0; items.len()];
+		for item in &items {
+			for i in tracked_sub_items(item) {
+				internal[i] += 1;
 			}
 		}
 
-		self.references_count
-	}
-
-	fn remove_reference(
-		&mut self,
-		item: Rc<RefCell<dyn StackItem>>,
-		parent: &dyn CompoundType,
-	) {
-		self.references_count -= 1;
-
-		if !self.need_track(item) {
-			return
-		}
-
-		self.cached_components = None;
-
-		if let Some(refs) = &mut item.object_references() {
-			if let Some(entry) = refs.get_mut(parent) {
-				entry.references -= 1;
+		let mut reachable = vec![false; items.len()];
+		let mut pending: Vec<usize> =
+			(0..items.len()).filter(|&i| owners[i] > internal[i]).collect();
+		while let Some(i) = pending.pop() {
+			if !reachable[i] {
+				reachable[i] = true;
+				pending.extend(tracked_sub_items(&items[i]));
 			}
 		}
 
-		if item.stack_references() == 0 {
-			self.zero_referred.insert(item.clone());
-		}
-	}
-
-	pub(crate) fn remove_stack_reference(&mut self, mut item: Rc<RefCell<dyn StackItem>>) {
-		self.references_count -= 1;
-
-		if !self.need_track(item.clone()) {
-			return
-		}
-
-		let mut it = item.get_mut().get_stack_item();
-		it.set_stack_references(it.stack_references() - 1);
-		if it.stack_references() == 0 {
-			self.zero_referred.insert(item.clone());
+		for (item, reachable) in items.iter().zip(reachable) {
+			if !reachable {
+				if let Some(compound) = as_compound_mut(&mut *item.borrow_mut()) {
+					compound.clear();
+				}
+			}
 		}
-	}
-
-	pub fn count(&self) -> usize {
-		self.references_count
+		self.count()
 	}
 }
@@ -1,130 +1,144 @@
 use crate::{
-	stack_item_type::StackItemType,
+	compound_types::Struct::Struct, execution_engine_limits::ExecutionEngineLimits,
+	pointer::Pointer, stack_item_type::StackItemType, vm_exception::VMException,
 };
-use std::{
-	cell::RefCell,
-	fmt::{Debug},
-	hash::{Hash, Hasher},
-	rc::Rc,
-	string::FromUtf8Error,
-};
-use std::any::Any;
-use std::collections::HashMap;
 use num_bigint::BigInt;
-use serde::{Deserialize, Serialize};
-use crate::execution_engine_limits::ExecutionEngineLimits;
-use crate::interop_interface::InteropInterface;
-use crate::null::Null;
-
-pub trait StackItem: Clone + Hash + Eq+PartialEq+Serialize+Deserialize {
-	const TRUE: Self;
-	const FALSE: Self;
-	const NULL: Self;
-
-	fn dfn(&self) -> isize;
-
-	fn set_dfn(&mut self, dfn: isize);
-
-	fn low_link(&self) -> usize;
-	fn set_low_link(&mut self, link: usize);
+use std::{any::Any, cell::RefCell, fmt::Debug, rc::Rc};
 
-	fn on_stack(&self) -> bool;
-	fn set_on_stack(&mut self, on_stack: bool);
-
-	fn set_object_references(&mut self, refs: Self::ObjectReferences);
-	fn object_references(&self) -> &Self::ObjectReferences;
+/// An item on the evaluation stack. Items are shared as `Rc<RefCell<dyn StackItem>>`, so every
+/// handle to an array or buffer sees changes made through the others.
+pub trait StackItem: Debug {
+	fn get_type(&self) -> StackItemType;
 
-	fn set_stack_references(&mut self, count: usize);
+	/// Exposes the concrete item so callers can downcast to e.g. `Array` or `Map`.
+	fn as_any(&self) -> &dyn Any;
 
-	fn stack_references(&self) -> usize;
+	fn as_any_mut(&mut self) -> &mut dyn Any;
 
-	fn successors(&self) -> Vec<dyn StackItem> {
-		self.object_references()
-			.borrow()
-			.as_ref()
-			.unwrap()
-			.values()
-			.map(|v| v.item())
-			.collect()
+	/// The truth value used by `JmpIf`, `Not` and `Assert`.
+	fn get_boolean(&self) -> Result<bool, VMException> {
+		Ok(true)
 	}
 
-	fn reset(&mut self) {
-		self.set_dfn(-1);
-		self.set_low_link(0);
-		self.set_on_stack(false);
+	fn get_integer(&self) -> Result<BigInt, VMException> {
+		Err(invalid_cast(self.get_type(), StackItemType::Integer))
 	}
 
-	fn is_null(&self) -> bool {
-		false
+	/// The bytes of a primitive item or buffer.
+	fn get_slice(&self) -> Result<&[u8], VMException> {
+		Err(invalid_cast(self.get_type(), StackItemType::ByteString))
 	}
 
-	fn cleanup(&mut self);
-
-	fn convert_to(&self, type_: StackItemType) -> Result<Self, Err> {
-		if type_ == self.get_type() {
-			Ok(self.to_owned())
-		} else if type_ == StackItemType::Boolean {
-			Ok(self.get_boolean())
-		} else {
-			Err(())
-		}
-	}
-
-	fn get_slice(&self) -> &[u8];
-
-	fn get_string(&self) -> Result<String, FromUtf8Error> {
-		String::from_utf8(self.get_slice().to_vec())
+	fn get_string(&self) -> Result<String, VMException> {
+		String::from_utf8(self.get_slice()?.to_vec())
+			.map_err(|_| VMException::InvalidType("The item is not valid UTF-8".to_string()))
 	}
 
-	fn get_hash_code(&self) -> u64 {
-		use std::hash::Hasher;
-		let mut hasher = std::collections::hash_map::DefaultHasher::new();
-		self.hash(&mut hasher);
-		hasher.finish()
+	fn is_null(&self) -> bool {
+		self.get_type() == StackItemType::Any
 	}
+}
 
-	fn get_type(&self) -> StackItemType;
-
-	fn get_boolean(&self) -> bool;
-
-
-	fn deep_copy(&self, asImmutable:bool) -> Box<dyn StackItem>;
-
-	fn deep_copy_with_ref_map(&self, ref_map: &HashMap<&dyn StackItem, &dyn StackItem>, asImmutable:bool) -> Box<dyn StackItem>;
-
-	fn equals(&self, other: &dyn StackItem) -> bool;
-
-	fn equals_with_limits(&self, other: &dyn StackItem, limits: &ExecutionEngineLimits) -> bool;
-
-	fn from_interface(value: Some(dyn Any)) -> Box<dyn StackItem>{
+pub(crate) fn invalid_cast(from: StackItemType, to: StackItemType) -> VMException {
+	VMException::InvalidType(format!("Cannot convert {from:?} to {to:?}"))
+}
 
-		match value {
-			Some(value)=>InteropInterface::new(value),
-			None => Null::new(),
+/// Compares two items the way `Equal` does: primitive items and pointers by value, structs field
+/// by field and everything else by reference.
+///
+/// Fails once more than `max_comparable_size` bytes or `max_stack_size` struct fields would
+/// have to be compared.
+pub fn equals(
+	a: &Rc<RefCell<dyn StackItem>>,
+	b: &Rc<RefCell<dyn StackItem>>,
+	limits: &ExecutionEngineLimits,
+) -> Result<bool, VMException> {
+	let mut pending = vec![(a.clone(), b.clone())];
+	let mut items_left = limits.max_stack_size;
+	let mut bytes_left = limits.max_comparable_size;
+	while let Some((a, b)) = pending.pop() {
+		items_left = items_left.checked_sub(1).ok_or_else(|| {
+			VMException::InvalidParameter("Too many items to compare".to_string())
+		})?;
+		if Rc::ptr_eq(&a, &b) {
+			continue
+		}
+		let (a, b) = (a.borrow(), b.borrow());
+		if a.get_type() != b.get_type() {
+			return Ok(false)
+		}
+		let size = match a.get_type() {
+			StackItemType::ByteString => a.get_slice()?.len().max(b.get_slice()?.len()).max(1),
+			_ => 1,
+		};
+		bytes_left = bytes_left.checked_sub(size).ok_or_else(|| {
+			VMException::InvalidParameter(
+				"The operands exceed the maximum comparable size".to_string(),
+			)
+		})?;
+		match a.get_type() {
+			StackItemType::Any => {},
+			StackItemType::Boolean | StackItemType::Integer | StackItemType::ByteString =>
+				if a.get_slice()? != b.get_slice()? {
+					return Ok(false)
+				},
+			StackItemType::Pointer => {
+				if a.as_any().downcast_ref::<Pointer>() != b.as_any().downcast_ref::<Pointer>() {
+					return Ok(false)
+				}
+			},
+			StackItemType::Struct => {
+				let a = a.as_any().downcast_ref::<Struct>().unwrap();
+				let b = b.as_any().downcast_ref::<Struct>().unwrap();
+				if a.len() != b.len() {
+					return Ok(false)
+				}
+				pending.extend(a.iter().cloned().zip(b.iter().cloned()));
+			},
+			// Buffers, arrays, maps and interop interfaces are only equal to themselves.
+			_ => return Ok(false),
 		}
 	}
-	fn get_integer(&self) -> BigInt;
+	Ok(true)
+}
 
-	fn get_interface<T: Any>(&self) -> Option<&T>{
-		panic!("Not implemented")
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		buffer::Buffer,
+		null::Null,
+		primitive_types::{boolean::Boolean, byte_string::ByteString, integer::Integer},
+	};
+
+	fn item(item: impl StackItem + 'static) -> Rc<RefCell<dyn StackItem>> {
+		Rc::new(RefCell::new(item))
 	}
 
-
-	fn get_bytes(&self) -> &[u8];
-
-	fn to_ref(&self) -> Rc<RefCell<dyn StackItem>> {
-		Rc::new(RefCell::new(self.clone()))
+	#[test]
+	fn compares_primitives_by_type_and_value() {
+		let limits = ExecutionEngineLimits::default();
+		let one = item(Integer::from(1));
+		assert!(equals(&one, &item(Integer::from(1)), &limits).unwrap());
+		assert!(!equals(&one, &item(Boolean::new(true)), &limits).unwrap());
+		assert!(!equals(&one, &item(ByteString::new(vec![1])), &limits).unwrap());
+		assert!(equals(&item(Null), &item(Null), &limits).unwrap());
+
+		let buffer = item(Buffer::from(vec![1]));
+		assert!(equals(&buffer, &buffer, &limits).unwrap());
+		assert!(!equals(&buffer, &item(Buffer::from(vec![1])), &limits).unwrap());
+
+		let limits = ExecutionEngineLimits { max_comparable_size: 2, ..limits };
+		let long = item(ByteString::new(vec![0; 3]));
+		assert!(equals(&long, &item(ByteString::new(vec![0; 3])), &limits).is_err());
 	}
 
-}
-
-pub struct ObjectReferenceEntry {
-	pub(crate) item: Rc<RefCell<dyn StackItem>>,
-	pub(crate) references: i32,
-}
-
-impl ObjectReferenceEntry {
-	pub fn new(item: Rc<RefCell<dyn StackItem>>) -> Self {
-		Self { item, references: 0 }
+	#[test]
+	fn compares_structs_by_fields() {
+		let limits = ExecutionEngineLimits::default();
+		let fields = || Some(vec![item(Integer::from(1)), item(ByteString::new(b"a".to_vec()))]);
+		let a = item(Struct::new(fields(), None));
+		assert!(equals(&a, &item(Struct::new(fields(), None)), &limits).unwrap());
+		assert!(!equals(&a, &item(Struct::new(None, None)), &limits).unwrap());
 	}
 }
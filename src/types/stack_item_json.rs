@@ -0,0 +1,510 @@
+use crate::{
+	buffer::Buffer,
+	compound_types::{array::Array, map::Map, Struct::Struct},
+	interop_interface::InteropInterface,
+	null::Null,
+	pointer::Pointer,
+	primitive_types::{
+		boolean::Boolean, byte_string::ByteString, integer::Integer, primitive_type::PrimitiveType,
+	},
+	reference_counter::ReferenceCounter,
+	stack_item::StackItem,
+	stack_item_type::StackItemType,
+	uint::UInt160,
+	vm::script::Script,
+	vm_exception::VMException,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use num_bigint::BigInt;
+use serde_json::{json, Map as JsonMap, Value};
+use std::{any::Any, cell::RefCell, collections::HashMap, rc::Rc};
+
+/// Converts a stack item into the `{"type": ..., "value": ...}` form used by RPC and hosts.
+///
+/// Integers are written as decimal strings, byte strings and buffers as base64, pointers as
+/// their position with the hash of their script in `"script"`, and maps as lists of
+/// `{"key", "value"}` pairs in insertion order. A compound item reached more than once gets an
+/// `"id"` where it first appears and is written as `{"type", "ref"}` after that. Cycles are an
+/// error.
+///
+/// An `InteropInterface` is written as its type alone, which loses the host object. Use
+/// [`to_json_all_with`] to write an id the host can resolve it from.
+pub fn to_json(item: &dyn StackItem) -> Result<Value, VMException> {
+	Ok(to_json_all(&[item])?.remove(0))
+}
+
+/// Converts several items at once, sharing ids between them.
+pub fn to_json_all(items: &[&dyn StackItem]) -> Result<Vec<Value>, VMException> {
+	Encoder::default().encode_all(items)
+}
+
+/// Like [`to_json_all`], but writes the `"value"` that `interop` returns for the host object of
+/// each `InteropInterface`, so that [`JsonContext::interop`] can recreate it.
+pub fn to_json_all_with(
+	items: &[&dyn StackItem],
+	interop: &InteropEncoder,
+) -> Result<Vec<Value>, VMException> {
+	Encoder { interop: Some(interop), ..Default::default() }.encode_all(items)
+}
+
+/// Identifies the host object of an `InteropInterface`, or returns `None` if it cannot.
+pub type InteropEncoder = dyn Fn(&InteropInterface) -> Option<Value>;
+
+/// Recreates the host object of an `InteropInterface` from its JSON.
+pub type InteropDecoder = dyn Fn(&Value) -> Option<Box<dyn Any>>;
+
+/// What [`from_json`] needs to rebuild items whose state is not all in their JSON.
+#[derive(Default)]
+pub struct JsonContext<'a> {
+	/// The scripts `Pointer` items point into, looked up by hash.
+	pub scripts: &'a [Rc<Script>],
+
+	/// Recreates the host object of an `InteropInterface` from its JSON.
+	pub interop: Option<&'a InteropDecoder>,
+
+	pub reference_counter: Option<Rc<RefCell<ReferenceCounter>>>,
+}
+
+/// Rebuilds a stack item from the form written by [`to_json`].
+pub fn from_json(
+	json: &Value,
+	context: &JsonContext,
+) -> Result<Rc<RefCell<dyn StackItem>>, VMException> {
+	Ok(from_json_all(std::slice::from_ref(json), context)?.remove(0))
+}
+
+/// Rebuilds several items written by [`to_json_all`], restoring the items they share.
+pub fn from_json_all(
+	json: &[Value],
+	context: &JsonContext,
+) -> Result<Vec<Rc<RefCell<dyn StackItem>>>, VMException> {
+	let mut decoder = Decoder { context, shared: HashMap::new() };
+	json.iter().map(|value| decoder.decode(value)).collect()
+}
+
+#[derive(Default)]
+struct Encoder<'a> {
+	interop: Option<&'a InteropEncoder>,
+	/// How often each compound item is reached.
+	counts: HashMap<*const (), usize>,
+	/// The compound items on the path from the root, for cycle detection.
+	path: Vec<*const ()>,
+	/// The ids of shared items that have been written.
+	ids: HashMap<*const (), usize>,
+}
+
+fn address(item: &dyn StackItem) -> *const () {
+	item as *const dyn StackItem as *const ()
+}
+
+fn is_compound(ty: StackItemType) -> bool {
+	matches!(ty, StackItemType::Array | StackItemType::Struct | StackItemType::Map)
+}
+
+/// The direct children of a compound item; map entries yield the key, then the value.
+fn children(item: &dyn StackItem) -> Vec<Rc<RefCell<dyn StackItem>>> {
+	let any = item.as_any();
+	if let Some(map) = any.downcast_ref::<Map>() {
+		let mut children = Vec::with_capacity(map.len() * 2);
+		for (key, value) in map.iter() {
+			children.push(key.clone() as Rc<RefCell<dyn StackItem>>);
+			children.push(value.clone());
+		}
+		return children
+	}
+	match any.downcast_ref::<Struct>() {
+		Some(st) => st.iter().cloned().collect(),
+		None => any.downcast_ref::<Array>().unwrap().iter().cloned().collect(),
+	}
+}
+
+impl Encoder<'_> {
+	fn encode_all(&mut self, items: &[&dyn StackItem]) -> Result<Vec<Value>, VMException> {
+		for item in items {
+			self.count(*item)?;
+		}
+		items.iter().map(|item| self.encode(*item)).collect()
+	}
+
+	fn count(&mut self, item: &dyn StackItem) -> Result<(), VMException> {
+		if !is_compound(item.get_type()) {
+			return Ok(())
+		}
+		let id = address(item);
+		if self.path.contains(&id) {
+			return Err(VMException::InvalidParameter("Circular reference.".to_string()))
+		}
+		let count = self.counts.entry(id).or_insert(0);
+		*count += 1;
+		if *count > 1 {
+			return Ok(())
+		}
+		self.path.push(id);
+		for child in children(item) {
+			self.count(&*child.borrow())?;
+		}
+		self.path.pop();
+		Ok(())
+	}
+
+	fn encode(&mut self, item: &dyn StackItem) -> Result<Value, VMException> {
+		let ty = item.get_type();
+		let mut object = JsonMap::new();
+		object.insert("type".to_string(), json!(format!("{ty:?}")));
+
+		let value = match ty {
+			StackItemType::Any => None,
+			StackItemType::InteropInterface => {
+				let interop = item.as_any().downcast_ref::<InteropInterface>().unwrap();
+				match self.interop {
+					Some(encode) => Some(encode(interop).ok_or_else(|| {
+						invalid(format!("No id for the host object of {interop:?}"))
+					})?),
+					None => None,
+				}
+			},
+			StackItemType::Boolean => Some(json!(item.get_boolean()?)),
+			StackItemType::Integer => Some(json!(item.get_integer()?.to_string())),
+			StackItemType::ByteString | StackItemType::Buffer =>
+				Some(json!(STANDARD.encode(item.get_slice()?))),
+			StackItemType::Pointer => {
+				let pointer = item.as_any().downcast_ref::<Pointer>().unwrap();
+				object.insert("script".to_string(), json!(pointer.script().hash().to_string()));
+				Some(json!(pointer.position()))
+			},
+			StackItemType::Array | StackItemType::Struct | StackItemType::Map => {
+				let address = address(item);
+				if let Some(&id) = self.ids.get(&address) {
+					object.insert("ref".to_string(), json!(id));
+					return Ok(Value::Object(object))
+				}
+				if self.counts.get(&address).copied().unwrap_or(0) > 1 {
+					let id = self.ids.len();
+					self.ids.insert(address, id);
+					object.insert("id".to_string(), json!(id));
+				}
+				Some(self.encode_compound(item)?)
+			},
+		};
+
+		if let Some(value) = value {
+			object.insert("value".to_string(), value);
+		}
+		Ok(Value::Object(object))
+	}
+
+	fn encode_compound(&mut self, item: &dyn StackItem) -> Result<Value, VMException> {
+		let children = children(item);
+		if item.get_type() != StackItemType::Map {
+			return children
+				.iter()
+				.map(|child| self.encode(&*child.borrow()))
+				.collect::<Result<Vec<_>, _>>()
+				.map(Value::Array)
+		}
+
+		let mut entries = Vec::with_capacity(children.len() / 2);
+		for pair in children.chunks(2) {
+			entries.push(json!({
+				"key": self.encode(&*pair[0].borrow())?,
+				"value": self.encode(&*pair[1].borrow())?,
+			}));
+		}
+		Ok(Value::Array(entries))
+	}
+}
+
+struct Decoder<'a, 'b> {
+	context: &'a JsonContext<'b>,
+	shared: HashMap<u64, Rc<RefCell<dyn StackItem>>>,
+}
+
+fn invalid(message: impl Into<String>) -> VMException {
+	VMException::InvalidParameter(message.into())
+}
+
+impl<'a, 'b> Decoder<'a, 'b> {
+	fn decode(&mut self, json: &Value) -> Result<Rc<RefCell<dyn StackItem>>, VMException> {
+		let object = json.as_object().ok_or_else(|| invalid("Stack item must be an object"))?;
+		let ty = object
+			.get("type")
+			.and_then(Value::as_str)
+			.and_then(StackItemType::from_name)
+			.ok_or_else(|| invalid(format!("Invalid stack item type in {json}")))?;
+
+		if let Some(reference) = object.get("ref") {
+			let item = reference
+				.as_u64()
+				.and_then(|id| self.shared.get(&id))
+				.ok_or_else(|| invalid(format!("Unknown or circular reference {reference}")))?;
+			if item.borrow().get_type() != ty {
+				return Err(invalid(format!("Reference {reference} is not a {ty:?}")))
+			}
+			return Ok(item.clone())
+		}
+
+		let value = object.get("value");
+		let item: Rc<RefCell<dyn StackItem>> = match ty {
+			StackItemType::Any => Rc::new(RefCell::new(Null)),
+			StackItemType::Pointer => {
+				let hash = object
+					.get("script")
+					.and_then(Value::as_str)
+					.and_then(|hash| hash.parse::<UInt160>().ok())
+					.ok_or_else(|| invalid(format!("Invalid pointer script in {json}")))?;
+				let script = self
+					.context
+					.scripts
+					.iter()
+					.find(|script| script.hash() == hash)
+					.ok_or_else(|| invalid(format!("Unknown pointer script {hash}")))?;
+				let position = value
+					.and_then(Value::as_u64)
+					.filter(|&position| position as usize <= script.len())
+					.ok_or_else(|| invalid(format!("Invalid pointer in {json}")))?;
+				Rc::new(RefCell::new(Pointer::new(script.clone(), position as usize)))
+			},
+			StackItemType::Buffer =>
+				Rc::new(RefCell::new(Buffer::from(decode_base64(value, json)?))),
+			StackItemType::Boolean | StackItemType::Integer | StackItemType::ByteString =>
+				decode_primitive(ty, value, json)?,
+			StackItemType::InteropInterface => {
+				let object = self
+					.context
+					.interop
+					.and_then(|interop| interop(json))
+					.ok_or_else(|| invalid("No host object for InteropInterface"))?;
+				Rc::new(RefCell::new(InteropInterface::new(object)))
+			},
+			StackItemType::Array | StackItemType::Struct => {
+				let items = value
+					.and_then(Value::as_array)
+					.ok_or_else(|| invalid(format!("Invalid {ty:?} in {json}")))?
+					.iter()
+					.map(|item| self.decode(item))
+					.collect::<Result<Vec<_>, _>>()?;
+				let reference_counter = self.context.reference_counter.clone();
+				if ty == StackItemType::Array {
+					Rc::new(RefCell::new(Array::new(Some(items), reference_counter)))
+				} else {
+					Rc::new(RefCell::new(Struct::new(Some(items), reference_counter)))
+				}
+			},
+			StackItemType::Map => {
+				let entries = value
+					.and_then(Value::as_array)
+					.ok_or_else(|| invalid(format!("Invalid Map in {json}")))?;
+				let mut map = Map::new(self.context.reference_counter.clone());
+				for entry in entries {
+					let (Some(key), Some(value)) = (entry.get("key"), entry.get("value")) else {
+						return Err(invalid(format!("Invalid map entry {entry}")))
+					};
+					map.insert(decode_key(key)?, self.decode(value)?)?;
+				}
+				Rc::new(RefCell::new(map))
+			},
+		};
+
+		if let Some(id) = object.get("id") {
+			let id = id.as_u64().ok_or_else(|| invalid(format!("Invalid id {id}")))?;
+			if !is_compound(ty) || self.shared.insert(id, item.clone()).is_some() {
+				return Err(invalid(format!("Invalid or duplicate id {id}")))
+			}
+		}
+		Ok(item)
+	}
+}
+
+fn decode_base64(value: Option<&Value>, json: &Value) -> Result<Vec<u8>, VMException> {
+	value
+		.and_then(Value::as_str)
+		.and_then(|text| STANDARD.decode(text).ok())
+		.ok_or_else(|| invalid(format!("Invalid base64 value in {json}")))
+}
+
+fn decode_primitive(
+	ty: StackItemType,
+	value: Option<&Value>,
+	json: &Value,
+) -> Result<Rc<RefCell<dyn PrimitiveType>>, VMException> {
+	let item: Rc<RefCell<dyn PrimitiveType>> = match ty {
+		StackItemType::Boolean => {
+			let value = value
+				.and_then(Value::as_bool)
+				.ok_or_else(|| invalid(format!("Invalid Boolean in {json}")))?;
+			Rc::new(RefCell::new(Boolean::new(value)))
+		},
+		StackItemType::Integer => {
+			let value = value
+				.and_then(Value::as_str)
+				.and_then(|text| BigInt::parse_bytes(text.as_bytes(), 10))
+				.and_then(|value| Integer::try_from(value).ok())
+				.ok_or_else(|| invalid(format!("Invalid Integer in {json}")))?;
+			Rc::new(RefCell::new(value))
+		},
+		StackItemType::ByteString =>
+			Rc::new(RefCell::new(ByteString::new(decode_base64(value, json)?))),
+		_ => return Err(invalid(format!("{ty:?} is not a primitive type"))),
+	};
+	Ok(item)
+}
+
+fn decode_key(json: &Value) -> Result<Rc<RefCell<dyn PrimitiveType>>, VMException> {
+	let ty = json
+		.get("type")
+		.and_then(Value::as_str)
+		.and_then(StackItemType::from_name)
+		.ok_or_else(|| invalid(format!("Invalid map key {json}")))?;
+	let key = decode_primitive(ty, json.get("value"), json)?;
+	if key.borrow().size() > Map::MAX_KEY_SIZE {
+		return Err(invalid(format!("Map key {json} is too large")))
+	}
+	Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn round_trip(json: Value) {
+		let item = from_json(&json, &JsonContext::default()).unwrap();
+		assert_eq!(to_json(&*item.borrow()).unwrap(), json);
+	}
+
+	#[test]
+	fn round_trips_every_type() {
+		round_trip(json!({ "type": "Any" }));
+		round_trip(json!({ "type": "Boolean", "value": true }));
+		round_trip(json!({ "type": "Integer", "value": "-42" }));
+		round_trip(json!({ "type": "ByteString", "value": "aGk=" }));
+		round_trip(json!({ "type": "Buffer", "value": "AAE=" }));
+		round_trip(json!({ "type": "Struct", "value": [{ "type": "Integer", "value": "1" }] }));
+		round_trip(json!({
+			"type": "Map",
+			"value": [{
+				"key": { "type": "ByteString", "value": "aw==" },
+				"value": { "type": "Array", "value": [] }
+			}]
+		}));
+	}
+
+	#[test]
+	fn rejects_integers_beyond_32_bytes() {
+		let value = "9".repeat(100);
+		let integer = json!({ "type": "Integer", "value": value });
+		let key = json!({
+			"type": "Map",
+			"value": [{ "key": integer, "value": { "type": "Any" } }]
+		});
+		for json in [integer, key] {
+			assert!(
+				matches!(
+					from_json(&json, &JsonContext::default()),
+					Err(VMException::InvalidParameter(_))
+				),
+				"{json}"
+			);
+		}
+	}
+
+	#[test]
+	fn keeps_map_entries_in_insertion_order() {
+		round_trip(json!({
+			"type": "Map",
+			"value": [
+				{ "key": { "type": "ByteString", "value": "eg==" }, "value": { "type": "Any" } },
+				{
+					"key": { "type": "Integer", "value": "5" },
+					"value": { "type": "Boolean", "value": false }
+				},
+				{
+					"key": { "type": "ByteString", "value": "YQ==" },
+					"value": { "type": "Integer", "value": "1" }
+				}
+			]
+		}));
+	}
+
+	#[test]
+	fn rebuilds_pointers_into_the_script_with_their_hash() {
+		let scripts = [
+			Rc::new(Script::new(vec![0x40], false).unwrap()),
+			Rc::new(Script::new(vec![0x21, 0x40], false).unwrap()),
+		];
+		let context = JsonContext { scripts: &scripts, ..Default::default() };
+		let pointer = json!({
+			"type": "Pointer",
+			"script": scripts[1].hash().to_string(),
+			"value": 1
+		});
+		let item = from_json(&pointer, &context).unwrap();
+		{
+			let item = item.borrow();
+			let rebuilt = item.as_any().downcast_ref::<Pointer>().unwrap();
+			assert!(Rc::ptr_eq(rebuilt.script(), &scripts[1]));
+			assert_eq!(rebuilt.position(), 1);
+		}
+		assert_eq!(to_json(&*item.borrow()).unwrap(), pointer);
+
+		let context = JsonContext { scripts: &scripts[..1], ..Default::default() };
+		assert!(from_json(&pointer, &context).is_err());
+	}
+
+	#[test]
+	fn writes_interop_ids_supplied_by_the_host() {
+		let decode = |json: &Value| {
+			let id = json.get("value")?.as_u64()?;
+			Some(Box::new(id as u32) as Box<dyn Any>)
+		};
+		let context = JsonContext { interop: Some(&decode), ..Default::default() };
+		let json = json!({ "type": "InteropInterface", "value": 7 });
+		let item = from_json(&json, &context).unwrap();
+		assert_eq!(
+			item.borrow()
+				.as_any()
+				.downcast_ref::<InteropInterface>()
+				.unwrap()
+				.get_interface::<u32>(),
+			Some(&7)
+		);
+
+		let encode =
+			|interop: &InteropInterface| interop.get_interface::<u32>().map(|id| json!(id));
+		assert_eq!(
+			to_json_all_with(&[&*item.borrow()], &encode).unwrap(),
+			std::slice::from_ref(&json)
+		);
+		// Without an encoder the host object is lost.
+		assert_eq!(to_json(&*item.borrow()).unwrap(), json!({ "type": "InteropInterface" }));
+	}
+
+	#[test]
+	fn keeps_shared_items_and_rejects_cycles() {
+		let shared = json!({
+			"type": "Array",
+			"value": [
+				{ "type": "Array", "id": 0, "value": [] },
+				{ "type": "Array", "ref": 0 }
+			]
+		});
+		let item = from_json(&shared, &JsonContext::default()).unwrap();
+		{
+			let item = item.borrow();
+			let array = item.as_any().downcast_ref::<Array>().unwrap();
+			assert!(Rc::ptr_eq(&array[0], &array[1]));
+		}
+		assert_eq!(to_json(&*item.borrow()).unwrap(), shared);
+
+		let cycle = json!({
+			"type": "Array",
+			"id": 0,
+			"value": [{ "type": "Array", "ref": 0 }]
+		});
+		assert!(from_json(&cycle, &JsonContext::default()).is_err());
+
+		let array = Rc::new(RefCell::new(Array::new(None, None)));
+		let item: Rc<RefCell<dyn StackItem>> = array.clone();
+		array.borrow_mut().add(item);
+		assert!(to_json(&*array.borrow()).is_err());
+	}
+}
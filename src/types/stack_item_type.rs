@@ -1,5 +1,5 @@
 #[repr(u8)]
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum StackItemType {
 	Any = 0x00,
 	Pointer = 0x10,
@@ -14,24 +14,36 @@ pub enum StackItemType {
 }
 
 impl StackItemType {
-	pub fn is_valid(tp: u8) -> bool {
+	pub fn from_u8(tp: u8) -> Option<Self> {
 		match tp {
-			0x00 | 0x10 | 0x20 | 0x21 | 0x28 | 0x30 | 0x40 | 0x41 | 0x48 | 0x60 => true,
-			_ => false,
+			0x00 => Some(Self::Any),
+			0x10 => Some(Self::Pointer),
+			0x20 => Some(Self::Boolean),
+			0x21 => Some(Self::Integer),
+			0x28 => Some(Self::ByteString),
+			0x30 => Some(Self::Buffer),
+			0x40 => Some(Self::Array),
+			0x41 => Some(Self::Struct),
+			0x48 => Some(Self::Map),
+			0x60 => Some(Self::InteropInterface),
+			_ => None,
 		}
 	}
 
+	/// Parses the name used in JSON and listings, e.g. `ByteString`.
+	pub fn from_name(name: &str) -> Option<Self> {
+		(0..=u8::MAX).filter_map(Self::from_u8).find(|ty| format!("{ty:?}") == name)
+	}
+
+	pub fn is_valid(tp: u8) -> bool {
+		matches!(tp, 0x00 | 0x10 | 0x20 | 0x21 | 0x28 | 0x30 | 0x40 | 0x41 | 0x48 | 0x60)
+	}
+
 	pub fn is_primitive(tp: u8) -> bool {
-		match tp {
-			0x20 | 0x21 | 0x28 => true,
-			_ => false,
-		}
+		matches!(tp, 0x20 | 0x21 | 0x28)
 	}
 
 	pub fn is_compound(tp: u8) -> bool {
-		match tp {
-			0x40 | 0x41 | 0x48 => true,
-			_ => false,
-		}
+		matches!(tp, 0x40 | 0x41 | 0x48)
 	}
 }
@@ -0,0 +1,210 @@
+use crate::crypto::{base58, hash::hash160};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+	error::Error,
+	fmt::{Debug, Display, Formatter},
+	str::FromStr,
+};
+
+/// The address version byte of Neo N3.
+pub const ADDRESS_VERSION: u8 = 0x35;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UIntError {
+	/// The input has the wrong number of bytes.
+	InvalidLength {
+		expected: usize,
+		actual: usize,
+	},
+	InvalidHex,
+	/// The address is not valid Base58Check.
+	InvalidAddress(base58::Base58Error),
+	InvalidAddressVersion {
+		expected: u8,
+		actual: u8,
+	},
+}
+
+impl Display for UIntError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			UIntError::InvalidLength { expected, actual } =>
+				write!(f, "Expected {expected} bytes, found {actual}"),
+			UIntError::InvalidHex => write!(f, "Invalid hex string"),
+			UIntError::InvalidAddress(error) => write!(f, "Invalid address: {error}"),
+			UIntError::InvalidAddressVersion { expected, actual } =>
+				write!(f, "Address version {actual:#04x}, expected {expected:#04x}"),
+		}
+	}
+}
+
+impl Error for UIntError {}
+
+macro_rules! uint {
+	($(#[$doc:meta])* $name:ident, $size:expr) => {
+		$(#[$doc])*
+		///
+		/// The bytes are kept little-endian, as they appear in scripts. The text form, also used
+		/// by serde, is `0x` followed by the bytes in big-endian hex.
+		#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+		pub struct $name([u8; $size]);
+
+		impl $name {
+			pub const LENGTH: usize = $size;
+			pub const ZERO: Self = Self([0; $size]);
+
+			pub fn new(bytes: [u8; $size]) -> Self {
+				Self(bytes)
+			}
+
+			pub fn from_slice(bytes: &[u8]) -> Result<Self, UIntError> {
+				let bytes = bytes.try_into().map_err(|_| UIntError::InvalidLength {
+					expected: $size,
+					actual: bytes.len(),
+				})?;
+				Ok(Self(bytes))
+			}
+
+			pub fn as_bytes(&self) -> &[u8; $size] {
+				&self.0
+			}
+		}
+
+		impl From<[u8; $size]> for $name {
+			fn from(bytes: [u8; $size]) -> Self {
+				Self(bytes)
+			}
+		}
+
+		impl Display for $name {
+			fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+				write!(f, "0x")?;
+				for byte in self.0.iter().rev() {
+					write!(f, "{byte:02x}")?;
+				}
+				Ok(())
+			}
+		}
+
+		impl Debug for $name {
+			fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+				Display::fmt(self, f)
+			}
+		}
+
+		impl FromStr for $name {
+			type Err = UIntError;
+
+			/// Parses big-endian hex, with or without the `0x` prefix.
+			fn from_str(text: &str) -> Result<Self, Self::Err> {
+				let text = text.strip_prefix("0x").unwrap_or(text);
+				if text.len() != $size * 2 {
+					return Err(UIntError::InvalidLength { expected: $size, actual: text.len() / 2 })
+				}
+				if !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+					return Err(UIntError::InvalidHex)
+				}
+				let mut bytes = [0; $size];
+				for (i, byte) in bytes.iter_mut().rev().enumerate() {
+					*byte = u8::from_str_radix(&text[i * 2..i * 2 + 2], 16).unwrap();
+				}
+				Ok(Self(bytes))
+			}
+		}
+
+		impl Serialize for $name {
+			fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+				serializer.collect_str(self)
+			}
+		}
+
+		impl<'de> Deserialize<'de> for $name {
+			fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+				String::deserialize(deserializer)?.parse().map_err(D::Error::custom)
+			}
+		}
+	};
+}
+
+uint!(
+	/// A 160-bit hash, such as a script hash.
+	UInt160,
+	20
+);
+
+uint!(
+	/// A 256-bit hash, such as a transaction or block hash.
+	UInt256,
+	32
+);
+
+impl UInt160 {
+	/// The hash that identifies `script`: RIPEMD-160 of its SHA-256.
+	pub fn from_script(script: &[u8]) -> Self {
+		Self(hash160(script))
+	}
+
+	/// Encodes the hash as a Neo address: Base58Check of the version byte and the hash.
+	pub fn to_address(&self, version: u8) -> String {
+		let mut data = Vec::with_capacity(1 + Self::LENGTH);
+		data.push(version);
+		data.extend_from_slice(&self.0);
+		base58::check_encode(&data)
+	}
+
+	/// Decodes a Neo address with the given version byte.
+	pub fn from_address(address: &str, version: u8) -> Result<Self, UIntError> {
+		let data = base58::check_decode(address).map_err(UIntError::InvalidAddress)?;
+		let Some((&actual, hash)) = data.split_first() else {
+			return Err(UIntError::InvalidLength { expected: 1 + Self::LENGTH, actual: 0 })
+		};
+		if actual != version {
+			return Err(UIntError::InvalidAddressVersion { expected: version, actual })
+		}
+		Self::from_slice(hash)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn text_form_is_big_endian() {
+		let mut bytes = [0; 20];
+		bytes[0] = 0x01;
+		let hash = UInt160::new(bytes);
+		assert_eq!(hash.to_string(), "0x0000000000000000000000000000000000000001");
+		assert_eq!(hash.to_string().parse::<UInt160>(), Ok(hash));
+		assert_eq!(
+			"01".parse::<UInt160>(),
+			Err(UIntError::InvalidLength { expected: 20, actual: 1 })
+		);
+		assert_eq!(UInt256::ZERO.to_string().len(), 66);
+	}
+
+	#[test]
+	fn serializes_as_text() {
+		let hash = UInt160::new([0xab; 20]);
+		let json = serde_json::to_string(&hash).unwrap();
+		assert_eq!(json, format!("\"{hash}\""));
+		assert_eq!(serde_json::from_str::<UInt160>(&json).unwrap(), hash);
+		assert!(serde_json::from_str::<UInt160>("\"0x01\"").is_err());
+	}
+
+	#[test]
+	fn script_hash_and_address() {
+		assert_eq!(
+			UInt160::from_script(b"").to_string(),
+			"0xcb9f3b7c6fb1cf2c13a40637c189bdd066a272b4"
+		);
+
+		let address = UInt160::ZERO.to_address(ADDRESS_VERSION);
+		assert_eq!(address, "NKuyBkoGdZZSLyPbJEetheRhMjeznFZszf");
+		assert_eq!(UInt160::from_address(&address, ADDRESS_VERSION), Ok(UInt160::ZERO));
+		assert_eq!(
+			UInt160::from_address(&address, 0x17),
+			Err(UIntError::InvalidAddressVersion { expected: 0x17, actual: ADDRESS_VERSION })
+		);
+	}
+}
@@ -0,0 +1,254 @@
+use crate::{
+	crypto::{
+		ecdsa::{self, Curve},
+		hash,
+	},
+	execution_engine::ExecutionEngine,
+	interop_service::{pop_bytes, pop_bytes_array, pop_integer},
+	primitive_types::{boolean::Boolean, byte_string::ByteString},
+	uint::UInt256,
+	vm_exception::VMException,
+};
+use num_traits::ToPrimitive;
+use std::{cell::RefCell, rc::Rc};
+
+pub const CHECK_SIG: &str = "System.Crypto.CheckSig";
+pub const CHECK_MULTISIG: &str = "System.Crypto.CheckMultisig";
+pub const SHA256: &str = "Neo.Crypto.SHA256";
+pub const RIPEMD160: &str = "Neo.Crypto.RIPEMD160";
+pub const KECCAK256: &str = "Neo.Crypto.Keccak256";
+pub const MURMUR32: &str = "Neo.Crypto.Murmur32";
+
+/// The price of checking one signature. `CheckMultisig` charges it once per public key.
+pub const CHECK_SIG_PRICE: u64 = 1 << 15;
+
+/// The price of each hash service but `Murmur32`.
+pub const HASH_PRICE: u64 = 1 << 15;
+
+pub const MURMUR32_PRICE: u64 = 1 << 13;
+
+/// The transaction or block whose witnesses are being verified.
+pub trait ScriptContainer {
+	/// The bytes that witness signatures are made over.
+	fn sign_data(&self) -> Vec<u8>;
+}
+
+/// Raw sign data, for hosts and tests that have no container type of their own.
+impl ScriptContainer for Vec<u8> {
+	fn sign_data(&self) -> Vec<u8> {
+		self.clone()
+	}
+}
+
+/// The sign data of a Neo N3 container: the network magic, little-endian, then its hash.
+pub fn sign_data(network: u32, hash: &UInt256) -> Vec<u8> {
+	let mut data = Vec::with_capacity(4 + UInt256::LENGTH);
+	data.extend_from_slice(&network.to_le_bytes());
+	data.extend_from_slice(hash.as_bytes());
+	data
+}
+
+/// Registers the signature and hash services on `engine`.
+///
+/// `CheckSig` pops a public key, then a signature, and pushes whether the signature is valid
+/// for the sign data of `container` on `curve`. `CheckMultisig` pops an array of public keys,
+/// then an array of signatures, and pushes whether every signature matches a distinct key, with
+/// signatures in the same order as their keys. The hash services pop their input and push the
+/// digest; `Murmur32` also pops a seed after the data and pushes the hash as 4 little-endian
+/// bytes.
+pub fn register_crypto_services(
+	engine: &mut ExecutionEngine,
+	container: Rc<dyn ScriptContainer>,
+	curve: Curve,
+) {
+	let signed = container.clone();
+	engine.register_syscall(
+		CHECK_SIG,
+		CHECK_SIG_PRICE,
+		Rc::new(move |engine| {
+			let public_key = pop_bytes(engine)?;
+			let signature = pop_bytes(engine)?;
+			let valid = ecdsa::verify(&signed.sign_data(), &signature, &public_key, curve);
+			engine.push(Rc::new(RefCell::new(Boolean::new(valid))))
+		}),
+	);
+
+	engine.register_syscall(
+		CHECK_MULTISIG,
+		0,
+		Rc::new(move |engine| {
+			let public_keys = pop_bytes_array(engine)?;
+			let signatures = pop_bytes_array(engine)?;
+			let valid =
+				check_multisig(engine, &container.sign_data(), &signatures, &public_keys, curve)?;
+			engine.push(Rc::new(RefCell::new(Boolean::new(valid))))
+		}),
+	);
+
+	register_hash(engine, SHA256, |data| hash::sha256(data).to_vec());
+	register_hash(engine, RIPEMD160, |data| hash::ripemd160(data).to_vec());
+	register_hash(engine, KECCAK256, |data| hash::keccak256(data).to_vec());
+
+	engine.register_syscall(
+		MURMUR32,
+		MURMUR32_PRICE,
+		Rc::new(|engine| {
+			let data = pop_bytes(engine)?;
+			let seed = pop_integer(engine)?;
+			let seed = seed.to_u32().ok_or_else(|| {
+				VMException::InvalidParameter(format!("Murmur32 seed out of range: {seed}"))
+			})?;
+			let hash = hash::murmur32(&data, seed).to_le_bytes().to_vec();
+			engine.push(Rc::new(RefCell::new(ByteString::new(hash))))
+		}),
+	);
+}
+
+fn register_hash(engine: &mut ExecutionEngine, name: &str, digest: fn(&[u8]) -> Vec<u8>) {
+	engine.register_syscall(
+		name,
+		HASH_PRICE,
+		Rc::new(move |engine| {
+			let data = pop_bytes(engine)?;
+			engine.push(Rc::new(RefCell::new(ByteString::new(digest(&data)))))
+		}),
+	);
+}
+
+/// Matches signatures to keys in order, giving up once too few keys are left.
+fn check_multisig(
+	engine: &mut ExecutionEngine,
+	message: &[u8],
+	signatures: &[Vec<u8>],
+	public_keys: &[Vec<u8>],
+	curve: Curve,
+) -> Result<bool, VMException> {
+	let (m, n) = (signatures.len(), public_keys.len());
+	if n == 0 {
+		return Err(VMException::InvalidParameter("CheckMultisig without public keys".to_string()))
+	}
+	if m == 0 || m > n {
+		return Err(VMException::InvalidParameter(format!(
+			"CheckMultisig with {m} signatures for {n} public keys"
+		)))
+	}
+	engine.add_gas(CHECK_SIG_PRICE * n as u64)?;
+
+	let (mut i, mut j) = (0, 0);
+	while i < m && j < n {
+		if ecdsa::verify(message, &signatures[i], &public_keys[j], curve) {
+			i += 1;
+		}
+		j += 1;
+		if m - i > n - j {
+			return Ok(false)
+		}
+	}
+	Ok(i == m)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		execution_result::{run_script, ExecutionResult},
+		interop_service::syscall_hash,
+		op_code::OpCode,
+		script::script_builder::ScriptBuilder,
+	};
+	use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+	use serde_json::json;
+
+	const SIGN_DATA: &[u8] = b"container";
+
+	/// A key and its compressed public key.
+	fn key(seed: u8) -> (SigningKey, Vec<u8>) {
+		let key = SigningKey::from_slice(&[seed; 32]).unwrap();
+		let public_key = key.verifying_key().to_encoded_point(true).as_bytes().to_vec();
+		(key, public_key)
+	}
+
+	fn sign(key: &SigningKey) -> Vec<u8> {
+		let signature: Signature = key.sign(SIGN_DATA);
+		signature.to_bytes().to_vec()
+	}
+
+	fn run(emit: impl FnOnce(&mut ScriptBuilder)) -> ExecutionResult {
+		let mut builder = ScriptBuilder::new();
+		emit(&mut builder);
+		run_script(&builder.to_bytes().unwrap(), |engine| {
+			register_crypto_services(engine, Rc::new(SIGN_DATA.to_vec()), Curve::Secp256r1)
+		})
+	}
+
+	/// Pushes an array holding `items` in order.
+	fn push_array(builder: &mut ScriptBuilder, items: &[Vec<u8>]) {
+		for item in items.iter().rev() {
+			builder.push_bytes(item.clone());
+		}
+		builder.push_int(items.len() as i64);
+		builder.emit(OpCode::Pack, &[]);
+	}
+
+	#[test]
+	fn check_sig_uses_container_data() {
+		let (signer, public_key) = key(1);
+		let signature = sign(&signer);
+		for (public_key, expected) in [(public_key, true), (key(2).1, false)] {
+			let result = run(|builder| {
+				builder.push_bytes(signature.clone());
+				builder.push_bytes(public_key);
+				builder.push_syscall(syscall_hash(CHECK_SIG));
+			});
+			assert_eq!(result.state, "HALT");
+			assert_eq!(result.stack, vec![json!({"type": "Boolean", "value": expected})]);
+			assert!(result.gas_consumed >= CHECK_SIG_PRICE);
+		}
+	}
+
+	#[test]
+	fn check_multisig_requires_key_order() {
+		let keys = (1..=3).map(key).collect::<Vec<_>>();
+		let public_keys = keys.iter().map(|(_, public_key)| public_key.clone()).collect::<Vec<_>>();
+		let check = |signatures: Vec<Vec<u8>>| {
+			run(|builder| {
+				push_array(builder, &signatures);
+				push_array(builder, &public_keys);
+				builder.push_syscall(syscall_hash(CHECK_MULTISIG));
+			})
+		};
+
+		let result = check(vec![sign(&keys[0].0), sign(&keys[2].0)]);
+		assert_eq!(result.stack, vec![json!({"type": "Boolean", "value": true})]);
+		assert!(result.gas_consumed >= 3 * CHECK_SIG_PRICE);
+
+		let result = check(vec![sign(&keys[2].0), sign(&keys[0].0)]);
+		assert_eq!(result.stack, vec![json!({"type": "Boolean", "value": false})]);
+
+		assert_eq!(check(vec![]).state, "FAULT");
+	}
+
+	#[test]
+	fn hashes() {
+		let result = run(|builder| {
+			builder.push_bytes(Vec::new());
+			builder.push_syscall(syscall_hash(SHA256));
+			builder.push_int(0);
+			builder.push_bytes(b"hello".to_vec());
+			builder.push_syscall(syscall_hash(MURMUR32));
+		});
+		let sha256 = hash::sha256(b"");
+		assert_eq!(
+			result.stack,
+			vec![
+				json!({"type": "ByteString", "value": base64(&sha256)}),
+				json!({"type": "ByteString", "value": base64(&0x248bfa47u32.to_le_bytes())}),
+			]
+		);
+	}
+
+	fn base64(bytes: &[u8]) -> String {
+		use base64::{engine::general_purpose::STANDARD, Engine};
+		STANDARD.encode(bytes)
+	}
+}
@@ -1,126 +1,150 @@
-use std::cell::RefCell;
-use std::collections::VecDeque;
-use std::rc::Rc;
-use crate::reference_counter::ReferenceCounter;
-use crate::stack_item::StackItem;
+use crate::{
+	reference_counter::ReferenceCounter, stack_item::StackItem, vm_exception::VMException,
+};
+use std::{cell::RefCell, rc::Rc};
 
+/// Represents the evaluation stack in the vm. Indexes count from the top of the stack.
+///
+/// Every item counts as a reference on the reference counter until it is popped or the stack is
+/// dropped.
+#[derive(Debug)]
 pub struct EvaluationStack {
-	inner_list: VecDeque<Rc<RefCell<dyn StackItem>>>,
+	inner_list: Vec<Rc<RefCell<dyn StackItem>>>,
 	reference_counter: Rc<RefCell<ReferenceCounter>>,
 }
 
 impl EvaluationStack {
-
 	pub fn new(reference_counter: Rc<RefCell<ReferenceCounter>>) -> Self {
-		Self {
-			inner_list: VecDeque::new(),
-			reference_counter,
-		}
+		Self { inner_list: Vec::new(), reference_counter }
 	}
 
 	pub fn clear(&mut self) {
-		for item in &self.inner_list {
-			self.reference_counter.remove_stack_reference(item);
-		}
+		self.reference_counter.borrow().remove_references(self.inner_list.len());
 		self.inner_list.clear();
 	}
 
-	pub fn copy_to(&self, stack: &mut EvaluationStack, count: i32) {
-		if count < -1 || count as usize > self.inner_list.len() {
-			panic!("Argument out of range");
-		}
-		if count == 0 {
-			return;
-		}
-		if count == -1 || count as usize == self.inner_list.len() {
-			stack.inner_list.extend(&self.inner_list);
-		} else {
-			let start = self.inner_list.len() - count as usize;
-			stack.inner_list.extend(&self.inner_list[start..]);
-		}
+	/// Inserts `item` so that it ends up `index` items from the top.
+	pub fn insert(
+		&mut self,
+		index: usize,
+		item: Rc<RefCell<dyn StackItem>>,
+	) -> Result<(), VMException> {
+		let position = self.position(index, true)?;
+		self.reference_counter.borrow().add_stack_reference(&item);
+		self.inner_list.insert(position, item);
+		Ok(())
 	}
 
-	pub fn insert(&mut self, index: usize, item: Rc<RefCell<dyn StackItem>>) {
-		if index > self.inner_list.len() {
-			panic!("Insert out of bounds");
-		}
-		self.inner_list.insert(self.inner_list.len() - index, item);
-		self.reference_counter.add_stack_reference(&item);
+	/// Moves every item onto `stack`, keeping their order.
+	pub fn move_to(&mut self, stack: &mut EvaluationStack) {
+		let count = self.inner_list.len();
+		self.reference_counter.borrow().remove_references(count);
+		stack.reference_counter.borrow().add_references(count);
+		stack.inner_list.append(&mut self.inner_list);
 	}
 
-	pub fn move_to(&mut self, stack: &mut EvaluationStack, count: i32) {
-		if count == 0 {
-			return;
-		}
-		self.copy_to(stack, count);
-		if count == -1 || count as usize == self.inner_list.len() {
-			self.inner_list.clear();
-		} else {
-			let end = self.inner_list.len() - count as usize;
-			self.inner_list.drain(end..);
-		}
-	}
-
-	pub fn peek(&self, index: i32) -> Rc<RefCell<dyn StackItem>> {
-		let index = index as isize;
-		if index >= self.inner_list.len() as isize {
-			panic!("Peek out of bounds");
-		}
-		if index < 0 {
-			let index = self.inner_list.len() as isize + index;
-			if index < 0 {
-				panic!("Peek out of bounds");
-			}
-		}
-		self.inner_list.get((self.inner_list.len() as isize - index - 1) as usize).unwrap().clone()
+	/// The item `index` items from the top.
+	pub fn peek(&self, index: usize) -> Result<Rc<RefCell<dyn StackItem>>, VMException> {
+		let position = self.position(index, false)?;
+		Ok(self.inner_list[position].clone())
 	}
 
 	pub fn push(&mut self, item: Rc<RefCell<dyn StackItem>>) {
-		self.inner_list.push_back(item);
-		self.reference_counter.add_stack_reference(&item);
+		self.reference_counter.borrow().add_stack_reference(&item);
+		self.inner_list.push(item);
 	}
 
-	pub fn reverse(&mut self, n: i32) {
-		let n = n as usize;
-		if n < 0 || n > self.inner_list.len() {
-			panic!("Argument out of range");
+	/// Reverses the order of the top `n` items.
+	pub fn reverse(&mut self, n: usize) -> Result<(), VMException> {
+		if n > self.inner_list.len() {
+			return Err(self.out_of_range(n))
 		}
-		if n <= 1 {
-			return;
-		}
-		let end = self.inner_list.len() - n;
-		self.inner_list.make_contiguous().reverse();
+		let start = self.inner_list.len() - n;
+		self.inner_list[start..].reverse();
+		Ok(())
 	}
 
-	pub fn pop(&mut self) -> Rc<RefCell<dyn StackItem>> {
+	pub fn pop(&mut self) -> Result<Rc<RefCell<dyn StackItem>>, VMException> {
 		self.remove(0)
 	}
 
-	pub fn pop_typed<T: StackItem>(&mut self) -> T {
-		self.remove::<T>(0)
+	/// Removes the item `index` items from the top.
+	pub fn remove(&mut self, index: usize) -> Result<Rc<RefCell<dyn StackItem>>, VMException> {
+		let position = self.position(index, false)?;
+		self.reference_counter.borrow().remove_references(1);
+		Ok(self.inner_list.remove(position))
 	}
 
-	pub fn remove<T: StackItem>(&mut self, index: i32) -> T {
-		let index = index as isize;
-		if index >= self.inner_list.len() as isize {
-			panic!("Argument out of range");
-		}
-		if index < 0 {
-			let index = self.inner_list.len() as isize + index;
-			if index < 0 {
-				panic!("Argument out of range");
-			}
-		}
-		let index = self.inner_list.len() as isize - index - 1;
-		let item = self.inner_list.remove(index as usize).unwrap();
-		if !item.is::<T>() {
-			panic!("Invalid cast");
+	pub fn size(&self) -> usize {
+		self.inner_list.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.inner_list.is_empty()
+	}
+
+	/// Iterates over the items from the bottom of the stack to the top.
+	pub fn iter(&self) -> impl Iterator<Item = &Rc<RefCell<dyn StackItem>>> {
+		self.inner_list.iter()
+	}
+
+	/// The position in `inner_list` of the item `index` items from the top. `index` may be the
+	/// size of the stack when inserting.
+	fn position(&self, index: usize, inserting: bool) -> Result<usize, VMException> {
+		let len = self.inner_list.len();
+		if index > len || (index == len && !inserting) {
+			return Err(self.out_of_range(index))
 		}
-		self.reference_counter.remove_stack_reference(&item);
-		item.try_into().unwrap()
+		Ok(len - index - usize::from(!inserting))
 	}
 
-	pub fn size(&self) -> usize {
-		self.inner_list.len()
+	fn out_of_range(&self, index: usize) -> VMException {
+		VMException::InvalidParameter(format!(
+			"Index {index} out of range for a stack of {} items",
+			self.inner_list.len()
+		))
+	}
+}
+
+impl Drop for EvaluationStack {
+	fn drop(&mut self) {
+		self.reference_counter.borrow().remove_references(self.inner_list.len());
 	}
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::primitive_types::integer::Integer;
+
+	fn int(stack: &EvaluationStack, index: usize) -> i64 {
+		let item = stack.peek(index).unwrap();
+		let value = item.borrow().get_integer().unwrap();
+		value.try_into().unwrap()
+	}
+
+	#[test]
+	fn indexes_from_the_top() {
+		let counter = Rc::new(RefCell::new(ReferenceCounter::new()));
+		let mut stack = EvaluationStack::new(counter.clone());
+		for i in 0..4 {
+			stack.push(Rc::new(RefCell::new(Integer::from(i))));
+		}
+		assert_eq!(int(&stack, 0), 3);
+		stack.reverse(3).unwrap();
+		assert_eq!((int(&stack, 0), int(&stack, 2)), (1, 3));
+		stack.insert(4, Rc::new(RefCell::new(Integer::from(9)))).unwrap();
+		assert_eq!(int(&stack, 4), 9);
+		assert!(stack.peek(5).is_err());
+		assert!(stack.insert(6, Rc::new(RefCell::new(Integer::from(0)))).is_err());
+		stack.remove(1).unwrap();
+		assert_eq!(counter.borrow().count(), 4);
+
+		let mut other = EvaluationStack::new(counter.clone());
+		stack.move_to(&mut other);
+		assert!(stack.is_empty());
+		assert_eq!(int(&other, 0), 1);
+		drop(other);
+		assert_eq!(counter.borrow().count(), 0);
+	}
+}
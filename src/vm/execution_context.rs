@@ -1,22 +1,28 @@
 use crate::{
 	evaluation_stack::EvaluationStack,
 	exception::exception_handling_context::ExceptionHandlingContext,
-	reference_counter::ReferenceCounter, slot::Slot, stack_item::StackItem, vm::script::Script,
+	instruction::Instruction,
+	reference_counter::ReferenceCounter,
+	slot::Slot,
+	uint::UInt160,
+	vm::script::{Script, ScriptError},
 };
 use std::{
 	any::{Any, TypeId},
-	cell::{Ref, RefCell},
+	cell::{Ref, RefCell, RefMut},
 	collections::HashMap,
 	rc::Rc,
 };
-use crate::instruction::Instruction;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+/// Represents a frame in the vm execution stack.
+#[derive(Debug)]
 pub struct ExecutionContext {
-	pub shared_states: Rc<RefCell<SharedStates>>,
+	shared_states: Rc<RefCell<SharedStates>>,
+
+	/// The offset of the instruction to execute next.
 	pub instruction_pointer: usize,
 
-	/// The number of return values when this context returns.
+	/// The number of return values when this context returns, or -1 for any number.
 	pub rv_count: i32,
 
 	/// The local variables of this context.
@@ -29,23 +35,25 @@ pub struct ExecutionContext {
 	pub try_stack: Option<Vec<ExceptionHandlingContext>>,
 }
 
-pub struct SharedStates {
-	pub(crate) script: Script,
-	pub(crate) evaluation_stack: Rc<RefCell<EvaluationStack>>,
-	pub(crate) static_fields: Option<Slot>,
-	pub(crate) states: HashMap<TypeId, Box<dyn Any>>,
+/// The state shared by a context and the contexts cloned from it by `Call`.
+#[derive(Debug)]
+struct SharedStates {
+	script: Rc<Script>,
+	evaluation_stack: Rc<RefCell<EvaluationStack>>,
+	static_fields: Option<Slot>,
+	states: HashMap<TypeId, Box<dyn Any>>,
 }
 
 impl ExecutionContext {
-	pub fn new(script: Script, reference_counter: Rc<RefCell<ReferenceCounter>>) -> Self {
+	pub fn new(script: Rc<Script>, reference_counter: Rc<RefCell<ReferenceCounter>>) -> Self {
 		let shared_states = SharedStates {
 			script,
-			evaluation_stack: Ref::new(RefCell::new(EvaluationStack::new(reference_counter))),
+			evaluation_stack: Rc::new(RefCell::new(EvaluationStack::new(reference_counter))),
 			static_fields: None,
 			states: HashMap::new(),
 		};
 		Self {
-			shared_states:Rc::new(RefCell::new(shared_states)),
+			shared_states: Rc::new(RefCell::new(shared_states)),
 			instruction_pointer: 0,
 			rv_count: 0,
 			local_variables: None,
@@ -54,106 +62,72 @@ impl ExecutionContext {
 		}
 	}
 
-	// Other fields and methods
-
-	pub fn get_state<T: 'static>(&mut self) -> &mut T
-	where
-		T: Default + Any,
-	{
-		self.shared_states
-			.borrow()
-			.states
-			.entry(TypeId::of::<T>())
-			.or_insert_with(|| Box::new(Default::default()))
-			.downcast_mut::<T>()
-			.unwrap()
+	/// Gets the custom state of type `T`, creating it on first use. The state is shared with
+	/// the contexts cloned from this one.
+	pub fn get_state<T: Default + Any>(&self) -> RefMut<'_, T> {
+		RefMut::map(self.shared_states.borrow_mut(), |shared| {
+			shared
+				.states
+				.entry(TypeId::of::<T>())
+				.or_insert_with(|| Box::new(T::default()))
+				.downcast_mut::<T>()
+				.unwrap()
+		})
 	}
 
-	// pub fn peek(&self, index: usize) -> Rc<RefCell<dyn StackItem>> {
-	// 	let idx = self.items.len() - index - 1;
-	// 	&self.items[idx]
-	// }
-
-	// pub fn push(&mut self, item:Rc<RefCell<dyn StackItem>>) {
-	// 	self.items.push(item);
-	// 	self.reference_counter.add_stack_reference(&item);
-	// }
-	//
-	// pub fn pop(&mut self) -> Rc<RefCell<dyn StackItem>> {
-	// 	let item = self.items.pop().expect("stack empty");
-	// 	self.reference_counter.remove_stack_reference(&item);
-	// 	item
-	// }
-	//
-	// pub fn remove(&mut self, index: usize) -> Rc<RefCell<dyn StackItem>> {
-	// 	let idx = self.items.len() - index - 1;
-	// 	let item = self.items.remove(idx).expect("index out of bounds");
-	// 	self.reference_counter.remove_stack_reference(&item);
-	// 	item.try_into().unwrap()
-	// }
-
 	pub fn evaluation_stack(&self) -> Rc<RefCell<EvaluationStack>> {
 		self.shared_states.borrow().evaluation_stack.clone()
 	}
-	pub fn evaluation_stack_mut(&mut self) -> Rc<RefCell<EvaluationStack>> {
-		self.shared_states.borrow().evaluation_stack.clone()
-	}
 
-	pub fn script(&self) -> &Script {
-		&self.shared_states.borrow().script
-	}
-	pub fn script_mut(&mut self) -> &mut Script {
-		&mut self.shared_states.borrow().script
+	pub fn script(&self) -> Rc<Script> {
+		self.shared_states.borrow().script.clone()
 	}
 
-	pub fn fields(&self) -> Option<&Slot> {
-		self.shared_states.borrow().static_fields.as_ref()
+	/// The hash of the running script, which identifies the contract in calls and
+	/// notifications.
+	pub fn script_hash(&self) -> UInt160 {
+		self.script().hash()
 	}
 
-	pub fn fields_mut(&mut self) -> Option<&mut Slot> {
-		self.shared_states.borrow().static_fields.as_mut()
+	/// The static fields, shared with the contexts cloned from this one.
+	pub fn fields(&self) -> Ref<'_, Option<Slot>> {
+		Ref::map(self.shared_states.borrow(), |shared| &shared.static_fields)
 	}
-	pub fn states(&self) -> &HashMap<TypeId, Box<dyn Any>> {
-		&self.shared_states.borrow().states
-	}
-	pub fn states_mut(&mut self) -> &mut HashMap<TypeId, Box<dyn Any>> {
-		&mut self.shared_states.borrow().states
-	}
-
-	pub fn move_next(&mut self) {
-		self.instruction_pointer += 1;
 
-		if self.instruction_pointer >= self.script().len() {
-			self.instruction_pointer = 0;
-		}
+	pub fn fields_mut(&self) -> RefMut<'_, Option<Slot>> {
+		RefMut::map(self.shared_states.borrow_mut(), |shared| &mut shared.static_fields)
 	}
 
-	pub fn clone(&self) -> Self {
-		Self::clone_with_ip(self, self.instruction_pointer)
-	}
-
-	pub fn clone_with_ip(&self, ip: usize) -> Self {
-		let shared_states = Rc::clone(&self.shared_states);
-
+	/// A context that shares the script, evaluation stack, static fields and states of this one
+	/// and starts at `ip`, as `Call` creates.
+	pub fn clone_at(&self, ip: usize) -> Self {
 		Self {
-			shared_states,
+			shared_states: self.shared_states.clone(),
 			instruction_pointer: ip,
 			rv_count: 0,
-
-			local_variables: self.local_variables.clone(),
-			arguments: self.arguments.clone(),
-			try_stack: self.try_stack.clone(),
+			local_variables: None,
+			arguments: None,
+			try_stack: None,
 		}
 	}
 
-	// Get the current instruction
-	pub fn current_instruction(&self) -> &Instruction {
-		self.script().get_instruction(self.instruction_pointer)?
+	/// The instruction at the instruction pointer. Past the end of the script this is the
+	/// implicit `Ret` the engine executes; bytes that cannot be decoded are an error.
+	pub fn current_instruction(&self) -> Result<Instruction, ScriptError> {
+		self.instruction_at(self.instruction_pointer)
+	}
+
+	/// The instruction after the current one, decoded like
+	/// [`current_instruction`](Self::current_instruction).
+	pub fn next_instruction(&self) -> Result<Instruction, ScriptError> {
+		self.instruction_at(self.instruction_pointer + self.current_instruction()?.size())
 	}
 
-	pub fn next_instruction(&self) -> &Instruction {
-		let next_ip = self.instruction_pointer + self.current_instruction().size();
-		self.script().get_instruction(next_ip)?
+	fn instruction_at(&self, ip: usize) -> Result<Instruction, ScriptError> {
+		let script = self.script();
+		if ip >= script.len() {
+			return Ok(Instruction::RET)
+		}
+		script.get_instruction(ip)
 	}
-	
 }
@@ -1,40 +1,43 @@
 use crate::{
 	buffer::Buffer,
-	compound_types::{array::Array, compound_type::CompoundType, map::Map, Struct::Struct},
+	compound_types::{
+		array::Array,
+		compound_type::{as_compound, as_compound_mut},
+		map::Map,
+		Struct::Struct,
+	},
 	evaluation_stack::EvaluationStack,
 	exception::{
 		exception_handling_context::ExceptionHandlingContext,
 		exception_handling_state::ExceptionHandlingState,
 	},
-	execution_context::{ExecutionContext, SharedStates},
+	execution_context::ExecutionContext,
 	execution_engine_limits::ExecutionEngineLimits,
-	instruction::Instruction,
+	instruction::{Instruction, SlotAccess, SlotKind},
+	interop_service::{syscall_hash, InteropDescriptor, SyscallHandler, TokenHandler},
 	null::Null,
 	op_code::OpCode,
 	pointer::Pointer,
-	primitive_types::{byte_string::ByteString, primitive_type::PrimitiveType},
+	primitive_types::{
+		boolean::Boolean,
+		byte_string::ByteString,
+		integer::Integer,
+		primitive_type::{to_primitive, PrimitiveType},
+	},
 	reference_counter::ReferenceCounter,
+	script::nef::MethodToken,
 	slot::Slot,
+	stack_item::{equals, invalid_cast, StackItem},
 	stack_item_type::StackItemType,
+	uint::UInt160,
 	vm::{script::Script, vm_exception::VMException},
 	vm_state::VMState,
 };
-use num_bigint::{BigInt, Sign};
-use num_traits::{FromBytes, Signed, ToPrimitive, Zero};
-use std::{
-	cell::{Ref, RefCell},
-	convert::TryInto,
-	fmt::Error,
-	ops::Neg,
-	rc::Rc,
-};
-use serde::Serialize;
-use crate::primitive_types::boolean::Boolean;
-use crate::primitive_types::integer::Integer;
-use crate::stack_item::StackItem;
+use num_bigint::BigInt;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+use std::{cell::RefCell, collections::HashMap, convert::TryFrom, rc::Rc};
 
 /// Represents the VM used to execute the script.
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
 pub struct ExecutionEngine {
 	/// Restrictions on the VM.
 	pub limits: ExecutionEngineLimits,
@@ -61,26 +64,30 @@ pub struct ExecutionEngine {
 	pub state: VMState,
 
 	pub is_jumping: bool,
-}
 
-/// Interface implemented by objects that can be reference counted.
-pub trait ReferenceCounted {
-	/// Returns a unique ID for the object.
-	fn id(&self) -> usize;
+	/// The services reachable through `OpCode::Syscall`, keyed by syscall id.
+	pub syscalls: HashMap<u32, InteropDescriptor>,
+
+	/// The gas consumed by the executed instructions and syscalls.
+	pub gas_consumed: u64,
+
+	/// The gas that may be consumed before the engine faults.
+	pub gas_limit: u64,
+
+	/// The engine error that caused the fault, if any.
+	pub fault_exception: Option<VMException>,
 
-	/// Free any resources used by the object.
-	fn free(&mut self);
+	/// The tokens `OpCode::CallT` indexes into, usually those of the loaded NEF.
+	pub method_tokens: Vec<MethodToken>,
+
+	/// Performs the calls described by `method_tokens`; `CallT` faults without one.
+	pub token_handler: Option<TokenHandler>,
 }
 
-impl<T> ReferenceCounted for T
-where
-	T: Sized + PartialEq,
-{
-	fn id(&self) -> usize {
-		self as *const T as usize
+impl Default for ExecutionEngine {
+	fn default() -> Self {
+		Self::new()
 	}
-
-	fn free(&mut self) {}
 }
 
 impl ExecutionEngine {
@@ -91,21 +98,51 @@ impl ExecutionEngine {
 
 	/// Constructs a VM engine with the given options.
 	pub fn with_options(limits: ExecutionEngineLimits) -> Self {
+		let reference_counter = Rc::new(RefCell::new(ReferenceCounter::new()));
 		Self {
 			limits,
-			reference_counter: Ref::new(RefCell::new(ReferenceCounter::new())),
+			result_stack: Rc::new(RefCell::new(EvaluationStack::new(reference_counter.clone()))),
+			reference_counter,
 			invocation_stack: Vec::new(),
 			current_context: None,
 			entry_context: None,
-			result_stack: Ref::new(RefCell::new(EvaluationStack::new(Rc::new(RefCell::new(
-				ReferenceCounter::new(),
-			))))),
 			uncaught_exception: None,
 			state: VMState::Break,
 			is_jumping: false,
+			syscalls: HashMap::new(),
+			gas_consumed: 0,
+			gas_limit: u64::MAX,
+			fault_exception: None,
+			method_tokens: Vec::new(),
+			token_handler: None,
 		}
 	}
 
+	/// Registers a service under the id derived from `name` and returns that id.
+	pub fn register_syscall(&mut self, name: &str, price: u64, handler: SyscallHandler) -> u32 {
+		let hash = syscall_hash(name);
+		self.syscalls
+			.insert(hash, InteropDescriptor { name: name.to_string(), hash, price, handler });
+		hash
+	}
+
+	/// Charges `gas`, failing once the consumed total exceeds `gas_limit`.
+	pub fn add_gas(&mut self, gas: u64) -> Result<(), VMException> {
+		self.gas_consumed = self.gas_consumed.saturating_add(gas);
+		if self.gas_consumed > self.gas_limit {
+			return Err(VMException::Custom(format!(
+				"Insufficient gas: {} consumed, limit {}",
+				self.gas_consumed, self.gas_limit
+			)))
+		}
+		Ok(())
+	}
+
+	fn on_fault(&mut self, e: VMException) {
+		self.state = VMState::Fault;
+		self.fault_exception = Some(e);
+	}
+
 	/// Starts executing the loaded script.
 	pub fn execute(&mut self) -> VMState {
 		if self.state == VMState::Break {
@@ -120,60 +157,182 @@ impl ExecutionEngine {
 	}
 
 	/// Steps through executing a single instr.
-	///
-	fn execute_next(&mut self) {
+	pub fn execute_next(&mut self) {
 		if self.invocation_stack.is_empty() {
 			self.state = VMState::Halt;
-		} else {
-			let context = self.current_context?.borrow();
-
-			let instruction = context.current_instruction().unwrap_or(Instruction::RET);
-
-			self.pre_execute_instruction(instruction);
+		} else if let Err(e) = self.step() {
+			self.on_fault(e);
+		}
+	}
 
-			match self.execute_instruction(instruction) {
-				Ok(_) => (),
-				Err(e) => Err(VMException::InvalidOpcode("{e}".parse().unwrap())), // self.on_fault(e),
-			}
+	fn step(&mut self) -> Result<(), VMException> {
+		let context = self.context()?;
+		let instruction = context
+			.borrow()
+			.current_instruction()
+			.map_err(|e| VMException::InvalidOpcode(e.to_string()))?;
+
+		self.add_gas(instruction.opcode.price())?;
+		match self.execute_instruction(&instruction) {
+			Err(e) if e.is_catchable() && self.limits.catch_engine_exceptions =>
+				self.execute_throw(Rc::new(RefCell::new(ByteString::from(e.to_string().as_str()))))?,
+			result => result?,
+		}
+		if self.state == VMState::Fault {
+			return Ok(())
+		}
+		self.post_execute_instruction()?;
 
-			self.post_execute_instruction(instruction);
-			if !self.is_jumping {
-				self.current_context.unwrap().move_next();
-			}
+		if !self.is_jumping {
+			context.borrow_mut().instruction_pointer += instruction.size();
+		}
+		self.is_jumping = false;
+		Ok(())
+	}
 
-			self.is_jumping = false;
+	fn post_execute_instruction(&mut self) -> Result<(), VMException> {
+		if self.reference_counter.borrow().count() <= self.limits.max_stack_size {
+			return Ok(())
 		}
+		let count = self.reference_counter.borrow().check_zero_referred();
+		if count > self.limits.max_stack_size {
+			return Err(VMException::StackOverflow(format!("MaxStackSize exceed: {count}")))
+		}
+		Ok(())
 	}
 
-	fn pop(&mut self) -> Rc<RefCell<dyn StackItem>> {
+	fn context(&self) -> Result<Rc<RefCell<ExecutionContext>>, VMException> {
 		self.current_context
-			.unwrap()
-			.get_mut()
-			.evaluation_stack()
-			.get_mut()
-			.pop()
+			.clone()
+			.ok_or_else(|| VMException::Custom("No context is loaded".to_string()))
 	}
 
-	fn push(&mut self, item: Rc<RefCell<dyn StackItem>>) {
-		self.current_context
-			.unwrap()
-			.get_mut()
-			.evaluation_stack()
-			.get_mut()
-			.push(item);
+	fn stack(&self) -> Result<Rc<RefCell<EvaluationStack>>, VMException> {
+		Ok(self.context()?.borrow().evaluation_stack())
 	}
 
-	fn peek(&self, index: usize) -> Rc<RefCell<dyn StackItem>> {
-		self.current_context
-			.unwrap()
-			.borrow()
-			.evaluation_stack()
-			.get_mut()
-			.peek(index as i32)
-			.unwrap()
+	/// Pops the top item of the current evaluation stack.
+	pub fn pop(&mut self) -> Result<Rc<RefCell<dyn StackItem>>, VMException> {
+		self.stack()?.borrow_mut().pop()
+	}
+
+	/// Pushes `item` onto the current evaluation stack.
+	pub fn push(&mut self, item: Rc<RefCell<dyn StackItem>>) -> Result<(), VMException> {
+		self.stack()?.borrow_mut().push(item);
+		Ok(())
+	}
+
+	/// The item `index` items from the top of the current evaluation stack.
+	pub fn peek(&self, index: usize) -> Result<Rc<RefCell<dyn StackItem>>, VMException> {
+		self.stack()?.borrow().peek(index)
+	}
+
+	/// The hash of the script that is running.
+	pub fn current_script_hash(&self) -> Option<UInt160> {
+		self.current_context.as_ref().map(|context| context.borrow().script_hash())
+	}
+
+	/// The hash of the script the engine was started with.
+	pub fn entry_script_hash(&self) -> Option<UInt160> {
+		self.entry_context.as_ref().map(|context| context.borrow().script_hash())
+	}
+
+	/// The hash of the script that called the running one, if any.
+	pub fn calling_script_hash(&self) -> Option<UInt160> {
+		let depth = self.invocation_stack.len();
+		let caller = self.invocation_stack.get(depth.checked_sub(2)?)?;
+		Some(caller.borrow().script_hash())
+	}
+
+	fn push_item(&mut self, item: impl StackItem + 'static) -> Result<(), VMException> {
+		self.push(Rc::new(RefCell::new(item)))
+	}
+
+	fn push_int(&mut self, value: BigInt) -> Result<(), VMException> {
+		self.push_item(Integer::try_from(value)?)
+	}
+
+	fn push_bool(&mut self, value: bool) -> Result<(), VMException> {
+		self.push_item(Boolean::new(value))
 	}
 
-	fn execute_instr(&mut self, instr: Instruction) -> Result<VMState, VMException> {
+	fn pop_integer(&mut self) -> Result<BigInt, VMException> {
+		let item = self.pop()?;
+		let value = item.borrow().get_integer();
+		value
+	}
+
+	fn pop_bool(&mut self) -> Result<bool, VMException> {
+		let item = self.pop()?;
+		let value = item.borrow().get_boolean();
+		value
+	}
+
+	fn pop_bytes(&mut self) -> Result<Vec<u8>, VMException> {
+		let item = self.pop()?;
+		let bytes = item.borrow().get_slice()?.to_vec();
+		Ok(bytes)
+	}
+
+	fn pop_string(&mut self) -> Result<String, VMException> {
+		let item = self.pop()?;
+		let value = item.borrow().get_string();
+		value
+	}
+
+	/// Pops a count or index, which must not be negative.
+	fn pop_index(&mut self) -> Result<usize, VMException> {
+		let value = self.pop_integer()?;
+		value.to_usize().ok_or_else(|| out_of_range(&value))
+	}
+
+	fn pop_primitive(&mut self) -> Result<Rc<RefCell<dyn PrimitiveType>>, VMException> {
+		let item = self.pop()?;
+		let key = to_primitive(&*item.borrow());
+		key
+	}
+
+	fn binary(
+		&mut self,
+		op: impl FnOnce(BigInt, BigInt) -> Result<BigInt, VMException>,
+	) -> Result<(), VMException> {
+		let x2 = self.pop_integer()?;
+		let x1 = self.pop_integer()?;
+		self.push_int(op(x1, x2)?)
+	}
+
+	fn compare(&mut self, op: impl FnOnce(&BigInt, &BigInt) -> bool) -> Result<(), VMException> {
+		let x2 = self.pop()?;
+		let x1 = self.pop()?;
+		let result = if x1.borrow().is_null() || x2.borrow().is_null() {
+			false
+		} else {
+			op(&x1.borrow().get_integer()?, &x2.borrow().get_integer()?)
+		};
+		self.push_bool(result)
+	}
+
+	fn jump_if(
+		&mut self,
+		offset: i32,
+		condition: impl FnOnce(&BigInt, &BigInt) -> bool,
+	) -> Result<(), VMException> {
+		let x2 = self.pop_integer()?;
+		let x1 = self.pop_integer()?;
+		if condition(&x1, &x2) {
+			self.execute_jump_offset(offset)?;
+		}
+		Ok(())
+	}
+
+	fn execute_instruction(&mut self, instr: &Instruction) -> Result<(), VMException> {
+		if let Some((kind, access, index)) = instr.slot_access() {
+			return match access {
+				SlotAccess::Load => self.execute_load_from_slot(kind, index),
+				SlotAccess::Store => self.execute_store_to_slot(kind, index),
+			}
+		}
+
 		match instr.opcode {
 			//Push
 			OpCode::PushInt8
@@ -181,32 +340,25 @@ impl ExecutionEngine {
 			| OpCode::PushInt32
 			| OpCode::PushInt64
 			| OpCode::PushInt128
-			| OpCode::PushInt256 => self.push(Rc::new(RefCell::new(Integer::new(&BigInt::from_be_bytes(instr.operand.as_slice()))))),
-			OpCode::PushTrue => self.push(Rc::new(RefCell::new(Boolean::new(true)))),
-			OpCode::PushFalse => self.push(Rc::new(RefCell::new(Boolean::new(false)))),
+			| OpCode::PushInt256 => self.push_int(instr.token_bigint())?,
+			OpCode::PushTrue => self.push_bool(true)?,
+			OpCode::PushFalse => self.push_bool(false)?,
 			OpCode::PushA => {
-				let position = (self.current_context?.get_mut().instruction_pointer as i32)
-					.checked_add(instr.token_i32())
-					.unwrap();
-				if position < 0
-					|| position > self.current_context?.get_mut().script().len() as i32
-				{
-					// return Err(VMException::InvalidOpcode("Bad pointer address: {position}");
-					return Err(VMException::new(Error::new("Bad pointer address")))
+				let context = self.context()?;
+				let (ip, script) = {
+					let context = context.borrow();
+					(context.instruction_pointer, context.script())
+				};
+				let position = ip as i64 + instr.token_i32() as i64;
+				if position < 0 || position > script.len() as i64 {
+					return Err(VMException::InvalidJump(format!("Bad pointer address: {position}")))
 				}
-
-				self.push(
-					StackItem::VMPointer(Pointer::new(
-						&self.current_context?.get_mut().script(),
-						position as usize,
-					))
-					.into(),
-				)
+				self.push_item(Pointer::new(script, position as usize))?
 			},
-			OpCode::PushNull => self.push(StackItem::VMNull(Null::default()).into()),
+			OpCode::PushNull => self.push_item(Null)?,
 			OpCode::PushData1 | OpCode::PushData2 | OpCode::PushData4 => {
-				self.limits.assert_max_item_size(instr.operand.len() as u32);
-				self.push(StackItem::from(instr.operand).into())
+				self.limits.assert_max_item_size(instr.operand.len())?;
+				self.push_item(ByteString::new(instr.operand.clone()))?
 			},
 			OpCode::PushM1
 			| OpCode::Push0
@@ -225,1396 +377,1281 @@ impl ExecutionEngine {
 			| OpCode::Push13
 			| OpCode::Push14
 			| OpCode::Push15
-			| OpCode::Push16 => self.push(StackItem::VMInteger(instr.opcode - OpCode::Push0).into()),
+			| OpCode::Push16 => self.push_int(BigInt::from(instr.opcode as i32 - OpCode::Push0 as i32))?,
 
 			// Control
-			OpCode::Nop => Ok(VMState::None),
-			OpCode::Jmp => self.execute_jump_offset(instr.token_i8() as i32),
-			OpCode::JmpL => self.execute_jump_offset(instr.token_i32()),
-			OpCode::JmpIf =>
-				if self.pop().get_bool() {
-					self.execute_jump_offset(instr.token_i8() as i32)
-				},
-			OpCode::JmpIfL =>
-				if self.pop().get_bool() {
-					self.execute_jump_offset(instr.token_i32())
-				},
-			OpCode::JmpIfNot =>
-				if !self.pop().get_bool() {
-					self.execute_jump_offset(instr.token_i8() as i32)
-				},
-			OpCode::JmpIfNotL =>
-				if !self.pop().get_bool() {
-					self.execute_jump_offset(instr.token_i32())
-				},
-			OpCode::JmpEq => {
-				let x2 = self.pop().get_integer();
-				let x1 = self.pop().get_integer();
-				if x1 == x2 {
-					self.execute_jump_offset(instr.token_i8() as i32)
-				}
+			OpCode::Nop => {},
+			OpCode::Jmp => self.execute_jump_offset(instr.token_i8() as i32)?,
+			OpCode::JmpL => self.execute_jump_offset(instr.token_i32())?,
+			OpCode::JmpIf | OpCode::JmpIfL | OpCode::JmpIfNot | OpCode::JmpIfNotL => {
+				let expected = matches!(instr.opcode, OpCode::JmpIf | OpCode::JmpIfL);
+				if self.pop_bool()? == expected {
+					self.execute_jump_offset(jump_offset(instr))?;
+				}
+			},
+			OpCode::JmpEq | OpCode::JmpEqL => self.jump_if(jump_offset(instr), |a, b| a == b)?,
+			OpCode::JmpNe | OpCode::JmpNeL => self.jump_if(jump_offset(instr), |a, b| a != b)?,
+			OpCode::JmpGt | OpCode::JmpGtL => self.jump_if(jump_offset(instr), |a, b| a > b)?,
+			OpCode::JmpGe | OpCode::JmpGeL => self.jump_if(jump_offset(instr), |a, b| a >= b)?,
+			OpCode::JmpLt | OpCode::JmpLtL => self.jump_if(jump_offset(instr), |a, b| a < b)?,
+			OpCode::JmpLe | OpCode::JmpLeL => self.jump_if(jump_offset(instr), |a, b| a <= b)?,
+			OpCode::Call | OpCode::CallL => {
+				let ip = self.context()?.borrow().instruction_pointer;
+				self.execute_call(ip as i64 + jump_offset(instr) as i64)?
 			},
-			OpCode::JmpEqL => {
-				let x2 = self.pop().get_integer();
-				let x1 = self.pop().get_integer();
-				if x1 == x2 {
-					self.execute_jump_offset(instr.token_i32())
-				}
-			},
-			OpCode::JmpNe => {
-				let x2 = self.pop().get_integer();
-				let x1 = self.pop().get_integer();
-				if x1 != x2 {
-					self.execute_jump_offset(instr.token_i8() as i32)
-				}
-			},
-			OpCode::JmpNeL => {
-				let x2 = self.pop().get_integer();
-				let x1 = self.pop().get_integer();
-				if x1 != x2 {
-					self.execute_jump_offset(instr.token_i32())
-				}
-			},
-			OpCode::JmpGt => {
-				let x2 = self.pop().get_integer();
-				let x1 = self.pop().get_integer();
-				if x1 > x2 {
-					self.execute_jump_offset(instr.token_i8() as i32)
-				}
-			},
-			OpCode::JmpGtL => {
-				let x2 = self.pop().get_integer();
-				let x1 = self.pop().get_integer();
-				if x1 > x2 {
-					self.execute_jump_offset(instr.token_i32())
-				}
-			},
-			OpCode::JmpGe => {
-				let x2 = self.pop().get_integer();
-				let x1 = self.pop().get_integer();
-				if x1 >= x2 {
-					self.execute_jump_offset(instr.token_i8() as i32)
-				}
-			},
-			OpCode::JmpGeL => {
-				let x2 = self.pop().get_integer();
-				let x1 = self.pop().get_integer();
-				if x1 >= x2 {
-					self.execute_jump_offset(instr.token_i32())
-				}
-			},
-			OpCode::JmpLt => {
-				let x2 = self.pop().get_integer();
-				let x1 = self.pop().get_integer();
-				if x1 < x2 {
-					self.execute_jump_offset(instr.token_i8() as i32)
-				}
-			},
-			OpCode::JmpLtL => {
-				let x2 = self.pop().get_integer();
-				let x1 = self.pop().get_integer();
-				if x1 < x2 {
-					self.execute_jump_offset(instr.token_i32())
-				}
-			},
-			OpCode::JmpLe => {
-				let x2 = self.pop().get_integer();
-				let x1 = self.pop().get_integer();
-				if x1 <= x2 {
-					self.execute_jump_offset(instr.token_i8() as i32)
-				}
-			},
-			OpCode::JmpLeL => {
-				let x2 = self.pop().get_integer();
-				let x1 = self.pop().get_integer();
-				if x1 <= x2 {
-					self.execute_jump_offset(instr.token_i32())
-				}
-			},
-			OpCode::Call => self.execute_call(
-				(self.current_context?.get_mut().instruction_pointer + instr.token_i8()) as i32,
-			),
-			OpCode::CallL => self
-				.execute_call((self.current_context?.get_mut().instruction_pointer + instr.token_i32()) as i32),
 			OpCode::CallA => {
-				let x: Pointer = self.pop().into();
-				if x.script() != self.current_context?.get_mut().script() {
-					return Err(VMException::InvalidOpcode(
-						"Pointers can't be shared between scripts".parse().unwrap(),
+				let x = self.pop()?;
+				let x = x.borrow();
+				let pointer = x
+					.as_any()
+					.downcast_ref::<Pointer>()
+					.ok_or_else(|| invalid_cast(x.get_type(), StackItemType::Pointer))?;
+				if *pointer.script() != self.context()?.borrow().script() {
+					return Err(VMException::InvalidJump(
+						"Pointers can't be shared between scripts".to_string(),
 					))
 				}
-				self.execute_call(x.position() as i32)
+				self.execute_call(pointer.position() as i64)?
 			},
-			OpCode::CallT => self.load_token(instr.token_u16()),
+			OpCode::CallT => self.load_token(instr.token_u16())?,
 			OpCode::Abort =>
-				Err(VMException::InvalidOpcode("{OpCode::ABORT} is executed.".parse().unwrap())),
-			OpCode::Assert => {
-				let x = self.pop().get_bool();
-				if !x {
-					Err(VMException::InvalidOpcode(
-						"{OpCode::ASSERT} is executed with false result.".parse().unwrap(),
-					))
-				}
-				// break;
-			},
-			OpCode::Throw => self.execute_throw(self.pop()),
-			OpCode::Try => {
-				let catch_offset = instr.token_i8();
-				let finally_offset = instr.token_i8_1();
-				self.execute_try(catch_offset as usize, finally_offset as usize)
-				// break;
-			},
-			OpCode::TryL => {
-				let catch_offset = instr.token_i32();
-				let finally_offset = instr.token_i32_1();
-				self.execute_try(catch_offset as usize, finally_offset as usize)
-			},
-			OpCode::EndTry => {
-				let end_offset = instr.token_i8();
-				self.execute_end_try(end_offset as usize)
-			},
-			OpCode::EndTryL => {
-				let end_offset = instr.token_i32();
-				self.execute_end_try(end_offset as usize)
+				return Err(VMException::Custom(format!("{:?} is executed.", OpCode::Abort))),
+			OpCode::Assert =>
+				if !self.pop_bool()? {
+					return Err(VMException::Custom(format!(
+						"{:?} is executed with false result.",
+						OpCode::Assert
+					)))
+				},
+			OpCode::Throw => {
+				let exception = self.pop()?;
+				self.execute_throw(exception)?
 			},
+			OpCode::Try => self.execute_try(instr.token_i8() as i32, instr.token_i8_1() as i32)?,
+			OpCode::TryL => self.execute_try(instr.token_i32(), instr.token_i32_1())?,
+			OpCode::EndTry | OpCode::EndTryL => self.execute_end_try(jump_offset(instr))?,
 			OpCode::EndFinally => {
-				if self.current_context?.get_mut().try_stack.is_none() {
-					return Err(VMException::InvalidOpcode(
-						"The corresponding TRY block cannot be found.".parse().unwrap(),
-					))
-				}
-				let current_try = match self.current_context?.get_mut().try_stack {
-					Some(ref mut x) => x,
-					None =>
-						return Err(VMException::InvalidOpcode(
-							"The corresponding TRY block cannot be found.".parse().unwrap(),
-						)),
-				};
-
+				let context = self.context()?;
+				let current_try =
+					context.borrow_mut().try_stack.as_mut().and_then(|stack| stack.pop());
+				let current_try = current_try.ok_or_else(try_not_found)?;
 				if self.uncaught_exception.is_none() {
-					self.current_context?.get_mut().instruction_pointer = current_try.get_mut().EndPointer;
+					context.borrow_mut().instruction_pointer = current_try.end_pointer() as usize;
 				} else {
-					self.handle_exception();
+					self.handle_exception()?;
 				}
-
-				self.is_jumping = true
+				self.is_jumping = true;
 			},
 			OpCode::Ret => {
-				let mut context_pop = self.invocation_stack.pop().unwrap();
-				let stack_eval = match self.invocation_stack.len() == 0 {
-					true => self.result_stack.clone(),
-					false => self
-						.invocation_stack
-						.last()
-						.unwrap()
-						.borrow()
-						.evaluation_stack()
-						.clone(),
+				let context_pop = self.invocation_stack.pop().unwrap();
+				let stack_eval = match self.invocation_stack.last() {
+					Some(caller) => caller.borrow().evaluation_stack(),
+					None => self.result_stack.clone(),
 				};
-				// }
-				// ? self.result_stack.clone() : self.invocation_stack.self.peek().EvaluationStack;
-				if context_pop.borrow().evaluation_stack() != stack_eval {
-					if context_pop.borrow().rv_count >= 0
-						&& context_pop.get_mut().evaluation_stack().get_mut().len() != context_pop.borrow().rv_count as usize
-					{
-						return Err(VMException::InvalidOpcode(
-							"RVCount doesn't match with EvaluationStack".parse().unwrap(),
+				let popped_stack = context_pop.borrow().evaluation_stack();
+				if !Rc::ptr_eq(&popped_stack, &stack_eval) {
+					let rv_count = context_pop.borrow().rv_count;
+					if rv_count >= 0 && popped_stack.borrow().size() != rv_count as usize {
+						return Err(VMException::InvalidParameter(
+							"RVCount doesn't match with EvaluationStack".to_string(),
 						))
 					}
-					context_pop.get_mut().evaluation_stack().CopyTo(stack_eval);
+					popped_stack.borrow_mut().move_to(&mut stack_eval.borrow_mut());
 				}
-				if self.invocation_stack.len() == 0 {
+				if self.invocation_stack.is_empty() {
 					self.state = VMState::Halt;
 				}
-
-				self.unload_context(context_pop);
-				self.is_jumping = true
-				// break;
+				self.unload_context();
+				self.is_jumping = true;
 			},
-			OpCode::Syscall => self.on_syscall(instr.token_u32()),
+			OpCode::Syscall => self.on_syscall(instr.token_u32())?,
 
 			// Stack ops
-			OpCode::Depth => self.push(self.current_context?.get_mut().evaluation_stack().borrow().len()),
-			OpCode::Drop => self.pop(),
-			OpCode::Nip => self.current_context.unwrap().evaluation_stack().remove(1),
+			OpCode::Depth => {
+				let depth = self.stack()?.borrow().size();
+				self.push_int(BigInt::from(depth))?
+			},
+			OpCode::Drop => {
+				self.pop()?;
+			},
+			OpCode::Nip => {
+				self.stack()?.borrow_mut().remove(1)?;
+			},
 			OpCode::Xdrop => {
-				let n = self.pop().get_integer().to_i32().unwrap();
-				if n < 0 {
-					return Err(VMException::InvalidOpcode(
-						"The negative value {n} is invalid for OpCode::{instr.OpCode}."
-							.parse()
-							.unwrap(),
-					))
-				}
-				self.current_context.unwrap().evaluation_stack().remove(n as i64)
+				let n = self.pop_index()?;
+				self.stack()?.borrow_mut().remove(n)?;
+			},
+			OpCode::Clear => self.stack()?.borrow_mut().clear(),
+			OpCode::Dup => {
+				let x = self.peek(0)?;
+				self.push(x)?
+			},
+			OpCode::Over => {
+				let x = self.peek(1)?;
+				self.push(x)?
 			},
-			OpCode::Clear => self.current_context.unwrap().evaluation_stack().Clear(),
-			OpCode::Dup => self.push(self.peek(0).clone()),
-			OpCode::Over => self.push(self.peek(1).clone()),
 			OpCode::Pick => {
-				let n = self.pop().get_integer();
-				if n < BigInt::zero() {
-					return Err(VMException::InvalidOpcode(
-						"The negative value {n} is invalid for OpCode::{instr.OpCode}."
-							.parse()
-							.unwrap(),
-					))
-				}
-				self.push(self.peek(n.to_i32().unwrap() as usize).clone())
-				// break;
-			},
-			OpCode::Tuck => self
-				.current_context?
-				.get_mut()
-				.evaluation_stack()
-				.get_mut()
-				.Insert(2, self.peek(0)),
+				let n = self.pop_index()?;
+				let x = self.peek(n)?;
+				self.push(x)?
+			},
+			OpCode::Tuck => {
+				let x = self.peek(0)?;
+				self.stack()?.borrow_mut().insert(2, x)?
+			},
 			OpCode::Swap => {
-				let x = self.current_context.unwrap().evaluation_stack().remove(1);
-				self.push(StackItem::from(x).into())
-				// break;
+				let x = self.stack()?.borrow_mut().remove(1)?;
+				self.push(x)?
 			},
 			OpCode::Rot => {
-				let x = self.current_context.unwrap().evaluation_stack().remove(2);
-				self.push(StackItem::from(x).into())
+				let x = self.stack()?.borrow_mut().remove(2)?;
+				self.push(x)?
 			},
 			OpCode::Roll => {
-				let n = self.pop().get_integer().to_i64().unwrap();
-				if n < 0 {
-					return Err(VMException::InvalidOpcode(
-						"The negative value {n} is invalid for OpCode::{instr.OpCode}."
-							.parse()
-							.unwrap(),
-					))
-				}
-				if n == 0 {
-					return Ok(VMState::None)
+				let n = self.pop_index()?;
+				if n != 0 {
+					let x = self.stack()?.borrow_mut().remove(n)?;
+					self.push(x)?
 				}
-				let x = self.current_context?.get_mut().evaluation_stack().remove(n);
-				self.push(StackItem::from(x).into())
 			},
-			OpCode::Reverse3 => self.current_context?.get_mut().evaluation_stack().Reverse(3),
-			OpCode::Reverse4 => self.current_context?.get_mut().evaluation_stack().Reverse(4),
+			OpCode::Reverse3 => self.stack()?.borrow_mut().reverse(3)?,
+			OpCode::Reverse4 => self.stack()?.borrow_mut().reverse(4)?,
 			OpCode::ReverseN => {
-				let n = self.pop().get_integer();
-				self.current_context?.get_mut().evaluation_stack().Reverse(n)
+				let n = self.pop_index()?;
+				self.stack()?.borrow_mut().reverse(n)?
 			},
 
 			//Slot
 			OpCode::InitSSLot => {
-				if self.current_context?.get_mut().static_fields().is_some() {
-					return Err(VMException::InvalidOpcode(
-						"{instr.OpCode} cannot be executed twice.".parse().unwrap(),
-					))
+				let context = self.context()?;
+				let context = context.borrow();
+				if context.fields().is_some() {
+					return Err(executed_twice(instr.opcode))
 				}
 				if instr.token_u8() == 0 {
-					return Err(VMException::InvalidOpcode(
-						"The operand {instr.token_u8()} is invalid for OpCode::{instr.OpCode}."
-							.parse()
-							.unwrap(),
-					))
+					return Err(invalid_operand(instr))
 				}
-				self.current_context?.get_mut().set_fields() = Some(
-					&Slot::new_with_count(instr.token_u8() as i32, self.reference_counter.clone()),
-				)
-				// break;
+				*context.fields_mut() = Some(Slot::new_with_count(
+					instr.token_u8() as usize,
+					self.reference_counter.clone(),
+				));
 			},
 			OpCode::InitSlot => {
-				if self.current_context?.get_mut().local_variables.is_some()
-					|| self.current_context?.get_mut().arguments.is_some()
+				let context = self.context()?;
 				{
-					return Err(VMException::InvalidOpcode(
-						"{instr.OpCode} cannot be executed twice.".parse().unwrap(),
-					))
+					let context = context.borrow();
+					if context.local_variables.is_some() || context.arguments.is_some() {
+						return Err(executed_twice(instr.opcode))
+					}
 				}
 				if instr.token_u16() == 0 {
-					return Err(VMException::InvalidOpcode(
-						"The operand {instr.token_u16()} is invalid for OpCode::{instr.OpCode}."
-							.parse()
-							.unwrap(),
-					))
+					return Err(invalid_operand(instr))
 				}
 				if instr.token_u8() > 0 {
-					self.current_context?.get_mut().local_variables = Some(Slot::new_with_count(
-						instr.token_u8() as i32,
+					context.borrow_mut().local_variables = Some(Slot::new_with_count(
+						instr.token_u8() as usize,
 						self.reference_counter.clone(),
 					));
 				}
 				if instr.token_u8_1() > 0 {
-					// generate a vector of instr.token_u8_1() StackItems
-					let mut items = Vec::new();
-					let size = instr.token_u8_1() as usize;
-
-					// for _ in 0..size{
-					//     items.push(StackItem::default());
-					// }
-					//
-					for i in 0..size {
-						items[i] = self.pop();
-					}
-
-					self.current_context?.get_mut().arguments =
-						Some(Slot::new(items, self.reference_counter.clone()))
+					let items = (0..instr.token_u8_1())
+						.map(|_| self.pop())
+						.collect::<Result<Vec<_>, _>>()?;
+					context.borrow_mut().arguments =
+						Some(Slot::new(items, self.reference_counter.clone()));
 				}
 			},
-			OpCode::LdSFLd0
-			| OpCode::LdSFLd1
-			| OpCode::LdSFLd2
-			| OpCode::LdSFLd3
-			| OpCode::LdSFLd4
-			| OpCode::LdSFLd5
-			| OpCode::LdSFLd6 => self.execute_load_from_slot(
-				&mut self.current_context?.get_mut().fields().unwrap(),
-				instr.opcode - OpCode::LdSFLd0,
-			),
-			OpCode::LdSFLd => self.execute_load_from_slot(
-				&mut self.current_context?.get_mut().fields().unwrap(),
-				instr.token_u8() as usize,
-			),
-			OpCode::StSFLd0
-			| OpCode::StSFLd1
-			| OpCode::StSFLd2
-			| OpCode::StSFLd3
-			| OpCode::StSFLd4
-			| OpCode::StSFLd5
-			| OpCode::StSFLd6 => self.execute_store_to_slot(
-				&mut self.current_context?.get_mut().fields()?,
-				instr.opcode - OpCode::StSFLd0,
-			),
-			OpCode::StSFLd => self.execute_store_to_slot(
-				&mut self.current_context?.get_mut().fields()?,
-				instr.token_u8() as usize,
-			),
-			OpCode::LdLoc0
-			| OpCode::LdLoc1
-			| OpCode::LdLoc2
-			| OpCode::LdLoc3
-			| OpCode::LdLoc4
-			| OpCode::LdLoc5
-			| OpCode::LdLoc6 => self.execute_load_from_slot(
-				& mut self.current_context?.get_mut().local_variables?,
-				instr.opcode - OpCode::LdLoc0,
-			),
-			OpCode::LdLoc => self.execute_load_from_slot(
-				&mut self.current_context?.get_mut().local_variables?,
-				instr.token_u8() as usize,
-			),
-			OpCode::StLoc0
-			| OpCode::StLoc1
-			| OpCode::StLoc2
-			| OpCode::StLoc3
-			| OpCode::StLoc4
-			| OpCode::StLoc5
-			| OpCode::StLoc6 => self.execute_store_to_slot(
-				&mut self.current_context?.get_mut().local_variables?,
-				instr.opcode - OpCode::StLoc0,
-			),
-			OpCode::StLoc => self.execute_store_to_slot(
-				&mut self.current_context?.get_mut().local_variables?,
-				instr.token_u8() as usize,
-			),
-			OpCode::LdArg0
-			| OpCode::LdArg1
-			| OpCode::LdArg2
-			| OpCode::LdArg3
-			| OpCode::LdArg4
-			| OpCode::LdArg5
-			| OpCode::LdArg6 => self.execute_load_from_slot(
-				&mut self.current_context?.get_mut().arguments.unwrap(),
-				instr.opcode - OpCode::LdArg0,
-			),
-			OpCode::LdArg => self.execute_load_from_slot(
-				&mut self.current_context?.get_mut().arguments.unwrap(),
-				instr.token_u8() as usize,
-			),
-			OpCode::StArg0
-			| OpCode::StArg1
-			| OpCode::StArg2
-			| OpCode::StArg3
-			| OpCode::StArg4
-			| OpCode::StArg5
-			| OpCode::StArg6 => self.execute_store_to_slot(
-				&mut self.current_context?.get_mut().arguments?,
-				instr.opcode - OpCode::StArg0,
-			),
-			OpCode::StArg => self.execute_store_to_slot(
-				&mut self.current_context?.get_mut().arguments?,
-				instr.token_u8() as usize,
-			),
 
 			// Splice
 			OpCode::NewBuffer => {
-				let length = self.pop().get_integer();
-				self.limits.assert_max_item_size(length.to_u32().unwrap());
-				self.push(StackItem::from(Buffer::new(length.to_usize().unwrap())).into())
+				let length = self.pop_index()?;
+				self.limits.assert_max_item_size(length)?;
+				self.push_item(Buffer::new(length))?
 			},
 			OpCode::MemCpy => {
-				let count = self.pop().get_integer().to_i64().unwrap();
-				if count < 0 {
-					return Err(VMException::InvalidOpcode(
-						"The value {count} is out of range.".parse().unwrap(),
-					))
-				}
-				let si = self.pop().get_integer().to_i64().unwrap();
-				if si < 0 {
-					return Err(VMException::InvalidOpcode(
-						"The value {si} is out of range.".parse().unwrap(),
-					))
-				}
-				let src = self.pop().get_mut().get_slice();
-				if si.checked_add(count).unwrap() > src.len() as i64 {
-					return Err(VMException::InvalidOpcode(
-						"The value {count} is out of range.".parse().unwrap(),
-					))
-				}
-				let di = self.pop().get_mut().get_integer().to_i64().unwrap();
-				if di < 0 {
-					return Err(VMException::InvalidOpcode(
-						"The value {di} is out of range.".parse().unwrap(),
-					))
-				}
-				let dst: Buffer = self.pop().into();
-				if di.checked_add(count)? > dst.size() as i64 {
-					return Err(VMException::InvalidOpcode(
-						"The value {count} is out of range.".parse().unwrap(),
-					))
-				}
-				src.Slice(si, count).CopyTo(dst.get_slice()[di..])
+				let count = self.pop_index()?;
+				let si = self.pop_index()?;
+				let src = self.pop_bytes()?;
+				if si.checked_add(count).is_none_or(|end| end > src.len()) {
+					return Err(out_of_range(&BigInt::from(count)))
+				}
+				let di = self.pop_index()?;
+				let dst = self.pop()?;
+				let mut dst = dst.borrow_mut();
+				let ty = dst.get_type();
+				let buffer = dst
+					.as_any_mut()
+					.downcast_mut::<Buffer>()
+					.ok_or_else(|| invalid_cast(ty, StackItemType::Buffer))?;
+				if di.checked_add(count).is_none_or(|end| end > buffer.len()) {
+					return Err(out_of_range(&BigInt::from(count)))
+				}
+				buffer.data_mut()[di..di + count].copy_from_slice(&src[si..si + count]);
 			},
 			OpCode::Cat => {
-				let x2 = self.pop().GetSpan();
-				let x1 = self.pop().GetSpan();
-				let length = x1.Length + x2.Length;
-				self.limits.assert_max_item_size(length);
-				let result = Buffer::new(length); //, false);
-				x1.CopyTo(result.get_slice());
-				x2.CopyTo(result.get_slice()[x1.Length..]);
-				self.push(StackItem::from(result).into())
-				// break;
+				let x2 = self.pop_bytes()?;
+				let mut x1 = self.pop_bytes()?;
+				self.limits.assert_max_item_size(x1.len() + x2.len())?;
+				x1.extend_from_slice(&x2);
+				self.push_item(Buffer::from(x1))?
 			},
 			OpCode::Substr => {
-				let count = self.pop().get_integer().to_usize().unwrap();
-				if count < 0 {
-					return Err(VMException::InvalidOpcode(
-						"The value {count} is out of range.".parse().unwrap(),
-					))
-				}
-				let index = self.pop().get_integer().to_usize().unwrap();
-				if index < 0 {
-					return Err(VMException::InvalidOpcode(
-						"The value {index} is out of range.".parse().unwrap(),
-					))
-				}
-				let x = self.pop().GetSpan();
-				if index + count > x.Length {
-					return Err(VMException::InvalidOpcode(
-						"The value {count} is out of range.".parse().unwrap(),
-					))
+				let count = self.pop_index()?;
+				let index = self.pop_index()?;
+				let x = self.pop_bytes()?;
+				if index.checked_add(count).is_none_or(|end| end > x.len()) {
+					return Err(out_of_range(&BigInt::from(count)))
 				}
-				let result = Buffer::new(count); //, false);
-				x.Slice(index, count).CopyTo(result.get_slice());
-				self.push(StackItem::from(result).into())
+				self.push_item(Buffer::from_slice(&x[index..index + count]))?
 			},
 			OpCode::Left => {
-				let count = self.pop().get_integer().to_i32().unwrap();
-				if count < 0 {
-					return Err(VMException::InvalidOpcode(
-						"The value {count} is out of range.".parse().unwrap(),
-					))
-				}
-				let x = self.pop().GetSpan();
-				if count > x.Length {
-					return Err(VMException::InvalidOpcode(
-						"The value {count} is out of range.".parse().unwrap(),
-					))
+				let count = self.pop_index()?;
+				let x = self.pop_bytes()?;
+				if count > x.len() {
+					return Err(out_of_range(&BigInt::from(count)))
 				}
-				let result = Buffer::new(count as usize); //, false);
-				x[..count].CopyTo(result.get_slice());
-				self.push(StackItem::from(result).into())
+				self.push_item(Buffer::from_slice(&x[..count]))?
 			},
 			OpCode::Right => {
-				let count = self.pop().get_integer().to_i32().unwrap();
-				if count < 0 {
-					return Err(VMException::InvalidOpcode(
-						"The value {count} is out of range.".parse().unwrap(),
-					))
-				}
-				let x = self.pop().get_slice();
-				if count > x.Length {
-					return Err(VMException::InvalidOpcode(
-						"The value {count} is out of range.".parse().unwrap(),
-					))
+				let count = self.pop_index()?;
+				let x = self.pop_bytes()?;
+				if count > x.len() {
+					return Err(out_of_range(&BigInt::from(count)))
 				}
-				let result = Buffer::from(x); //, false);
-							  // x[^count.. ^ 0].CopyTo(result.InnerBuffer.Span);
-				self.push(StackItem::from(result).into())
-				// break;
+				self.push_item(Buffer::from_slice(&x[x.len() - count..]))?
 			},
 
 			// Bitwise logic
 			OpCode::Invert => {
-				let x = self.pop().get_integer();
-				self.push(StackItem::from(BigInt::neg(x)).into())
+				let x = self.pop_integer()?;
+				self.push_int(!x)?
 			},
-			OpCode::And => {
-				let x2 = self.pop().get_integer();
-				let x1 = self.pop().get_integer();
-				self.push(StackItem::from(x1 & x2).into())
-			},
-			OpCode::Or => {
-				let x2 = self.pop().get_integer();
-				let x1 = self.pop().get_integer();
-				self.push(StackItem::from(x1 | x2).into())
-			},
-			OpCode::Xor => {
-				let x2 = self.pop().get_integer();
-				let x1 = self.pop().get_integer();
-				self.push(StackItem::from(x1 ^ x2).into())
-			},
-			OpCode::Equal => {
-				let x2 = self.pop();
-				let x1 = self.pop();
-				self.push(x1.Equal(x2, self.limits))
-			},
-			OpCode::NotEqual => {
-				let x2 = self.pop();
-				let x1 = self.pop();
-				self.push(!x1.Equals(x2, self.limits))
+			OpCode::And => self.binary(|x1, x2| Ok(x1 & x2))?,
+			OpCode::Or => self.binary(|x1, x2| Ok(x1 | x2))?,
+			OpCode::Xor => self.binary(|x1, x2| Ok(x1 ^ x2))?,
+			OpCode::Equal | OpCode::NotEqual => {
+				let x2 = self.pop()?;
+				let x1 = self.pop()?;
+				let equal = equals(&x1, &x2, &self.limits)?;
+				self.push_bool(equal == (instr.opcode == OpCode::Equal))?
 			},
 
 			// Numeric
 			OpCode::Sign => {
-				let x = self.pop().get_integer();
-				self.push(StackItem::from(x.Sign).into())
+				let x = self.pop_integer()?;
+				self.push_int(x.signum())?
 			},
 			OpCode::Abs => {
-				let x = self.pop().get_integer();
-				self.push(StackItem::from(BigInt::abs(&x)).into())
+				let x = self.pop_integer()?;
+				self.push_int(x.abs())?
 			},
 			OpCode::Negate => {
-				let x = self.pop().get_integer();
-				self.push(StackItem::from(-x).into())
+				let x = self.pop_integer()?;
+				self.push_int(-x)?
 			},
 			OpCode::Inc => {
-				let x = self.pop().get_integer();
-				self.push(StackItem::from(x + 1).into())
+				let x = self.pop_integer()?;
+				self.push_int(x + 1)?
 			},
 			OpCode::Dec => {
-				let x = self.pop().get_integer();
-				self.push(StackItem::from(x - 1).into())
-			},
-			OpCode::Add => {
-				let x2 = self.pop().get_integer();
-				let x1 = self.pop().get_integer();
-				self.push(StackItem::from(x1 + x2).into())
-			},
-			OpCode::Sub => {
-				let x2 = self.pop().get_integer();
-				let x1 = self.pop().get_integer();
-				self.push(StackItem::from(x1 - x2).into())
-			},
-			OpCode::Mul => {
-				let x2 = self.pop().get_integer();
-				let x1 = self.pop().get_integer();
-				self.push(StackItem::from(x1 * x2).into())
-			},
-			OpCode::Div => {
-				let x2 = self.pop().get_integer();
-				let x1 = self.pop().get_integer();
-				self.push(StackItem::from(x1 / x2).into())
-			},
-			OpCode::Mod => {
-				let x2 = self.pop().get_integer();
-				let x1 = self.pop().get_integer();
-				self.push(StackItem::from(x1 % x2).into())
-			},
+				let x = self.pop_integer()?;
+				self.push_int(x - 1)?
+			},
+			OpCode::Add => self.binary(|x1, x2| Ok(x1 + x2))?,
+			OpCode::Sub => self.binary(|x1, x2| Ok(x1 - x2))?,
+			OpCode::Mul => self.binary(|x1, x2| Ok(x1 * x2))?,
+			OpCode::Div => self.binary(|x1, x2| {
+				check_divisor(&x2)?;
+				Ok(x1 / x2)
+			})?,
+			OpCode::Mod => self.binary(|x1, x2| {
+				check_divisor(&x2)?;
+				Ok(x1 % x2)
+			})?,
 			OpCode::Pow => {
-				let exponent = self.pop().get_integer().to_i32().unwrap();
-				self.limits.assert_shift(exponent);
-				let value = self.pop().get_integer();
-				self.push(StackItem::from(value.pow(exponent as u32)).into())
+				let exponent = self.pop_integer()?;
+				let exponent = exponent.to_i64().ok_or_else(|| out_of_range(&exponent))?;
+				self.limits.assert_shift(exponent)?;
+				let value = self.pop_integer()?;
+				self.push_int(value.pow(exponent as u32))?
+			},
+			OpCode::Sqrt => {
+				let x = self.pop_integer()?;
+				if x.is_negative() {
+					return Err(VMException::InvalidParameter(
+						"value can not be negative".to_string(),
+					))
+				}
+				self.push_int(x.sqrt())?
 			},
-			OpCode::Sqrt => self.push(self.pop().get_integer().Sqrt()),
 			OpCode::ModMul => {
-				let modulus = self.pop().get_integer();
-				let x2 = self.pop().get_integer();
-				let x1 = self.pop().get_integer();
-				self.push(StackItem::from(x1 * x2 % modulus).into())
+				let modulus = self.pop_integer()?;
+				check_divisor(&modulus)?;
+				let x2 = self.pop_integer()?;
+				let x1 = self.pop_integer()?;
+				self.push_int(x1 * x2 % modulus)?
 			},
 			OpCode::ModPow => {
-				let modulus = self.pop().get_integer();
-				let exponent = self.pop().get_integer().to_i32().unwrap();
-				let value = self.pop().get_integer();
-				let result = match exponent == -1 {
-					true => value.ModInverse(modulus),
-					false => value.ModPow(exponent, modulus),
+				let modulus = self.pop_integer()?;
+				let exponent = self.pop_integer()?;
+				let value = self.pop_integer()?;
+				let result = if exponent == -BigInt::one() {
+					mod_inverse(&value, &modulus)?
+				} else {
+					mod_pow(&value, &exponent, &modulus)?
 				};
-				// } value.ModInverse(modulus) :  BigInteger.ModPow(value, exponent, modulus);
-				self.push(StackItem::from(result).into())
-			},
-			OpCode::Shl => {
-				let shift = self.pop().get_integer().to_i32().unwrap();
-				self.limits.assert_shift(shift);
-				if shift == 0 {
-					return Ok(VMState::None)
-				}
-				let x = self.pop().get_integer();
-				self.push(StackItem::from(x << shift).into())
-			},
-			OpCode::Shr => {
-				let shift = self.pop().get_integer().to_i32().unwrap();
-				self.limits.assert_shift(shift);
-				if shift == 0 {
-					return Ok(VMState::None) // break;
+				self.push_int(result)?
+			},
+			OpCode::Shl | OpCode::Shr => {
+				let shift = self.pop_integer()?;
+				let shift = shift.to_i64().ok_or_else(|| out_of_range(&shift))?;
+				self.limits.assert_shift(shift)?;
+				if shift != 0 {
+					let x = self.pop_integer()?;
+					let shift = shift as usize;
+					self.push_int(if instr.opcode == OpCode::Shl {
+						x << shift
+					} else {
+						x >> shift
+					})?
 				}
-				let x = self.pop().get_integer();
-				self.push(StackItem::from(x >> shift).into())
 			},
 			OpCode::Not => {
-				let x = self.pop().get_bool();
-				self.push(StackItem::from(!x).into())
+				let x = self.pop_bool()?;
+				self.push_bool(!x)?
 			},
 			OpCode::BoolAnd => {
-				let x2 = self.pop().get_bool();
-				let x1 = self.pop().get_bool();
-				self.push(StackItem::from(x1 && x2).into())
+				let x2 = self.pop_bool()?;
+				let x1 = self.pop_bool()?;
+				self.push_bool(x1 && x2)?
 			},
 			OpCode::BoolOr => {
-				let x2 = self.pop().get_bool();
-				let x1 = self.pop().get_bool();
-				self.push(StackItem::from(x1 || x2).into())
+				let x2 = self.pop_bool()?;
+				let x1 = self.pop_bool()?;
+				self.push_bool(x1 || x2)?
 			},
 			OpCode::Nz => {
-				let x = self.pop().get_integer();
-				self.push(StackItem::from(!x.is_zero()).into())
+				let x = self.pop_integer()?;
+				self.push_bool(!x.is_zero())?
 			},
 			OpCode::NumEqual => {
-				let x2 = self.pop().get_integer();
-				let x1 = self.pop().get_integer();
-				self.push(StackItem::from(x1 == x2).into())
+				let x2 = self.pop_integer()?;
+				let x1 = self.pop_integer()?;
+				self.push_bool(x1 == x2)?
 			},
 			OpCode::NumNotEqual => {
-				let x2 = self.pop().get_integer();
-				let x1 = self.pop().get_integer();
-				self.push(StackItem::from(x1 != x2).into())
-			},
-			OpCode::Lt => {
-				let x2 = self.pop();
-				let x1 = self.pop();
-				if x1.get_item_type() == StackItemType::Any
-					|| x2.get_item_type() == StackItemType::Any
-				{
-					self.push(StackItem::from(false).into())
-				} else {
-					self.push(StackItem::from(x1.get_integer() < x2.get_integer()).into())
-				}
-			},
-			OpCode::Le => {
-				let x2 = self.pop().borrow();
-				let x1 = self.pop().borrow();
-				if x1.get_type() == StackItemType::Any
-					|| x2.get_item_type() == StackItemType::Any
-				{
-					self.push(StackItem::from(false).into())
-				} else {
-					self.push(StackItem::from(x1.get_integer() <= x2.get_integer()).into())
-				}
-				// break;
-			},
-			OpCode::Gt => {
-				let x2 = self.pop().borrow();
-				let x1 = self.pop().borrow();
-				if x1.get_type() == StackItemType::Any
-					|| x2.get_item_type() == StackItemType::Any
-				{
-					self.push(StackItem::from(false).into())
-				} else {
-					self.push(StackItem::from(x1.get_integer() > x2.get_integer()).into())
-				}
-				// break;
-			},
-			OpCode::Ge => {
-				let x2 = self.pop();
-				let x1 = self.pop();
-				if x1.get_item_type() == StackItemType::Any
-					|| x2.get_item_type() == StackItemType::Any
-				{
-					self.push(StackItem::from(false).into())
-				} else {
-					self.push(StackItem::from(x1.get_integer() >= x2.get_integer()).into())
-				}
-				// break;
-			},
-			OpCode::Min => {
-				let x2 = self.pop().get_integer();
-				let x1 = self.pop().get_integer();
-				self.push(StackItem::from(BigInt::min(x1, x2)).into())
-			},
-			OpCode::Max => {
-				let x2 = self.pop().get_integer();
-				let x1 = self.pop().get_integer();
-				self.push(StackItem::from(BigInt::max(x1, x2)).into())
-				// break;
-			},
+				let x2 = self.pop_integer()?;
+				let x1 = self.pop_integer()?;
+				self.push_bool(x1 != x2)?
+			},
+			OpCode::Lt => self.compare(|x1, x2| x1 < x2)?,
+			OpCode::Le => self.compare(|x1, x2| x1 <= x2)?,
+			OpCode::Gt => self.compare(|x1, x2| x1 > x2)?,
+			OpCode::Ge => self.compare(|x1, x2| x1 >= x2)?,
+			OpCode::Min => self.binary(|x1, x2| Ok(x1.min(x2)))?,
+			OpCode::Max => self.binary(|x1, x2| Ok(x1.max(x2)))?,
 			OpCode::Within => {
-				let b = self.pop().get_integer();
-				let a = self.pop().get_integer();
-				let x = self.pop().get_integer();
-				self.push(StackItem::from(a <= x && x < b).into())
+				let b = self.pop_integer()?;
+				let a = self.pop_integer()?;
+				let x = self.pop_integer()?;
+				self.push_bool(a <= x && x < b)?
 			},
 
 			// Compound-type
 			OpCode::PackMap => {
-				let size = self.pop().get_integer().to_usize().unwrap();
-				if size < 0 || size * 2 > self.current_context?.get_mut().evaluation_stack().borrow().size() {
-					return Err(VMException::InvalidOpcode(
-						"The value {size} is out of range.".parse().unwrap(),
-					))
-				}
-				let map = Map::new(Some(self.reference_counter.clone()));
-				for i in 0..size {
-					let key: dyn PrimitiveType = self.pop().into();
-					let value = self.pop();
-					map[key] = value;
-				}
-				self.push(StackItem::VMMap(map).into())
-			},
-			OpCode::PackStruct => {
-				let size = self.pop().get_integer().to_i64().unwrap();
-				if size < 0 || size > self.current_context?.get_mut().evaluation_stack().borrow().size() {
-					return Err(VMException::InvalidOpcode(
-						"The value {size} is out of range.".parse().unwrap(),
-					))
-				}
-				let _struct = Struct::new(None, Some(self.reference_counter.clone()));
-				for i in 0..size {
-					let item = self.pop();
-					_struct.Add(item);
-				}
-				self.push(StackItem::VMStruct(_struct).into())
-				// break;
-			},
-			OpCode::Pack => {
-				let size = self.pop().get_integer().to_usize().unwrap();
-				if size < 0
-					|| size > self.current_context.unwrap().evaluation_stack().len()
-				{
-					return Err(VMException::InvalidOpcode(
-						"The value {size} is out of range.".parse().unwrap(),
-					))
-				}
-				let array = Array::new(None, Some(self.reference_counter.clone()));
-				for i in 0..size {
-					let item = self.pop();
-					array.Add(item);
+				let size = self.pop_index()?;
+				if size.saturating_mul(2) > self.stack()?.borrow().size() {
+					return Err(out_of_range(&BigInt::from(size)))
+				}
+				let mut map = Map::new(Some(self.reference_counter.clone()));
+				for _ in 0..size {
+					let key = self.pop_primitive()?;
+					let value = self.pop()?;
+					map.insert(key, value)?;
+				}
+				self.push_item(map)?
+			},
+			OpCode::PackStruct | OpCode::Pack => {
+				let size = self.pop_index()?;
+				if size > self.stack()?.borrow().size() {
+					return Err(out_of_range(&BigInt::from(size)))
+				}
+				let items = (0..size).map(|_| self.pop()).collect::<Result<Vec<_>, _>>()?;
+				let counter = Some(self.reference_counter.clone());
+				if instr.opcode == OpCode::Pack {
+					self.push_item(Array::new(Some(items), counter))?
+				} else {
+					self.push_item(Struct::new(Some(items), counter))?
 				}
-				self.push(StackItem::from(array).into())
 			},
 			OpCode::Unpack => {
-				let compound: dyn CompoundType = self.pop().into();
-				match compound {
-					CompoundType::VMMap(map) =>
-						for (key, value) in map.values().rev() {
-							self.push((value).into().into());
-							self.push((key).into());
-						},
-
-					// break;
-					CompoundType::VMArray(array) =>
-						for i in (0..=array.array.len()).rev() {
-							self.push(array[i].into());
-						},
-					// break;
-					_ =>
-						return Err(VMException::InvalidOpcode(
-							"Invalid type for {instr.OpCode}: {compound.Type}".parse().unwrap(),
-						)),
-				}
-				self.push(StackItem::from(compound.count()).into())
-			},
-			OpCode::NewArray0 => self.push(
-				StackItem::from(Array::new(None, Some(self.reference_counter.clone()))).into(),
-			),
-			OpCode::NewArray | OpCode::NewArrayT => {
-				let n = self.pop().get_integer().to_i64().unwrap();
-				if n < 0 || n > self.limits.max_stack_size {
-					return Err(VMException::InvalidOpcode(
-						"MaxStackSize exceed: {n}".parse().unwrap(),
-					))
-				}
-				let item: dyn StackItem;
-				if instr.opcode == OpCode::NewArrayT {
-					let _type = instr.token_u8();
-					if !StackItemType::is_valid(_type) {
-						return Err(VMException::InvalidOpcode(
-							"Invalid type for {instr.OpCode}: {instr.token_u8()}".parse().unwrap(),
-						))
+				let compound = self.pop()?;
+				let (items, count) = {
+					let compound = compound.borrow();
+					if let Some(map) = compound.as_any().downcast_ref::<Map>() {
+						let mut items = Vec::with_capacity(map.len() * 2);
+						for (key, value) in map.iter() {
+							items.push(key.clone() as Rc<RefCell<dyn StackItem>>);
+							items.push(value.clone());
+						}
+						(items, map.len())
+					} else if let Some(array) = as_array(&*compound) {
+						(array.iter().rev().cloned().collect(), array.len())
+					} else {
+						return Err(invalid_type(instr.opcode, compound.get_type()))
+					}
+				};
+				if compound.borrow().get_type() == StackItemType::Map {
+					// Pairs are pushed last entry first, the value below its key.
+					for pair in items.chunks(2).rev() {
+						self.push(pair[1].clone())?;
+						self.push(pair[0].clone())?;
 					}
-					item = match _type as StackItemType {
-						StackItemType::Boolean => StackItem::from(false),
-						StackItemType::Integer => StackItem::from(BigInt::zero()),
-						StackItemType::ByteString => StackItem::from(ByteString::new(Vec::new())),
-						_ => StackItem::from(Null::default()),
-					};
 				} else {
-					item = StackItem::VMNull(Null::default());
-				}
-				self.push(
-					StackItem::from(Array::new(
-						std::iter::repeat(item).take(n as usize).collect(),
-						Some(self.reference_counter.clone()),
-					))
-					.into(),
-				)
-			},
-			OpCode::NewStruct0 => self.push(
-				StackItem::from(Struct::new(None, Some(self.reference_counter.clone()))).into(),
-			),
-			OpCode::NewStruct => {
-				let n = self.pop().get_integer() as usize;
-				if n < 0 || n > self.limits.max_stack_size {
-					return Err(VMException::InvalidOpcode(
-						"MaxStackSize exceed: {n}".parse().unwrap(),
-					))
+					for item in items {
+						self.push(item)?;
+					}
 				}
-				let result = Struct::new(None, Some(self.reference_counter.clone()));
-				for i in 0..n {
-					result.Add(StackItem::from(Null::default()));
+				self.push_int(BigInt::from(count))?
+			},
+			OpCode::NewArray0 =>
+				self.push_item(Array::new(None, Some(self.reference_counter.clone())))?,
+			OpCode::NewArray | OpCode::NewArrayT | OpCode::NewStruct => {
+				let n = self.pop_index()?;
+				if n > self.limits.max_stack_size {
+					return Err(VMException::StackOverflow(format!("MaxStackSize exceed: {n}")))
+				}
+				let item: Rc<RefCell<dyn StackItem>> = if instr.opcode == OpCode::NewArrayT {
+					let ty = StackItemType::from_u8(instr.token_u8()).ok_or_else(|| {
+						VMException::InvalidType(format!("Invalid type: {}", instr.token_u8()))
+					})?;
+					match ty {
+						StackItemType::Boolean => Rc::new(RefCell::new(Boolean::new(false))),
+						StackItemType::Integer => Rc::new(RefCell::new(Integer::from(0))),
+						StackItemType::ByteString => Rc::new(RefCell::new(ByteString::EMPTY)),
+						_ => Rc::new(RefCell::new(Null)),
+					}
+				} else {
+					Rc::new(RefCell::new(Null))
+				};
+				let items = Some(vec![item; n]);
+				let counter = Some(self.reference_counter.clone());
+				if instr.opcode == OpCode::NewStruct {
+					self.push_item(Struct::new(items, counter))?
+				} else {
+					self.push_item(Array::new(items, counter))?
 				}
-				self.push(StackItem::from(result).into())
-				// break;
 			},
-			OpCode::NewMap =>
-				self.push(StackItem::from(Map::new(Some(self.reference_counter.clone()))).into()),
+			OpCode::NewStruct0 =>
+				self.push_item(Struct::new(None, Some(self.reference_counter.clone())))?,
+			OpCode::NewMap => self.push_item(Map::new(Some(self.reference_counter.clone())))?,
 			OpCode::Size => {
-				let x = self.pop();
-				match x {
-					StackItem::VMArray(array) => self.push(StackItem::from(array.Count).into()),
-					StackItem::VMMap(map) => self.push(StackItem::from(map.Count).into()),
-					StackItem::VMStruct(_struct) =>
-						self.push(StackItem::from(_struct.Count).into()),
-					StackItem::VMByteString(array) => self.push(StackItem::from(array.Size).into()),
-					StackItem::VMBuffer(buffer) => self.push(StackItem::from(buffer.Size).into()),
-					StackItem::VMInteger(integer) =>
-						self.push(StackItem::from(integer.size()).into()),
-					_ =>
-						return Err(VMException::InvalidOpcode(
-							"Invalid type for {instr.OpCode}: {x.Type}".parse().unwrap(),
-						)),
-				}
+				let x = self.pop()?;
+				let size = {
+					let x = x.borrow();
+					match (as_compound(&*x), x.get_type()) {
+						(Some(compound), _) => compound.count(),
+						(
+							None,
+							StackItemType::Boolean
+							| StackItemType::Integer
+							| StackItemType::ByteString
+							| StackItemType::Buffer,
+						) => x.get_slice()?.len(),
+						(None, ty) => return Err(invalid_type(instr.opcode, ty)),
+					}
+				};
+				self.push_int(BigInt::from(size))?
 			},
 			OpCode::HasKey => {
-				let key: Rc<RefCell<dyn PrimitiveType>> = self.pop().into();
-				let x = self.pop();
-				match x {
-					StackItem::VMMap(map) =>
-						self.push(StackItem::from(map.contains_key(key)).into()),
-					StackItem::VMByteString(array) => {
-						let index = key.get_integer().to_u32().unwrap();
-						if index < 0 {
-							return Err(VMException::InvalidOpcode(
-								"The negative value {index} is invalid for OpCode::{instr.OpCode}."
-									.parse()
-									.unwrap(),
-							))
-						}
-						self.push(StackItem::from(index < array.size() as u32).into())
+				let key = self.pop_primitive()?;
+				let x = self.pop()?;
+				let x = x.borrow();
+				let result = match x.get_type() {
+					StackItemType::Array | StackItemType::Struct => {
+						let index = key_index(&key)?;
+						index < as_array(&*x).unwrap().len()
 					},
-					StackItem::VMBuffer(buffer) => {
-						let index = key.get_integer().to_u32().unwrap();
-						if index < 0 {
-							return Err(VMException::InvalidOpcode(
-								"The negative value {index} is invalid for OpCode::{instr.OpCode}."
-									.parse()
-									.unwrap(),
-							))
-						}
-						self.push(StackItem::from(index < buffer.size() as u32).into())
+					StackItemType::Map =>
+						x.as_any().downcast_ref::<Map>().unwrap().contains_key(&*key.borrow())?,
+					StackItemType::Buffer | StackItemType::ByteString => {
+						let index = key_index(&key)?;
+						index < x.get_slice()?.len()
 					},
-					StackItem::VMArray(array) => {
-						let index = key.get_integer().to_u32().unwrap();
-						if index < 0 {
-							return Err(VMException::InvalidOpcode(
-								"The negative value {index} is invalid for OpCode::{instr.OpCode}."
-									.parse()
-									.unwrap(),
-							))
-						}
-
-						self.push(StackItem::from(index < array.count() as u32).into())
-					},
-					_ =>
-						return Err(VMException::InvalidOpcode(
-							"Invalid type for {instr.OpCode}: {x.Type}".parse().unwrap(),
-						)),
-				}
-				// break;
+					ty => return Err(invalid_type(instr.opcode, ty)),
+				};
+				drop(x);
+				self.push_bool(result)?
 			},
 			OpCode::Keys => {
-				let map: Map = self.pop().into();
-				self.push(
-					StackItem::from(Array::new(
-						Some(map.keys()),
-						Some(self.reference_counter.clone()),
-					))
-					.into(),
-				)
+				let x = self.pop()?;
+				let keys = {
+					let x = x.borrow();
+					let map = x
+						.as_any()
+						.downcast_ref::<Map>()
+						.ok_or_else(|| invalid_type(instr.opcode, x.get_type()))?;
+					map.keys().map(|key| key.clone() as Rc<RefCell<dyn StackItem>>).collect()
+				};
+				self.push_item(Array::new(Some(keys), Some(self.reference_counter.clone())))?
 			},
 			OpCode::Values => {
-				let x = self.pop();
-				let values = match x {
-					StackItem::VMArray(array) => array,
-					StackItem::VMMap(map) => map.values(),
-					_ => panic!(), //return Err(VMException::InvalidOpcode("Invalid type for {instr.OpCode}: {x.Type}".parse().unwrap())),
-				};
-				let mut new_array = Array::new(None, Some(self.reference_counter.clone()));
-				for item in values.array {
-					if item.get_item_type() == StackItemType::Struct {
-						let s: Struct = item.into();
-						new_array.add(s.clone(&self.limits).try_into().unwrap());
-
-					// new_array.Add(s.Clone(self.limits));
+				let x = self.pop()?;
+				let values = {
+					let x = x.borrow();
+					if let Some(map) = x.as_any().downcast_ref::<Map>() {
+						map.values().cloned().collect::<Vec<_>>()
+					} else if let Some(array) = as_array(&*x) {
+						array.iter().cloned().collect()
 					} else {
-						new_array.Add(item);
+						return Err(invalid_type(instr.opcode, x.get_type()))
 					}
-				}
-
-				self.push(StackItem::from(new_array).into())
+				};
+				let values = values
+					.into_iter()
+					.map(|value| self.clone_if_struct(value))
+					.collect::<Result<Vec<_>, _>>()?;
+				self.push_item(Array::new(Some(values), Some(self.reference_counter.clone())))?
 			},
 			OpCode::PickItem => {
-				let key: Rc<RefCell<dyn PrimitiveType>> = self.pop().into();
-				let x = self.pop();
-				match x {
-					StackItem::VMArray(array) => {
-						let index = key.get_integer().to_i64().unwrap();
-						if index < 0 || index >= array.Count {
-							return Err(VMException::InvalidOpcode(
-								"The value {index} is out of range.".parse().unwrap(),
-							))
-						}
-						self.push(array[index])
-					},
-					StackItem::VMMap(map) => {
-						let value = match map.get(key) {
-							Some(v) => v,
-							None =>
-								return Err(VMException::InvalidOpcode(
-									"Key not found in {nameof(Map)}".parse().unwrap(),
-								)),
-						};
-						self.push(StackItem::from(value).into())
-					},
-					StackItem::VMByteString(byte_string) => {},
-					StackItem::VMBoolean(boolean) => {},
-					StackItem::VMInteger(integer) => {
-						let byte_array = integer.get_slice();
-						let index = key.get_integer().to_i64().unwrap();
-						if index < 0 || index >= byte_array.Length {
-							return Err(VMException::InvalidOpcode(
-								"The value {index} is out of range.".parse().unwrap(),
-							))
-						}
-						self.push(
-							StackItem::from(BigInt::from_bytes_le(
-								Sign::NoSign,
-								byte_array.get(index).unwrap(),
-							))
-							.into(),
-						)
-					},
-					StackItem::VMBuffer(buffer) => {
-						let index = key.get_integer().to_i64().unwrap();
-						if index < 0 || index >= buffer.Size {
-							return Err(VMException::InvalidOpcode(
-								"The value {index} is out of range.".parse().unwrap(),
-							))
-						}
-						self.push(
-							StackItem::from(BigInt::from_bytes_le(
-								Sign::NoSign,
-								buffer.get_slice().get(index).unwrap(),
-							))
-							.into(),
-						)
-					},
-					_ =>
-						return Err(VMException::InvalidOpcode(
-							"Invalid type for {instr.OpCode}: {x.Type}".parse().unwrap(),
-						)),
-				}
-				// break;
+				let key = self.pop_primitive()?;
+				let x = self.pop()?;
+				let item: Rc<RefCell<dyn StackItem>> = {
+					let x = x.borrow();
+					match x.get_type() {
+						StackItemType::Array | StackItemType::Struct => {
+							let array = as_array(&*x).unwrap();
+							let index = key.borrow().get_integer()?;
+							index
+								.to_usize()
+								.and_then(|index| array.get(index))
+								.cloned()
+								.ok_or_else(|| item_not_found(&index))?
+						},
+						StackItemType::Map => x
+							.as_any()
+							.downcast_ref::<Map>()
+							.unwrap()
+							.get(&*key.borrow())?
+							.ok_or_else(|| {
+								VMException::ItemNotFound("Key not found in Map".to_string())
+							})?,
+						StackItemType::Boolean
+						| StackItemType::Integer
+						| StackItemType::ByteString
+						| StackItemType::Buffer => {
+							let index = key.borrow().get_integer()?;
+							let byte = index
+								.to_usize()
+								.and_then(|index| x.get_slice().ok()?.get(index).copied())
+								.ok_or_else(|| item_not_found(&index))?;
+							Rc::new(RefCell::new(Integer::from(byte)))
+						},
+						ty => return Err(invalid_type(instr.opcode, ty)),
+					}
+				};
+				self.push(item)?
 			},
 			OpCode::Append => {
-				let mut new_item = self.pop();
-				let array: Array = self.pop().into();
-				if new_item.get_item_type() == StackItemType::Struct {
-					let s: Struct = new_item.into();
-					new_item = s.clone(&self.limits).try_into().unwrap();
-					// new_item = s.Clone(self.limits);
-				}
-				array.Add(new_item)
+				let new_item = self.pop()?;
+				let new_item = self.clone_if_struct(new_item)?;
+				let array = self.pop()?;
+				let mut array = array.borrow_mut();
+				let ty = array.get_type();
+				as_array_mut(&mut *array)
+					.ok_or_else(|| invalid_type(instr.opcode, ty))?
+					.add(new_item);
 			},
 			OpCode::SetItem => {
-				let mut value = self.pop();
-				if value.get_item_type() == StackItemType::Struct {
-					let s: Struct = value.into();
-					value = s.clone(&self.limits).try_into().unwrap();
-				}
-				let key: dyn PrimitiveType = self.pop().into();
-				let x = self.pop();
-				match x {
-					VMArray(array) => {
-						let index = key.get_integer().to_i32().unwrap();
-						if index < 0 || index >= array.Count {
-							return Err(VMException::InvalidOpcode(
-								"The value {index} is out of range.".parse().unwrap(),
-							))
+				let value = self.pop()?;
+				let value = self.clone_if_struct(value)?;
+				let key = self.pop_primitive()?;
+				let x = self.pop()?;
+				let mut x = x.borrow_mut();
+				match x.get_type() {
+					StackItemType::Array | StackItemType::Struct => {
+						let array = as_array_mut(&mut *x).unwrap();
+						let index = key.borrow().get_integer()?;
+						match index.to_usize().filter(|&index| index < array.len()) {
+							Some(index) => array.set(index, value)?,
+							None => return Err(item_not_found(&index)),
 						}
-						array[index] = value
 					},
-					StackItem::VMMap(map) => map[key] = value,
-					StackItem::VMBuffer(buffer) => {
-						let index = key.get_integer().to_i32().unwrap();
-						if index < 0 || index >= buffer.Size {
-							return Err(VMException::InvalidOpcode(
-								"The value {index} is out of range.".parse().unwrap(),
-							))
+					StackItemType::Map =>
+						x.as_any_mut().downcast_mut::<Map>().unwrap().insert(key, value)?,
+					StackItemType::Buffer => {
+						let buffer = x.as_any_mut().downcast_mut::<Buffer>().unwrap();
+						let index = key.borrow().get_integer()?;
+						let index = index
+							.to_usize()
+							.filter(|&index| index < buffer.len())
+							.ok_or_else(|| item_not_found(&index))?;
+						let value = value.borrow();
+						if !StackItemType::is_primitive(value.get_type() as u8) {
+							return Err(VMException::InvalidType(format!(
+								"Value must be a primitive type in {:?}",
+								instr.opcode
+							)))
 						}
-						if !StackItemType::is_primitive(value.get_item_type() as u8) {
-							return Err(VMException::InvalidOpcode(
-								"Value must be a primitive type in {instr.OpCode}".parse().unwrap(),
-							))
-						}
-						let b = value.get_integer().to_i64().unwrap();
-						if b < i8::min as i64 || b > i8::max as i64 {
-							return Err(VMException::InvalidOpcode(
-								"Overflow in {instr.OpCode}, {b} is not a byte type."
-									.parse()
-									.unwrap(),
-							))
-						}
-						buffer.InnerBuffer.Span[index] = b
+						let b = value.get_integer()?;
+						let byte =
+							b.to_i16().filter(|b| (-128..=255).contains(b)).ok_or_else(|| {
+								VMException::InvalidParameter(format!(
+									"Overflow in {:?}, {b} is not a byte type.",
+									instr.opcode
+								))
+							})?;
+						buffer.data_mut()[index] = byte as u8;
 					},
-					_ => Err(VMException::InvalidOpcode(
-						"Invalid type for {instr.OpCode}: {x.Type}".parse().unwrap(),
-					)),
+					ty => return Err(invalid_type(instr.opcode, ty)),
 				}
-				// break;
 			},
 			OpCode::ReverseItems => {
-				let x = self.pop();
-				match x {
-					StackItem::VMArray(array) => array.Reverse(),
-					StackItem::VMBuffer(buffer) => buffer.InnerBuffer.Span.Reverse(),
-					_ => Err(VMException::InvalidOpcode(
-						"Invalid type for {instr.OpCode}: {x.Type}".parse().unwrap(),
-					)),
+				let x = self.pop()?;
+				let mut x = x.borrow_mut();
+				let ty = x.get_type();
+				if ty == StackItemType::Buffer {
+					x.as_any_mut().downcast_mut::<Buffer>().unwrap().data_mut().reverse();
+				} else {
+					as_array_mut(&mut *x).ok_or_else(|| invalid_type(instr.opcode, ty))?.reverse();
 				}
 			},
 			OpCode::Remove => {
-				let key: Rc<RefCell<dyn PrimitiveType>> = self.pop().into();
-				let x = self.pop();
-				match x {
-					StackItem::VMArray(mut array) => {
-						let index = key.get_integer().to_i32().unwrap();
-						if index < 0 || index >= array.Count {
-							return Err(VMException::InvalidOpcode(
-								"The value {index} is out of range.".parse().unwrap(),
-							))
+				let key = self.pop_primitive()?;
+				let x = self.pop()?;
+				let mut x = x.borrow_mut();
+				match x.get_type() {
+					StackItemType::Array | StackItemType::Struct => {
+						let array = as_array_mut(&mut *x).unwrap();
+						let index = key.borrow().get_integer()?;
+						match index.to_usize().filter(|&index| index < array.len()) {
+							Some(index) => drop(array.remove_at(index)?),
+							None => return Err(out_of_range(&index)),
 						}
-						array.remove_at(index as usize)
 					},
-					StackItem::VMMap(mut map) => map.remove(key),
-					_ =>
-						return Err(VMException::InvalidOpcode(
-							"Invalid type for {instr.OpCode}: {x.Type}".parse().unwrap(),
-						)),
+					StackItemType::Map => {
+						x.as_any_mut().downcast_mut::<Map>().unwrap().remove(&*key.borrow())?;
+					},
+					ty => return Err(invalid_type(instr.opcode, ty)),
 				}
 			},
 			OpCode::ClearItems => {
-				let x: dyn CompoundType = self.pop().into();
-				x.Clear()
+				let x = self.pop()?;
+				let mut x = x.borrow_mut();
+				let ty = x.get_type();
+				as_compound_mut(&mut *x).ok_or_else(|| invalid_type(instr.opcode, ty))?.clear();
 			},
 			OpCode::PopItem => {
-				let mut x: Rc<RefCell<dyn CompoundType>> = self.pop();
-				let index = x.count() - 1;
-				self.push(x[index].clone());
-				x.remove_at(index)
+				let x = self.pop()?;
+				let item = {
+					let mut x = x.borrow_mut();
+					let ty = x.get_type();
+					let array =
+						as_array_mut(&mut *x).ok_or_else(|| invalid_type(instr.opcode, ty))?;
+					array.pop().ok_or_else(|| out_of_range(&BigInt::from(-1)))?
+				};
+				self.push(item)?
 			},
 
 			//Types
 			OpCode::IsNull => {
-				let x = self.pop();
-				self.push(StackItem::from(x.get_item_type() == StackItemType::Any).into())
+				let x = self.pop()?;
+				let is_null = x.borrow().is_null();
+				self.push_bool(is_null)?
 			},
 			OpCode::IsType => {
-				let x = self.pop();
-				let _type: StackItemType = instr.token_u8() as StackItemType;
-				if _type == StackItemType::Any || !StackItemType::is_valid(instr.token_u8()) {
-					return Err(VMException::InvalidOpcode("Invalid type: {type}".parse().unwrap()))
-				}
-				self.push(StackItem::from(x.get_item_type() == _type).into())
+				let x = self.pop()?;
+				let ty = StackItemType::from_u8(instr.token_u8())
+					.filter(|&ty| ty != StackItemType::Any)
+					.ok_or_else(|| {
+						VMException::InvalidType(format!("Invalid type: {}", instr.token_u8()))
+					})?;
+				let is_type = x.borrow().get_type() == ty;
+				self.push_bool(is_type)?
 			},
 			OpCode::Convert => {
-				let x = self.pop();
-				self.push(x.ConvertTo(instr.token_u8()))
+				let x = self.pop()?;
+				let converted = self.convert(x, instr.token_u8())?;
+				self.push(converted)?
 			},
 			OpCode::AbortMsg => {
-				let msg = self.pop().GetString();
-				Err(VMException::InvalidOpcode(
-					"{OpCode::ABORTMSG} is executed. Reason: {msg}".parse().unwrap(),
-				))
+				let msg = self.pop_string()?;
+				return Err(VMException::Custom(format!(
+					"{:?} is executed. Reason: {msg}",
+					OpCode::AbortMsg
+				)))
 			},
 			OpCode::AssertMsg => {
-				let msg = self.pop().GetString();
-				let x = self.pop().get_bool();
-				if !x {
-					return Err(VMException::InvalidOpcode(
-						"{OpCode::ASSERTMSG} is executed with false result. Reason: {msg}"
-							.parse()
-							.unwrap(),
-					))
+				let msg = self.pop_string()?;
+				if !self.pop_bool()? {
+					return Err(VMException::Custom(format!(
+						"{:?} is executed with false result. Reason: {msg}",
+						OpCode::AssertMsg
+					)))
 				}
-				// break;
 			},
-			_ => panic!("Opcode {instr} is undefined."),
+			// Every slot instruction is handled by `slot_access` above.
+			_ => return Err(VMException::InvalidOpcode(format!("{:?}", instr.opcode))),
 		}
 
-		Ok(VMState::Halt)
+		Ok(())
 	}
 
-	fn execute_call(&mut self, offset: i32) {
-		let new_context = self.current_context.unwrap().clone_at_offset(offset);
-		self.load_context(new_context);
+	/// Structs are copied by value when stored into a compound item.
+	fn clone_if_struct(
+		&self,
+		item: Rc<RefCell<dyn StackItem>>,
+	) -> Result<Rc<RefCell<dyn StackItem>>, VMException> {
+		let copy = match item.borrow().as_any().downcast_ref::<Struct>() {
+			Some(st) => st.clone_with_limits(&self.limits)?,
+			None => return Ok(item.clone()),
+		};
+		Ok(Rc::new(RefCell::new(copy)))
 	}
 
-	fn execute_jump_offset(&mut self, offset: i32) {
-		self.execute_jump(
-			(self.current_context?.borrow().instruction_pointer as i32)
-				.checked_add(offset)
-				.unwrap(),
-		)
-	}
-	fn execute_jump(&mut self, offset: i32) {
-		let new_ip = (self.current_context?.borrow().instruction_pointer as i32 + offset) as usize;
-		if new_ip >= self.current_context?.borrow().script.0.len() {
-			return self.handle_error(Error::InvalidJump)
+	/// Converts `item` as `Convert` does.
+	fn convert(
+		&self,
+		item: Rc<RefCell<dyn StackItem>>,
+		type_code: u8,
+	) -> Result<Rc<RefCell<dyn StackItem>>, VMException> {
+		let x = item.borrow();
+		let from = x.get_type();
+		let to = StackItemType::from_u8(type_code).ok_or_else(|| {
+			VMException::InvalidType(format!(
+				"Type can't be converted to StackItemType: {type_code}"
+			))
+		})?;
+		if x.is_null() {
+			if to == StackItemType::Any {
+				return Err(invalid_cast(from, to))
+			}
+			return Ok(item.clone())
 		}
-		self.current_context?.borrow().instruction_pointer = new_ip;
+		if from == to {
+			return Ok(item.clone())
+		}
+		let converted: Rc<RefCell<dyn StackItem>> = match (from, to) {
+			(_, StackItemType::Boolean) => Rc::new(RefCell::new(Boolean::new(x.get_boolean()?))),
+			(
+				StackItemType::Boolean
+				| StackItemType::Integer
+				| StackItemType::ByteString
+				| StackItemType::Buffer,
+				StackItemType::Integer,
+			) => Rc::new(RefCell::new(Integer::try_from(x.get_integer()?)?)),
+			(
+				StackItemType::Boolean
+				| StackItemType::Integer
+				| StackItemType::ByteString
+				| StackItemType::Buffer,
+				StackItemType::ByteString,
+			) => Rc::new(RefCell::new(ByteString::new(x.get_slice()?.to_vec()))),
+			(
+				StackItemType::Boolean | StackItemType::Integer | StackItemType::ByteString,
+				StackItemType::Buffer,
+			) => Rc::new(RefCell::new(Buffer::from_slice(x.get_slice()?))),
+			(StackItemType::Array, StackItemType::Struct) => {
+				let items = as_array(&*x).unwrap().iter().cloned().collect();
+				Rc::new(RefCell::new(Struct::new(
+					Some(items),
+					Some(self.reference_counter.clone()),
+				)))
+			},
+			(StackItemType::Struct, StackItemType::Array) => {
+				let items = as_array(&*x).unwrap().iter().cloned().collect();
+				Rc::new(RefCell::new(Array::new(Some(items), Some(self.reference_counter.clone()))))
+			},
+			_ => return Err(invalid_cast(from, to)),
+		};
+		Ok(converted)
 	}
 
-	fn handle_error(&mut self, err: Error) {
-		self.state = VMState::Fault;
-		self.uncaught_exception = Some(StackItem::from(Null::default()).into());
+	fn execute_jump_offset(&mut self, offset: i32) -> Result<(), VMException> {
+		let ip = self.context()?.borrow().instruction_pointer;
+		self.execute_jump(ip as i64 + offset as i64)
 	}
 
-	fn load_context(&mut self, context: &Rc<RefCell<ExecutionContext>>) {
-		self.invocation_stack.push(context.clone());
-		self.current_context = Some(self.invocation_stack.last().unwrap().clone());
-		if self.entry_context.is_none() {
-			self.entry_context = self.current_context.clone();
-		}
+	fn execute_jump(&mut self, position: i64) -> Result<(), VMException> {
+		let context = self.context()?;
+		let position = check_target(&context.borrow(), position)?;
+		context.borrow_mut().instruction_pointer = position;
+		self.is_jumping = true;
+		Ok(())
 	}
 
-	fn unload_context(&mut self, mut context: Rc<RefCell<ExecutionContext>>) {
-		if self.invocation_stack.is_empty() {
-			self.current_context = None;
-			self.entry_context = None;
-		} else {
-			self.current_context = Some(self.invocation_stack.last().unwrap().clone());
-		}
+	fn execute_call(&mut self, position: i64) -> Result<(), VMException> {
+		let context = self.context()?;
+		let position = check_target(&context.borrow(), position)?;
+		let new_context = context.borrow().clone_at(position);
+		self.load_context(Rc::new(RefCell::new(new_context)))
+	}
 
-		if let Some(current) = &mut self.current_context {
-			if current.borrow().fields()
-				!= context.borrow().fields()
-			{
-				context.borrow().fields()?.clear_references();
-			}
+	fn load_context(&mut self, context: Rc<RefCell<ExecutionContext>>) -> Result<(), VMException> {
+		if self.invocation_stack.len() >= self.limits.max_invocation_stack_size {
+			return Err(VMException::InvocationStackOverflow(format!(
+				"MaxInvocationStackSize exceed: {}",
+				self.invocation_stack.len()
+			)))
+		}
+		self.invocation_stack.push(context.clone());
+		if self.entry_context.is_none() {
+			self.entry_context = Some(context.clone());
 		}
-		context.borrow().local_variables.unwrap().clear_references();
-		context.borrow().arguments.unwrap().clear_references();
+		self.current_context = Some(context);
+		Ok(())
 	}
 
-	fn create_context(
-		&self,
-		script: Script,
-		rvcount: i32,
-		initial_position: usize,
-	) -> ExecutionContext {
-		let share = SharedStates {
-			script,
-			evaluation_stack: Default::default(),
-			static_fields: None,
-			states: Default::default(),
-		};
-
-		ExecutionContext {
-			shared_states,
-			instruction_pointer: initial_position,
-			rv_count: rvcount,
-			local_variables: None,
-			try_stack: None,
-			arguments: None,
+	/// Makes the top of the invocation stack current after a context was popped.
+	fn unload_context(&mut self) {
+		self.current_context = self.invocation_stack.last().cloned();
+		if self.current_context.is_none() {
+			self.entry_context = None;
 		}
 	}
 
-	fn load_script(
+	/// Loads a script as a new context on the invocation stack.
+	pub fn load_script(
 		&mut self,
 		script: Script,
 		rvcount: i32,
 		initial_position: usize,
 	) -> Rc<RefCell<ExecutionContext>> {
-		let context = Rc::new(RefCell::new(self.create_context(script, rvcount, initial_position)));
-
-		self.load_context(&context);
-
+		let mut context = ExecutionContext::new(Rc::new(script), self.reference_counter.clone());
+		context.rv_count = rvcount;
+		context.instruction_pointer = initial_position;
+		let context = Rc::new(RefCell::new(context));
+		self.invocation_stack.push(context.clone());
+		if self.entry_context.is_none() {
+			self.entry_context = Some(context.clone());
+		}
+		self.current_context = Some(context.clone());
 		context
 	}
 
-	fn pre_execute_instruction(&mut self, instruction: Instruction) {
-		if self.reference_counter.borrow().count() > self.limits.max_stack_size {
-			panic!("Max stack size exceeded");
-		}
+	/// Unwinds to the innermost `catch` or `finally` block, unloading the contexts in between,
+	/// and faults if there is none.
+	fn handle_exception(&mut self) -> Result<(), VMException> {
+		for depth in (0..self.invocation_stack.len()).rev() {
+			let context = self.invocation_stack[depth].clone();
+			let mut context = context.borrow_mut();
+			let Some(try_stack) = context.try_stack.as_mut() else { continue };
+			while let Some(try_context) = try_stack.last_mut() {
+				if try_context.state() == ExceptionHandlingState::Finally
+					|| (try_context.state() == ExceptionHandlingState::Catch
+						&& !try_context.has_finally())
+				{
+					try_stack.pop();
+					continue
+				}
+
+				let target = if try_context.state() == ExceptionHandlingState::Try
+					&& try_context.has_catch()
+				{
+					try_context.set_state(ExceptionHandlingState::Catch);
+					try_context.catch_pointer()
+				} else {
+					try_context.set_state(ExceptionHandlingState::Finally);
+					try_context.finally_pointer()
+				};
+				let caught = try_context.state() == ExceptionHandlingState::Catch;
+				context.instruction_pointer = target as usize;
+				drop(context);
 
-		match instruction {
-			Instruction::JMP(offset) => {
+				self.invocation_stack.truncate(depth + 1);
+				self.unload_context();
+				if caught {
+					let exception = self.uncaught_exception.take().unwrap();
+					self.push(exception)?;
+				}
 				self.is_jumping = true;
-			},
-			Instruction::CALL(context) => {
-				self.load_context(context);
-			},
-			_ => (),
+				return Ok(())
+			}
 		}
+
+		self.state = VMState::Fault;
+		Ok(())
 	}
 
-	fn post_execute_instruction(&mut self, instruction: Instruction) {
-		let count = self.reference_counter.borrow().count();
-		if count > self.limits.max_stack_size {
-			panic!("Max stack size exceeded: {}", count);
+	fn execute_try(&mut self, catch_offset: i32, finally_offset: i32) -> Result<(), VMException> {
+		if catch_offset == 0 && finally_offset == 0 {
+			return Err(VMException::InvalidParameter(
+				"catchOffset and finallyOffset can't be 0 in a TRY block".to_string(),
+			))
 		}
-
-		match instruction {
-			Instruction::RET => {
-				let context = self.invocation_stack.pop().unwrap();
-				// do something with returned context
-			},
-			Instruction::THROW => {
-				self.handle_exception();
-			},
-			_ => (),
+		let context = self.context()?;
+		let mut context = context.borrow_mut();
+		let ip = context.instruction_pointer as i32;
+		let try_stack = context.try_stack.get_or_insert_with(Vec::new);
+		if try_stack.len() >= self.limits.max_try_nesting_depth {
+			return Err(VMException::TryNestingOverflow("MaxTryNestingDepth exceed.".to_string()))
 		}
+		let pointer = |offset: i32| {
+			if offset == 0 {
+				Ok(-1)
+			} else {
+				ip.checked_add(offset)
+					.ok_or_else(|| VMException::InvalidJump(format!("Bad try offset: {offset}")))
+			}
+		};
+		try_stack
+			.push(ExceptionHandlingContext::new(pointer(catch_offset)?, pointer(finally_offset)?));
+		Ok(())
+	}
+
+	fn execute_throw(&mut self, exception: Rc<RefCell<dyn StackItem>>) -> Result<(), VMException> {
+		self.uncaught_exception = Some(exception);
+		self.handle_exception()
 	}
-	fn handle_exception(&mut self) {
-		// loop through contexts
-		// set instruction pointer to catch or finally
-		// pop contexts
-		if let Some(exception) = self.uncaught_exception.take() {
-			panic!("Unhandled exception: {:?}", exception.borrow().get_slice());
+
+	fn execute_end_try(&mut self, end_offset: i32) -> Result<(), VMException> {
+		let context = self.context()?;
+		let mut context = context.borrow_mut();
+		let end_pointer = context.instruction_pointer as i64 + end_offset as i64;
+		let try_stack = context.try_stack.as_mut().ok_or_else(try_not_found)?;
+		let current_try = try_stack.last_mut().ok_or_else(try_not_found)?;
+		if current_try.state() == ExceptionHandlingState::Finally {
+			return Err(VMException::InvalidOpcode(format!(
+				"The opcode {:?} can't be executed in a FINALLY block.",
+				OpCode::EndTry
+			)))
 		}
+
+		let target = if current_try.has_finally() {
+			current_try.set_state(ExceptionHandlingState::Finally);
+			current_try.set_end_pointer(end_pointer as i32);
+			current_try.finally_pointer() as i64
+		} else {
+			try_stack.pop();
+			end_pointer
+		};
+		context.instruction_pointer = usize::try_from(target)
+			.map_err(|_| VMException::InvalidJump(format!("Bad end pointer: {target}")))?;
+		self.is_jumping = true;
+		Ok(())
 	}
 
-	fn execute_try(&mut self, catch_offset: usize, finally_offset: usize) {
-		let context = self.current_context.as_mut().unwrap().borrow_mut();
+	fn execute_load_from_slot(&mut self, kind: SlotKind, index: usize) -> Result<(), VMException> {
+		let context = self.context()?;
+		let item = {
+			let context = context.borrow();
+			match kind {
+				SlotKind::Static => slot(context.fields().as_ref())?.get(index)?,
+				SlotKind::Local => slot(context.local_variables.as_ref())?.get(index)?,
+				SlotKind::Argument => slot(context.arguments.as_ref())?.get(index)?,
+			}
+		};
+		self.push(item)
+	}
 
-		if catch_offset == 0 && finally_offset == 0 {
-			panic!("Invalid try block offsets");
+	fn execute_store_to_slot(&mut self, kind: SlotKind, index: usize) -> Result<(), VMException> {
+		let value = self.pop()?;
+		let context = self.context()?;
+		let mut context = context.borrow_mut();
+		match kind {
+			SlotKind::Static => slot_mut(context.fields_mut().as_mut())?.set(index, value),
+			SlotKind::Local => slot_mut(context.local_variables.as_mut())?.set(index, value),
+			SlotKind::Argument => slot_mut(context.arguments.as_mut())?.set(index, value),
 		}
+	}
 
-		if context.try_stack.is_none() {
-			context.try_stack = Some(Vec::new());
+	fn load_token(&mut self, token: u16) -> Result<(), VMException> {
+		let method_token =
+			self.method_tokens.get(token as usize).cloned().ok_or_else(|| {
+				VMException::InvalidToken(format!("Method token not found: {token}"))
+			})?;
+		let available = self.stack()?.borrow().size();
+		if available < method_token.parameters_count as usize {
+			return Err(VMException::InvalidParameter(format!(
+				"{} expects {} arguments, the stack holds {available}",
+				method_token.method, method_token.parameters_count
+			)))
 		}
+		let handler = self
+			.token_handler
+			.clone()
+			.ok_or_else(|| VMException::InvalidToken("No handler for CallT".to_string()))?;
+		handler(self, &method_token)
+	}
 
-		if context.try_stack.as_ref().unwrap().len() >= self.limits.max_try_nesting_depth {
-			panic!("Max try nesting depth exceeded");
-		}
+	fn on_syscall(&mut self, method: u32) -> Result<(), VMException> {
+		let descriptor = match self.syscalls.get(&method) {
+			Some(descriptor) => descriptor.clone(),
+			None =>
+				return Err(VMException::InvalidToken(format!("Syscall not found: {method:#010x}"))),
+		};
 
-		let catch_pointer =
-			if catch_offset > 0 { Some(context.instruction_pointer + catch_offset) } else { None };
+		self.add_gas(descriptor.price)?;
+		(descriptor.handler)(self)
+	}
+}
 
-		let finally_pointer = if finally_offset > 0 {
-			Some(context.instruction_pointer + finally_offset)
-		} else {
-			None
-		};
+/// The relative target of a jump, call or `EndTry`, short or long form.
+fn jump_offset(instr: &Instruction) -> i32 {
+	match instr.operand.len() {
+		1 => instr.token_i8() as i32,
+		_ => instr.token_i32(),
+	}
+}
 
-		context.try_stack.as_mut().unwrap().push(ExceptionHandlingContext {
-			state: ExceptionHandlingState::Try,
-			catch_pointer: catch_pointer.unwrap() as i32,
-			finally_pointer: finally_pointer.unwrap() as i32,
-			end_pointer: 0,
-		});
+/// Checks that `position` is an offset inside the script of `context`.
+fn check_target(context: &ExecutionContext, position: i64) -> Result<usize, VMException> {
+	if position < 0 || position >= context.script().len() as i64 {
+		return Err(VMException::InvalidJump(format!("Jump out of range for position: {position}")))
+	}
+	Ok(position as usize)
+}
 
-		self.is_jumping = true;
+/// The elements of an array or struct.
+fn as_array(item: &dyn StackItem) -> Option<&Array> {
+	let any = item.as_any();
+	any.downcast_ref::<Array>()
+		.or_else(|| any.downcast_ref::<Struct>().map(|st| &**st))
+}
+
+fn as_array_mut(item: &mut dyn StackItem) -> Option<&mut Array> {
+	match item.get_type() {
+		StackItemType::Struct => item.as_any_mut().downcast_mut::<Struct>().map(|st| &mut **st),
+		_ => item.as_any_mut().downcast_mut::<Array>(),
 	}
+}
 
-	fn execute_throw(&mut self, exception: Rc<RefCell<dyn StackItem>>) {
-		self.uncaught_exception = Some(exception);
-		self.handle_exception();
+/// Reads an array, buffer or byte string index, which must not be negative.
+fn key_index(key: &Rc<RefCell<dyn PrimitiveType>>) -> Result<usize, VMException> {
+	let index = key.borrow().get_integer()?;
+	index.to_usize().ok_or_else(|| out_of_range(&index))
+}
+
+fn slot(slot: Option<&Slot>) -> Result<&Slot, VMException> {
+	slot.ok_or_else(|| VMException::InvalidParameter("Slot has not been initialized.".to_string()))
+}
+
+fn slot_mut(slot: Option<&mut Slot>) -> Result<&mut Slot, VMException> {
+	slot.ok_or_else(|| VMException::InvalidParameter("Slot has not been initialized.".to_string()))
+}
+
+fn out_of_range(value: &BigInt) -> VMException {
+	VMException::InvalidParameter(format!("The value {value} is out of range."))
+}
+
+fn item_not_found(index: &BigInt) -> VMException {
+	VMException::ItemNotFound(format!("The index {index} is out of range."))
+}
+
+fn invalid_type(opcode: OpCode, ty: StackItemType) -> VMException {
+	VMException::InvalidType(format!("Invalid type for {opcode:?}: {ty:?}"))
+}
+
+fn invalid_operand(instr: &Instruction) -> VMException {
+	VMException::InvalidParameter(format!(
+		"The operand {:?} is invalid for {:?}.",
+		instr.operand, instr.opcode
+	))
+}
+
+fn executed_twice(opcode: OpCode) -> VMException {
+	VMException::InvalidOpcode(format!("{opcode:?} cannot be executed twice."))
+}
+
+fn try_not_found() -> VMException {
+	VMException::InvalidOpcode("The corresponding TRY block cannot be found.".to_string())
+}
+
+fn check_divisor(divisor: &BigInt) -> Result<(), VMException> {
+	if divisor.is_zero() {
+		return Err(VMException::DivisionByZero("Attempted to divide by zero.".to_string()))
 	}
+	Ok(())
+}
 
-	fn execute_end_try(&mut self, end_offset: usize) {
-		let context = self.current_context.as_mut().unwrap().borrow_mut();
+/// `value ^ exponent % modulus` with the sign of `value`, as .NET computes it.
+fn mod_pow(value: &BigInt, exponent: &BigInt, modulus: &BigInt) -> Result<BigInt, VMException> {
+	if exponent.is_negative() {
+		return Err(out_of_range(exponent))
+	}
+	check_divisor(modulus)?;
+	let modulus = modulus.abs();
+	let result = value.abs().modpow(exponent, &modulus) % &modulus;
+	Ok(if value.is_negative() && exponent.bit(0) { -result } else { result })
+}
 
-		let mut current_try = match context.try_stack.as_mut().unwrap().pop() {
-			Some(try_context) => try_context,
-			None => panic!("No matching try block found"),
-		};
+/// The inverse of `value` modulo `modulus`, as `ModPow` computes it for an exponent of -1.
+fn mod_inverse(value: &BigInt, modulus: &BigInt) -> Result<BigInt, VMException> {
+	if !value.is_positive() {
+		return Err(out_of_range(value))
+	}
+	if *modulus < BigInt::from(2) {
+		return Err(out_of_range(modulus))
+	}
+	let (mut r, mut old_r) = (value.clone(), modulus.clone());
+	let (mut s, mut old_s) = (BigInt::one(), BigInt::zero());
+	while r.is_positive() {
+		let q = &old_r / &r;
+		let next_r = &old_r % &r;
+		old_r = std::mem::replace(&mut r, next_r);
+		let next_s = &old_s - &q * &s;
+		old_s = std::mem::replace(&mut s, next_s);
+	}
+	let mut result = old_s % modulus;
+	if result.is_negative() {
+		result += modulus;
+	}
+	if !(value * &result % modulus).is_one() {
+		return Err(VMException::InvalidParameter(format!(
+			"No modular inverse of {value} modulo {modulus}"
+		)))
+	}
+	Ok(result)
+}
 
-		if let ExceptionHandlingState::Finally = current_try.state() {
-			panic!("EndTry cannot be called in finally block");
-		}
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::script::script_builder::ScriptBuilder;
+
+	fn run(emit: impl FnOnce(&mut ScriptBuilder)) -> ExecutionEngine {
+		let mut builder = ScriptBuilder::new();
+		emit(&mut builder);
+		let mut engine = ExecutionEngine::new();
+		engine.load_script(Script::new(builder.to_bytes().unwrap(), true).unwrap(), -1, 0);
+		engine.execute();
+		engine
+	}
 
-		let end_pointer = context.instruction_pointer + end_offset;
+	fn results(engine: &ExecutionEngine) -> Vec<BigInt> {
+		let stack = engine.result_stack.borrow();
+		stack.iter().map(|item| item.borrow().get_integer().unwrap()).collect()
+	}
 
-		if let Some(handler) = current_try.finally_pointer() {
-			current_try.set_state(ExceptionHandlingState::Finally);
-			current_try.set_end_pointer(end_pointer as i32);
-			context.instruction_pointer = handler;
-		} else {
-			context.instruction_pointer = end_pointer;
-		}
+	#[test]
+	fn computes_arithmetic() {
+		let engine = run(|builder| {
+			builder.push_int(-7);
+			builder.push_int(2);
+			builder.emit(OpCode::Div, &[]);
+			builder.push_int(-7);
+			builder.push_int(2);
+			builder.emit(OpCode::Mod, &[]);
+			builder.push_int(3);
+			builder.push_int(-1);
+			builder.push_int(7);
+			builder.emit(OpCode::ModPow, &[]);
+		});
+		assert_eq!(engine.state, VMState::Halt);
+		assert_eq!(results(&engine), [BigInt::from(-3), BigInt::from(-1), BigInt::from(5)]);
+		assert_eq!(engine.reference_counter.borrow().count(), 3);
+	}
 
-		self.is_jumping = true;
+	#[test]
+	fn catches_thrown_items_and_runs_finally() {
+		let engine = run(|builder| {
+			// TRY catch=+5 finally=+9; PUSH1; THROW; catch: DROP; PUSH2; ENDTRY +4;
+			// finally: PUSH3; ENDFINALLY; RET
+			builder.emit(OpCode::Try, &[5, 9]);
+			builder.push_int(1);
+			builder.emit(OpCode::Throw, &[]);
+			builder.emit(OpCode::Drop, &[]);
+			builder.push_int(2);
+			builder.emit(OpCode::EndTry, &[4]);
+			builder.push_int(3);
+			builder.emit(OpCode::EndFinally, &[]);
+			builder.emit(OpCode::Ret, &[]);
+		});
+		assert_eq!(engine.state, VMState::Halt, "{:?}", engine.fault_exception);
+		assert_eq!(results(&engine), [BigInt::from(2), BigInt::from(3)]);
 	}
 
-	fn execute_load_from_slot(&mut self, slot: &mut Slot, index: usize) {
-		if let Some(values) = slot {
-			if index < values.len() {
-				let value = values[index].clone();
-				self.push(value);
-			} else {
-				panic!("Invalid slot index: {}", index);
-			}
-		} else {
-			panic!("Slot not initialized");
-		}
+	#[test]
+	fn faults_on_uncaught_exceptions_and_missing_items() {
+		let engine = run(|builder| {
+			builder.push_int(1);
+			builder.emit(OpCode::Throw, &[]);
+		});
+		assert_eq!(engine.state, VMState::Fault);
+		assert!(engine.fault_exception.is_none());
+		assert!(engine.uncaught_exception.is_some());
+
+		let engine = run(|builder| {
+			builder.emit(OpCode::NewArray0, &[]);
+			builder.push_int(0);
+			builder.emit(OpCode::PickItem, &[]);
+		});
+		assert_eq!(engine.state, VMState::Fault);
+		let exception = engine.uncaught_exception.as_ref().unwrap().borrow().get_string().unwrap();
+		assert!(exception.contains("out of range"), "{exception}");
 	}
 
-	fn execute_store_to_slot(&mut self, slot: &mut Slot, index: usize) {
-		if let Some(slot) = slot {
-			if index >= slot.len() {
-				panic!("Index out of range when storing to slot: {}", index);
-			}
+	#[test]
+	fn releases_unreachable_cycles() {
+		let engine = run(|builder| {
+			// An array that contains itself and stays on the stack.
+			builder.emit(OpCode::NewArray0, &[]);
+			builder.emit(OpCode::Dup, &[]);
+			builder.emit(OpCode::Dup, &[]);
+			builder.emit(OpCode::Append, &[]);
+
+			// Each iteration leaves an array that only contains itself.
+			builder.push_int(3000);
+			let start = builder.new_label();
+			builder.mark_label(start).unwrap();
+			builder.emit(OpCode::NewArray0, &[]);
+			builder.emit(OpCode::Dup, &[]);
+			builder.emit(OpCode::Dup, &[]);
+			builder.emit(OpCode::Append, &[]);
+			builder.emit(OpCode::Drop, &[]);
+			builder.emit(OpCode::Dec, &[]);
+			builder.emit(OpCode::Dup, &[]);
+			builder.jump_to(OpCode::JmpIf, start).unwrap();
+			builder.emit(OpCode::Drop, &[]);
+		});
+		assert_eq!(engine.state, VMState::Halt, "{:?}", engine.fault_exception);
+		assert!(engine.reference_counter.borrow().count() <= engine.limits.max_stack_size);
+
+		let stack = engine.result_stack.borrow();
+		let result = stack.peek(0).unwrap();
+		let result = result.borrow();
+		let array = result.as_any().downcast_ref::<Array>().unwrap();
+		assert_eq!(array.len(), 1);
+	}
 
-			let value = self.result_stack.get_mut().pop();
-			slot[index] = value;
-		} else {
-			panic!("Slot has not been initialized.");
-		}
+	#[test]
+	fn limits_references() {
+		let mut builder = ScriptBuilder::new();
+		builder.push_int(10);
+		builder.emit(OpCode::NewArray, &[]);
+		let limits = ExecutionEngineLimits { max_stack_size: 8, ..Default::default() };
+		let mut engine = ExecutionEngine::with_options(limits);
+		engine.load_script(Script::new(builder.to_bytes().unwrap(), true).unwrap(), -1, 0);
+		assert_eq!(engine.execute(), VMState::Fault);
+		assert!(matches!(engine.fault_exception, Some(VMException::StackOverflow(_))));
 	}
 
-	fn load_token(&mut self, token: u16) -> Result<ExecutionContext, &'static str> {
-		panic!("Not implemented");
+	#[test]
+	fn calls_and_returns() {
+		let engine = run(|builder| {
+			// CALL +3; RET; PUSH5; RET
+			builder.emit(OpCode::Call, &[3]);
+			builder.emit(OpCode::Ret, &[]);
+			builder.push_int(5);
+			builder.emit(OpCode::Ret, &[]);
+		});
+		assert_eq!(engine.state, VMState::Halt);
+		assert_eq!(results(&engine), [BigInt::from(5)]);
 	}
 
-	fn on_syscall(&mut self, method: u32) {
-		panic!("Not implemented")
-		// let syscall = match method {
-		//     0 => Syscall::Syscall0,
-		//     1 => Syscall::Syscall1,
-		//     _ => panic!("Invalid syscall: {}", method),
-		// };
-		//
-		// syscall.invoke(self);
+	#[test]
+	fn returns_past_the_end_and_faults_on_undecodable_bytes() {
+		let mut engine = ExecutionEngine::new();
+		let context = engine.load_script(Script::new(vec![0x11], true).unwrap(), -1, 0);
+		assert_eq!(context.borrow().next_instruction(), Ok(Instruction::RET));
+		assert_eq!(engine.execute(), VMState::Halt);
+		assert_eq!(results(&engine), [BigInt::from(1)]);
+
+		// PUSH1, then a byte that is not an opcode.
+		let mut engine = ExecutionEngine::new();
+		let context = engine.load_script(Script::new(vec![0x11, 0xFF], false).unwrap(), -1, 0);
+		assert!(context.borrow().next_instruction().is_err());
+		assert_eq!(engine.execute(), VMState::Fault);
+		assert!(matches!(engine.fault_exception, Some(VMException::InvalidOpcode(_))));
 	}
 }
@@ -0,0 +1,69 @@
+#[cfg(test)]
+use crate::vm::script::Script;
+use crate::{
+	execution_engine::ExecutionEngine, stack_item::StackItem, stack_item_json,
+	vm_exception::VMException, vm_state::VMState,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The observable outcome of running an engine, in the shape hosts serialize to JSON.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExecutionResult {
+	/// `HALT`, `FAULT`, `BREAK` or `NONE`.
+	pub state: String,
+
+	/// The uncaught exception, if the engine faulted with one.
+	pub exception: Option<Value>,
+
+	/// The result stack, bottom item first. Items shared between entries keep their ids, so
+	/// [`stack_item_json::from_json_all`] rebuilds the same graph.
+	pub stack: Vec<Value>,
+
+	/// The gas charged for the executed instructions and syscalls, see
+	/// [`ExecutionEngine::gas_consumed`].
+	pub gas_consumed: u64,
+}
+
+impl ExecutionResult {
+	/// Captures the state, uncaught exception and result stack of `engine`.
+	pub fn from_engine(engine: &ExecutionEngine) -> Result<Self, VMException> {
+		let state = match engine.state {
+			VMState::None => "NONE",
+			VMState::Halt => "HALT",
+			VMState::Fault => "FAULT",
+			VMState::Break => "BREAK",
+		};
+
+		let exception = match &engine.uncaught_exception {
+			Some(item) => Some(stack_item_json::to_json(&*item.borrow())?),
+			None => None,
+		};
+
+		let result_stack = engine.result_stack.borrow();
+		let items = result_stack.iter().map(|item| item.borrow()).collect::<Vec<_>>();
+		let stack = stack_item_json::to_json_all(
+			&items.iter().map(|item| &**item as &dyn StackItem).collect::<Vec<_>>(),
+		)?;
+
+		Ok(Self { state: state.to_string(), exception, stack, gas_consumed: engine.gas_consumed })
+	}
+
+	pub fn to_json_string(&self) -> String {
+		serde_json::to_string(self).unwrap()
+	}
+}
+
+/// Runs `script` on a new engine prepared by `setup` and captures the result. Shared by the
+/// tests of the services and the optimizer.
+#[cfg(test)]
+pub(crate) fn run_script(
+	script: &[u8],
+	setup: impl FnOnce(&mut ExecutionEngine),
+) -> ExecutionResult {
+	let mut engine = ExecutionEngine::new();
+	setup(&mut engine);
+	engine.load_script(Script::new(script.to_vec(), true).unwrap(), -1, 0);
+	engine.execute();
+	ExecutionResult::from_engine(&engine).unwrap()
+}
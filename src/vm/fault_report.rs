@@ -0,0 +1,96 @@
+use crate::{
+	execution_engine::ExecutionEngine,
+	instruction::Instruction,
+	op_code::OpCode,
+	script::debug_info::{DebugInfo, SourceLocation},
+	stack_item_json,
+	vm_state::VMState,
+};
+use std::fmt::{Display, Formatter};
+
+/// One frame of the invocation stack at the time of a fault, innermost first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaultFrame {
+	pub instruction_pointer: usize,
+
+	/// The instruction at `instruction_pointer`, if it could be decoded.
+	pub opcode: Option<OpCode>,
+
+	/// The method containing `instruction_pointer`, filled in by [`FaultReport::annotate`].
+	pub method: Option<String>,
+
+	/// The source line of `instruction_pointer`, filled in by [`FaultReport::annotate`].
+	pub source: Option<SourceLocation>,
+}
+
+/// Describes why and where an engine faulted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaultReport {
+	pub message: String,
+	pub frames: Vec<FaultFrame>,
+}
+
+impl FaultReport {
+	/// Builds a report for `engine`, or returns `None` if it has not faulted.
+	pub fn from_engine(engine: &ExecutionEngine) -> Option<Self> {
+		if engine.state != VMState::Fault {
+			return None
+		}
+
+		let message = match (&engine.fault_exception, &engine.uncaught_exception) {
+			(Some(e), _) => e.to_string(),
+			(None, Some(item)) => match stack_item_json::to_json(&*item.borrow()) {
+				Ok(json) => format!("uncaught exception: {json}"),
+				Err(_) => "uncaught exception".to_string(),
+			},
+			(None, None) => "unknown fault".to_string(),
+		};
+
+		let frames = engine
+			.invocation_stack
+			.iter()
+			.rev()
+			.map(|context| {
+				let context = context.borrow();
+				let instruction_pointer = context.instruction_pointer;
+				let opcode =
+					Instruction::from_script(context.script().as_bytes(), instruction_pointer)
+						.ok()
+						.map(|instruction| instruction.opcode);
+				FaultFrame { instruction_pointer, opcode, method: None, source: None }
+			})
+			.collect();
+
+		Some(Self { message, frames })
+	}
+
+	/// Adds method names and source locations from `debug_info` to every frame.
+	pub fn annotate(&mut self, debug_info: &DebugInfo) {
+		for frame in &mut self.frames {
+			frame.method = debug_info
+				.method_at(frame.instruction_pointer)
+				.map(|method| method.display_name());
+			frame.source = debug_info.location_at(frame.instruction_pointer);
+		}
+	}
+}
+
+impl Display for FaultReport {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		writeln!(f, "FAULT: {}", self.message)?;
+		for frame in &self.frames {
+			write!(f, "  at {:04X}", frame.instruction_pointer)?;
+			if let Some(opcode) = frame.opcode {
+				write!(f, " {opcode:?}")?;
+			}
+			if let Some(method) = &frame.method {
+				write!(f, " in {method}")?;
+			}
+			if let Some(source) = &frame.source {
+				write!(f, " ({source})")?;
+			}
+			writeln!(f)?;
+		}
+		Ok(())
+	}
+}
@@ -1,14 +1,30 @@
 use crate::op_code::OpCode;
+use num_bigint::BigInt;
 use std::convert::TryFrom;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Instruction {
 	pub opcode: OpCode,
 	pub operand: Vec<u8>,
 }
 
-#[derive(Debug)]
-enum Error {
+/// The slot a load or store instruction addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SlotKind {
+	Static,
+	Local,
+	Argument,
+}
+
+/// Whether a slot instruction reads or writes its slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SlotAccess {
+	Load,
+	Store,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
 	InvalidOpcode,
 	InvalidOperandSize,
 	InvalidPrefixSize(usize),
@@ -18,11 +34,11 @@ impl Instruction {
 	pub const RET: Self = Self { opcode: OpCode::Ret, operand: Vec::new() };
 
 	pub fn size(&self) -> usize {
-		let prefix_size = self.opcode.operand_prefix().unwrap_or(0); //  OPERAND_SIZE_PREFIX[self.opcode as usize];
+		let prefix_size = self.opcode.operand_prefix().unwrap_or(0) as usize;
 		if prefix_size > 0 {
-			(1 + prefix_size + self.operand.len()) as usize
+			1 + prefix_size + self.operand.len()
 		} else {
-			(1 + self.opcode.operand_size().unwrap_or(0)) as usize
+			1 + self.opcode.operand_size().unwrap_or(0) as usize
 		}
 	}
 
@@ -60,40 +76,113 @@ impl Instruction {
 		u32::from_le_bytes(self.operand[..4].try_into().unwrap())
 	}
 
+	/// The operand of a `PushInt*` instruction, as .NET little-endian two's complement.
+	pub fn token_bigint(&self) -> BigInt {
+		BigInt::from_signed_bytes_le(&self.operand)
+	}
+
 	pub fn token_string(&self) -> String {
 		String::from_utf8(self.operand.clone()).unwrap()
 	}
+
+	/// The slot and index read or written by `LdSFld*`, `StSFld*`, `LdLoc*`, `StLoc*`,
+	/// `LdArg*` and `StArg*`.
+	pub fn slot_access(&self) -> Option<(SlotKind, SlotAccess, usize)> {
+		let code = self.opcode as u8;
+		if !(OpCode::LdSFLd0 as u8..=OpCode::StArg as u8).contains(&code) {
+			return None
+		}
+		let group = (code - OpCode::LdSFLd0 as u8) / 8;
+		let kind = match group / 2 {
+			0 => SlotKind::Static,
+			1 => SlotKind::Local,
+			_ => SlotKind::Argument,
+		};
+		let access = if group % 2 == 0 { SlotAccess::Load } else { SlotAccess::Store };
+		let index = match (code - OpCode::LdSFLd0 as u8) % 8 {
+			7 => self.token_u8() as usize,
+			index => index as usize,
+		};
+		Some((kind, access, index))
+	}
+
+	/// The relative offsets encoded in a jump, call, `PushA`, `Try` or `EndTry` operand, in
+	/// operand order. `Try` yields its catch and finally offsets, where 0 means "absent".
+	pub fn target_offsets(&self) -> Vec<i32> {
+		match self.opcode {
+			OpCode::Jmp
+			| OpCode::JmpIf
+			| OpCode::JmpIfNot
+			| OpCode::JmpEq
+			| OpCode::JmpNe
+			| OpCode::JmpGt
+			| OpCode::JmpGe
+			| OpCode::JmpLt
+			| OpCode::JmpLe
+			| OpCode::Call
+			| OpCode::EndTry => vec![self.token_i8() as i32],
+			OpCode::PushA
+			| OpCode::JmpL
+			| OpCode::JmpIfL
+			| OpCode::JmpIfNotL
+			| OpCode::JmpEqL
+			| OpCode::JmpNeL
+			| OpCode::JmpGtL
+			| OpCode::JmpGeL
+			| OpCode::JmpLtL
+			| OpCode::JmpLeL
+			| OpCode::CallL
+			| OpCode::EndTryL => vec![self.token_i32()],
+			OpCode::Try => vec![self.token_i8() as i32, self.token_i8_1() as i32],
+			OpCode::TryL => vec![self.token_i32(), self.token_i32_1()],
+			_ => Vec::new(),
+		}
+	}
 	pub fn from_script(script: &[u8], ip: usize) -> Result<Self, Error> {
-		let opcode = OpCode::try_from(script[ip])?;
-		let mut ip = ip + 1;
+		let opcode = match script.get(ip) {
+			Some(&byte) => OpCode::try_from(byte).map_err(|_| Error::InvalidOpcode)?,
+			None =>
+				return Err(Error::OperandOutOfBounds {
+					instruction_pointer: ip,
+					operand_size: 0,
+					script_length: script.len(),
+				}),
+		};
+		let mut offset = ip + 1;
 
-		let mut operand_size = 0;
 		let prefix_size = opcode.operand_prefix().unwrap_or(0) as usize;
-		match prefix_size {
-			0 => {
-				operand_size = opcode.operand_size().unwrap_or(0) as usize;
-			},
-			1 => {
-				operand_size = script[ip] as usize;
-				ip += 1;
-			},
-			2 => {
-				operand_size = u16::from_le_bytes([script[ip], script[ip + 1]]) as usize;
-				ip += 2;
-			},
-			4 => {
-				operand_size = i32::from_le_bytes([
-					script[ip],
-					script[ip + 1],
-					script[ip + 2],
-					script[ip + 3],
-				]) as usize;
-				ip += 4;
+		let operand_size = match prefix_size {
+			0 => opcode.operand_size().unwrap_or(0) as usize,
+			1 | 2 | 4 => {
+				let prefix = Self::read_operand(script, ip, offset, prefix_size)?;
+				offset += prefix_size;
+				match prefix_size {
+					1 => prefix[0] as usize,
+					2 => u16::from_le_bytes([prefix[0], prefix[1]]) as usize,
+					_ => {
+						let size = i32::from_le_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]);
+						if size < 0 {
+							return Err(Error::InvalidOperandSize)
+						}
+						size as usize
+					},
+				}
 			},
 			_ => return Err(Error::InvalidPrefixSize(prefix_size)),
-		}
+		};
 
-		let operand = script[ip..ip + operand_size].to_vec();
+		let operand = Self::read_operand(script, ip, offset, operand_size)?.to_vec();
 		Ok(Self { opcode, operand })
 	}
+
+	/// Reads `size` bytes at `offset`, failing instead of indexing past the end of the script.
+	fn read_operand(script: &[u8], ip: usize, offset: usize, size: usize) -> Result<&[u8], Error> {
+		offset.checked_add(size).and_then(|end| script.get(offset..end)).ok_or(
+			Error::OperandOutOfBounds {
+				instruction_pointer: ip,
+				operand_size: size,
+				script_length: script.len(),
+			},
+		)
+	}
 }
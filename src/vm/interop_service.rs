@@ -0,0 +1,152 @@
+use crate::{
+	compound_types::{array::Array, Struct::Struct},
+	execution_engine::ExecutionEngine,
+	script::nef::MethodToken,
+	stack_item_type::StackItemType,
+	vm_exception::VMException,
+};
+use num_bigint::BigInt;
+use sha2::{Digest, Sha256};
+use std::{
+	fmt::{Debug, Formatter},
+	rc::Rc,
+};
+
+/// The callback invoked when a script executes `Syscall` with a registered id.
+pub type SyscallHandler = Rc<dyn Fn(&mut ExecutionEngine) -> Result<(), VMException>>;
+
+/// The callback invoked when a script executes `CallT`, with the arguments still on the stack.
+pub type TokenHandler = Rc<dyn Fn(&mut ExecutionEngine, &MethodToken) -> Result<(), VMException>>;
+
+/// A service that can be invoked through `OpCode::Syscall`.
+#[derive(Clone)]
+pub struct InteropDescriptor {
+	/// The name of the service, e.g. `System.Runtime.Log`.
+	pub name: String,
+
+	/// The id that appears as the `Syscall` operand.
+	pub hash: u32,
+
+	/// The gas charged before the handler runs.
+	pub price: u64,
+
+	pub handler: SyscallHandler,
+}
+
+impl Debug for InteropDescriptor {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("InteropDescriptor")
+			.field("name", &self.name)
+			.field("hash", &self.hash)
+			.field("price", &self.price)
+			.finish()
+	}
+}
+
+/// Computes the syscall id of a service: the first four bytes of SHA-256 over its name,
+/// read as a little-endian integer.
+pub fn syscall_hash(name: &str) -> u32 {
+	let digest = Sha256::digest(name.as_bytes());
+	u32::from_le_bytes([digest[0], digest[1], digest[2], digest[3]])
+}
+
+/// The services defined by the Neo N3 `ApplicationEngine`, used to name syscall ids.
+pub const KNOWN_SYSCALLS: &[&str] = &[
+	"System.Contract.Call",
+	"System.Contract.CallNative",
+	"System.Contract.GetCallFlags",
+	"System.Contract.CreateStandardAccount",
+	"System.Contract.CreateMultisigAccount",
+	"System.Contract.NativeOnPersist",
+	"System.Contract.NativePostPersist",
+	"System.Crypto.CheckSig",
+	"System.Crypto.CheckMultisig",
+	"System.Iterator.Next",
+	"System.Iterator.Value",
+	"System.Runtime.Platform",
+	"System.Runtime.GetNetwork",
+	"System.Runtime.GetAddressVersion",
+	"System.Runtime.GetTrigger",
+	"System.Runtime.GetTime",
+	"System.Runtime.GetScriptContainer",
+	"System.Runtime.GetExecutingScriptHash",
+	"System.Runtime.GetCallingScriptHash",
+	"System.Runtime.GetEntryScriptHash",
+	"System.Runtime.LoadScript",
+	"System.Runtime.CheckWitness",
+	"System.Runtime.GetInvocationCounter",
+	"System.Runtime.GetRandom",
+	"System.Runtime.Log",
+	"System.Runtime.Notify",
+	"System.Runtime.GetNotifications",
+	"System.Runtime.GasLeft",
+	"System.Runtime.BurnGas",
+	"System.Runtime.CurrentSigners",
+	"System.Storage.GetContext",
+	"System.Storage.GetReadOnlyContext",
+	"System.Storage.AsReadOnly",
+	"System.Storage.Get",
+	"System.Storage.Find",
+	"System.Storage.Put",
+	"System.Storage.Delete",
+];
+
+/// Returns the name of a well-known syscall id.
+pub fn syscall_name(hash: u32) -> Option<&'static str> {
+	KNOWN_SYSCALLS.iter().copied().find(|name| syscall_hash(name) == hash)
+}
+
+/// Pops a service argument that must be a primitive item and returns its bytes.
+pub fn pop_bytes(engine: &mut ExecutionEngine) -> Result<Vec<u8>, VMException> {
+	let item = engine.pop()?;
+	let item = item.borrow();
+	match item.get_type() {
+		StackItemType::ByteString
+		| StackItemType::Buffer
+		| StackItemType::Integer
+		| StackItemType::Boolean => Ok(item.get_slice()?.to_vec()),
+		found => Err(VMException::InvalidParameter(format!("Expected bytes, found {found:?}"))),
+	}
+}
+
+/// Pops a service argument that must be a primitive item and returns it as an integer.
+pub fn pop_integer(engine: &mut ExecutionEngine) -> Result<BigInt, VMException> {
+	let item = engine.pop()?;
+	let item = item.borrow();
+	match item.get_type() {
+		StackItemType::ByteString | StackItemType::Integer | StackItemType::Boolean =>
+			item.get_integer(),
+		found =>
+			Err(VMException::InvalidParameter(format!("Expected an integer, found {found:?}"))),
+	}
+}
+
+/// Pops a service argument that must be an array or struct of primitive items and returns the
+/// bytes of each element.
+pub fn pop_bytes_array(engine: &mut ExecutionEngine) -> Result<Vec<Vec<u8>>, VMException> {
+	let item = engine.pop()?;
+	let item = item.borrow();
+	let any = item.as_any();
+	let elements = match (any.downcast_ref::<Struct>(), any.downcast_ref::<Array>()) {
+		(Some(st), _) => st.iter().cloned().collect::<Vec<_>>(),
+		(_, Some(array)) => array.iter().cloned().collect(),
+		_ =>
+			return Err(VMException::InvalidParameter(format!(
+				"Expected an array, found {:?}",
+				item.get_type()
+			))),
+	};
+	elements
+		.iter()
+		.map(|element| {
+			let element = element.borrow();
+			match element.get_type() {
+				StackItemType::ByteString | StackItemType::Buffer | StackItemType::Integer =>
+					Ok(element.get_slice()?.to_vec()),
+				found => Err(VMException::InvalidParameter(format!(
+					"Expected bytes in array, found {found:?}"
+				))),
+			}
+		})
+		.collect()
+}
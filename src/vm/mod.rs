@@ -1,4 +1,6 @@
+pub mod crypto_service;
 pub mod instruction;
+pub mod interop_service;
 pub mod op_code;
 
 pub mod script;
@@ -8,9 +10,12 @@ pub mod evaluation_stack;
 pub mod execution_context;
 
 pub mod slot;
+pub mod std_lib_service;
 
-mod execution_engine;
-mod vm_exception;
+pub mod execution_engine;
+pub mod execution_result;
+pub mod fault_report;
+pub mod vm_exception;
 pub mod vm_state;
 
 pub fn add(left: usize, right: usize) -> usize {
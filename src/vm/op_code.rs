@@ -1,8 +1,9 @@
 use lazy_static::lazy_static;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
-use std::{collections::HashMap, fmt::Error};
-#[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive)]
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, convert::TryFrom};
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, FromPrimitive, Serialize, Deserialize)]
 pub enum OpCode {
 	PushInt8 = 0x00,
 	PushInt16 = 0x01,
@@ -271,18 +272,313 @@ lazy_static! {
 	};
 }
 
+impl TryFrom<u8> for OpCode {
+	type Error = u8;
+
+	fn try_from(value: u8) -> Result<Self, Self::Error> {
+		OpCode::from_u8(value).ok_or(value)
+	}
+}
+
 impl OpCode {
-	pub fn operand_size(&self) -> Result<u8, Error> {
+	pub fn operand_size(&self) -> Result<u8, OpCode> {
 		match OPERAND_SIZES.get(self) {
 			Some(size) => Ok(size.size),
-			None => Err(Error::InvalidOpCode(*self)),
+			None => Err(*self),
 		}
 	}
 
-	pub fn operand_prefix(&self) -> Result<u8, Error> {
+	pub fn operand_prefix(&self) -> Result<u8, OpCode> {
 		match OPERAND_SIZES.get(self) {
 			Some(size) => Ok(size.prefix),
-			None => Err(Error::InvalidOpCode(*self)),
+			None => Err(*self),
+		}
+	}
+
+	/// The mnemonic used by the reference implementation, e.g. `PUSHINT8` or `JMP_L`.
+	pub fn mnemonic(&self) -> &'static str {
+		use OpCode::*;
+		match self {
+			PushInt8 => "PUSHINT8",
+			PushInt16 => "PUSHINT16",
+			PushInt32 => "PUSHINT32",
+			PushInt64 => "PUSHINT64",
+			PushInt128 => "PUSHINT128",
+			PushInt256 => "PUSHINT256",
+			PushTrue => "PUSHTRUE",
+			PushFalse => "PUSHFALSE",
+			PushA => "PUSHA",
+			PushNull => "PUSHNULL",
+			PushData1 => "PUSHDATA1",
+			PushData2 => "PUSHDATA2",
+			PushData4 => "PUSHDATA4",
+			PushM1 => "PUSHM1",
+			Push0 => "PUSH0",
+			Push1 => "PUSH1",
+			Push2 => "PUSH2",
+			Push3 => "PUSH3",
+			Push4 => "PUSH4",
+			Push5 => "PUSH5",
+			Push6 => "PUSH6",
+			Push7 => "PUSH7",
+			Push8 => "PUSH8",
+			Push9 => "PUSH9",
+			Push10 => "PUSH10",
+			Push11 => "PUSH11",
+			Push12 => "PUSH12",
+			Push13 => "PUSH13",
+			Push14 => "PUSH14",
+			Push15 => "PUSH15",
+			Push16 => "PUSH16",
+			Nop => "NOP",
+			Jmp => "JMP",
+			JmpL => "JMP_L",
+			JmpIf => "JMPIF",
+			JmpIfL => "JMPIF_L",
+			JmpIfNot => "JMPIFNOT",
+			JmpIfNotL => "JMPIFNOT_L",
+			JmpEq => "JMPEQ",
+			JmpEqL => "JMPEQ_L",
+			JmpNe => "JMPNE",
+			JmpNeL => "JMPNE_L",
+			JmpGt => "JMPGT",
+			JmpGtL => "JMPGT_L",
+			JmpGe => "JMPGE",
+			JmpGeL => "JMPGE_L",
+			JmpLt => "JMPLT",
+			JmpLtL => "JMPLT_L",
+			JmpLe => "JMPLE",
+			JmpLeL => "JMPLE_L",
+			Call => "CALL",
+			CallL => "CALL_L",
+			CallA => "CALLA",
+			CallT => "CALLT",
+			Abort => "ABORT",
+			Assert => "ASSERT",
+			Throw => "THROW",
+			Try => "TRY",
+			TryL => "TRY_L",
+			EndTry => "ENDTRY",
+			EndTryL => "ENDTRY_L",
+			EndFinally => "ENDFINALLY",
+			Ret => "RET",
+			Syscall => "SYSCALL",
+			Depth => "DEPTH",
+			Drop => "DROP",
+			Nip => "NIP",
+			Xdrop => "XDROP",
+			Clear => "CLEAR",
+			Dup => "DUP",
+			Over => "OVER",
+			Pick => "PICK",
+			Tuck => "TUCK",
+			Swap => "SWAP",
+			Rot => "ROT",
+			Roll => "ROLL",
+			Reverse3 => "REVERSE3",
+			Reverse4 => "REVERSE4",
+			ReverseN => "REVERSEN",
+			InitSSLot => "INITSSLOT",
+			InitSlot => "INITSLOT",
+			LdSFLd0 => "LDSFLD0",
+			LdSFLd1 => "LDSFLD1",
+			LdSFLd2 => "LDSFLD2",
+			LdSFLd3 => "LDSFLD3",
+			LdSFLd4 => "LDSFLD4",
+			LdSFLd5 => "LDSFLD5",
+			LdSFLd6 => "LDSFLD6",
+			LdSFLd => "LDSFLD",
+			StSFLd0 => "STSFLD0",
+			StSFLd1 => "STSFLD1",
+			StSFLd2 => "STSFLD2",
+			StSFLd3 => "STSFLD3",
+			StSFLd4 => "STSFLD4",
+			StSFLd5 => "STSFLD5",
+			StSFLd6 => "STSFLD6",
+			StSFLd => "STSFLD",
+			LdLoc0 => "LDLOC0",
+			LdLoc1 => "LDLOC1",
+			LdLoc2 => "LDLOC2",
+			LdLoc3 => "LDLOC3",
+			LdLoc4 => "LDLOC4",
+			LdLoc5 => "LDLOC5",
+			LdLoc6 => "LDLOC6",
+			LdLoc => "LDLOC",
+			StLoc0 => "STLOC0",
+			StLoc1 => "STLOC1",
+			StLoc2 => "STLOC2",
+			StLoc3 => "STLOC3",
+			StLoc4 => "STLOC4",
+			StLoc5 => "STLOC5",
+			StLoc6 => "STLOC6",
+			StLoc => "STLOC",
+			LdArg0 => "LDARG0",
+			LdArg1 => "LDARG1",
+			LdArg2 => "LDARG2",
+			LdArg3 => "LDARG3",
+			LdArg4 => "LDARG4",
+			LdArg5 => "LDARG5",
+			LdArg6 => "LDARG6",
+			LdArg => "LDARG",
+			StArg0 => "STARG0",
+			StArg1 => "STARG1",
+			StArg2 => "STARG2",
+			StArg3 => "STARG3",
+			StArg4 => "STARG4",
+			StArg5 => "STARG5",
+			StArg6 => "STARG6",
+			StArg => "STARG",
+			NewBuffer => "NEWBUFFER",
+			MemCpy => "MEMCPY",
+			Cat => "CAT",
+			Substr => "SUBSTR",
+			Left => "LEFT",
+			Right => "RIGHT",
+			Invert => "INVERT",
+			And => "AND",
+			Or => "OR",
+			Xor => "XOR",
+			Equal => "EQUAL",
+			NotEqual => "NOTEQUAL",
+			Sign => "SIGN",
+			Abs => "ABS",
+			Negate => "NEGATE",
+			Inc => "INC",
+			Dec => "DEC",
+			Add => "ADD",
+			Sub => "SUB",
+			Mul => "MUL",
+			Div => "DIV",
+			Mod => "MOD",
+			Pow => "POW",
+			Sqrt => "SQRT",
+			ModMul => "MODMUL",
+			ModPow => "MODPOW",
+			Shl => "SHL",
+			Shr => "SHR",
+			Not => "NOT",
+			BoolAnd => "BOOLAND",
+			BoolOr => "BOOLOR",
+			Nz => "NZ",
+			NumEqual => "NUMEQUAL",
+			NumNotEqual => "NUMNOTEQUAL",
+			Lt => "LT",
+			Le => "LE",
+			Gt => "GT",
+			Ge => "GE",
+			Min => "MIN",
+			Max => "MAX",
+			Within => "WITHIN",
+			PackMap => "PACKMAP",
+			PackStruct => "PACKSTRUCT",
+			Pack => "PACK",
+			Unpack => "UNPACK",
+			NewArray0 => "NEWARRAY0",
+			NewArray => "NEWARRAY",
+			NewArrayT => "NEWARRAY_T",
+			NewStruct0 => "NEWSTRUCT0",
+			NewStruct => "NEWSTRUCT",
+			NewMap => "NEWMAP",
+			Size => "SIZE",
+			HasKey => "HASKEY",
+			Keys => "KEYS",
+			Values => "VALUES",
+			PickItem => "PICKITEM",
+			Append => "APPEND",
+			SetItem => "SETITEM",
+			ReverseItems => "REVERSEITEMS",
+			Remove => "REMOVE",
+			ClearItems => "CLEARITEMS",
+			PopItem => "POPITEM",
+			IsNull => "ISNULL",
+			IsType => "ISTYPE",
+			Convert => "CONVERT",
+			AbortMsg => "ABORTMSG",
+			AssertMsg => "ASSERTMSG",
+		}
+	}
+
+	/// Looks up an opcode by its mnemonic, ignoring case.
+	pub fn from_mnemonic(mnemonic: &str) -> Option<Self> {
+		(0..=u8::MAX)
+			.filter_map(OpCode::from_u8)
+			.find(|opcode| opcode.mnemonic().eq_ignore_ascii_case(mnemonic))
+	}
+
+	/// The 4-byte-offset variant of a jump, call, `Try` or `EndTry` opcode with a 1-byte offset.
+	pub fn long_form(&self) -> Option<Self> {
+		use OpCode::*;
+		match self {
+			Jmp => Some(JmpL),
+			JmpIf => Some(JmpIfL),
+			JmpIfNot => Some(JmpIfNotL),
+			JmpEq => Some(JmpEqL),
+			JmpNe => Some(JmpNeL),
+			JmpGt => Some(JmpGtL),
+			JmpGe => Some(JmpGeL),
+			JmpLt => Some(JmpLtL),
+			JmpLe => Some(JmpLeL),
+			Call => Some(CallL),
+			Try => Some(TryL),
+			EndTry => Some(EndTryL),
+			_ => None,
+		}
+	}
+
+	/// The 1-byte-offset variant of a long jump, call, `TryL` or `EndTryL` opcode.
+	pub fn short_form(&self) -> Option<Self> {
+		use OpCode::*;
+		match self {
+			JmpL => Some(Jmp),
+			JmpIfL => Some(JmpIf),
+			JmpIfNotL => Some(JmpIfNot),
+			JmpEqL => Some(JmpEq),
+			JmpNeL => Some(JmpNe),
+			JmpGtL => Some(JmpGt),
+			JmpGeL => Some(JmpGe),
+			JmpLtL => Some(JmpLt),
+			JmpLeL => Some(JmpLe),
+			CallL => Some(Call),
+			TryL => Some(Try),
+			EndTryL => Some(EndTry),
+			_ => None,
+		}
+	}
+
+	/// The base gas price of the opcode, matching the reference `ApplicationEngine` table.
+	pub fn price(&self) -> u64 {
+		use OpCode::*;
+		match self {
+			PushInt8 | PushInt16 | PushInt32 | PushInt64 | PushNull | PushM1 | Push0 | Push1
+			| Push2 | Push3 | Push4 | Push5 | Push6 | Push7 | Push8 | Push9 | Push10 | Push11
+			| Push12 | Push13 | Push14 | Push15 | Push16 | PushTrue | PushFalse | Nop | Assert
+			| AssertMsg => 1,
+			PushInt128 | PushInt256 | PushA | Try | TryL | EndTry | EndTryL | EndFinally
+			| Invert | Sign | Abs | Negate | Inc | Dec | Not | Nz | Size => 1 << 2,
+			PushData1 | And | Or | Xor | Add | Sub | Mul | Div | Mod | Shl | Shr | BoolAnd
+			| BoolOr | NumEqual | NumNotEqual | Lt | Le | Gt | Ge | Min | Max | Within | NewMap => 1 << 3,
+			PushData2 => 1 << 9,
+			PushData4 => 1 << 12,
+			Jmp | JmpL | JmpIf | JmpIfL | JmpIfNot | JmpIfNotL | JmpEq | JmpEqL | JmpNe
+			| JmpNeL | JmpGt | JmpGtL | JmpGe | JmpGeL | JmpLt | JmpLtL | JmpLe | JmpLeL
+			| Depth | Drop | Nip | Dup | Over | Pick | Tuck | Swap | Rot | Reverse3 | Reverse4
+			| LdSFLd0 | LdSFLd1 | LdSFLd2 | LdSFLd3 | LdSFLd4 | LdSFLd5 | LdSFLd6 | LdSFLd
+			| StSFLd0 | StSFLd1 | StSFLd2 | StSFLd3 | StSFLd4 | StSFLd5 | StSFLd6 | StSFLd
+			| LdLoc0 | LdLoc1 | LdLoc2 | LdLoc3 | LdLoc4 | LdLoc5 | LdLoc6 | LdLoc | StLoc0
+			| StLoc1 | StLoc2 | StLoc3 | StLoc4 | StLoc5 | StLoc6 | StLoc | LdArg0 | LdArg1
+			| LdArg2 | LdArg3 | LdArg4 | LdArg5 | LdArg6 | LdArg | StArg0 | StArg1 | StArg2
+			| StArg3 | StArg4 | StArg5 | StArg6 | StArg | IsNull | IsType => 1 << 1,
+			Xdrop | Clear | Roll | ReverseN | InitSSLot | NewArray0 | NewStruct0 | Keys
+			| Remove | ClearItems | PopItem => 1 << 4,
+			Equal | NotEqual | ModMul => 1 << 5,
+			InitSlot | Pow | Sqrt | HasKey | PickItem => 1 << 6,
+			NewBuffer => 1 << 8,
+			Call | CallL | CallA | Throw | NewArray | NewArrayT | NewStruct => 1 << 9,
+			MemCpy | Cat | Substr | Left | Right | ModPow | PackMap | PackStruct | Pack
+			| Unpack => 1 << 11,
+			Values | Append | SetItem | ReverseItems | Convert => 1 << 13,
+			CallT => 1 << 15,
+			Abort | AbortMsg | Ret | Syscall => 0,
 		}
 	}
 }
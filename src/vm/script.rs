@@ -1,11 +1,26 @@
-use crate::{instruction::Instruction, op_code::OpCode, stack_item_type::StackItemType};
-use std::{collections::HashMap, convert::TryFrom, ops::Index};
+use crate::{
+	instruction::{Error as InstructionError, Instruction},
+	op_code::OpCode,
+	stack_item_type::StackItemType,
+	uint::UInt160,
+};
+use std::{
+	cell::{OnceCell, RefCell},
+	collections::HashMap,
+	convert::TryFrom,
+	error::Error,
+	fmt::{Display, Formatter},
+	hash::{Hash, Hasher},
+};
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+/// Scripts are equal when their hashes are, which makes the hash their identity.
+#[derive(Debug, Clone, Default)]
 pub struct Script {
 	value: Vec<u8>,
 	strict_mode: bool,
-	instructions: HashMap<usize, Instruction>,
+	/// Decoded instructions by offset. Strict scripts decode all of them up front.
+	instructions: RefCell<HashMap<usize, Instruction>>,
+	hash: OnceCell<UInt160>,
 }
 
 impl Script {
@@ -13,12 +28,26 @@ impl Script {
 		self.value.len()
 	}
 
-	pub fn get(&self, index: usize) -> OpCode {
-		OpCode::try_from(self.value[index]).unwrap()
+	pub fn is_empty(&self) -> bool {
+		self.value.is_empty()
+	}
+
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.value
+	}
+
+	/// The script hash: RIPEMD-160 of the SHA-256 of the script, computed once.
+	pub fn hash(&self) -> UInt160 {
+		*self.hash.get_or_init(|| UInt160::from_script(&self.value))
 	}
 
 	pub fn new(bytes: Vec<u8>, strict_mode: bool) -> Result<Self, ScriptError> {
-		let mut script = Self { value: bytes, strict_mode, instructions: HashMap::new() };
+		let mut script = Self {
+			value: bytes,
+			strict_mode,
+			instructions: Default::default(),
+			hash: OnceCell::new(),
+		};
 
 		if strict_mode {
 			script.validate()?;
@@ -27,97 +56,103 @@ impl Script {
 		Ok(script)
 	}
 
+	/// Decodes every instruction and checks jump targets and type operands, reporting the first
+	/// problem in script order.
 	pub fn validate(&mut self) -> Result<(), ScriptError> {
 		let mut ip = 0;
+		let mut instructions = HashMap::new();
 		while ip < self.len() {
-			let instruction = self.get_instruction(ip).unwrap();
-			ip += instruction.size();
+			let instruction = self.decode(ip)?;
+			let size = instruction.size();
+			instructions.insert(ip, instruction);
+			ip += size;
 		}
 
-		for (ip, instruction) in &self.instructions {
-			match instruction.opcode {
-				OpCode::Jmp
-				| OpCode::JmpIf
-				| OpCode::JmpIfNot
-				| OpCode::JmpEq
-				| OpCode::JmpNe
-				| OpCode::JmpGt
-				| OpCode::JmpGe
-				| OpCode::JmpLt
-				| OpCode::JmpLe
-				| OpCode::Call
-				| OpCode::EndTry =>
-					if !self.instructions.contains_key(&(ip + instruction.token_i8())) {
-						panic!("ip: {}, opcode: {:?}", ip, instruction.opcode);
-					},
-				OpCode::PushA
-				| OpCode::JmpL
-				| OpCode::JmpIfL
-				| OpCode::JmpIfNotL
-				| OpCode::JmpEqL
-				| OpCode::JmpNeL
-				| OpCode::JmpGtL
-				| OpCode::JmpGeL
-				| OpCode::JmpLtL
-				| OpCode::JmpLeL
-				| OpCode::CallL
-				| OpCode::EndTryL =>
-					if !self.instructions.contains_key(&(ip + instruction.token_i32())) {
-						panic!("ip: {}, opcode: {:?}", ip, instruction.opcode);
-					},
-				OpCode::Try => {
-					if !self.instructions.contains_key(&(ip + instruction.token_i8())) {
-						panic!("ip: {}, opcode: {:?}", ip, instruction.opcode);
-					}
-					if !self.instructions.contains_key(&(ip + instruction.token_i8_1())) {
-						panic!("ip: {}, opcode: {:?}", ip, instruction.opcode);
-					}
-				},
-				OpCode::TryL => {
-					if !self.instructions.contains_key(&(ip + instruction.token_i32())) {
-						panic!("ip: {}, opcode: {:?}", ip, instruction.opcode);
-					}
-					if !self.instructions.contains_key(&(ip + instruction.token_i32_1())) {
-						panic!("ip: {}, opcode: {:?}", ip, instruction.opcode);
-					}
-				},
-				OpCode::NewArrayT | OpCode::IsType | OpCode::Convert => {
-					let type_code = instruction.token_u8();
-					if !StackItemType::is_valid(type_code) {
-						panic!("Invalid type code: {}", type_code);
-					}
-					if instruction.opcode != OpCode::NewArrayT
-						&& type_code == StackItemType::Any as u8
-					{
-						panic!("ip: {}, opcode: {:?} with Any type", ip, instruction.opcode);
-					}
-				},
-				_ => {},
+		let mut offsets = instructions.keys().copied().collect::<Vec<_>>();
+		offsets.sort_unstable();
+		for ip in offsets {
+			let instruction = &instructions[&ip];
+			for offset in instruction.target_offsets() {
+				let target = ip as i64 + offset as i64;
+				if target < 0 || !instructions.contains_key(&(target as usize)) {
+					return Err(ScriptError::InvalidJump { ip, target })
+				}
+			}
+
+			if let OpCode::NewArrayT | OpCode::IsType | OpCode::Convert = instruction.opcode {
+				let type_code = instruction.token_u8();
+				if !StackItemType::is_valid(type_code)
+					|| (instruction.opcode != OpCode::NewArrayT
+						&& type_code == StackItemType::Any as u8)
+				{
+					return Err(ScriptError::InvalidType { ip, type_code })
+				}
 			}
 		}
 
+		*self.instructions.get_mut() = instructions;
 		Ok(())
 	}
 
-	pub fn get_instruction(&mut self, ip: usize) -> Result<&Instruction, ScriptError> {
-		if !self.instructions.contains_key(&ip) {
-			if self.strict_mode {
-				return Err(ScriptError::InvalidInstrPointer(ip))
+	/// Decodes the instruction at `ip`, describing why it cannot be decoded.
+	fn decode(&self, ip: usize) -> Result<Instruction, ScriptError> {
+		Instruction::from_script(&self.value, ip).map_err(|error| {
+			let script_length = self.len();
+			let opcode = self.value.get(ip).and_then(|&byte| OpCode::try_from(byte).ok());
+			let prefix_size = opcode.and_then(|op| op.operand_prefix().ok()).unwrap_or(0) as usize;
+			match error {
+				InstructionError::InvalidOpcode =>
+					ScriptError::UnknownOpcode { ip, opcode: self.value[ip] },
+				InstructionError::OperandOutOfBounds { operand_size, .. }
+					if prefix_size > 0 && ip + 1 + prefix_size <= script_length =>
+					ScriptError::InvalidPushDataLength {
+						ip,
+						length: operand_size as i64,
+						available: script_length - ip - 1 - prefix_size,
+					},
+				InstructionError::OperandOutOfBounds { operand_size, .. } =>
+					ScriptError::TruncatedOperand { ip, operand_size, script_length },
+				InstructionError::InvalidOperandSize => {
+					let prefix = &self.value[ip + 1..ip + 5];
+					ScriptError::InvalidPushDataLength {
+						ip,
+						length: i32::from_le_bytes(prefix.try_into().unwrap()) as i64,
+						available: script_length - ip - 5,
+					}
+				},
+				InstructionError::InvalidPrefixSize(_) =>
+					ScriptError::UnknownOpcode { ip, opcode: self.value[ip] },
 			}
+		})
+	}
 
-			let instr = Instruction::parse(&self.value, ip)?;
-			self.instructions.insert(ip, instr);
+	/// The instruction at `ip`. Strict scripts only have instructions at the offsets that
+	/// validation decoded; other scripts decode any offset on first use.
+	pub fn get_instruction(&self, ip: usize) -> Result<Instruction, ScriptError> {
+		if let Some(instruction) = self.instructions.borrow().get(&ip) {
+			return Ok(instruction.clone())
 		}
+		if self.strict_mode {
+			return Err(ScriptError::InvalidInstrPointer(ip))
+		}
+
+		let instruction = self.decode(ip)?;
+		self.instructions.borrow_mut().insert(ip, instruction.clone());
+		Ok(instruction)
+	}
+}
 
-		Ok(self.instructions.get(&ip).unwrap())
+impl PartialEq for Script {
+	fn eq(&self, other: &Self) -> bool {
+		self.hash() == other.hash()
 	}
 }
 
-impl Index<usize> for Script {
-	type Output = OpCode;
+impl Eq for Script {}
 
-	fn index(&self, index: usize) -> &Self::Output {
-		&self.get(index)
+impl Hash for Script {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		Script::hash(self).hash(state);
 	}
 }
 
@@ -129,7 +164,121 @@ impl TryFrom<Vec<u8>> for Script {
 	}
 }
 
-enum ScriptError {
+/// Why a script was rejected. Every variant carries the offset of the offending instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptError {
+	/// `ip` is not the start of an instruction of a strict-mode script.
 	InvalidInstrPointer(usize),
-	// other errors
+	/// The byte at `ip` is not an opcode.
+	UnknownOpcode { ip: usize, opcode: u8 },
+	/// The operand of the instruction at `ip` runs past the end of the script.
+	TruncatedOperand { ip: usize, operand_size: usize, script_length: usize },
+	/// A `PushData` length prefix is negative or larger than the bytes left in the script.
+	InvalidPushDataLength { ip: usize, length: i64, available: usize },
+	/// A jump, call, `PushA` or try target is outside the script or inside an instruction.
+	InvalidJump { ip: usize, target: i64 },
+	/// `NewArrayT`, `IsType` or `Convert` names a type that does not exist or is not allowed.
+	InvalidType { ip: usize, type_code: u8 },
+}
+
+impl ScriptError {
+	/// The offset of the instruction that was rejected.
+	pub fn ip(&self) -> usize {
+		match *self {
+			ScriptError::InvalidInstrPointer(ip)
+			| ScriptError::UnknownOpcode { ip, .. }
+			| ScriptError::TruncatedOperand { ip, .. }
+			| ScriptError::InvalidPushDataLength { ip, .. }
+			| ScriptError::InvalidJump { ip, .. }
+			| ScriptError::InvalidType { ip, .. } => ip,
+		}
+	}
+}
+
+impl Display for ScriptError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ScriptError::InvalidInstrPointer(ip) =>
+				write!(f, "ip {ip:04X}: not the start of an instruction"),
+			ScriptError::UnknownOpcode { ip, opcode } =>
+				write!(f, "ip {ip:04X}: unknown opcode 0x{opcode:02x}"),
+			ScriptError::TruncatedOperand { ip, operand_size, script_length } => write!(
+				f,
+				"ip {ip:04X}: operand of {operand_size} bytes runs past the end of the \
+				 {script_length}-byte script"
+			),
+			ScriptError::InvalidPushDataLength { ip, length, available } => write!(
+				f,
+				"ip {ip:04X}: PushData length {length} with {available} bytes left in the script"
+			),
+			ScriptError::InvalidJump { ip, target } =>
+				write!(f, "ip {ip:04X}: jump target {target} is not an instruction boundary"),
+			ScriptError::InvalidType { ip, type_code } =>
+				write!(f, "ip {ip:04X}: invalid stack item type 0x{type_code:02x}"),
+		}
+	}
+}
+
+impl Error for ScriptError {}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn error(bytes: &[u8]) -> ScriptError {
+		Script::new(bytes.to_vec(), true).unwrap_err()
+	}
+
+	#[test]
+	fn accepts_valid_scripts() {
+		// PUSH1, JMP +2, RET
+		assert!(Script::new(vec![0x11, 0x22, 0x02, 0x40], true).is_ok());
+		// TRY with no finally block
+		assert!(Script::new(vec![0x3b, 0x03, 0x00, 0x40], true).is_ok());
+	}
+
+	#[test]
+	fn scripts_are_identified_by_hash() {
+		let strict = Script::new(vec![0x11, 0x40], true).unwrap();
+		let lenient = Script::new(vec![0x11, 0x40], false).unwrap();
+		assert_eq!(strict.hash(), UInt160::from_script(&[0x11, 0x40]));
+		assert_eq!(strict, lenient);
+		assert_ne!(strict, Script::new(vec![0x12, 0x40], true).unwrap());
+	}
+
+	#[test]
+	fn reports_decoding_errors() {
+		assert_eq!(error(&[0x11, 0xff]), ScriptError::UnknownOpcode { ip: 1, opcode: 0xff });
+		assert_eq!(
+			error(&[0x11, 0x01, 0x05]),
+			ScriptError::TruncatedOperand { ip: 1, operand_size: 2, script_length: 3 }
+		);
+		assert_eq!(
+			error(&[0x0c, 0x05, 0x61]),
+			ScriptError::InvalidPushDataLength { ip: 0, length: 5, available: 1 }
+		);
+		assert_eq!(
+			error(&[0x0e, 0xff, 0xff, 0xff, 0xff]),
+			ScriptError::InvalidPushDataLength { ip: 0, length: -1, available: 0 }
+		);
+		assert_eq!(
+			error(&[0x0d, 0x01]),
+			ScriptError::TruncatedOperand { ip: 0, operand_size: 2, script_length: 2 }
+		);
+	}
+
+	#[test]
+	fn reports_invalid_jumps_and_types() {
+		// JMP into the middle of PUSHINT16
+		assert_eq!(
+			error(&[0x22, 0x03, 0x01, 0x00, 0x00, 0x40]),
+			ScriptError::InvalidJump { ip: 0, target: 3 }
+		);
+		assert_eq!(error(&[0x40, 0x22, 0x80]), ScriptError::InvalidJump { ip: 1, target: -127 });
+		// CONVERT to Any and ISTYPE 0x55
+		assert_eq!(error(&[0xdb, 0x00]), ScriptError::InvalidType { ip: 0, type_code: 0x00 });
+		assert_eq!(error(&[0xd9, 0x55]), ScriptError::InvalidType { ip: 0, type_code: 0x55 });
+		assert!(Script::new(vec![0xc4, 0x00], true).is_ok());
+		assert_eq!(error(&[0x40, 0xd9, 0x55]).ip(), 1);
+	}
 }
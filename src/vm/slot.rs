@@ -1,66 +1,72 @@
-use crate::{null::Null, reference_counter::ReferenceCounter, stack_item::StackItem};
+use crate::{
+	null::Null, reference_counter::ReferenceCounter, stack_item::StackItem,
+	vm_exception::VMException,
+};
 use std::{cell::RefCell, rc::Rc};
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+/// Used to store local variables, arguments and static fields in the vm.
+///
+/// Every item counts as a reference on the reference counter until the slot is dropped.
+#[derive(Debug)]
 pub struct Slot {
 	items: Vec<Rc<RefCell<dyn StackItem>>>,
 	reference_counter: Rc<RefCell<ReferenceCounter>>,
 }
 
 impl Slot {
+	/// Creates a slot holding `items`.
 	pub fn new(
 		items: Vec<Rc<RefCell<dyn StackItem>>>,
 		reference_counter: Rc<RefCell<ReferenceCounter>>,
 	) -> Self {
-		let mut slot = Self { items, reference_counter };
-		for item in &slot.items {
-			slot.reference_counter.add_stack_reference(item, 1);
-		}
-		slot
-	}
-
-	pub fn new_with_count(count: i32, reference_counter: Rc<RefCell<ReferenceCounter>>) -> Self {
-		let mut items = Vec::new();
-		for _ in 0..count {
-			items.push(StackItem::from(Null::default()).into());
-		}
-
+		reference_counter.borrow().add_references(items.len());
 		Self { items, reference_counter }
 	}
 
-	pub fn with_capacity(
-		capacity: usize,
-		reference_counter: Rc<RefCell<ReferenceCounter>>,
-	) -> Self {
-		Self { items: Vec::with_capacity(capacity), reference_counter }
+	/// Creates a slot of `count` nulls.
+	pub fn new_with_count(count: usize, reference_counter: Rc<RefCell<ReferenceCounter>>) -> Self {
+		let items = (0..count)
+			.map(|_| Rc::new(RefCell::new(Null)) as Rc<RefCell<dyn StackItem>>)
+			.collect();
+		Self::new(items, reference_counter)
 	}
 
-	pub fn get(&self, index: usize) -> Rc<RefCell<dyn StackItem>> {
-		self.items[index].clone()
+	pub fn get(&self, index: usize) -> Result<Rc<RefCell<dyn StackItem>>, VMException> {
+		self.items.get(index).cloned().ok_or_else(|| self.out_of_range(index))
 	}
 
-	pub fn set(&mut self, index: usize, value: Rc<RefCell<dyn StackItem>>) {
-		let old_value = std::mem::replace(&mut self.items[index], value);
-		self.reference_counter.remove_stack_reference(&old_value);
-		self.reference_counter.add_stack_reference(&value, 1);
+	pub fn set(
+		&mut self,
+		index: usize,
+		value: Rc<RefCell<dyn StackItem>>,
+	) -> Result<(), VMException> {
+		let error = self.out_of_range(index);
+		*self.items.get_mut(index).ok_or(error)? = value;
+		Ok(())
 	}
 
 	pub fn len(&self) -> usize {
 		self.items.len()
 	}
 
-	pub fn clear_references(&mut self) {
-		for item in &self.items {
-			self.reference_counter.get_mut().remove_stack_reference(item.clone());
-		}
+	pub fn is_empty(&self) -> bool {
+		self.items.is_empty()
+	}
+
+	pub fn iter(&self) -> std::slice::Iter<'_, Rc<RefCell<dyn StackItem>>> {
+		self.items.iter()
 	}
-}
 
-impl IntoIterator for Slot {
-	type Item = Rc<RefCell<dyn StackItem>>;
-	type IntoIter = std::vec::IntoIter<Self::Item>;
+	fn out_of_range(&self, index: usize) -> VMException {
+		VMException::InvalidParameter(format!(
+			"Slot index {index} out of range for {} items",
+			self.items.len()
+		))
+	}
+}
 
-	fn into_iter(self) -> Self::IntoIter {
-		self.items.into_iter()
+impl Drop for Slot {
+	fn drop(&mut self) {
+		self.reference_counter.borrow().remove_references(self.items.len());
 	}
 }
@@ -0,0 +1,370 @@
+use crate::{
+	binary_serializer,
+	compound_types::array::Array,
+	crypto::base58,
+	execution_engine::ExecutionEngine,
+	interop_service::{pop_bytes, pop_integer},
+	json_serializer,
+	primitive_types::{byte_string::ByteString, integer::Integer},
+	stack_item::StackItem,
+	vm_exception::VMException,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use num_bigint::BigInt;
+use num_traits::{One, ToPrimitive};
+use std::{cell::RefCell, rc::Rc};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The longest string or byte array, in bytes, that the length-checked arguments accept.
+pub const MAX_INPUT_LENGTH: usize = 1024;
+
+type Method = fn(&mut ExecutionEngine) -> Result<(), VMException>;
+
+/// The StdLib methods with their prices.
+const METHODS: &[(&str, u64, Method)] = &[
+	("serialize", 1 << 12, serialize),
+	("deserialize", 1 << 14, deserialize),
+	("jsonSerialize", 1 << 12, json_serialize),
+	("jsonDeserialize", 1 << 14, json_deserialize),
+	("itoa", 1 << 12, itoa),
+	("atoi", 1 << 6, atoi),
+	("base64Encode", 1 << 5, base64_encode),
+	("base64Decode", 1 << 5, base64_decode),
+	("base58Encode", 1 << 13, base58_encode),
+	("base58Decode", 1 << 10, base58_decode),
+	("base58CheckEncode", 1 << 16, base58_check_encode),
+	("base58CheckDecode", 1 << 16, base58_check_decode),
+	("memoryCompare", 1 << 5, memory_compare),
+	("memorySearch", 1 << 6, memory_search),
+	("stringSplit", 1 << 8, string_split),
+	("strLen", 1 << 8, str_len),
+];
+
+/// The syscall name of a StdLib method, e.g. `StdLib.itoa`.
+pub fn std_lib_syscall(method: &str) -> String {
+	format!("StdLib.{method}")
+}
+
+/// Registers the StdLib methods on `engine`, each as the syscall `StdLib.<method>` with the
+/// native contract's price.
+///
+/// A method takes the arguments of its longest overload, first argument on top: `itoa` and
+/// `atoi` need a base, `memorySearch` needs `start` and `backward`, and `stringSplit` needs
+/// `removeEmptyEntries`. Errors follow the native contract: strings must be valid UTF-8,
+/// length-checked inputs are limited to [`MAX_INPUT_LENGTH`] bytes and only bases 10 and 16
+/// are supported.
+pub fn register_std_lib_services(engine: &mut ExecutionEngine) {
+	for &(method, price, handler) in METHODS {
+		engine.register_syscall(&std_lib_syscall(method), price, Rc::new(handler));
+	}
+}
+
+fn invalid(message: impl Into<String>) -> VMException {
+	VMException::InvalidParameter(message.into())
+}
+
+/// Pops an argument that is limited to [`MAX_INPUT_LENGTH`] bytes.
+fn pop_input(engine: &mut ExecutionEngine) -> Result<Vec<u8>, VMException> {
+	let data = pop_bytes(engine)?;
+	if data.len() > MAX_INPUT_LENGTH {
+		return Err(invalid(format!("The input exceeds the maximum length of {MAX_INPUT_LENGTH}.")))
+	}
+	Ok(data)
+}
+
+fn to_string(data: Vec<u8>) -> Result<String, VMException> {
+	String::from_utf8(data).map_err(|_| invalid("The input is not valid UTF-8."))
+}
+
+fn pop_i32(engine: &mut ExecutionEngine) -> Result<i32, VMException> {
+	let value = pop_integer(engine)?;
+	value
+		.to_i32()
+		.ok_or_else(|| invalid(format!("The value {value} is out of range.")))
+}
+
+fn pop_bool(engine: &mut ExecutionEngine) -> Result<bool, VMException> {
+	let item = engine.pop()?;
+	let value = item.borrow().get_boolean();
+	value
+}
+
+fn push_bytes(engine: &mut ExecutionEngine, data: Vec<u8>) -> Result<(), VMException> {
+	engine.push(Rc::new(RefCell::new(ByteString::new(data))))
+}
+
+fn push_integer(engine: &mut ExecutionEngine, value: BigInt) -> Result<(), VMException> {
+	engine.push(Rc::new(RefCell::new(Integer::try_from(value)?)))
+}
+
+fn serialize(engine: &mut ExecutionEngine) -> Result<(), VMException> {
+	let item = engine.pop()?;
+	let data = binary_serializer::serialize(&*item.borrow(), &engine.limits)?;
+	push_bytes(engine, data)
+}
+
+fn deserialize(engine: &mut ExecutionEngine) -> Result<(), VMException> {
+	let data = pop_bytes(engine)?;
+	let item = binary_serializer::deserialize(
+		&data,
+		&engine.limits,
+		Some(engine.reference_counter.clone()),
+	)?;
+	engine.push(item)
+}
+
+fn json_serialize(engine: &mut ExecutionEngine) -> Result<(), VMException> {
+	let item = engine.pop()?;
+	let json = json_serializer::serialize(&*item.borrow(), engine.limits.max_item_size)?;
+	push_bytes(engine, json)
+}
+
+fn json_deserialize(engine: &mut ExecutionEngine) -> Result<(), VMException> {
+	let json = pop_bytes(engine)?;
+	let item = json_serializer::deserialize(
+		&json,
+		&engine.limits,
+		Some(engine.reference_counter.clone()),
+	)?;
+	engine.push(item)
+}
+
+fn itoa(engine: &mut ExecutionEngine) -> Result<(), VMException> {
+	let value = pop_integer(engine)?;
+	let text = match pop_i32(engine)? {
+		10 => value.to_string(),
+		16 => to_hex(&value),
+		base => return Err(invalid(format!("Invalid base: {base}"))),
+	};
+	push_bytes(engine, text.into_bytes())
+}
+
+/// Formats `value` as two's-complement hex with the fewest digits that keep its sign, like
+/// .NET's `BigInteger.ToString("x")`: 255 is `0ff` and -1 is `f`.
+fn to_hex(value: &BigInt) -> String {
+	let hex: String = value.to_signed_bytes_be().iter().map(|byte| format!("{byte:02x}")).collect();
+	let digits = hex.as_bytes();
+	let redundant = digits.len() > 1
+		&& match digits[0] {
+			b'0' => digits[1] < b'8',
+			b'f' => digits[1] >= b'8',
+			_ => false,
+		};
+	if redundant {
+		hex[1..].to_string()
+	} else {
+		hex
+	}
+}
+
+fn atoi(engine: &mut ExecutionEngine) -> Result<(), VMException> {
+	let text = to_string(pop_input(engine)?)?;
+	let value = match pop_i32(engine)? {
+		10 => parse_decimal(&text),
+		16 => parse_hex(&text),
+		base => return Err(invalid(format!("Invalid base: {base}"))),
+	};
+	let value = value.ok_or_else(|| invalid(format!("Invalid number: {text}")))?;
+	push_integer(engine, value)
+}
+
+/// Parses decimal digits with an optional leading sign.
+fn parse_decimal(text: &str) -> Option<BigInt> {
+	let digits = text.strip_prefix(['+', '-']).unwrap_or(text);
+	if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+		return None
+	}
+	let value = BigInt::parse_bytes(digits.as_bytes(), 10)?;
+	Some(if text.starts_with('-') { -value } else { value })
+}
+
+/// Parses two's-complement hex, where a first digit of 8 or more makes the value negative.
+fn parse_hex(text: &str) -> Option<BigInt> {
+	if text.is_empty() || !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+		return None
+	}
+	let value = BigInt::parse_bytes(text.as_bytes(), 16)?;
+	if text.as_bytes()[0] >= b'8' {
+		return Some(value - (BigInt::one() << (4 * text.len())))
+	}
+	Some(value)
+}
+
+fn base64_encode(engine: &mut ExecutionEngine) -> Result<(), VMException> {
+	let data = pop_input(engine)?;
+	push_bytes(engine, STANDARD.encode(data).into_bytes())
+}
+
+fn base64_decode(engine: &mut ExecutionEngine) -> Result<(), VMException> {
+	let mut text = to_string(pop_input(engine)?)?;
+	text.retain(|c| !c.is_ascii_whitespace());
+	let data = STANDARD.decode(text).map_err(|e| invalid(format!("Invalid base64: {e}")))?;
+	push_bytes(engine, data)
+}
+
+fn base58_encode(engine: &mut ExecutionEngine) -> Result<(), VMException> {
+	let data = pop_input(engine)?;
+	push_bytes(engine, base58::encode(&data).into_bytes())
+}
+
+fn base58_decode(engine: &mut ExecutionEngine) -> Result<(), VMException> {
+	let text = to_string(pop_input(engine)?)?;
+	let data = base58::decode(&text).map_err(|e| invalid(e.to_string()))?;
+	push_bytes(engine, data)
+}
+
+fn base58_check_encode(engine: &mut ExecutionEngine) -> Result<(), VMException> {
+	let data = pop_input(engine)?;
+	push_bytes(engine, base58::check_encode(&data).into_bytes())
+}
+
+fn base58_check_decode(engine: &mut ExecutionEngine) -> Result<(), VMException> {
+	let text = to_string(pop_input(engine)?)?;
+	let data = base58::check_decode(&text).map_err(|e| invalid(e.to_string()))?;
+	push_bytes(engine, data)
+}
+
+fn memory_compare(engine: &mut ExecutionEngine) -> Result<(), VMException> {
+	let first = pop_input(engine)?;
+	let second = pop_input(engine)?;
+	push_integer(engine, BigInt::from(first.cmp(&second) as i8))
+}
+
+/// Pushes the index of `value` in `mem`, searching forward from `start` or backward from
+/// just before it, or -1 when it is not found.
+fn memory_search(engine: &mut ExecutionEngine) -> Result<(), VMException> {
+	let mem = pop_input(engine)?;
+	let value = pop_bytes(engine)?;
+	let start = pop_i32(engine)?;
+	let backward = pop_bool(engine)?;
+	if start < 0 || start as usize > mem.len() {
+		return Err(invalid(format!("The value {start} is out of range.")))
+	}
+	let start = start as usize;
+
+	let index = if value.is_empty() {
+		Some(start)
+	} else if backward {
+		mem[..start].windows(value.len()).rposition(|window| window == value)
+	} else {
+		mem[start..]
+			.windows(value.len())
+			.position(|window| window == value)
+			.map(|i| i + start)
+	};
+	push_integer(engine, index.map_or(BigInt::from(-1), BigInt::from))
+}
+
+fn string_split(engine: &mut ExecutionEngine) -> Result<(), VMException> {
+	let text = to_string(pop_input(engine)?)?;
+	let separator = to_string(pop_bytes(engine)?)?;
+	let remove_empty_entries = pop_bool(engine)?;
+
+	// As in .NET, an empty separator leaves the string whole.
+	let mut parts =
+		if separator.is_empty() { vec![text.as_str()] } else { text.split(&*separator).collect() };
+	if remove_empty_entries {
+		parts.retain(|part| !part.is_empty());
+	}
+	let items = parts
+		.into_iter()
+		.map(|part| {
+			Rc::new(RefCell::new(ByteString::new(part.as_bytes().to_vec())))
+				as Rc<RefCell<dyn StackItem>>
+		})
+		.collect();
+	let array = Array::new(Some(items), Some(engine.reference_counter.clone()));
+	engine.push(Rc::new(RefCell::new(array)))
+}
+
+/// Pushes the number of user-perceived characters, so an emoji with modifiers counts once.
+fn str_len(engine: &mut ExecutionEngine) -> Result<(), VMException> {
+	let text = to_string(pop_input(engine)?)?;
+	push_integer(engine, BigInt::from(text.graphemes(true).count()))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		execution_result::{run_script, ExecutionResult},
+		interop_service::syscall_hash,
+		script::script_builder::ScriptBuilder,
+	};
+	use serde_json::{json, Value};
+
+	/// Pushes `args` so the first one ends up on top, calls `method` and returns the result.
+	fn call(method: &str, args: &[Value]) -> ExecutionResult {
+		let mut builder = ScriptBuilder::new();
+		for arg in args.iter().rev() {
+			match arg {
+				Value::Number(n) => builder.push_int(n.as_i64().unwrap()),
+				Value::Bool(b) => builder.push_bool(*b),
+				Value::String(s) => builder.push_string(s),
+				_ => unreachable!(),
+			}
+		}
+		builder.push_syscall(syscall_hash(&std_lib_syscall(method)));
+		run_script(&builder.to_bytes().unwrap(), register_std_lib_services)
+	}
+
+	fn string(text: &str) -> Value {
+		json!({"type": "ByteString", "value": STANDARD.encode(text)})
+	}
+
+	fn integer(value: i64) -> Value {
+		json!({"type": "Integer", "value": value.to_string()})
+	}
+
+	#[test]
+	fn converts_integers() {
+		for (value, base, text) in
+			[(255, 16, "0ff"), (-1, 16, "f"), (-128, 16, "80"), (0, 16, "0"), (-42, 10, "-42")]
+		{
+			assert_eq!(call("itoa", &[json!(value), json!(base)]).stack, vec![string(text)]);
+			assert_eq!(call("atoi", &[json!(text), json!(base)]).stack, vec![integer(value)]);
+		}
+		assert_eq!(call("atoi", &[json!("+7"), json!(10)]).stack, vec![integer(7)]);
+		assert_eq!(call("atoi", &[json!("1_000"), json!(10)]).state, "FAULT");
+		assert_eq!(call("atoi", &[json!("9".repeat(80)), json!(10)]).state, "FAULT");
+		assert_eq!(call("itoa", &[json!(1), json!(2)]).state, "FAULT");
+	}
+
+	#[test]
+	fn encodes_and_decodes() {
+		assert_eq!(call("base64Encode", &[json!("hello")]).stack, vec![string("aGVsbG8=")]);
+		assert_eq!(call("base64Decode", &[json!("aGVsbG8=")]).stack, vec![string("hello")]);
+		assert_eq!(call("base58Encode", &[json!("hello")]).stack, vec![string("Cn8eVZg")]);
+		assert_eq!(call("base58Decode", &[json!("Cn8eVZg")]).stack, vec![string("hello")]);
+		assert_eq!(call("base58CheckDecode", &[json!("Cn8eVZg")]).state, "FAULT");
+		assert_eq!(call("base64Encode", &[json!("a".repeat(MAX_INPUT_LENGTH + 1))]).state, "FAULT");
+	}
+
+	#[test]
+	fn compares_and_searches_memory() {
+		assert_eq!(call("memoryCompare", &[json!("abc"), json!("abd")]).stack, vec![integer(-1)]);
+		assert_eq!(call("memoryCompare", &[json!("b"), json!("abc")]).stack, vec![integer(1)]);
+		let search = |start, backward| {
+			call("memorySearch", &[json!("abcabc"), json!("bc"), json!(start), json!(backward)])
+		};
+		assert_eq!(search(0, false).stack, vec![integer(1)]);
+		assert_eq!(search(2, false).stack, vec![integer(4)]);
+		assert_eq!(search(6, true).stack, vec![integer(4)]);
+		assert_eq!(search(2, true).stack, vec![integer(-1)]);
+		assert_eq!(search(7, false).state, "FAULT");
+	}
+
+	#[test]
+	fn splits_and_measures_strings() {
+		let result = call("stringSplit", &[json!("a,,b"), json!(","), json!(true)]);
+		assert_eq!(
+			result.stack,
+			vec![json!({"type": "Array", "value": [string("a"), string("b")]})]
+		);
+		let result = call("stringSplit", &[json!("a,,b"), json!(","), json!(false)]);
+		assert_eq!(result.stack[0]["value"].as_array().unwrap().len(), 3);
+
+		assert_eq!(call("strLen", &[json!("héllo")]).stack, vec![integer(5)]);
+		assert_eq!(call("strLen", &[json!("👩‍👩‍👧")]).stack, vec![integer(1)]);
+	}
+}
@@ -1,4 +1,3 @@
-use crate::vm_state::VMState;
 use std::{
 	error::Error,
 	fmt,
@@ -45,9 +44,31 @@ pub enum VMException {
 	Custom(String),
 }
 
+impl VMException {
+	/// Whether scripts can catch the error, when `ExecutionEngineLimits::catch_engine_exceptions`
+	/// allows it. Only missing items are catchable, as in Neo.
+	pub fn is_catchable(&self) -> bool {
+		matches!(self, Self::ItemNotFound(_))
+	}
+}
+
 impl Display for VMException {
 	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-		todo!()
+		match self {
+			Self::InvocationStackOverflow(msg) =>
+				write!(f, "invocation stack size limit exceeded: {msg}"),
+			Self::TryNestingOverflow(msg) => write!(f, "try nesting depth limit exceeded: {msg}"),
+			Self::StackOverflow(msg) => write!(f, "stack size limit exceeded: {msg}"),
+			Self::ItemTooLarge(msg) => write!(f, "item size exceeds limit: {msg}"),
+			Self::InvalidOpcode(msg) => write!(f, "encountered invalid opcode: {msg}"),
+			Self::DivisionByZero(msg) => write!(f, "tried to divide by zero: {msg}"),
+			Self::InvalidJump(msg) => write!(f, "invalid jump offset or pointer: {msg}"),
+			Self::InvalidToken(msg) => write!(f, "invalid token encountered: {msg}"),
+			Self::InvalidParameter(msg) => write!(f, "invalid parameter for operation: {msg}"),
+			Self::ItemNotFound(msg) => write!(f, "item not found in collection: {msg}"),
+			Self::InvalidType(msg) => write!(f, "type mismatch for operation: {msg}"),
+			Self::Custom(msg) => write!(f, "custom VM error: {msg}"),
+		}
 	}
 }
 
@@ -56,46 +77,3 @@ impl Error for VMException {
 		None
 	}
 }
-
-// impl fmt::Display for VMException {
-//     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-//         match self {
-//             Self::InvocationStackOverflow => {
-//                 write!(f, "invocation stack size limit exceeded")
-//             }
-//             Self::TryNestingOverflow => {
-//                 write!(f, "try nesting depth limit exceeded")
-//             }
-//             Self::StackOverflow => {
-//                 write!(f, "stack size limit exceeded")
-//             }
-//             Self::ItemTooLarge => {
-//                 write!(f, "item size exceeds limit")
-//             }
-//             Self::InvalidOpcode => {
-//                 write!(f, "encountered invalid opcode")
-//             }
-//             Self::DivisionByZero => {
-//                 write!(f, "tried to divide by zero")
-//             }
-//             Self::InvalidJump => {
-//                 write!(f, "invalid jump offset or pointer")
-//             }
-//             Self::InvalidToken => {
-//                 write!(f, "invalid token encountered")
-//             }
-//             Self::InvalidParameter => {
-//                 write!(f, "invalid parameter for operation")
-//             }
-//             Self::ItemNotFound => {
-//                 write!(f, "item not found in collection")
-//             }
-//             Self::InvalidType => {
-//                 write!(f, "type mismatch for operation")
-//             }
-//             Self::Custom(msg) => {
-//                 write!(f, "custom VM error: {}", msg)
-//             }
-//         }
-//     }
-// }
@@ -0,0 +1,136 @@
+//! JavaScript bindings, built with `--features wasm` for `wasm32-unknown-unknown`.
+
+use crate::{
+	execution_engine::ExecutionEngine, execution_engine_limits::ExecutionEngineLimits,
+	execution_result::ExecutionResult, vm::script::Script,
+};
+use wasm_bindgen::prelude::*;
+
+/// The gas a script may consume unless [`WasmEngine::set_gas_limit`] sets another budget, so
+/// that a script that never halts faults instead of hanging the page.
+pub const DEFAULT_GAS_LIMIT: u64 = 10_000_000;
+
+/// A reusable engine configuration exposed to JavaScript as `Engine`.
+#[wasm_bindgen(js_name = Engine)]
+pub struct WasmEngine {
+	limits: ExecutionEngineLimits,
+	gas_limit: u64,
+}
+
+impl Default for WasmEngine {
+	fn default() -> Self {
+		Self { limits: ExecutionEngineLimits::default(), gas_limit: DEFAULT_GAS_LIMIT }
+	}
+}
+
+#[wasm_bindgen(js_class = Engine)]
+impl WasmEngine {
+	#[wasm_bindgen(constructor)]
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Replaces the limits from a JSON object such as `{"maxStackSize": 512}`.
+	/// Omitted fields keep their default values.
+	#[wasm_bindgen(js_name = setLimits)]
+	pub fn set_limits(&mut self, limits: &str) -> Result<(), JsError> {
+		self.limits = serde_json::from_str(limits)?;
+		Ok(())
+	}
+
+	/// Returns the current limits as a JSON object.
+	#[wasm_bindgen(js_name = getLimits)]
+	pub fn get_limits(&self) -> String {
+		serde_json::to_string(&self.limits).unwrap()
+	}
+
+	/// Sets the gas a script may consume before the engine faults.
+	#[wasm_bindgen(js_name = setGasLimit)]
+	pub fn set_gas_limit(&mut self, gas_limit: u64) {
+		self.gas_limit = gas_limit;
+	}
+
+	/// Returns the gas a script may consume, [`DEFAULT_GAS_LIMIT`] unless changed.
+	#[wasm_bindgen(js_name = getGasLimit)]
+	pub fn get_gas_limit(&self) -> u64 {
+		self.gas_limit
+	}
+
+	/// Runs `script` until it halts, faults or runs out of gas and returns
+	/// `{"state", "exception", "stack", "gas_consumed"}` as JSON.
+	pub fn execute(&self, script: &[u8]) -> Result<String, JsError> {
+		execute_with_limits(script, self.limits, self.gas_limit)
+			.map(|result| result.to_json_string())
+			.map_err(|e| JsError::new(&e))
+	}
+}
+
+/// Runs `script` with the default limits and [`DEFAULT_GAS_LIMIT`] and returns the result as
+/// JSON.
+#[wasm_bindgen(js_name = executeScript)]
+pub fn execute_script(script: &[u8]) -> Result<String, JsError> {
+	WasmEngine::default().execute(script)
+}
+
+fn execute_with_limits(
+	script: &[u8],
+	limits: ExecutionEngineLimits,
+	gas_limit: u64,
+) -> Result<ExecutionResult, String> {
+	let script = Script::new(script.to_vec(), true).map_err(|e| format!("Invalid script: {e}"))?;
+
+	let mut engine = ExecutionEngine::with_options(limits);
+	engine.gas_limit = gas_limit;
+	engine.load_script(script, -1, 0);
+	engine.execute();
+
+	ExecutionResult::from_engine(&engine).map_err(|e| format!("{e:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::{json, Value};
+
+	fn execute(engine: &WasmEngine, script: &[u8]) -> Value {
+		serde_json::from_str(&engine.execute(script).unwrap()).unwrap()
+	}
+
+	#[test]
+	fn returns_the_result_as_json() {
+		// PUSH1 PUSH2 ADD
+		let result = execute(&WasmEngine::new(), &[0x11, 0x12, 0x9E]);
+		assert_eq!(result["state"], "HALT");
+		assert_eq!(result["stack"], json!([{ "type": "Integer", "value": "3" }]));
+	}
+
+	#[test]
+	fn stops_endless_loops_at_the_gas_limit() {
+		let mut engine = WasmEngine::new();
+		assert_eq!(engine.get_gas_limit(), DEFAULT_GAS_LIMIT);
+		engine.set_gas_limit(100);
+
+		// JMP 0
+		let result = execute(&engine, &[0x22, 0x00]);
+		assert_eq!(result["state"], "FAULT");
+		assert_eq!(result["gas_consumed"], 102);
+	}
+
+	#[test]
+	fn applies_limits_from_json() {
+		let mut engine = WasmEngine::new();
+		engine.set_limits(r#"{"maxStackSize": 1}"#).unwrap();
+		assert_eq!(engine.limits.max_stack_size, 1);
+		assert_eq!(engine.limits.max_shift, ExecutionEngineLimits::default().max_shift);
+
+		// PUSH1 PUSH1
+		assert_eq!(execute(&engine, &[0x11, 0x11])["state"], "FAULT");
+	}
+
+	#[test]
+	fn rejects_invalid_scripts() {
+		// JMP past the end of the script.
+		let error = execute_with_limits(&[0x22, 0x10], Default::default(), DEFAULT_GAS_LIMIT);
+		assert!(error.unwrap_err().starts_with("Invalid script"));
+	}
+}
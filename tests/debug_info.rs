@@ -0,0 +1,87 @@
+//! Runs a script against `fixtures/token.debug.json` and checks what the debug info adds to
+//! fault reports, listings and slot dumps.
+
+use neo_vm_rs::{
+	disassembler::Disassembler, execution_engine::ExecutionEngine, fault_report::FaultReport,
+	instruction::SlotKind, script::debug_info::DebugInfo, vm::script::Script, vm_state::VMState,
+};
+use std::{fs, path::Path};
+
+/// PUSH7 PUSH5 INITSSLOT 1 INITSLOT 1 2 LDARG1 STLOC0 LDLOC0 PUSH0 DIV RET
+const SCRIPT: [u8; 13] =
+	[0x17, 0x15, 0x56, 0x01, 0x57, 0x01, 0x02, 0x79, 0x70, 0x68, 0x10, 0xA1, 0x40];
+
+fn debug_info() -> DebugInfo {
+	let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/token.debug.json");
+	DebugInfo::parse(&fs::read_to_string(path).unwrap()).unwrap()
+}
+
+fn run() -> ExecutionEngine {
+	let mut engine = ExecutionEngine::new();
+	engine.load_script(Script::new(SCRIPT.to_vec(), true).unwrap(), -1, 0);
+	engine.execute();
+	assert_eq!(engine.state, VMState::Fault);
+	engine
+}
+
+#[test]
+fn annotates_fault_reports() {
+	let mut report = FaultReport::from_engine(&run()).unwrap();
+	report.annotate(&debug_info());
+
+	let frame = &report.frames[0];
+	assert_eq!(frame.instruction_pointer, 0x0B);
+	assert_eq!(frame.method.as_deref(), Some("Token.Transfer"));
+	assert_eq!(frame.source.as_ref().unwrap().to_string(), "Token.cs:13:9");
+	assert!(report
+		.to_string()
+		.ends_with("  at 000B Div in Token.Transfer (Token.cs:13:9)\n"));
+}
+
+#[test]
+fn comments_listings() {
+	let info = debug_info();
+	assert_eq!(
+		Disassembler::new(&SCRIPT).with_debug_info(&info).disassemble(),
+		"; Token.Transfer\n\
+		 ; Token.cs:10:5\n\
+		 0000: PUSH7\n\
+		 0001: PUSH5\n\
+		 0002: INITSSLOT 1\n\
+		 0004: INITSLOT 1, 2\n\
+		 ; Token.cs:12:9\n\
+		 0007: LDARG1 ; arg amount\n\
+		 0008: STLOC0 ; local balance\n\
+		 ; Token.cs:13:9\n\
+		 0009: LDLOC0 ; local balance\n\
+		 000A: PUSH0\n\
+		 000B: DIV\n\
+		 000C: RET\n"
+	);
+}
+
+#[test]
+fn names_slots() {
+	let engine = run();
+	let context = engine.current_context.as_ref().unwrap().borrow();
+	let slots = debug_info().describe_slots(&context).unwrap();
+
+	let described: Vec<_> = slots
+		.iter()
+		.map(|slot| (slot.kind, slot.index, slot.name.as_deref(), slot.value.to_string()))
+		.collect();
+	assert_eq!(
+		described,
+		[
+			(SlotKind::Static, 0, Some("owner"), r#"{"type":"Any"}"#.to_string()),
+			(SlotKind::Local, 0, Some("balance"), r#"{"type":"Integer","value":"7"}"#.to_string()),
+			(SlotKind::Argument, 0, Some("from"), r#"{"type":"Integer","value":"5"}"#.to_string()),
+			(
+				SlotKind::Argument,
+				1,
+				Some("amount"),
+				r#"{"type":"Integer","value":"7"}"#.to_string()
+			),
+		]
+	);
+}
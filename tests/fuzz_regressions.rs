@@ -0,0 +1,65 @@
+//! Replays every input saved under `fuzz/regressions/<target>` through the matching harness.
+//! New crashes found by `cargo fuzz` are added by copying the artifact into that directory.
+
+use neo_vm_rs::fuzzing::{fuzz_execute, fuzz_instruction, fuzz_script, FuzzOutcome};
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
+const TARGETS: &[&str] = &["execute", "instruction_from_script", "script_new"];
+
+fn regressions() -> PathBuf {
+	Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/regressions")
+}
+
+fn replay<T>(target: &str, mut harness: impl FnMut(&[u8]) -> T) -> Vec<(String, T)> {
+	let dir = regressions().join(target);
+	let mut entries: Vec<_> = fs::read_dir(&dir)
+		.unwrap_or_else(|e| panic!("cannot read {}: {e}", dir.display()))
+		.map(|entry| entry.unwrap().path())
+		.collect();
+	entries.sort();
+	assert!(!entries.is_empty(), "no regressions in {}", dir.display());
+
+	entries
+		.into_iter()
+		.map(|path| {
+			let data = fs::read(&path).unwrap();
+			println!("replaying {}", path.display());
+			let name = path.file_name().unwrap().to_string_lossy().into_owned();
+			(name, harness(&data))
+		})
+		.collect()
+}
+
+#[test]
+fn every_target_has_a_replay() {
+	let mut dirs: Vec<_> = fs::read_dir(regressions())
+		.unwrap()
+		.map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+		.collect();
+	dirs.sort();
+	assert_eq!(dirs, TARGETS);
+}
+
+#[test]
+fn instruction_from_script_regressions() {
+	replay("instruction_from_script", fuzz_instruction);
+}
+
+#[test]
+fn script_new_regressions() {
+	replay("script_new", fuzz_script);
+}
+
+#[test]
+fn execute_regressions() {
+	for (name, outcome) in replay("execute", fuzz_execute) {
+		let expected = match name.as_str() {
+			"infinite-jump" => FuzzOutcome::OutOfGas,
+			_ => FuzzOutcome::Faulted,
+		};
+		assert_eq!(outcome, expected, "{name}");
+	}
+}
@@ -0,0 +1,108 @@
+//! Runs the `neovm` binary on small scripts and checks its output and exit code.
+
+use serde_json::Value;
+use std::{fs, process::Command};
+
+struct Run {
+	code: i32,
+	stdout: String,
+	stderr: String,
+}
+
+impl Run {
+	fn result(&self) -> Value {
+		serde_json::from_str(&self.stdout).unwrap()
+	}
+}
+
+fn neovm(args: &[&str]) -> Run {
+	let output = Command::new(env!("CARGO_BIN_EXE_neovm")).args(args).output().unwrap();
+	Run {
+		code: output.status.code().unwrap(),
+		stdout: String::from_utf8(output.stdout).unwrap(),
+		stderr: String::from_utf8(output.stderr).unwrap(),
+	}
+}
+
+fn integers(result: &Value) -> Vec<&str> {
+	result["stack"]
+		.as_array()
+		.unwrap()
+		.iter()
+		.map(|item| item["value"].as_str().unwrap())
+		.collect()
+}
+
+#[test]
+fn decodes_hex_by_default() {
+	// PUSH1 PUSH2 ADD
+	for script in ["11129e", "0x11129E"] {
+		let run = neovm(&[script]);
+		assert_eq!(run.code, 0, "{}", run.stderr);
+		assert_eq!(run.result()["state"], "HALT");
+		assert_eq!(integers(&run.result()), ["3"]);
+	}
+}
+
+#[test]
+fn decodes_base64_only_when_asked() {
+	let run = neovm(&["--base64", "ERKe"]);
+	assert_eq!(run.code, 0, "{}", run.stderr);
+	assert_eq!(integers(&run.result()), ["3"]);
+
+	let run = neovm(&["ERKe"]);
+	assert_eq!(run.code, 2);
+	assert!(run.stderr.contains("--base64"), "{}", run.stderr);
+
+	// Valid as both hex and base64: read as hex unless `--base64` is given.
+	let run = neovm(&["1111"]);
+	assert_eq!(integers(&run.result()), ["1", "1"]);
+	let run = neovm(&["--base64", "EREQ"]);
+	assert_eq!(integers(&run.result()), ["1", "1", "0"]);
+}
+
+#[test]
+fn reads_script_files() {
+	let path = std::env::temp_dir().join(format!("neovm-cli-{}.bin", std::process::id()));
+	fs::write(&path, [0x11, 0x12, 0x9E]).unwrap();
+	let run = neovm(&["--file", path.to_str().unwrap()]);
+	fs::remove_file(&path).unwrap();
+	assert_eq!(run.code, 0, "{}", run.stderr);
+	assert_eq!(integers(&run.result()), ["3"]);
+}
+
+#[test]
+fn applies_limits_and_gas() {
+	// PUSH1 PUSH1
+	let run = neovm(&["--max-stack-size", "1", "1111"]);
+	assert_eq!(run.code, 1);
+	assert_eq!(run.result()["state"], "FAULT");
+	assert!(run.stderr.contains("stack size limit exceeded"), "{}", run.stderr);
+
+	// JMP 0
+	let run = neovm(&["--gas", "10", "2200"]);
+	assert_eq!(run.code, 1);
+	assert_eq!(run.result()["state"], "FAULT");
+	assert_eq!(run.result()["gas_consumed"], 12);
+	assert!(run.stderr.contains("Insufficient gas"), "{}", run.stderr);
+}
+
+#[test]
+fn traces_every_instruction() {
+	let run = neovm(&["--trace", "11129e"]);
+	assert_eq!(run.code, 0, "{}", run.stderr);
+	let lines: Vec<&str> = run.stderr.lines().collect();
+	assert_eq!(lines.len(), 4, "{}", run.stderr);
+	assert!(lines[0].starts_with("0000 Push1"), "{}", lines[0]);
+	assert!(lines[2].starts_with("0002 Add"), "{}", lines[2]);
+	assert!(lines[2].ends_with(r#"top={"type":"Integer","value":"2"}"#), "{}", lines[2]);
+	assert!(lines[3].starts_with("0003 Ret"), "{}", lines[3]);
+}
+
+#[test]
+fn rejects_invalid_scripts() {
+	// JMP past the end of the script.
+	let run = neovm(&["2210"]);
+	assert_eq!(run.code, 2);
+	assert!(run.stderr.contains("invalid script"), "{}", run.stderr);
+}